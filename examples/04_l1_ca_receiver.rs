@@ -7,6 +7,7 @@ extern crate rust_radio;
 extern crate rustfft;
 extern crate serde;
 
+use std::collections::BTreeMap;
 use std::fs::File;
 
 use clap::{Arg, App};
@@ -17,9 +18,16 @@ use rustfft::num_complex::Complex;
 use rust_radio::block::{BlockFunctionality, BlockResult};
 use rust_radio::block::block_tree_sync_static::split_and_merge::RotatingSplitAndMerge;
 use rust_radio::{io::BufferedSource, Sample};
+use rust_radio::gnss::galileo_e1;
+use rust_radio::gnss::gps_l2c;
 use rust_radio::gnss::gps_l1_ca::pvt;
 use rust_radio::gnss::gps_l1_ca::channel::{self, ChannelReport};
-use rust_radio::utils::kinematics;
+use rust_radio::gnss::gps_l1_ca::pvt::ephemeris::Almanac;
+use rust_radio::gnss::gps_l1_ca::pvt::ephemeris_store::EphemerisError;
+use rust_radio::gnss::gps_l1_ca::telemetry_decode::ErrorCorrection;
+use rust_radio::gnss::gps_l1_ca::telemetry_decode::subframe::{SubframeBody, subframe5};
+use rust_radio::gnss::sbas_l1;
+use rust_radio::utils::{geoid, kinematics};
 
 // TODO: make these configurable
 const WEEK_SEC:f64 = 3600.0 * 24.0 * 7.0;
@@ -41,10 +49,73 @@ pub fn main() -> Result<(), &'static str> {
 		.arg(Arg::with_name("sample_rate_sps")
 			.short("s").long("sample_rate_sps")
 			.takes_value(true).required(true))
+		.arg(Arg::with_name("intermediate_freq_hz")
+			.long("intermediate_freq_hz")
+			.help("Intermediate frequency of the L1 C/A input, or zero for complex baseband")
+			.takes_value(true).default_value("0.0"))
 		.arg(Arg::with_name("output_fixes")
 			.long("output_fixes")
 			.help("Output filename for JSON-formatted fixes")
 			.takes_value(true))
+		.arg(Arg::with_name("ekf")
+			.long("ekf")
+			.help("Navigate with an extended Kalman filter tuned for the given dynamics instead of snapshot least squares")
+			.takes_value(true)
+			.possible_values(&["static", "pedestrian", "vehicle"]))
+		.arg(Arg::with_name("vector")
+			.long("vector")
+			.help("Close each channel's code loop through the navigation filter so weak SVs stay in track")
+			.requires("ekf"))
+		.arg(Arg::with_name("output_observations")
+			.long("output_observations")
+			.help("Output filename for JSON-formatted observations, e.g. to use as a DGPS base station log")
+			.takes_value(true))
+		.arg(Arg::with_name("dgps_base")
+			.long("dgps_base")
+			.help("Observation log from a base station, used to correct the observations as they come in")
+			.takes_value(true)
+			.requires("base_ecef"))
+		.arg(Arg::with_name("base_ecef")
+			.long("base_ecef")
+			.help("Surveyed base station position as x,y,z [m] in ECEF")
+			.takes_value(true))
+		.arg(Arg::with_name("reference_ecef")
+			.long("reference_ecef")
+			.help("Known receiver position as x,y,z [m] in ECEF, to report east, north and up errors against")
+			.takes_value(true))
+		.arg(Arg::with_name("integrity")
+			.long("integrity")
+			.help("Check the signals for signs of spoofing and interference"))
+		.arg(Arg::with_name("output_alerts")
+			.long("output_alerts")
+			.help("Output filename for JSON-formatted integrity alerts")
+			.takes_value(true)
+			.requires("integrity"))
+		.arg(Arg::with_name("sbas")
+			.long("sbas")
+			.help("PRN of an SBAS GEO (120-158) to track and correct the observations with")
+			.takes_value(true))
+		.arg(Arg::with_name("galileo_codes")
+			.long("galileo_codes")
+			.help("E1B/E1C code listing to track Galileo with as well, for snapshot fixes only since the EKF has no inter-system bias")
+			.takes_value(true)
+			.conflicts_with("ekf"))
+		.arg(Arg::with_name("l2_filename")
+			.long("l2_filename")
+			.help("L2 input filename, sampled alongside the L1 input, for ionosphere-free fixes from L1 C/A and L2C")
+			.takes_value(true)
+			.conflicts_with_all(&["ekf", "sbas", "dgps_base", "galileo_codes"]))
+		.arg(Arg::with_name("parity_correction")
+			.long("parity_correction")
+			.help("Correct up to two words with parity errors per subframe instead of dropping the subframe"))
+		.arg(Arg::with_name("geoid_grid")
+			.long("geoid_grid")
+			.help("EGM96 grid in NGA's WW15MGH.GRD format for heights above mean sea level, instead of the built-in approximation")
+			.takes_value(true))
+		.arg(Arg::with_name("output_almanac")
+			.long("output_almanac")
+			.help("Output filename for the JSON-formatted almanac decoded from subframes 4 and 5, e.g. for the planner example")
+			.takes_value(true))
 		.arg(Arg::with_name("output_rollovers")
 			.long("output_rollovers")
 			.help("Output filename for JSON-formatted TOW rollovers")
@@ -53,6 +124,7 @@ pub fn main() -> Result<(), &'static str> {
 
 	let fname:&str = matches.value_of("filename").unwrap();
 	let fs = matches.value_of("sample_rate_sps").unwrap().parse().unwrap();
+	let intermediate_freq_hz:f64 = matches.value_of("intermediate_freq_hz").unwrap().parse().unwrap();
 	
 	let mut tow_rcv:f64 = 0.0;
 	let mut last_tow_rcv:f64 = 0.0;
@@ -62,18 +134,97 @@ pub fn main() -> Result<(), &'static str> {
 
 	let pvt_rate_samples:usize = (fs * 0.5) as usize;
 
+	let error_correction = if matches.is_present("parity_correction") {
+		ErrorCorrection{ max_corrected_words: 2, double_bit_candidates: 4, word_level_acceptance: false }
+	} else { ErrorCorrection::default() };
+
 	let mut sam = RotatingSplitAndMerge::from_iter((1..=32).map( |prn| {
 
-		channel::new_channel(prn, fs, 0.008, pvt_rate_samples)
+		channel::new_channel(prn, fs, intermediate_freq_hz, 0.008, pvt_rate_samples, error_correction)
 	
 	}), 200_000, None);
 
 
 	let mut all_fixes:Vec<pvt::GnssFix> = vec![];
+	let mut all_solutions:Vec<pvt::ekf::NavigationSolution> = vec![];
 	let mut all_rollovers:Vec<(f64, usize)> = vec![];
+	let mut all_observations:Vec<pvt::dgps::ObservationEpoch> = vec![];
+	let mut all_alerts:Vec<pvt::integrity::IntegrityEvent> = vec![];
+	let mut all_almanacs:BTreeMap<usize, Almanac> = BTreeMap::new();
 
 	let mut x_master = Vector4::new(0.0, 0.0, 0.0, 0.0);
-	let ionosphere:Option<pvt::ionosphere::Model> = None;
+	let mut ionosphere:Option<pvt::ionosphere::Model> = None;
+	let reference_ecef:Option<(f64, f64, f64)> = match matches.value_of("reference_ecef") {
+		Some(s) => {
+			let r:Vec<f64> = s.split(',').map(|x| x.trim().parse().map_err(|_| "Unable to parse reference position")).collect::<Result<_, _>>()?;
+			if r.len() != 3 { return Err("Reference position should be three comma-separated values"); }
+			Some((r[0], r[1], r[2]))
+		},
+		None => None,
+	};
+	let solver_config = pvt::SolverConfig{ reference_ecef, ..Default::default() };
+	let vector_tracking:bool = matches.is_present("vector");
+	let vector_config = pvt::vector_tracking::VectorTrackingConfig::default();
+
+	let mut opt_dgps:Option<(pvt::dgps::Dgps, std::iter::Peekable<std::vec::IntoIter<pvt::dgps::ObservationEpoch>>)> = match matches.value_of("dgps_base") {
+		Some(base_fname) => {
+			let base_ecef:Vec<f64> = matches.value_of("base_ecef").unwrap().split(',').map(|x| x.trim().parse().map_err(|_| "Unable to parse base position")).collect::<Result<_, _>>()?;
+			if base_ecef.len() != 3 { return Err("Base position should be three comma-separated values"); }
+			let base_log = pvt::dgps::read_log(File::open(base_fname).map_err(|_| "Unable to open base station log")?)?;
+			Some((pvt::dgps::Dgps::new((base_ecef[0], base_ecef[1], base_ecef[2]), solver_config, 30.0), base_log.into_iter().peekable()))
+		},
+		None => None,
+	};
+	let mut opt_monitor:Option<pvt::integrity::IntegrityMonitor> = if matches.is_present("integrity") {
+		Some(pvt::integrity::IntegrityMonitor::new(pvt::integrity::IntegrityConfig::default()))
+	} else { None };
+	let mut almanac_week:u16 = 0;
+	let mut opt_sbas:Option<(sbas_l1::channel::Channel, pvt::sbas::Sbas)> = match matches.value_of("sbas") {
+		Some(s) => {
+			let prn:usize = s.parse().map_err(|_| "Unable to parse SBAS PRN")?;
+			if !sbas_l1::is_sbas_prn(prn) { return Err("SBAS PRN should be between 120 and 158"); }
+			Some((sbas_l1::channel::new_channel(prn, fs, sbas_l1::channel::DEFAULT_TEST_STAT_THRESHOLD), pvt::sbas::Sbas::new(Default::default())))
+		},
+		None => None,
+	};
+
+	let mut opt_galileo = match matches.value_of("galileo_codes") {
+		Some(codes_fname) => {
+			let codes = galileo_e1::signal_modulation::PrimaryCodes::load(File::open(codes_fname).map_err(|_| "Unable to open Galileo code file")?)?;
+			let channels:Vec<galileo_e1::channel::Channel> = codes.prns().into_iter()
+				.map(|prn| galileo_e1::channel::new_channel(prn, &codes, fs, galileo_e1::channel::DEFAULT_TEST_STAT_THRESHOLD, pvt_rate_samples))
+				.collect::<Result<_, _>>()?;
+			Some(RotatingSplitAndMerge::from_iter(channels.into_iter(), 200_000, None))
+		},
+		None => None,
+	};
+
+	let opt_geoid:Option<geoid::GeoidGrid> = match matches.value_of("geoid_grid") {
+		Some(grid_fname) => Some(geoid::GeoidGrid::from_grd(File::open(grid_fname).map_err(|_| "Unable to open geoid grid")?)?),
+		None => None,
+	};
+	let height_msl_m = |pos:&kinematics::PositionWGS84| match opt_geoid.as_ref().and_then(|grid| grid.undulation_m(pos.latitude, pos.longitude)) {
+		Some(undulation_m) => pos.height_above_ellipsoid - undulation_m,
+		None => geoid::height_msl_m(pos.latitude, pos.longitude, pos.height_above_ellipsoid),
+	};
+
+	let mut opt_l2 = match matches.value_of("l2_filename") {
+		Some(l2_fname) => {
+			let l2_src:BufferedSource<File, (i16, i16)> = BufferedSource::new(File::open(l2_fname).map_err(|_| "Unable to open L2 input file")?)?;
+			let channels = (1..=32).map(|prn| gps_l2c::channel::new_channel(prn, fs, gps_l2c::channel::DEFAULT_TEST_STAT_THRESHOLD, pvt_rate_samples));
+			Some((l2_src, RotatingSplitAndMerge::from_iter(channels, 200_000, None)))
+		},
+		None => None,
+	};
+
+	let mut opt_filter:Option<pvt::ekf::NavigationFilter> = matches.value_of("ekf").map(|dynamics| {
+		let dynamics = match dynamics {
+			"static" => pvt::ekf::Dynamics::Static,
+			"pedestrian" => pvt::ekf::Dynamics::Pedestrian,
+			_ => pvt::ekf::Dynamics::Vehicle,
+		};
+		pvt::ekf::NavigationFilter::new(pvt::ekf::EkfConfig{ dynamics, solver: solver_config, ..Default::default() })
+	});
 
 	let src:BufferedSource<File, (i16, i16)> = BufferedSource::new(File::open(&fname).unwrap()).unwrap();
 	for s in src.map(|(x, idx)| Sample{ val: Complex{ re: x.0 as f64, im: x.1 as f64 }, idx }) {
@@ -91,37 +242,188 @@ pub fn main() -> Result<(), &'static str> {
 
 		last_tow_rcv = tow_rcv;
 
+		if let Some((sbas_chn, sbas)) = opt_sbas.as_mut() {
+			if let BlockResult::Ready(msg) = sbas_chn.apply(&s) {
+				eprintln!("New SBAS Message: {}", format!("type {}", msg.type_id).cyan());
+				sbas.add_message(&msg, tow_rcv);
+			}
+		}
+
+		let is_pvt_epoch:bool = s.idx % pvt_rate_samples == 0;
 		let sample_w_time = (s, tow_rcv);
 
 		let mut obs_this_soln:Vec<pvt::Observation> = Vec::new();
+		let mut got_reports:bool = false;
 
 		let result:BlockResult<Vec<ChannelReport>> = sam.apply(&sample_w_time);
 
 		match result {
 			BlockResult::Ready(reports) => {
-				for ChannelReport { opt_subframe, opt_observation, new_ionosphere:_ } in reports {
+				got_reports = true;
+				let mut got_new_ionosphere:bool = false;
+				for ChannelReport { opt_subframe, opt_observation, new_ionosphere, opt_cutover } in reports {
+					got_new_ionosphere |= new_ionosphere;
 					if let Some(new_sf) = opt_subframe {
 
 						if (new_sf.time_of_week() - tow_rcv).abs() > 1.0 { tow_rcv = new_sf.time_of_week() + 0.086 }
 						eprintln!("New Subframe: {}", format!("{:?}", new_sf).cyan());
 
+						if let SubframeBody::Subframe5(subframe5::Body{ page: subframe5::Page::Page25{ WN_a, .. }, .. }) = new_sf.body { almanac_week = WN_a as u16; }
+						if let Some(almanac) = Almanac::from_subframe(&new_sf, almanac_week) {
+							if let Some(monitor) = opt_monitor.as_mut() { monitor.set_almanac(almanac); }
+							all_almanacs.insert(almanac.prn, almanac);
+						}
+
+					}
+
+					if let Some(cutover) = opt_cutover {
+						eprintln!("{}", format!("Ephemeris Cutover: PRN {}, IODE {} -> {}, t_oe {:.0} [sec]", 
+							cutover.prn, cutover.old_iode, cutover.new_iode, cutover.new_t_oe).yellow());
 					}
 
-					if let Some(obs) = opt_observation { 
-						obs_this_soln.push(obs);
+					match opt_observation {
+						Some(Ok(obs)) => obs_this_soln.push(obs),
+						// Not having an ephemeris yet is expected for the first 30 seconds or so, so only report the interesting cases
+						Some(Err(channel::ObservationError::Ephemeris(EphemerisError::NotAvailable{..}))) => {},
+						Some(Err(channel::ObservationError::Ephemeris(e))) => eprintln!("{}", format!("Observation refused: {:?}", e).yellow()),
+						_ => {}
 					}
 				}
+
+				// Every SV broadcasts the same Klobuchar coefficients, so any channel that has decoded them will do
+				if got_new_ionosphere {
+					ionosphere = sam.blocks.iter().find_map(|(_, chn)| chn.ionosphere());
+					eprintln!("New Ionosphere Model: {}", format!("{:?}", ionosphere).cyan());
+				}
 			},
 			BlockResult::Err(e) => eprintln!("{}", format!("Error: {:?}", e).red()),
 			_ => {}
 
 		}
 
-		if let Ok((fix, x)) = pvt::solve_position_and_time(obs_this_soln, x_master, current_rx_time, ionosphere) {
+		// Galileo observations are kept apart until after the corrections below, which only cover GPS and would drop them
+		let mut galileo_obs:Vec<pvt::Observation> = Vec::new();
+		if let Some(galileo_sam) = opt_galileo.as_mut() {
+			let result:BlockResult<Vec<galileo_e1::channel::ChannelReport>> = galileo_sam.apply(&sample_w_time);
+			match result {
+				BlockResult::Ready(reports) => {
+					for galileo_e1::channel::ChannelReport{ opt_word, opt_observation } in reports {
+						if let Some(word) = opt_word { eprintln!("New I/NAV Word: {}", format!("{:?}", word).cyan()); }
+						if let Some(Ok(obs)) = opt_observation { galileo_obs.push(obs); }
+					}
+				},
+				BlockResult::Err(e) => eprintln!("{}", format!("Error: {:?}", e).red()),
+				_ => {}
+			}
+		}
+
+		// The L2 input is sampled at the same instants as the L1 input, so it's read one sample per L1 sample
+		let mut l2c_obs:Vec<gps_l2c::channel::Observation> = Vec::new();
+		if let Some((l2_src, l2_sam)) = opt_l2.as_mut() {
+			let (x, idx) = l2_src.next().ok_or("L2 input ended before L1 input")?;
+			let l2_sample = (Sample{ val: Complex{ re: x.0 as f64, im: x.1 as f64 }, idx }, tow_rcv);
+			let result:BlockResult<Vec<gps_l2c::channel::ChannelReport>> = l2_sam.apply(&l2_sample);
+			match result {
+				BlockResult::Ready(reports) => {
+					for gps_l2c::channel::ChannelReport{ opt_message, opt_observation } in reports {
+						if let Some(msg) = opt_message { eprintln!("New CNAV Message: {}", format!("{:?}", msg).cyan()); }
+						if let Some(Ok(obs)) = opt_observation { l2c_obs.push(obs); }
+					}
+				},
+				BlockResult::Err(e) => eprintln!("{}", format!("Error: {:?}", e).red()),
+				_ => {}
+			}
+		}
+
+		// Paired before the DGPS and SBAS corrections below, since the combination needs both pseudoranges to still carry the ionosphere
+		let dual_frequency_obs = pvt::dual_frequency::pair_observations(&obs_this_soln, &l2c_obs);
+
+		if got_reports && !obs_this_soln.is_empty() && matches.is_present("output_observations") {
+			all_observations.push(pvt::dgps::ObservationEpoch{ tow_sec: tow_rcv, observations: obs_this_soln.clone() });
+		}
+
+		if let Some((dgps, base_log)) = opt_dgps.as_mut() {
+			// Use every base epoch up to this point, as if they'd been streamed in
+			while let Some(base_epoch) = base_log.peek() {
+				if base_epoch.tow_sec > tow_rcv { break; }
+				let _ = dgps.add_base_epoch(base_epoch, ionosphere);
+				base_log.next();
+			}
+			if got_reports { obs_this_soln = dgps.correct(&obs_this_soln, tow_rcv); }
+		}
+
+		// Once SBAS has taken the ionospheric delay out of the pseudoranges, the solver mustn't take it out again
+		let mut solver_ionosphere:Option<pvt::ionosphere::Model> = ionosphere;
+		if let (true, Some((_, sbas))) = (got_reports, opt_sbas.as_ref()) {
+			// The ionospheric grid needs a position to find the pierce points, so it only comes in after the first fix
+			let opt_pos = if updated_once { Some((x_master[0], x_master[1], x_master[2])) } else { None };
+			obs_this_soln = sbas.correct(&obs_this_soln, tow_rcv, opt_pos, ionosphere);
+			if opt_pos.is_some() { solver_ionosphere = None; }
+		}
+
+		// The EKF has no inter-system bias state, so Galileo only goes to the snapshot solver
+		if opt_filter.is_none() { obs_this_soln.extend(galileo_obs); }
+
+		if let (true, Some(monitor)) = (got_reports, opt_monitor.as_mut()) {
+			// Every channel sees the same input, so any one that's tracking can report its power.  Channels report at their own bit
+			// boundaries, so the power is only checked once per solution epoch to keep the baseline to the configured length.
+			let mut events:Vec<pvt::integrity::IntegrityEvent> = vec![];
+			if let (true, Some(power_db)) = (is_pvt_epoch, sam.blocks.iter().find_map(|(_, chn)| chn.input_power_db())) {
+				events.extend(monitor.check_input_power(tow_rcv, power_db));
+			}
+			for (_, chn) in sam.blocks.iter() {
+				if let Some(eph) = chn.ephemeris() { events.extend(monitor.check_ephemeris(chn.prn, &eph, tow_rcv)); }
+			}
+			for event in events.iter() { eprintln!("{}", format!("Integrity Alert: {:.3} [sec], {:?}", event.tow_sec, event.alert).red().bold()); }
+			all_alerts.extend(events);
+		}
+
+		// SVs tracked on both frequencies get an ionosphere-free fix; until there are enough of them the L1 fix below is used
+		let opt_dual_frequency_fix = if dual_frequency_obs.len() >= 5 {
+			pvt::dual_frequency::solve_position_and_time(dual_frequency_obs, x_master, current_rx_time, &solver_config).ok()
+		} else { None };
+
+		if let Some(filter) = opt_filter.as_mut() {
+			// The filter propagates through epochs with too few SVs for a snapshot fix, so run it on every set of channel reports
+			if got_reports {
+				if let Ok(soln) = filter.update(&obs_this_soln, current_rx_time, solver_ionosphere) {
+					let new_pos = kinematics::ecef_to_wgs84(soln.pos_ecef.0, soln.pos_ecef.1, soln.pos_ecef.2);
+					eprintln!("{}", format!("EKF Solution: {:.3} [sec], {:.5} [deg] lat, {:.5} [deg] lon, {:.1} [m] MSL, {} pseudoranges", 
+						tow_rcv, new_pos.latitude * 57.3, new_pos.longitude * 57.3, height_msl_m(&new_pos), soln.pseudoranges_used).green().bold());
+
+					// The aiding has to be computed against the receiver time the observations were taken at, so before steering it
+					if vector_tracking {
+						for (_, chn) in sam.blocks.iter_mut() {
+							let _ = chn.apply_aiding(&soln, tow_rcv, ionosphere, &solver_config, &vector_config);
+						}
+					}
+
+					tow_rcv -= soln.clock_bias_m / (kinematics::C);
+					filter.shift_clock_bias(-soln.clock_bias_m);
+					// SBAS needs a position for the ionospheric grid whichever way it was found
+					x_master = Vector4::new(soln.pos_ecef.0, soln.pos_ecef.1, soln.pos_ecef.2, 0.0);
+					updated_once = true;
+					all_solutions.push(soln);
+				}
+			}
+		} else if let Ok((mut fix, x)) = match opt_dual_frequency_fix {
+			Some(fix_and_x) => Ok(fix_and_x),
+			None => pvt::solve_position_and_time(obs_this_soln, x_master, current_rx_time, solver_ionosphere, &solver_config),
+		} {
 			if fix.residual_norm < 400.0 {
+				// The written fixes carry the same height as the printed one, from the grid when there is one
 				let new_pos = kinematics::ecef_to_wgs84(fix.pos_ecef.0, fix.pos_ecef.1, fix.pos_ecef.2);
-				eprintln!("{}", format!("Position/Time Fix: {:.3} [sec], {:.5} [deg] lat, {:.5} [deg] lon, {:.1} [m]", 
-					tow_rcv, new_pos.latitude * 57.3, new_pos.longitude * 57.3, new_pos.height_above_ellipsoid).green().bold());
+				fix.height_msl_m = height_msl_m(&new_pos);
+				eprintln!("{}", format!("Position/Time Fix: {:.3} [sec], {:.5} [deg] lat, {:.5} [deg] lon, {:.1} [m] MSL", 
+					tow_rcv, new_pos.latitude * 57.3, new_pos.longitude * 57.3, fix.height_msl_m).green().bold());
+				if let Some((e, n, u)) = fix.enu_error_m {
+					eprintln!("Error from reference: {:.1} [m] east, {:.1} [m] north, {:.1} [m] up", e, n, u);
+				}
+				if let Some(monitor) = opt_monitor.as_mut() {
+					let events = monitor.check_fix(&fix, tow_rcv - x[3] / (kinematics::C));
+					for event in events.iter() { eprintln!("{}", format!("Integrity Alert: {:.3} [sec], {:?}", event.tow_sec, event.alert).red().bold()); }
+					all_alerts.extend(events);
+				}
 
 				tow_rcv -= x[3] / (kinematics::C);
 				for i in 0..3 { x_master[i] = x[i]; }
@@ -133,7 +435,21 @@ pub fn main() -> Result<(), &'static str> {
 	}
 
 	if let Some(outfile) = matches.value_of("output_fixes") {
-		std::fs::write(outfile, serde_json::to_string_pretty(&all_fixes).unwrap().as_bytes()).map_err(|_| "Unable to write fixes JSON")?;
+		let json = if opt_filter.is_some() { serde_json::to_string_pretty(&all_solutions) } else { serde_json::to_string_pretty(&all_fixes) };
+		std::fs::write(outfile, json.unwrap().as_bytes()).map_err(|_| "Unable to write fixes JSON")?;
+	}
+
+	if let Some(outfile) = matches.value_of("output_alerts") {
+		std::fs::write(outfile, serde_json::to_string_pretty(&all_alerts).unwrap().as_bytes()).map_err(|_| "Unable to write alerts JSON")?;
+	}
+
+	if let Some(outfile) = matches.value_of("output_observations") {
+		std::fs::write(outfile, serde_json::to_string_pretty(&all_observations).unwrap().as_bytes()).map_err(|_| "Unable to write observations JSON")?;
+	}
+
+	if let Some(outfile) = matches.value_of("output_almanac") {
+		let almanacs:Vec<Almanac> = all_almanacs.values().cloned().collect();
+		std::fs::write(outfile, serde_json::to_string_pretty(&almanacs).unwrap().as_bytes()).map_err(|_| "Unable to write almanac JSON")?;
 	}
 
 	if let Some(outfile) = matches.value_of("output_rollovers") {
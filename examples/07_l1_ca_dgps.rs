@@ -0,0 +1,80 @@
+extern crate clap;
+extern crate colored;
+extern crate rust_radio;
+extern crate serde;
+
+use std::fs::File;
+
+use clap::{Arg, App};
+use colored::*;
+
+use rust_radio::gnss::gps_l1_ca::pvt;
+use rust_radio::utils::kinematics;
+
+pub fn main() -> Result<(), &'static str> {
+
+	let matches = App::new("GPS L1 C/A DGPS Post-Processing")
+		.version("0.1.0")
+		.about("Corrects a rover observation log with a base station observation log and produces differential fixes or RTK baselines")
+		.arg(Arg::with_name("base")
+			.long("base")
+			.help("Base station observation log, as written by the receiver example with --output_observations")
+			.required(true).takes_value(true))
+		.arg(Arg::with_name("rover")
+			.long("rover")
+			.help("Rover observation log")
+			.required(true).takes_value(true))
+		.arg(Arg::with_name("base_ecef")
+			.long("base_ecef")
+			.help("Surveyed base station position as x,y,z [m] in ECEF")
+			.required(true).takes_value(true))
+		.arg(Arg::with_name("max_age_sec")
+			.long("max_age_sec")
+			.help("Oldest correction to apply [sec], 30 by default")
+			.takes_value(true))
+		.arg(Arg::with_name("rtk")
+			.long("rtk")
+			.help("Estimate the baseline from double-differenced carrier phase with integer ambiguity resolution"))
+		.arg(Arg::with_name("output_fixes")
+			.long("output_fixes")
+			.help("Output filename for JSON-formatted fixes")
+			.takes_value(true))
+		.get_matches();
+
+	let base_ecef:Vec<f64> = matches.value_of("base_ecef").unwrap().split(',').map(|x| x.trim().parse().map_err(|_| "Unable to parse base position")).collect::<Result<_, _>>()?;
+	if base_ecef.len() != 3 { return Err("Base position should be three comma-separated values"); }
+	let max_age_sec:f64 = matches.value_of("max_age_sec").map(|s| s.parse().map_err(|_| "Unable to parse max age")).unwrap_or(Ok(30.0))?;
+
+	let base = pvt::dgps::read_log(File::open(matches.value_of("base").unwrap()).map_err(|_| "Unable to open base log")?)?;
+	let rover = pvt::dgps::read_log(File::open(matches.value_of("rover").unwrap()).map_err(|_| "Unable to open rover log")?)?;
+	eprintln!("{} base epochs, {} rover epochs", base.len(), rover.len());
+
+	if matches.is_present("rtk") {
+		let config = pvt::rtk::RtkConfig{ max_age_sec, ..Default::default() };
+		let solutions = pvt::rtk::post_process(&base, &rover, (base_ecef[0], base_ecef[1], base_ecef[2]), None, &config);
+		for soln in solutions.iter() {
+			let line = format!("RTK Baseline: {:.3} [sec], {:.3} [m] east, {:.3} [m] north, {:.3} [m] up, ratio {:.1}, {} SVs",
+				soln.tow_sec, soln.baseline_enu.0, soln.baseline_enu.1, soln.baseline_enu.2, soln.ratio, soln.sv_ids.len());
+			eprintln!("{}", if soln.fixed { line.green().bold() } else { line.yellow() });
+		}
+
+		if let Some(outfile) = matches.value_of("output_fixes") {
+			std::fs::write(outfile, serde_json::to_string_pretty(&solutions).unwrap().as_bytes()).map_err(|_| "Unable to write solutions JSON")?;
+		}
+		return Ok(());
+	}
+
+	// The logs don't carry the broadcast ionosphere, so leave it out of both the corrections and the solutions
+	let fixes = pvt::dgps::post_process(&base, &rover, (base_ecef[0], base_ecef[1], base_ecef[2]), None, &pvt::SolverConfig::default(), max_age_sec);
+	for fix in fixes.iter() {
+		let pos = kinematics::ecef_to_wgs84(fix.pos_ecef.0, fix.pos_ecef.1, fix.pos_ecef.2);
+		eprintln!("{}", format!("DGPS Fix: {:.3} [sec], {:.7} [deg] lat, {:.7} [deg] lon, {:.2} [m] MSL, {} SVs",
+			fix.current_rx_time, pos.latitude * 57.3, pos.longitude * 57.3, fix.height_msl_m, fix.observations.len()).green().bold());
+	}
+
+	if let Some(outfile) = matches.value_of("output_fixes") {
+		std::fs::write(outfile, serde_json::to_string_pretty(&fixes).unwrap().as_bytes()).map_err(|_| "Unable to write fixes JSON")?;
+	}
+
+	Ok(())
+}
@@ -0,0 +1,115 @@
+extern crate clap;
+extern crate colored;
+extern crate rust_radio;
+extern crate serde;
+
+use std::collections::HashMap;
+use std::fs::File;
+
+use clap::{Arg, App};
+use colored::*;
+
+use rust_radio::gnss::gps_l1_ca::{planner, snapshot};
+use rust_radio::gnss::gps_l1_ca::pvt::ephemeris::{Almanac, Ephemeris};
+use rust_radio::utils::kinematics;
+
+pub fn main() -> Result<(), &'static str> {
+
+	let matches = App::new("GPS L1 C/A Visibility Planner")
+		.version("0.1.0")
+		.about("Predicts satellite visibility, Doppler, C/N0 and DOP at a location over a span of time")
+		.arg(Arg::with_name("ephemerides")
+			.long("ephemerides")
+			.help("Ephemerides JSON, as written by the ephemerides example")
+			.takes_value(true)
+			.required_unless("almanac"))
+		.arg(Arg::with_name("almanac")
+			.long("almanac")
+			.help("JSON array of almanac entries as written by the receiver example's --output_almanac, for spans longer than the ephemerides are good for")
+			.takes_value(true)
+			.conflicts_with("ephemerides"))
+		.arg(Arg::with_name("week")
+			.long("week")
+			.help("GPS week number of the start time")
+			.required(true).takes_value(true))
+		.arg(Arg::with_name("tow")
+			.long("tow")
+			.help("GPS time of week of the start [sec]")
+			.required(true).takes_value(true))
+		.arg(Arg::with_name("duration_sec")
+			.long("duration_sec")
+			.help("Length of the span [sec], 12 hours by default")
+			.takes_value(true))
+		.arg(Arg::with_name("step_sec")
+			.long("step_sec")
+			.help("Time between predictions [sec], 60 by default")
+			.takes_value(true))
+		.arg(Arg::with_name("position")
+			.long("position")
+			.help("Receiver position as lat,lon [deg],height [m] above the ellipsoid")
+			.required(true).takes_value(true))
+		.arg(Arg::with_name("elevation_mask_deg")
+			.long("elevation_mask_deg")
+			.help("Lowest elevation to count as visible [deg], 5 by default")
+			.takes_value(true))
+		.arg(Arg::with_name("doppler_margin_hz")
+			.long("doppler_margin_hz")
+			.help("Added to both ends of the Doppler search range for the receiver oscillator error [Hz], 1000 by default")
+			.takes_value(true))
+		.arg(Arg::with_name("output_plan")
+			.long("output_plan")
+			.help("Output filename for the JSON-formatted predictions")
+			.takes_value(true))
+		.get_matches();
+
+	let parse = |name:&str, default:f64, err:&'static str| -> Result<f64, &'static str> {
+		matches.value_of(name).map(|s| s.parse().map_err(|_| err)).unwrap_or(Ok(default))
+	};
+	let week:u16 = matches.value_of("week").unwrap().parse().map_err(|_| "Unable to parse week number")?;
+	let tow:f64 = parse("tow", 0.0, "Unable to parse time of week")?;
+	let duration_sec:f64 = parse("duration_sec", 12.0*3600.0, "Unable to parse duration")?;
+	let config = planner::PlannerConfig{
+		step_sec: parse("step_sec", 60.0, "Unable to parse step")?,
+		elevation_mask_radians: parse("elevation_mask_deg", 5.0, "Unable to parse elevation mask")?.to_radians(),
+		..Default::default()
+	};
+	let doppler_margin_hz:f64 = parse("doppler_margin_hz", 1000.0, "Unable to parse Doppler margin")?;
+
+	let position:Vec<f64> = matches.value_of("position").unwrap().split(',').map(|x| x.trim().parse().map_err(|_| "Unable to parse position")).collect::<Result<_, _>>()?;
+	if position.len() != 3 { return Err("Position should be three comma-separated values"); }
+	let p = kinematics::wgs84_to_ecef(position[0].to_radians(), position[1].to_radians(), position[2]);
+
+	let ephemerides:HashMap<usize, Ephemeris> = match (matches.value_of("ephemerides"), matches.value_of("almanac")) {
+		(Some(fname), _) => snapshot::load_ephemerides(File::open(fname).map_err(|_| "Unable to open ephemerides")?, week, tow + 0.5*duration_sec)?,
+		(_, Some(fname)) => {
+			let almanacs:Vec<Almanac> = serde_json::from_reader(File::open(fname).map_err(|_| "Unable to open almanac")?).map_err(|_| "Unable to parse almanac JSON")?;
+			planner::from_almanacs(&almanacs)
+		},
+		_ => unreachable!(),
+	};
+	eprintln!("{} SVs", ephemerides.len());
+
+	let plan = planner::plan(&ephemerides, (p[0], p[1], p[2]), tow, duration_sec, &config);
+
+	for pass in planner::passes(&plan) {
+		eprintln!("{}", format!("PRN {:2}: rises {:.0} [sec], sets {:.0} [sec], max elevation {:.1} [deg], Doppler {:.0} to {:.0} [Hz]",
+			pass.prn, pass.rise_tow_sec, pass.set_tow_sec, pass.max_el_radians.to_degrees(), pass.min_doppler_hz, pass.max_doppler_hz).green());
+	}
+
+	let pdops:Vec<(f64, f64)> = plan.iter().filter_map(|epoch| epoch.dop.map(|dop| (epoch.tow_sec, dop.pdop))).collect();
+	if let Some((tow_best, best)) = pdops.iter().copied().min_by(|a, b| a.1.partial_cmp(&b.1).unwrap()) {
+		let worst = pdops.iter().map(|(_, pdop)| *pdop).fold(0.0, f64::max);
+		eprintln!("{}", format!("PDOP: best {:.2} at {:.0} [sec], worst {:.2}, {} of {} epochs with at least four SVs",
+			best, tow_best, worst, pdops.len(), plan.len()).bold());
+	}
+	match planner::doppler_search_limits(&plan, doppler_margin_hz) {
+		Some((lo, hi)) => eprintln!("{}", format!("Doppler search range: {:.0} to {:.0} [Hz]", lo, hi).bold()),
+		None => eprintln!("{}", "No SVs visible".red()),
+	}
+
+	if let Some(outfile) = matches.value_of("output_plan") {
+		std::fs::write(outfile, serde_json::to_string_pretty(&plan).unwrap().as_bytes()).map_err(|_| "Unable to write plan JSON")?;
+	}
+
+	Ok(())
+}
@@ -0,0 +1,81 @@
+extern crate clap;
+extern crate colored;
+extern crate rust_radio;
+extern crate serde;
+
+use std::fs::File;
+
+use clap::{Arg, App};
+use colored::*;
+
+use rust_radio::gnss::gps_l1_ca::pvt::GnssFix;
+use rust_radio::gnss::gps_l1_ca::pvt::ins;
+
+pub fn main() -> Result<(), &'static str> {
+
+	let matches = App::new("GPS L1 C/A GNSS/INS Post-Processing")
+		.version("0.1.0")
+		.about("Blends an IMU log with GNSS fixes and produces position, velocity and attitude at the IMU rate")
+		.arg(Arg::with_name("imu")
+			.long("imu")
+			.help("IMU log, CSV with t,ax,ay,az,gx,gy,gz per line or a JSON array if the name ends in .json; body frame is forward-right-down")
+			.required(true).takes_value(true))
+		.arg(Arg::with_name("fixes")
+			.long("fixes")
+			.help("Fixes, as written by the receiver example with --output_fixes")
+			.required(true).takes_value(true))
+		.arg(Arg::with_name("time_offset_sec")
+			.long("time_offset_sec")
+			.help("Added to the fix times to put them on the IMU's clock [sec], 0 by default")
+			.takes_value(true))
+		.arg(Arg::with_name("initial_heading_deg")
+			.long("initial_heading_deg")
+			.help("Heading at the first fix [deg], otherwise taken from the GNSS track once moving")
+			.takes_value(true))
+		.arg(Arg::with_name("outage")
+			.long("outage")
+			.help("Drops the fixes between start,end [sec] on the IMU's clock to simulate a GNSS outage")
+			.takes_value(true))
+		.arg(Arg::with_name("output_solutions")
+			.long("output_solutions")
+			.help("Output filename for the JSON-formatted solutions")
+			.takes_value(true))
+		.get_matches();
+
+	let time_offset_sec:f64 = matches.value_of("time_offset_sec").map(|s| s.parse().map_err(|_| "Unable to parse time offset")).unwrap_or(Ok(0.0))?;
+	let initial_yaw:Option<f64> = match matches.value_of("initial_heading_deg") {
+		Some(s) => Some(s.parse::<f64>().map_err(|_| "Unable to parse initial heading")?.to_radians()),
+		None => None,
+	};
+
+	let imu_fname = matches.value_of("imu").unwrap();
+	let imu_file = File::open(imu_fname).map_err(|_| "Unable to open IMU log")?;
+	let imu = if imu_fname.ends_with(".json") { ins::read_imu_json(imu_file)? } else { ins::read_imu_csv(imu_file)? };
+	let mut fixes:Vec<GnssFix> = serde_json::from_reader(File::open(matches.value_of("fixes").unwrap()).map_err(|_| "Unable to open fixes")?)
+		.map_err(|_| "Unable to parse fixes JSON")?;
+
+	if let Some(s) = matches.value_of("outage") {
+		let limits:Vec<f64> = s.split(',').map(|x| x.trim().parse().map_err(|_| "Unable to parse outage")).collect::<Result<_, _>>()?;
+		if limits.len() != 2 { return Err("Outage should be two comma-separated values"); }
+		fixes.retain(|fix| !(limits[0]..=limits[1]).contains(&(fix.current_rx_time + time_offset_sec)));
+	}
+	eprintln!("{} IMU samples, {} fixes", imu.len(), fixes.len());
+
+	let solutions = ins::run(&imu, &fixes, time_offset_sec, initial_yaw, ins::InsConfig::default());
+	if solutions.is_empty() { return Err("Unable to align, try giving an initial heading"); }
+
+	// Print about once a second
+	let mut t_next:f64 = f64::NEG_INFINITY;
+	for soln in solutions.iter().filter(|soln| if soln.t_sec >= t_next { t_next = soln.t_sec.floor() + 1.0; true } else { false }) {
+		let line = format!("INS: {:.2} [sec], ({:.2}, {:.2}, {:.2}) [m], ({:.2}, {:.2}, {:.2}) [m/s] NED, roll {:.1}, pitch {:.1}, yaw {:.1} [deg], h_acc {:.1} [m]",
+			soln.t_sec, soln.pos_ecef.0, soln.pos_ecef.1, soln.pos_ecef.2, soln.vel_ned.0, soln.vel_ned.1, soln.vel_ned.2,
+			soln.roll_radians.to_degrees(), soln.pitch_radians.to_degrees(), soln.yaw_radians.to_degrees(), soln.h_accuracy_m);
+		eprintln!("{}", if soln.sec_since_fix < 2.0 { line.green() } else { line.yellow() });
+	}
+
+	if let Some(outfile) = matches.value_of("output_solutions") {
+		std::fs::write(outfile, serde_json::to_string_pretty(&solutions).unwrap().as_bytes()).map_err(|_| "Unable to write solutions JSON")?;
+	}
+
+	Ok(())
+}
@@ -14,7 +14,7 @@ use rust_radio::gnss::gps_l2c::tracking_cm;
 use rustfft::num_complex::Complex;
 
 const MAX_ACQ_TRIES_SAMPLES:usize = 2000000;
-const FEC_DECODE_LEN:usize = 300;
+const MIN_FEC_QUALITY:f64 = 0.5;
 
 #[derive(Debug)]
 enum ChannelState {
@@ -79,7 +79,7 @@ fn main() {
 
 	let mut state = ChannelState::Acquisition(0);
 
-	let mut symbols:Vec<bool> = vec![];
+	let mut viterbi = error_correction::ViterbiDecoder::new(error_correction::DEFAULT_TRACEBACK_LEN);
 	let mut messages:Vec<message_decode::Message> = vec![];
 
 	let src:BufferedSource<File, (i16, i16)> = BufferedSource::new(File::open(fname).unwrap()).unwrap();
@@ -122,43 +122,37 @@ fn main() {
 				match trk.apply(&s) {
 					BlockResult::Ready(report) => {
 
-						symbols.push(report.prompt_i > 0.0);
-						if symbols.len() == FEC_DECODE_LEN {
-							let opt_decoded_bits = error_correction::decode(symbols.drain(..).collect());
-							match opt_decoded_bits {
-								Some(decoded_bits) => {
-									
-									for b in decoded_bits {
-										if let Some(msg_bits) = pac.apply(b) {
-											// This set of bits passed the preamble and CRC check, so decode them as a message
-											match message_decode::Message::new(&msg_bits) {
-												Ok(msg) => {
-													eprintln!("{:6.1} [sec] PRN {:02} {}", (s.idx as f64)/fs, prn, format!("New MSG: {:?}", msg).blue());
-													messages.push(msg)
-												},
-												Err(e)  => eprintln!("{:6.1} [sec] PRN {:02} {}", (s.idx as f64)/fs, prn, format!("MSG Decode Err: {:?}", e).red())
-											}
-										}
+						match viterbi.apply(report.prompt_i) {
+							Some(b) => {
+								if let Some(msg_bits) = pac.apply(b) {
+									// This set of bits passed the preamble and CRC check, so decode them as a message
+									match message_decode::Message::new(&msg_bits) {
+										Ok(msg) => {
+											eprintln!("{:6.1} [sec] PRN {:02} {}", (s.idx as f64)/fs, prn, format!("New MSG: {:?}", msg).blue());
+											messages.push(msg)
+										},
+										Err(e)  => eprintln!("{:6.1} [sec] PRN {:02} {}", (s.idx as f64)/fs, prn, format!("MSG Decode Err: {:?}", e).red())
 									}
 
-									eprintln!("{:6.1} [sec] PRN {:02} {}", (s.idx as f64)/fs, prn, format!("TRK OK: {:.8}, {:.1} [Hz]", trk.test_stat(), trk.carrier_freq_hz()).green());
-									
-									None
-								},
-								None => {
+									eprintln!("{:6.1} [sec] PRN {:02} {}", (s.idx as f64)/fs, prn, 
+										format!("TRK OK: {:.8}, {:.1} [Hz], FEC quality {:.3}", trk.test_stat(), trk.carrier_freq_hz(), viterbi.normalized_path_metric()).green());
+								}
+
+								if viterbi.normalized_path_metric() < MIN_FEC_QUALITY {
 									if tried_skip {
 										Some(ChannelState::LostLock)
 									} else {
 										// Try skipping a symbol if this doesn't work the first time; we don't know if we started on a G1 or G2 symbol
-										symbols.push(report.prompt_i > 0.0);
+										viterbi.initialize();
+										viterbi.apply(report.prompt_i);
 										tried_skip = true;
-										None
+										Some(ChannelState::Tracking{ tried_skip })
 									}
-									
+								} else {
+									None
 								}
-							}
-						} else {
-							None
+							},
+							None => None
 						}
 
 					},
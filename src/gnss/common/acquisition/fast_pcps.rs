@@ -26,6 +26,7 @@ pub struct Acquisition {
 	pub last_sample_idx: usize,
 	pub fast_freq_inc:f64,
 	pub n_skip:usize,
+	pub center_freq_hz:f64,		// The search covers the Doppler range around this, e.g. the intermediate frequency
 }
 
 impl BlockFunctionality<(), (), Sample, AcquisitionResult> for Acquisition {
@@ -72,7 +73,7 @@ impl Acquisition {
 
 				// Try every frequency and update best_match every time we find a new best
 				for fine_idx in 0..self.n_fine {
-					let base_freq:f64 = (fine_idx as f64 * self.fast_freq_inc) / (self.n_fine as f64);
+					let base_freq:f64 = self.center_freq_hz + (fine_idx as f64 * self.fast_freq_inc) / (self.n_fine as f64);
 
 					// Wipe the carrier off the input signal
 					let phase_step_rad:f64 = (-2.0 * consts::PI * base_freq) / self.fs;			
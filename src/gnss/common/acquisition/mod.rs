@@ -51,7 +51,7 @@ pub fn make_acquisition(symbol:Vec<Complex<f64>>, fs:f64, prn:usize, n_coarse:us
 
 	fast_pcps::Acquisition{ fs, prn, test_statistic_threshold, n_coarse, n_fine, 
 		buffer, len_fft, fft, local_code_freq_domain, fft_out, ifft, ifft_out, 
-		skip_count: 0, last_sample_idx: 0, fast_freq_inc: -fs / len_fft as f64, n_skip }
+		skip_count: 0, last_sample_idx: 0, fast_freq_inc: -fs / len_fft as f64, n_skip, center_freq_hz: 0.0 }
 }
 
 
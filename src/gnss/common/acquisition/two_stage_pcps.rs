@@ -47,6 +47,9 @@ impl Acquisition {
 
 	pub fn prn(&self) -> usize { self.stage_one.prn }
 
+	// Stage two refines around whatever stage one finds, so only stage one needs to know where to look
+	pub fn set_center_freq_hz(&mut self, hz:f64) { self.stage_one.center_freq_hz = hz; }
+
 	pub fn provide_sample(&mut self, sample:&Sample) -> Result<(), DSPErr> { match self.state {
 		State::StageOne => self.stage_one.provide_sample(sample),
 		State::StageTwo{ current_freq_hz:_, current_step_hz:_, last_code_phase:_ } => self.stage_two.provide_sample(sample),
@@ -0,0 +1,141 @@
+use serde::{Serialize, Deserialize};
+
+#[derive(Debug, Clone, Copy)]
+pub struct HatchConfig {
+	pub window_epochs: usize,			// Upper limit on the averaging length; one means no smoothing
+	pub cmc_threshold_m: f64,			// Largest tolerated jump of code minus carrier relative to the smoothed value
+	pub phase_rate_threshold_mps: f64,	// Largest tolerated difference between the carrier phase rate and the mean range rate from Doppler
+}
+
+impl Default for HatchConfig {
+	fn default() -> Self { Self{ window_epochs: 100, cmc_threshold_m: 15.0, phase_rate_threshold_mps: 5.0 } }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum CycleSlip {
+	LockLoss,				// The tracker restarted carrier tracking, so the accumulated phase starts over
+	CodeMinusCarrier,
+	PhaseRate,
+}
+
+/// One epoch of code and carrier for a single signal, with the carrier expressed as a range so it moves in the same
+/// direction as the code, i.e. the negative of the accumulated Doppler times the wavelength
+#[derive(Debug, Clone, Copy)]
+pub struct CarrierMeasurement {
+	pub t: f64,					// [sec] Receiver time
+	pub code_m: f64,
+	pub carrier_m: f64,
+	pub range_rate_mps: f64,	// From the Doppler
+	pub clock_steering_m: f64,	// Total receiver clock steering included in the code and carrier, which the Doppler never sees
+	pub lock_count: usize,		// Changes whenever carrier tracking restarts
+}
+
+#[derive(Debug, Clone, Copy)]
+struct State {
+	epochs: usize,
+	smoothed_m: f64,
+	last: CarrierMeasurement,
+}
+
+/// Carrier-smoothed code for a single signal.  Each epoch, the code is averaged with the previous smoothed value propagated
+/// by the change in carrier phase, over a window that grows up to the configured length and starts over on a cycle slip.
+#[derive(Debug, Clone)]
+pub struct HatchFilter {
+	pub config: HatchConfig,
+	pub slip_count: usize,
+	pub last_slip: Option<CycleSlip>,
+	opt_state: Option<State>,
+}
+
+impl HatchFilter {
+
+	pub fn new(config:HatchConfig) -> Self { Self{ config, slip_count: 0, last_slip: None, opt_state: None } }
+
+	pub fn reset(&mut self) { self.opt_state = None; }
+
+	/// Number of epochs in the current average, or zero if there isn't one
+	pub fn epochs(&self) -> usize { self.opt_state.map(|s| s.epochs).unwrap_or(0) }
+
+	pub fn detect_slip(&self, m:&CarrierMeasurement) -> Option<CycleSlip> {
+		let state = self.opt_state?;
+		if state.last.lock_count != m.lock_count { return Some(CycleSlip::LockLoss); }
+
+		// Code and carrier both step with the receiver clock, so only the phase rate check has to leave the steering out
+		let dt:f64 = m.t - state.last.t;
+		let d_carrier_m:f64 = m.carrier_m - state.last.carrier_m;
+		let d_steering_m:f64 = m.clock_steering_m - state.last.clock_steering_m;
+		let mean_range_rate:f64 = 0.5 * (m.range_rate_mps + state.last.range_rate_mps);
+		if dt > 0.0 && ((d_carrier_m - d_steering_m) / dt - mean_range_rate).abs() > self.config.phase_rate_threshold_mps {
+			Some(CycleSlip::PhaseRate)
+		} else if (m.code_m - (state.smoothed_m + d_carrier_m)).abs() > self.config.cmc_threshold_m {
+			Some(CycleSlip::CodeMinusCarrier)
+		} else {
+			None
+		}
+	}
+
+	/// Returns the smoothed code [m] for this epoch
+	pub fn apply(&mut self, m:CarrierMeasurement) -> f64 {
+		let opt_slip = self.detect_slip(&m);
+		if opt_slip.is_some() {
+			self.slip_count += 1;
+			self.last_slip = opt_slip;
+		}
+
+		let next = match (self.opt_state, opt_slip) {
+			(Some(state), None) => {
+				let epochs:usize = (state.epochs + 1).min(self.config.window_epochs.max(1));
+				let propagated_m:f64 = state.smoothed_m + (m.carrier_m - state.last.carrier_m);
+				let smoothed_m:f64 = m.code_m / (epochs as f64) + propagated_m * ((epochs - 1) as f64 / epochs as f64);
+				State{ epochs, smoothed_m, last: m }
+			},
+			_ => State{ epochs: 1, smoothed_m: m.code_m, last: m },
+		};
+
+		self.opt_state = Some(next);
+		next.smoothed_m
+	}
+
+}
+
+#[test]
+fn test_hatch_filter() {
+	let mut hatch = HatchFilter::new(HatchConfig{ window_epochs: 50, ..Default::default() });
+	let measurement = |i:usize, noise:f64, slip_m:f64, lock_count:usize| {
+		let t:f64 = i as f64;
+		let range:f64 = 2.0e7 + 300.0*t;
+		CarrierMeasurement{ t, code_m: range + noise, carrier_m: range - 1.0e5 + slip_m, range_rate_mps: 300.0, clock_steering_m: 0.0, lock_count }
+	};
+
+	// Alternating 3 m code noise averages down over the window
+	for i in 0..200 {
+		let noise:f64 = if i % 2 == 0 { 3.0 } else { -3.0 };
+		let smoothed = hatch.apply(measurement(i, noise, 0.0, 1));
+		if i > 100 { assert!((smoothed - 2.0e7 - 300.0*(i as f64)).abs() < 0.1); }
+	}
+	assert_eq!((hatch.epochs(), hatch.slip_count), (50, 0));
+
+	// A jump in the carrier shows up in the phase rate, a restarted tracker shows up in the lock count
+	hatch.apply(measurement(200, 0.0, 19.0, 1));
+	assert_eq!((hatch.epochs(), hatch.last_slip), (1, Some(CycleSlip::PhaseRate)));
+	hatch.apply(measurement(201, 0.0, 19.0, 1));
+	assert_eq!(hatch.epochs(), 2);
+	hatch.apply(measurement(202, 0.0, 19.0, 2));
+	assert_eq!((hatch.epochs(), hatch.last_slip), (1, Some(CycleSlip::LockLoss)));
+
+	// With a loose phase rate threshold, the same kind of jump is still caught by the code minus carrier check
+	let mut hatch = HatchFilter::new(HatchConfig{ phase_rate_threshold_mps: 50.0, ..Default::default() });
+	for i in 0..10 { hatch.apply(measurement(i, 0.0, 0.0, 1)); }
+	hatch.apply(measurement(10, 0.0, 20.0, 1));
+	assert_eq!(hatch.last_slip, Some(CycleSlip::CodeMinusCarrier));
+	assert_eq!(hatch.slip_count, 1);
+
+	// Steering the receiver clock by 10 us moves code and carrier by 3 km together, which isn't a slip
+	let mut hatch = HatchFilter::new(HatchConfig::default());
+	for i in 0..20 {
+		let steering_m:f64 = if i < 10 { 0.0 } else { 2997.92458 };
+		let m = measurement(i, 0.0, 0.0, 1);
+		hatch.apply(CarrierMeasurement{ code_m: m.code_m + steering_m, carrier_m: m.carrier_m + steering_m, clock_steering_m: steering_m, ..m });
+	}
+	assert_eq!((hatch.epochs(), hatch.slip_count), (20, 0));
+}
@@ -0,0 +1,180 @@
+use nalgebra::base::{DMatrix, DVector};
+
+const MAX_SEARCH_LOOPS:usize = 10000;
+
+// Factors Q = L' * diag(D) * L with L unit lower triangular
+fn ld(q:&DMatrix<f64>) -> Result<(DMatrix<f64>, DVector<f64>), &'static str> {
+	let n:usize = q.nrows();
+	let mut a = q.clone();
+	let mut l:DMatrix<f64> = DMatrix::zeros(n, n);
+	let mut d:DVector<f64> = DVector::zeros(n);
+	for i in (0..n).rev() {
+		d[i] = a[(i,i)];
+		if d[i] <= 0.0 { return Err("Ambiguity covariance isn't positive definite"); }
+		let s:f64 = d[i].sqrt();
+		for j in 0..=i { l[(i,j)] = a[(i,j)] / s; }
+		for j in 0..i {
+			for k in 0..=j { a[(j,k)] -= l[(i,k)] * l[(i,j)]; }
+		}
+		let l_ii:f64 = l[(i,i)];
+		for j in 0..=i { l[(i,j)] /= l_ii; }
+	}
+	Ok((l, d))
+}
+
+// Integer Gauss transformation that reduces L(i,j)
+fn gauss(l:&mut DMatrix<f64>, z:&mut DMatrix<f64>, i:usize, j:usize) {
+	let mu:f64 = l[(i,j)].round();
+	if mu != 0.0 {
+		for k in i..l.nrows() { l[(k,j)] -= mu * l[(k,i)]; }
+		for k in 0..z.nrows() { z[(k,j)] -= mu * z[(k,i)]; }
+	}
+}
+
+fn permute(l:&mut DMatrix<f64>, d:&mut DVector<f64>, z:&mut DMatrix<f64>, j:usize, del:f64) {
+	let n:usize = l.nrows();
+	let eta:f64 = d[j] / del;
+	let lam:f64 = d[j+1] * l[(j+1,j)] / del;
+	d[j] = eta * d[j+1];
+	d[j+1] = del;
+	for k in 0..j {
+		let (a0, a1) = (l[(j,k)], l[(j+1,k)]);
+		l[(j,k)] = -l[(j+1,j)]*a0 + a1;
+		l[(j+1,k)] = eta*a0 + lam*a1;
+	}
+	l[(j+1,j)] = lam;
+	for k in (j+2)..n { l.swap((k,j), (k,j+1)); }
+	for k in 0..n { z.swap((k,j), (k,j+1)); }
+}
+
+// Decorrelates the ambiguities with a unimodular Z so that Z' * Q * Z = L' * diag(D) * L is closer to diagonal
+fn reduction(l:&mut DMatrix<f64>, d:&mut DVector<f64>, z:&mut DMatrix<f64>) {
+	let n:usize = l.nrows();
+	if n < 2 { return; }
+	let mut j:isize = n as isize - 2;
+	let mut k:isize = n as isize - 2;
+	while j >= 0 {
+		let ju:usize = j as usize;
+		if j <= k { for i in (ju+1)..n { gauss(l, z, i, ju); } }
+		let del:f64 = d[ju] + l[(ju+1,ju)].powi(2) * d[ju+1];
+		if del + 1.0e-6 < d[ju+1] {
+			permute(l, d, z, ju, del);
+			k = j;
+			j = n as isize - 2;
+		} else {
+			j -= 1;
+		}
+	}
+}
+
+fn sgn(x:f64) -> f64 { if x <= 0.0 { -1.0 } else { 1.0 } }
+
+// Depth-first search with shrinking ellipsoid for the m integer vectors closest to zs in the decorrelated space
+fn search(l:&DMatrix<f64>, d:&DVector<f64>, zs:&DVector<f64>, m:usize) -> Result<Vec<(DVector<f64>, f64)>, &'static str> {
+	let n:usize = l.nrows();
+	let mut s_mat:DMatrix<f64> = DMatrix::zeros(n, n);
+	let mut dist:DVector<f64> = DVector::zeros(n);
+	let mut zb:DVector<f64> = DVector::zeros(n);
+	let mut z:DVector<f64> = DVector::zeros(n);
+	let mut step:DVector<f64> = DVector::zeros(n);
+	let mut found:Vec<(DVector<f64>, f64)> = vec![];
+	let mut max_dist:f64 = f64::MAX;
+
+	let mut k:usize = n - 1;
+	zb[k] = zs[k];
+	z[k] = zb[k].round();
+	let mut y:f64 = zb[k] - z[k];
+	step[k] = sgn(y);
+
+	let mut loops:usize = 0;
+	loop {
+		loops += 1;
+		if loops > MAX_SEARCH_LOOPS { return Err("Integer search didn't finish"); }
+
+		let new_dist:f64 = dist[k] + y*y / d[k];
+		if new_dist < max_dist {
+			if k != 0 {
+				k -= 1;
+				dist[k] = new_dist;
+				for i in 0..=k { s_mat[(k,i)] = s_mat[(k+1,i)] + (z[k+1] - zb[k+1]) * l[(k+1,i)]; }
+				zb[k] = zs[k] + s_mat[(k,k)];
+				z[k] = zb[k].round();
+				y = zb[k] - z[k];
+				step[k] = sgn(y);
+			} else {
+				if found.len() < m {
+					found.push((z.clone(), new_dist));
+				} else {
+					if let Some(worst) = (0..m).max_by(|a, b| found[*a].1.total_cmp(&found[*b].1)) {
+						if new_dist < found[worst].1 { found[worst] = (z.clone(), new_dist); }
+					}
+				}
+				if found.len() == m { max_dist = found.iter().map(|(_, s)| *s).fold(0.0, f64::max); }
+
+				z[0] += step[0];
+				y = zb[0] - z[0];
+				step[0] = -step[0] - sgn(step[0]);
+			}
+		} else {
+			if k == n - 1 { break; }
+			k += 1;
+			z[k] += step[k];
+			y = zb[k] - z[k];
+			step[k] = -step[k] - sgn(step[k]);
+		}
+	}
+
+	found.sort_by(|a, b| a.1.total_cmp(&b.1));
+	Ok(found)
+}
+
+/// Integer least squares by the LAMBDA method: the m integer vectors closest to the float ambiguities a in the metric of their
+/// covariance q, best first, each with its squared distance.  The ratio of the second distance to the first is the usual test
+/// of whether the best one can be trusted.
+pub fn integer_least_squares(a:&DVector<f64>, q:&DMatrix<f64>, m:usize) -> Result<Vec<(DVector<f64>, f64)>, &'static str> {
+	let n:usize = a.len();
+	if n == 0 || m == 0 { return Ok(vec![]); }
+	if a.iter().chain(q.iter()).any(|x| !x.is_finite()) { return Err("Float ambiguities or their covariance aren't finite"); }
+
+	let (mut l, mut d) = ld(q)?;
+	let mut z = DMatrix::identity(n, n);
+	reduction(&mut l, &mut d, &mut z);
+	let zs = z.transpose() * a;
+
+	// Back to the original ambiguities; Z is unimodular, so its inverse is an integer matrix too
+	let z_t_inv = z.transpose().try_inverse().ok_or("Non-invertible matrix")?;
+	Ok(search(&l, &d, &zs, m)?.into_iter().map(|(v, s)| ((&z_t_inv * v).map(|x| x.round()), s)).collect())
+}
+
+#[test]
+fn test_integer_least_squares() {
+	// Strongly correlated ambiguities, where rounding each one on its own gets the wrong answer
+	let q = DMatrix::from_row_slice(3, 3, &[
+		6.290, 5.978, 0.544,
+		5.978, 6.292, 2.340,
+		0.544, 2.340, 6.288]);
+	let a = DVector::from_vec(vec![5.45, 3.10, 2.97]);
+	let q_inv = q.clone().try_inverse().unwrap();
+
+	// Brute force over a box around the float solution
+	let mut best:Vec<(DVector<f64>, f64)> = vec![];
+	for i in -5..=15 { for j in -5..=15 { for k in -5..=15 {
+		let v = DVector::from_vec(vec![i as f64, j as f64, k as f64]);
+		let r = &a - &v;
+		best.push((v, (r.transpose() * &q_inv * &r)[(0,0)]));
+	}}}
+	best.sort_by(|x, y| x.1.total_cmp(&y.1));
+
+	let found = integer_least_squares(&a, &q, 2).unwrap();
+	assert_eq!(found.len(), 2);
+	for (f, b) in found.iter().zip(best.iter()) {
+		assert_eq!(f.0, b.0);
+		assert!((f.1 - b.1).abs() < 1.0e-9);
+	}
+	assert!(found[0].0 != a.map(|x| x.round()));
+
+	// A NaN from upstream is an error rather than a panic
+	let mut a_nan = a.clone();
+	a_nan[1] = f64::NAN;
+	assert!(integer_least_squares(&a_nan, &q, 2).is_err());
+}
@@ -1,3 +1,6 @@
 
 pub mod acquisition;
+pub mod hatch_filter;
+pub mod lambda;
+pub mod nav_data_stats;
 pub mod tracking;
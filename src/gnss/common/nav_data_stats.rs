@@ -0,0 +1,26 @@
+use serde::{Serialize, Deserialize};
+
+/// Running statistics describing the quality of the navigation data coming out of a channel.  The telemetry decoders keep these
+/// up to date and they're never cleared by a decoder reset, so they cover everything since the channel was created.  Not every
+/// field applies to every signal (e.g. word-level parity is specific to LNAV and CRC failures are specific to CNAV).
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct NavDataStats {
+	pub words_checked: usize,
+	pub word_parity_failures: usize,
+	pub words_corrected: usize,
+	pub crc_failures: usize,
+	pub subframes_decoded: usize,
+	pub subframes_partially_accepted: usize,
+	pub preamble_detections: usize,
+	pub preamble_false_detections: usize,
+	pub polarity_flips: usize,
+	pub ephemeris_changes: usize,
+}
+
+impl NavDataStats {
+
+	pub fn word_error_rate(&self) -> f64 {
+		if self.words_checked == 0 { 0.0 } else { self.word_parity_failures as f64 / self.words_checked as f64 }
+	}
+
+}
@@ -0,0 +1,146 @@
+
+use num_complex::Complex;
+
+use crate::{Sample, DigSigProcErr as DSPErr};
+
+use crate::block::{BlockFunctionality, BlockResult};
+use crate::block::block_tree_sync_static::acquire_and_track::AcquireAndTrack;
+
+use crate::filters::{SecondOrderFIR as FIR};
+use crate::gnss::common::acquisition::{two_stage_pcps::Acquisition, AcquisitionResult};
+use crate::gnss::common::nav_data_stats::NavDataStats;
+use crate::gnss::common::tracking::TrackReport;
+use crate::gnss::gps_l1_ca::channel::{ObservationError, C_METERS_PER_SEC};
+use crate::gnss::gps_l1_ca::pvt;
+
+use super::inav::{EphemerisWords, Ggto, Word, PAGE_LEN_SEC};
+use super::signal_modulation::{self, PrimaryCodes};
+use super::tlm_decode::TelemetryDecoder;
+use super::tracking;
+
+pub const DEFAULT_TEST_STAT_THRESHOLD:f64 = 0.002;
+
+#[derive(Debug)]
+pub struct ChannelReport {
+	pub opt_word:Option<Word>,
+	pub opt_observation:Option<Result<pvt::Observation, ObservationError>>,
+}
+
+/// Acquires and tracks one Galileo SV on E1, decodes its I/NAV words and produces observations for the GPS PVT.  The
+/// observations carry SV IDs offset from the PRN (see `galileo_e1::sv_id`) so they can't be mistaken for GPS ones.
+pub struct Channel {
+	pub prn: usize,
+	pub fs:  f64,
+	pub aat: AcquireAndTrack<Sample, AcquisitionResult, TrackReport, Acquisition, tracking::Tracking<FIR>>,
+	pub tlm: TelemetryDecoder,
+	pub last_sample_idx: usize,
+	pub ephemeris_words: EphemerisWords,
+	pub ephemerides: pvt::ephemeris_store::EphemerisStore,
+	pub ggto: Option<Ggto>,
+	pub week_number: Option<u16>,		// GST
+	pub pvt_rate_samples: usize,
+	pub intermediate_freq_hz: f64,
+}
+
+impl BlockFunctionality<(), bool, (Sample, f64), ChannelReport> for Channel {
+
+	fn control(&mut self, _:&()) -> Result<bool, &'static str> {
+		Ok(!self.aat.awaiting_acq)
+	}
+
+	fn apply(&mut self, input:&(Sample, f64)) -> BlockResult<ChannelReport> {
+		let (s, tow_rcv) = input;
+		self.last_sample_idx = s.idx;
+
+		let opt_word:Option<Word> = match self.aat.apply(s) {
+			BlockResult::Ready(TrackReport{ prompt_i, .. }) => self.tlm.apply(prompt_i),
+			BlockResult::NotReady => {
+				// Start the decoder over whenever the tracker has to reacquire
+				if self.aat.awaiting_acq { self.tlm.initialize(); }
+				None
+			},
+			BlockResult::Err(_) => return BlockResult::Err(DSPErr::LossOfLock),
+		};
+		if let Some(word) = opt_word { self.add_word(&word); }
+
+		let opt_observation = if s.idx % self.pvt_rate_samples == 0 { Some(self.observation(*tow_rcv)) } else { None };
+		if opt_word.is_none() && opt_observation.is_none() { BlockResult::NotReady }
+		else { BlockResult::Ready(ChannelReport{ opt_word, opt_observation }) }
+	}
+
+}
+
+impl Channel {
+
+	pub fn carrier_freq_hz(&self) -> f64 { self.aat.trk.carrier_freq_hz() }
+	pub fn test_stat(&self) -> f64 { self.aat.trk.test_stat() }
+	pub fn cn0_dbhz(&self) -> f64 { self.aat.trk.cn0_dbhz() }
+	pub fn ephemeris(&self) -> Option<pvt::ephemeris::Ephemeris> { self.ephemerides.current(self.prn).map(|stored| stored.ephemeris) }
+	pub fn nav_data_stats(&self) -> NavDataStats { self.tlm.stats }
+
+	fn add_word(&mut self, word:&Word) {
+		// The word is complete at the end of its odd page part, which is one page after the time of week it carries
+		if let Some(tow) = word.time_of_week() {
+			self.aat.trk.reset_clock(tow as f64 + PAGE_LEN_SEC + (self.aat.trk.code_phase_samples()/self.fs));
+		}
+
+		match word {
+			Word::IonosphereAndHealth(w5) => self.week_number = Some(w5.week_number),
+			Word::AlmanacAndGgto(ggto) => self.ggto = Some(*ggto),
+			_ => {},
+		}
+
+		self.ephemeris_words.add(word);
+		if let Some(complete) = self.ephemeris_words.ephemeris() {
+			if self.ephemerides.insert(self.prn, complete.ephemeris, complete.sv_health, complete.ura_index).is_some() {
+				self.tlm.stats.ephemeris_changes += 1;
+			}
+		}
+	}
+
+	/// Observation from the code NCO.  The SV time is converted from GST to GPS time with the broadcast GGTO once it's been
+	/// received; until then the difference, which is tens of nanoseconds at most, is left to the inter-system bias in the PVT.
+	pub fn observation(&self, rx_tow_sec:f64) -> Result<pvt::Observation, ObservationError> {
+		if self.aat.awaiting_acq { return Err(ObservationError::NotTracking); }
+
+		let sv_tow_sec:f64 = self.aat.trk.sv_time_of_week();
+		let stored = self.ephemerides.get(self.prn, sv_tow_sec).map_err(ObservationError::Ephemeris)?;
+		let eph = stored.ephemeris;
+		let pvt::ephemeris::SvState{ pos_ecef, vel_ecef:sv_vel_ecef, clock:sv_clock, clock_drift:sv_clock_drift, .. } = eph.sv_state(sv_tow_sec);
+		let ggto_sec:f64 = match (self.ggto, self.week_number) {
+			(Some(ggto), Some(week_number)) => ggto.offset_sec(week_number, sv_tow_sec),
+			_ => 0.0,
+		};
+
+		let carrier_freq_hz:f64 = self.aat.trk.carrier_freq_hz();
+		let doppler_hz:f64 = carrier_freq_hz - self.intermediate_freq_hz;
+		let corrections_m:f64 = (sv_clock - eph.t_gd) * C_METERS_PER_SEC;
+		let pseudorange_m:f64 = (rx_tow_sec - sv_tow_sec + ggto_sec) * C_METERS_PER_SEC + corrections_m;
+
+		let wavelength_m:f64 = C_METERS_PER_SEC / pvt::L1_FREQ_HZ;
+		let carrier_phase_cycles:Option<f64> = if self.aat.trk.has_carrier_lock() {
+			let rx_clock_steering_sec:f64 = rx_tow_sec - (self.last_sample_idx as f64) / self.fs;
+			Some((-wavelength_m * self.aat.trk.carrier_cycles() + rx_clock_steering_sec * C_METERS_PER_SEC + corrections_m) / wavelength_m)
+		} else { None };
+
+		Ok(pvt::Observation{ sv_id: super::sv_id(self.prn), sv_tow_sec, pseudorange_m, pseudorange_raw_m: pseudorange_m, smoothing_epochs: 0,
+			pos_ecef, sv_clock, t_gd: eph.t_gd, carrier_freq_hz, doppler_hz, sv_vel_ecef, sv_clock_drift, cn0_dbhz: self.aat.trk.cn0_dbhz(),
+			ura_m: stored.ura_m(), carrier_phase_cycles, carrier_lock_count: self.aat.trk.carrier_lock_count(), iodc: Some(eph.iodc) })
+	}
+
+}
+
+/// Sets up a channel for one PRN, or returns an error if the code file didn't have both of its codes.  Acquisition runs on the
+/// pilot over a single 4-ms code period, so the Doppler bins are 250 Hz wide before the second stage refines them.
+pub fn new_channel(prn:usize, codes:&PrimaryCodes, fs:f64, test_stat_threshold:f64, pvt_rate_samples:usize) -> Result<Channel, &'static str> {
+	let data_code:Vec<i8> = codes.e1b(prn).ok_or("No E1B code for this PRN")?.to_vec();
+	let pilot_code:Vec<i8> = codes.e1c(prn).ok_or("No E1C code for this PRN")?.to_vec();
+
+	let symbol:Vec<Complex<f64>> = signal_modulation::boc_sampled(&pilot_code, fs).into_iter().map(|x| Complex{ re: x as f64, im: 0.0 }).collect();
+	let acq = Acquisition::new(symbol, fs, prn, 24, 2, 20.0, test_stat_threshold, 2);
+	let trk = tracking::new_default_tracker(prn, data_code, pilot_code, 0.0, fs);
+
+	Ok(Channel { prn, fs, aat: AcquireAndTrack::new(acq, trk), tlm: TelemetryDecoder::new(), last_sample_idx: 0,
+		ephemeris_words: EphemerisWords::default(), ephemerides: pvt::ephemeris_store::EphemerisStore::new(), ggto: None, week_number: None,
+		pvt_rate_samples, intermediate_freq_hz: 0.0 /* The input samples are complex baseband */ })
+}
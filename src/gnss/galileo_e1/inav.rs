@@ -0,0 +1,404 @@
+
+use std::f64::consts;
+
+use ::serde::{Serialize, Deserialize};
+
+use crate::gnss::gps_l1_ca::pvt::ephemeris::{self, Ephemeris};
+use crate::gnss::gps_l2c::tlm_decode::error_detection;
+use crate::utils::bools_to_int;
+use crate::DigSigProcErr;
+
+use super::GST_WEEK_OFFSET;
+
+// Every page part starts with this pattern, which isn't encoded or interleaved
+pub const SYNC_PATTERN:[bool; 10] = [false, true, false, true, true, false, false, false, false, false];
+
+pub const PAGE_PART_SYMBOLS:usize = 250;		// Sync pattern and one encoded page part, 1 sec on E1B
+pub const ENCODED_SYMBOLS:usize = 240;
+pub const PAGE_PART_BITS:usize = 120;			// Decoded, including the six tail bits
+pub const WORD_LEN_BITS:usize = 128;
+pub const PAGE_LEN_SEC:f64 = 2.0;
+
+// Block interleaver dimensions; symbols are written in by row and read out by column
+const INTERLEAVER_ROWS:usize = 8;
+const INTERLEAVER_COLS:usize = 30;
+
+// The CRC covers both page parts up to the CRC itself in the odd part
+const ODD_CRC_START:usize = 82;
+
+pub const GM:f64 = 3.986004418e14;				// [m^3/s^2] Galileo value of the earth's gravitational constant
+
+/// One I/NAV word, reassembled from the data fields of an even and odd page part
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum Word {
+	Spare{ week_number:Option<u16>, tow_sec:Option<u32> },		// Type 0, which only carries GST when its time field is 2
+	Ephemeris1(Ephemeris1),										// Types 1-4
+	Ephemeris2(Ephemeris2),
+	Ephemeris3(Ephemeris3),
+	Ephemeris4(Ephemeris4),
+	IonosphereAndHealth(IonosphereAndHealth),					// Type 5
+	GstUtc(GstUtc),												// Type 6
+	AlmanacAndGgto(Ggto),										// Type 10, only the GST-GPS conversion is kept
+	Other{ word_type:u8 },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Ephemeris1 {
+	pub iod_nav: u16,
+	pub t0e: f64,			// [sec]
+	pub m0: f64,			// [semi-circles]
+	pub e: f64,
+	pub sqrt_a: f64,		// [m^1/2]
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Ephemeris2 {
+	pub iod_nav: u16,
+	pub omega0: f64,		// [semi-circles]
+	pub i0: f64,			// [semi-circles]
+	pub omega: f64,			// [semi-circles]
+	pub idot: f64,			// [semi-circles/sec]
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Ephemeris3 {
+	pub iod_nav: u16,
+	pub omega_dot: f64,		// [semi-circles/sec]
+	pub dn: f64,			// [semi-circles/sec]
+	pub cuc: f64,			// [rad]
+	pub cus: f64,			// [rad]
+	pub crc: f64,			// [m]
+	pub crs: f64,			// [m]
+	pub sisa: u8,			// Signal in space accuracy index for E1/E5b
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Ephemeris4 {
+	pub iod_nav: u16,
+	pub svid: u8,
+	pub cic: f64,			// [rad]
+	pub cis: f64,			// [rad]
+	pub t0c: f64,			// [sec]
+	pub af0: f64,			// [sec]
+	pub af1: f64,			// [sec/sec]
+	pub af2: f64,			// [sec/sec^2]
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct IonosphereAndHealth {
+	pub ai0: f64,			// [sfu] NeQuick effective ionisation level coefficients
+	pub ai1: f64,			// [sfu/deg]
+	pub ai2: f64,			// [sfu/deg^2]
+	pub region_flags: u8,
+	pub bgd_e1_e5a: f64,	// [sec]
+	pub bgd_e1_e5b: f64,	// [sec]
+	pub e5b_hs: u8,
+	pub e1b_hs: u8,
+	pub e5b_dvs: bool,
+	pub e1b_dvs: bool,
+	pub week_number: u16,	// GST
+	pub tow_sec: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct GstUtc {
+	pub a0: f64,			// [sec]
+	pub a1: f64,			// [sec/sec]
+	pub dt_ls: i8,			// [sec]
+	pub t0t: f64,			// [sec]
+	pub wn0t: u8,
+	pub wn_lsf: u8,
+	pub dn: u8,
+	pub dt_lsf: i8,			// [sec]
+	pub tow_sec: u32,
+}
+
+/// Offset between Galileo system time and GPS time
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Ggto {
+	pub a0g: f64,			// [sec]
+	pub a1g: f64,			// [sec/sec]
+	pub t0g: f64,			// [sec]
+	pub wn0g: u8,			// Six least significant bits of the reference week
+}
+
+impl Ggto {
+
+	/// GST minus GPS time [sec] at a GST week and time of week (Galileo OS SIS ICD, 5.1.9)
+	pub fn offset_sec(&self, week_number:u16, tow_sec:f64) -> f64 {
+		let weeks:i32 = (week_number as i32 - self.wn0g as i32).rem_euclid(64);
+		self.a0g + self.a1g * (tow_sec - self.t0g + 604800.0 * weeks as f64)
+	}
+
+}
+
+impl Word {
+
+	pub fn new(bits:&[bool]) -> Result<Self, DigSigProcErr> {
+		if bits.len() != WORD_LEN_BITS { return Err(DigSigProcErr::InvalidTelemetryData("I/NAV words should be 128 bits")); }
+
+		let mut f = Fields{ bits, idx: 0 };
+		let word_type = f.unsigned(6)? as u8;
+		match word_type {
+			0 => {
+				let time = f.unsigned(2)?;
+				f.skip(88);
+				let (week_number, tow_sec) = (f.unsigned(12)? as u16, f.unsigned(20)?);
+				if time == 2 { Ok(Word::Spare{ week_number: Some(week_number), tow_sec: Some(tow_sec) }) }
+				else { Ok(Word::Spare{ week_number: None, tow_sec: None }) }
+			},
+			1 => Ok(Word::Ephemeris1(Ephemeris1{ iod_nav: f.unsigned(10)? as u16, t0e: f.unsigned(14)? as f64 * 60.0,
+				m0: f.signed(32)? as f64 * 2.0_f64.powi(-31), e: f.unsigned(32)? as f64 * 2.0_f64.powi(-33),
+				sqrt_a: f.unsigned(32)? as f64 * 2.0_f64.powi(-19) })),
+			2 => Ok(Word::Ephemeris2(Ephemeris2{ iod_nav: f.unsigned(10)? as u16, omega0: f.signed(32)? as f64 * 2.0_f64.powi(-31),
+				i0: f.signed(32)? as f64 * 2.0_f64.powi(-31), omega: f.signed(32)? as f64 * 2.0_f64.powi(-31),
+				idot: f.signed(14)? as f64 * 2.0_f64.powi(-43) })),
+			3 => Ok(Word::Ephemeris3(Ephemeris3{ iod_nav: f.unsigned(10)? as u16, omega_dot: f.signed(24)? as f64 * 2.0_f64.powi(-43),
+				dn: f.signed(16)? as f64 * 2.0_f64.powi(-43), cuc: f.signed(16)? as f64 * 2.0_f64.powi(-29),
+				cus: f.signed(16)? as f64 * 2.0_f64.powi(-29), crc: f.signed(16)? as f64 * 2.0_f64.powi(-5),
+				crs: f.signed(16)? as f64 * 2.0_f64.powi(-5), sisa: f.unsigned(8)? as u8 })),
+			4 => Ok(Word::Ephemeris4(Ephemeris4{ iod_nav: f.unsigned(10)? as u16, svid: f.unsigned(6)? as u8,
+				cic: f.signed(16)? as f64 * 2.0_f64.powi(-29), cis: f.signed(16)? as f64 * 2.0_f64.powi(-29),
+				t0c: f.unsigned(14)? as f64 * 60.0, af0: f.signed(31)? as f64 * 2.0_f64.powi(-34),
+				af1: f.signed(21)? as f64 * 2.0_f64.powi(-46), af2: f.signed(6)? as f64 * 2.0_f64.powi(-59) })),
+			5 => Ok(Word::IonosphereAndHealth(IonosphereAndHealth{ ai0: f.unsigned(11)? as f64 * 2.0_f64.powi(-2),
+				ai1: f.signed(11)? as f64 * 2.0_f64.powi(-8), ai2: f.signed(14)? as f64 * 2.0_f64.powi(-15), region_flags: f.unsigned(5)? as u8,
+				bgd_e1_e5a: f.signed(10)? as f64 * 2.0_f64.powi(-32), bgd_e1_e5b: f.signed(10)? as f64 * 2.0_f64.powi(-32),
+				e5b_hs: f.unsigned(2)? as u8, e1b_hs: f.unsigned(2)? as u8, e5b_dvs: f.unsigned(1)? == 1, e1b_dvs: f.unsigned(1)? == 1,
+				week_number: f.unsigned(12)? as u16, tow_sec: f.unsigned(20)? })),
+			6 => Ok(Word::GstUtc(GstUtc{ a0: f.signed(32)? as f64 * 2.0_f64.powi(-30), a1: f.signed(24)? as f64 * 2.0_f64.powi(-50),
+				dt_ls: f.signed(8)? as i8, t0t: f.unsigned(8)? as f64 * 3600.0, wn0t: f.unsigned(8)? as u8, wn_lsf: f.unsigned(8)? as u8,
+				dn: f.unsigned(3)? as u8, dt_lsf: f.signed(8)? as i8, tow_sec: f.unsigned(20)? })),
+			10 => {
+				f.skip(80);
+				Ok(Word::AlmanacAndGgto(Ggto{ a0g: f.signed(16)? as f64 * 2.0_f64.powi(-35), a1g: f.signed(12)? as f64 * 2.0_f64.powi(-51),
+					t0g: f.unsigned(8)? as f64 * 3600.0, wn0g: f.unsigned(6)? as u8 }))
+			},
+			_ => Ok(Word::Other{ word_type }),
+		}
+	}
+
+	/// GST time of week at the start of the even page part that carried this word, for the words that have one
+	pub fn time_of_week(&self) -> Option<u32> {
+		match self {
+			Word::Spare{ tow_sec, .. } => *tow_sec,
+			Word::IonosphereAndHealth(w) => Some(w.tow_sec),
+			Word::GstUtc(w) => Some(w.tow_sec),
+			_ => None,
+		}
+	}
+
+}
+
+// Reads fields one after another from a word
+struct Fields<'a> {
+	bits: &'a [bool],
+	idx: usize,
+}
+
+impl<'a> Fields<'a> {
+
+	fn unsigned(&mut self, n:usize) -> Result<u32, DigSigProcErr> {
+		let x = bools_to_int::to_u32(&self.bits[self.idx..self.idx+n])?;
+		self.idx += n;
+		Ok(x)
+	}
+
+	fn signed(&mut self, n:usize) -> Result<i32, DigSigProcErr> {
+		let x = bools_to_int::to_i32(&self.bits[self.idx..self.idx+n])?;
+		self.idx += n;
+		Ok(x)
+	}
+
+	fn skip(&mut self, n:usize) { self.idx += n; }
+
+}
+
+/// Undoes the block interleaving of one page part
+pub fn deinterleave(symbols:&[f64]) -> Vec<f64> {
+	let mut ans:Vec<f64> = vec![0.0; INTERLEAVER_ROWS * INTERLEAVER_COLS];
+	for r in 0..INTERLEAVER_ROWS {
+		for c in 0..INTERLEAVER_COLS { ans[c*INTERLEAVER_ROWS + r] = symbols[r*INTERLEAVER_COLS + c]; }
+	}
+	ans
+}
+
+/// Checks a decoded even and odd page part, each without its tail, and returns the 128-bit word they carry.  Alert pages are
+/// refused since they hold integrity data rather than a word.
+pub fn word_from_page(even:&[bool], odd:&[bool]) -> Result<Vec<bool>, DigSigProcErr> {
+	if even[0] || !odd[0] { return Err(DigSigProcErr::InvalidTelemetryData("I/NAV page parts out of order")); }
+	if even[1] || odd[1] { return Err(DigSigProcErr::InvalidTelemetryData("I/NAV alert page")); }
+
+	let covered:Vec<bool> = even.iter().chain(odd[..ODD_CRC_START].iter()).cloned().collect();
+	if error_detection::crc_24q(&covered) != odd[ODD_CRC_START..ODD_CRC_START+24] {
+		return Err(DigSigProcErr::InvalidTelemetryData("I/NAV CRC failure"));
+	}
+	Ok(even[2..114].iter().chain(odd[2..18].iter()).cloned().collect())
+}
+
+/// Signal in space accuracy [m] for a SISA index (Galileo OS SIS ICD, Table 89), or None for no accuracy prediction available
+pub fn sisa_m(sisa:u8) -> Option<f64> {
+	match sisa {
+		0..=49    => Some(sisa as f64 * 0.01),
+		50..=74   => Some(0.5 + (sisa - 50) as f64 * 0.02),
+		75..=99   => Some(1.0 + (sisa - 75) as f64 * 0.04),
+		100..=125 => Some(2.0 + (sisa - 100) as f64 * 0.16),
+		_ => None,
+	}
+}
+
+/// Collects ephemeris words 1-5 as they come in, in any order
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EphemerisWords {
+	pub word1: Option<Ephemeris1>,
+	pub word2: Option<Ephemeris2>,
+	pub word3: Option<Ephemeris3>,
+	pub word4: Option<Ephemeris4>,
+	pub word5: Option<IonosphereAndHealth>,
+}
+
+/// A complete Galileo ephemeris mapped onto the GPS model, along with health and accuracy in the GPS terms the ephemeris store uses
+#[derive(Debug, Clone, Copy)]
+pub struct CompleteEphemeris {
+	pub ephemeris: Ephemeris,
+	pub sv_health: u8,		// E1B signal health status and data validity, zero when both are good
+	pub ura_index: u8,		// Smallest GPS URA index that covers the SISA
+}
+
+impl EphemerisWords {
+
+	pub fn add(&mut self, word:&Word) {
+		match word {
+			Word::Ephemeris1(w) => self.word1 = Some(*w),
+			Word::Ephemeris2(w) => self.word2 = Some(*w),
+			Word::Ephemeris3(w) => self.word3 = Some(*w),
+			Word::Ephemeris4(w) => self.word4 = Some(*w),
+			Word::IonosphereAndHealth(w) => self.word5 = Some(*w),
+			_ => {},
+		}
+	}
+
+	/// The ephemeris once words 1-4 with the same IODnav and a word 5 have come in.  The Galileo orbit is defined with its own
+	/// gravitational constant, so the difference in mean motion is folded into dn for the GPS orbit equations.  The clock is
+	/// corrected for the E1 group delay through t_gd, which is BGD(E1,E5b) since the clock parameters are for the E1/E5b pair.
+	pub fn ephemeris(&self) -> Option<CompleteEphemeris> {
+		let (w1, w2, w3, w4, w5) = (self.word1?, self.word2?, self.word3?, self.word4?, self.word5?);
+		if w2.iod_nav != w1.iod_nav || w3.iod_nav != w1.iod_nav || w4.iod_nav != w1.iod_nav { return None; }
+
+		let a:f64 = w1.sqrt_a.powi(2);
+		let dn_gm:f64 = ((GM / a.powi(3)).sqrt() - (ephemeris::MU / a.powi(3)).sqrt()) / consts::PI;
+		let eph = Ephemeris{ week_number: (w5.week_number + GST_WEEK_OFFSET) % 1024, t_gd: w5.bgd_e1_e5b, aodo: 0, fit_interval: false,
+			t_oc: w4.t0c, a_f0: w4.af0, a_f1: w4.af1, a_f2: w4.af2,
+			t_oe: w1.t0e, sqrt_a: w1.sqrt_a, dn: w3.dn + dn_gm, m0: w1.m0,
+			e: w1.e, omega: w2.omega, omega0: w2.omega0, omega_dot: w3.omega_dot,
+			cus: w3.cus, cuc: w3.cuc, crs: w3.crs, crc: w3.crc,
+			cis: w4.cis, cic: w4.cic, i0: w2.i0, idot: w2.idot,
+			iodc: w1.iod_nav };
+
+		let ura_m:[f64; 15] = [2.4, 3.4, 4.85, 6.85, 9.65, 13.65, 24.0, 48.0, 96.0, 192.0, 384.0, 768.0, 1536.0, 3072.0, 6144.0];
+		let ura_index:u8 = sisa_m(w3.sisa).and_then(|s| ura_m.iter().position(|u| *u >= s)).unwrap_or(15) as u8;
+		let sv_health:u8 = (w5.e1b_hs << 1) | (w5.e1b_dvs as u8);
+		Some(CompleteEphemeris{ ephemeris: eph, sv_health, ura_index })
+	}
+
+}
+
+// Packs fields into a word for the tests, with negative values in two's complement
+#[cfg(test)]
+pub(crate) fn encode_word(word_type:u8, fields:&[(i64, usize)]) -> Vec<bool> {
+	use crate::utils::int_to_bools;
+	let mut bits:Vec<bool> = vec![false; WORD_LEN_BITS];
+	int_to_bools::from_unsigned(word_type as i64, &mut bits[0..6]).unwrap();
+	let mut idx:usize = 6;
+	for (x, n) in fields {
+		if *x < 0 { int_to_bools::from_signed(*x, &mut bits[idx..idx+n]).unwrap(); }
+		else { int_to_bools::from_unsigned(*x, &mut bits[idx..idx+n]).unwrap(); }
+		idx += n;
+	}
+	bits
+}
+
+#[test]
+fn test_ephemeris_words() {
+	use crate::utils::int_to_bools::scaled;
+
+	// Round numbers in the units of each field, so they come back exactly
+	let (iod_nav, sqrt_a, e, m0) = (77, 5440.588, 0.0002, 0.25);
+	let words = [
+		encode_word(1, &[(iod_nav, 10), (600, 14), (scaled(m0, 2.0_f64.powi(-31)), 32), (scaled(e, 2.0_f64.powi(-33)), 32),
+			(scaled(sqrt_a, 2.0_f64.powi(-19)), 32)]),
+		encode_word(2, &[(iod_nav, 10), (scaled(-0.6, 2.0_f64.powi(-31)), 32), (scaled(0.31, 2.0_f64.powi(-31)), 32),
+			(scaled(-0.1, 2.0_f64.powi(-31)), 32), (-100, 14)]),
+		encode_word(3, &[(iod_nav, 10), (-2000, 24), (1500, 16), (-300, 16), (400, 16), (3200, 16), (-640, 16), (60, 8)]),
+		encode_word(4, &[(iod_nav, 10), (11, 6), (-5, 16), (7, 16), (600, 14), (scaled(-2.0e-4, 2.0_f64.powi(-34)), 31), (-3000, 21), (0, 6)]),
+		encode_word(5, &[(100, 11), (-12, 11), (30, 14), (0, 5), (-4, 10), (-5, 10), (0, 2), (0, 2), (0, 1), (0, 1), (1250, 12), (345_602, 20)]),
+		encode_word(10, &[(0, 40), (0, 40), (-64, 16), (8, 12), (12, 8), (34, 6)]),
+	];
+
+	let mut collected = EphemerisWords::default();
+	for (i, bits) in words.iter().enumerate() {
+		let word = Word::new(bits).unwrap();
+		collected.add(&word);
+		// Every word up to the fifth is needed
+		assert_eq!(collected.ephemeris().is_some(), i >= 4);
+		if let Word::IonosphereAndHealth(w5) = word {
+			assert_eq!(word.time_of_week(), Some(345_602));
+			assert_eq!(w5.ai0, 25.0);
+			assert_eq!(w5.bgd_e1_e5b, -5.0 * 2.0_f64.powi(-32));
+		}
+		if let Word::AlmanacAndGgto(ggto) = word {
+			assert_eq!(ggto.wn0g, 34);
+			let dt:f64 = 604800.0 - 12.0 * 3600.0;
+			assert!((ggto.offset_sec(1250 + 64 + 1, 0.0) - (-64.0 * 2.0_f64.powi(-35) + 8.0 * 2.0_f64.powi(-51) * dt)).abs() < 1.0e-15);
+		}
+	}
+
+	let complete = collected.ephemeris().unwrap();
+	let eph = complete.ephemeris;
+	assert_eq!((eph.iodc, eph.t_oe, eph.t_oc, eph.week_number), (77, 36000.0, 36000.0, (1250 + 1024) % 1024));
+	assert_eq!((complete.sv_health, complete.ura_index), (0, 0));
+	assert_eq!(eph.crc, 100.0);
+	assert!((eph.e - e).abs() < 1.0e-9 && (eph.sqrt_a - sqrt_a).abs() < 1.0e-5 && (eph.m0 - m0).abs() < 1.0e-9);
+
+	// With the gravitational constant folded into dn, the GPS equations give the Galileo mean motion
+	let a:f64 = eph.sqrt_a.powi(2);
+	let n_gps:f64 = (ephemeris::MU / a.powi(3)).sqrt() + eph.dn * consts::PI;
+	let n_gal:f64 = (GM / a.powi(3)).sqrt() + 1500.0 * 2.0_f64.powi(-43) * consts::PI;
+	assert!((n_gps - n_gal).abs() < 1.0e-15);
+
+	// A word from a different issue of data holds the ephemeris back until the rest catch up
+	collected.add(&Word::new(&encode_word(3, &[(iod_nav + 1, 10)])).unwrap());
+	assert!(collected.ephemeris().is_none());
+
+	// Unhealthy signals and missing accuracy predictions carry over into the GPS terms
+	let mut w5 = collected.word5.unwrap();
+	w5.e1b_hs = 3;
+	collected.word5 = Some(w5);
+	collected.word3 = Some(Ephemeris3{ iod_nav: iod_nav as u16, sisa: 255, ..collected.word3.unwrap() });
+	let complete = collected.ephemeris().unwrap();
+	assert_eq!((complete.sv_health, complete.ura_index), (6, 15));
+}
+
+#[test]
+fn test_page_parts() {
+	// The interleaver writes by row and reads by column
+	let interleaved:Vec<f64> = (0..ENCODED_SYMBOLS).map(|i| ((i % INTERLEAVER_COLS) * INTERLEAVER_ROWS + i / INTERLEAVER_COLS) as f64).collect();
+	assert_eq!(deinterleave(&interleaved), (0..ENCODED_SYMBOLS).map(|i| i as f64).collect::<Vec<f64>>());
+
+	let word:Vec<bool> = encode_word(0, &[(2, 2), (0, 44), (0, 44), (1250, 12), (3600, 20)]);
+	let mut even:Vec<bool> = vec![false; 114];
+	let mut odd:Vec<bool> = vec![false; 114];
+	odd[0] = true;
+	even[2..114].copy_from_slice(&word[..112]);
+	odd[2..18].copy_from_slice(&word[112..]);
+	let covered:Vec<bool> = even.iter().chain(odd[..ODD_CRC_START].iter()).cloned().collect();
+	odd[ODD_CRC_START..ODD_CRC_START+24].copy_from_slice(&error_detection::crc_24q(&covered));
+
+	assert_eq!(word_from_page(&even, &odd).unwrap(), word);
+	assert_eq!(Word::new(&word).unwrap(), Word::Spare{ week_number: Some(1250), tow_sec: Some(3600) });
+	assert!(word_from_page(&odd, &even).is_err());
+
+	// Any bit error shows up in the CRC
+	odd[40] = !odd[40];
+	assert!(word_from_page(&even, &odd).is_err());
+}
@@ -0,0 +1,32 @@
+
+/*	Galileo E1 OS receive chain:
+	- 4092-chip E1B (data) and E1C (pilot) memory codes, loaded from the ICD listing, on a BOC(1,1) subcarrier
+	- Acquisition on the pilot over one 4-ms code period
+	- Tracking with a bump-jumping discriminator to stay off the BOC side peaks, and the 25-chip secondary code on the pilot
+	  to resolve the carrier phase ambiguity
+	- I/NAV pages: sync pattern, block deinterleaving, rate 1/2 Viterbi decoding and CRC-24Q
+	- Ephemeris words 1-5 mapped onto the GPS ephemeris model so the observations go into the same PVT
+*/
+
+pub const FIRST_PRN:usize = 1;
+pub const LAST_PRN:usize = 50;
+
+pub const CODE_LEN_CHIPS:usize = 4092;
+pub const CHIP_RATE_HZ:f64 = 1.023e6;
+pub const CODE_PERIOD_SEC:f64 = 4.0e-3;
+pub const SECONDARY_CODE_LEN:usize = 25;
+
+// Galileo system time started at the beginning of GPS week 1024, and otherwise the two share the same week and time of week
+pub const GST_WEEK_OFFSET:u16 = 1024;
+
+// Observations from Galileo are told apart from GPS by their SV ID, which is the PRN plus this offset, as in NMEA 4.11
+pub const SV_ID_OFFSET:usize = 300;
+
+pub fn sv_id(prn:usize) -> usize { SV_ID_OFFSET + prn }
+pub fn is_galileo_sv_id(sv_id:usize) -> bool { (SV_ID_OFFSET + FIRST_PRN..=SV_ID_OFFSET + LAST_PRN).contains(&sv_id) }
+
+pub mod channel;
+pub mod inav;
+pub mod signal_modulation;
+pub mod tlm_decode;
+pub mod tracking;
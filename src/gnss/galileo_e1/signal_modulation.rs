@@ -0,0 +1,109 @@
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+
+use super::{CODE_LEN_CHIPS, CHIP_RATE_HZ, CODE_PERIOD_SEC, SECONDARY_CODE_LEN};
+
+// CS25_1 from the Galileo OS SIS ICD, Table 20, the first 25 bits of 0x380AD90
+pub const SECONDARY_CODE:[i8; SECONDARY_CODE_LEN] = [1, 1, -1, -1, -1, 1, 1, 1, 1, 1, 1, 1, -1, 1, -1, 1, -1, -1, 1, -1, -1, 1, 1, -1, 1];
+
+/// The E1B and E1C primary codes are memory codes, so rather than being generated they're read from the listing in Annex C
+/// of the ICD.  Each line of the file holds the component, the PRN and the 1023 hex digits of one code, e.g. "E1B,11,0A4C...".
+/// Blank lines and lines starting with '#' are skipped.
+#[derive(Debug, Default, Clone)]
+pub struct PrimaryCodes {
+	e1b: HashMap<usize, Vec<i8>>,
+	e1c: HashMap<usize, Vec<i8>>,
+}
+
+impl PrimaryCodes {
+
+	pub fn load<R: Read>(reader:R) -> Result<Self, &'static str> {
+		let mut codes = Self::default();
+		for line in BufReader::new(reader).lines() {
+			let line = line.map_err(|_| "Unable to read Galileo code file")?;
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') { continue; }
+
+			let fields:Vec<&str> = line.split(',').map(|s| s.trim()).collect();
+			if fields.len() != 3 { return Err("Galileo code lines should have three comma-separated fields"); }
+			let prn:usize = fields[1].parse().map_err(|_| "Unable to parse Galileo PRN")?;
+			let code:Vec<i8> = parse_hex_code(fields[2])?;
+			match fields[0] {
+				"E1B" => codes.e1b.insert(prn, code),
+				"E1C" => codes.e1c.insert(prn, code),
+				_ => return Err("Galileo code component should be E1B or E1C"),
+			};
+		}
+		Ok(codes)
+	}
+
+	pub fn e1b(&self, prn:usize) -> Option<&[i8]> { self.e1b.get(&prn).map(|c| c.as_slice()) }
+	pub fn e1c(&self, prn:usize) -> Option<&[i8]> { self.e1c.get(&prn).map(|c| c.as_slice()) }
+
+	/// PRNs with both a data and a pilot code, in ascending order
+	pub fn prns(&self) -> Vec<usize> {
+		let mut prns:Vec<usize> = self.e1b.keys().filter(|prn| self.e1c.contains_key(prn)).cloned().collect();
+		prns.sort_unstable();
+		prns
+	}
+
+}
+
+/// Expands the hex listing of one code into chips, most significant bit first, with logic 0 as +1 and logic 1 as -1.  The
+/// 4092 chips fill exactly 1023 hex digits.
+pub fn parse_hex_code(hex:&str) -> Result<Vec<i8>, &'static str> {
+	if hex.chars().count() != CODE_LEN_CHIPS / 4 { return Err("Galileo code should be 1023 hex digits"); }
+	let mut chips:Vec<i8> = Vec::with_capacity(CODE_LEN_CHIPS);
+	for c in hex.chars() {
+		let nibble:u32 = c.to_digit(16).ok_or("Galileo code should only contain hex digits")?;
+		for i in (0..4).rev() { chips.push(if (nibble >> i) & 1 == 0 { 1 } else { -1 }); }
+	}
+	Ok(chips)
+}
+
+/// Value of the code on its sine-phased BOC(1,1) subcarrier at a code phase in chips; the subcarrier is +1 for the first half of
+/// each chip and -1 for the second.  This leaves out the BOC(6,1) part of the CBOC modulation, which only carries about a tenth
+/// of the power and mostly widens the spectrum.
+pub fn boc_value(code:&[i8], chip:f64) -> i8 {
+	let chip = chip.rem_euclid(CODE_LEN_CHIPS as f64);
+	let subcarrier:i8 = if chip.fract() < 0.5 { 1 } else { -1 };
+	code[(chip as usize) % CODE_LEN_CHIPS] * subcarrier
+}
+
+/// One code period of the BOC(1,1) replica sampled at fs, e.g. for acquisition
+pub fn boc_sampled(code:&[i8], fs:f64) -> Vec<i8> {
+	let samples_per_code:usize = (fs * CODE_PERIOD_SEC).round() as usize;
+	(0..samples_per_code).map(|i| boc_value(code, (i as f64) * CHIP_RATE_HZ / fs)).collect()
+}
+
+#[test]
+fn test_codes() {
+	// The secondary code as listed in hex
+	let cs25:Vec<i8> = parse_hex_code(&format!("380AD90{}", "0".repeat(1016))).unwrap();
+	assert_eq!(cs25[..SECONDARY_CODE_LEN], SECONDARY_CODE);
+
+	// Loading keeps the PRNs that have both components, and rejects anything malformed
+	let code = |seed:u32| (0..1023_u32).map(|i| std::char::from_digit((i.wrapping_mul(2654435761).wrapping_add(seed) >> 13) % 16, 16).unwrap())
+		.collect::<String>();
+	let listing = format!("# Test codes\nE1B,1,{}\nE1C,1,{}\n\nE1B,2,{}\n", code(1), code(2), code(3));
+	let codes = PrimaryCodes::load(listing.as_bytes()).unwrap();
+	assert_eq!(codes.prns(), vec![1]);
+	assert_eq!(codes.e1b(1).unwrap().len(), CODE_LEN_CHIPS);
+	assert_ne!(codes.e1b(1), codes.e1c(1));
+	assert!(PrimaryCodes::load("E5a,1,0".as_bytes()).is_err());
+	assert!(parse_hex_code(&format!("12G{}", "0".repeat(1020))).is_err());
+	assert!(parse_hex_code(&code(1)[..1022]).is_err());
+	assert!(parse_hex_code(&format!("{}0", code(1))).is_err());
+
+	// The BOC(1,1) autocorrelation has its negative side peaks half a chip either side of the main one
+	let fs:f64 = 8.0 * CHIP_RATE_HZ;
+	let replica:Vec<i8> = boc_sampled(codes.e1c(1).unwrap(), fs);
+	let correlation = |shift:usize| -> f64 {
+		let n:usize = replica.len();
+		(0..n).map(|i| (replica[i] * replica[(i + shift) % n]) as f64).sum::<f64>() / n as f64
+	};
+	assert!((correlation(0) - 1.0).abs() < 1.0e-9);
+	assert!((correlation(4) + 0.5).abs() < 0.05);
+	assert!(correlation(2).abs() < 0.3);
+}
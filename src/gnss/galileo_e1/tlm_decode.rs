@@ -0,0 +1,176 @@
+
+use crate::gnss::common::nav_data_stats::NavDataStats;
+use crate::gnss::gps_l2c::tlm_decode::error_correction;
+
+use super::inav::{self, Word, SYNC_PATTERN, PAGE_PART_SYMBOLS};
+
+const SYNC_LEN:usize = 10;
+
+// Looks for the sync pattern at the start of two page parts in a row, then checks it at the start of each page part after that
+#[derive(Debug)]
+enum State {
+	Initial,
+	Valid{ is_inverse:bool },
+}
+
+// Polarity of a run of symbols if it matches the sync pattern either way
+fn sync_polarity(symbols:&[f64]) -> Option<bool> {
+	let matches = |is_inverse:bool| symbols.iter().zip(SYNC_PATTERN.iter()).all(|(s, b)| (*s < 0.0) == (*b ^ is_inverse));
+	if matches(false) { Some(false) } else if matches(true) { Some(true) } else { None }
+}
+
+/// Turns 250 sps soft symbols from E1B into I/NAV words.  Positive symbols are logic 0 as transmitted.  Each page part is
+/// decoded on its own since the encoder is flushed by the tail bits, and a word is returned once an even part and the odd part
+/// right after it pass the CRC, at the last symbol of the odd part.
+pub struct TelemetryDecoder {
+	buffer: Vec<f64>,
+	state: State,
+	pending_even: Option<Vec<bool>>,
+	last_is_inverse: Option<bool>,
+	pub stats: NavDataStats,
+}
+
+impl TelemetryDecoder {
+
+	pub fn new() -> Self { Self{ buffer: vec![], state: State::Initial, pending_even: None, last_is_inverse: None, stats: Default::default() } }
+
+	pub fn initialize(&mut self) {
+		self.buffer.clear();
+		self.state = State::Initial;
+		self.pending_even = None;
+		self.last_is_inverse = None;
+	}
+
+	pub fn apply(&mut self, symbol:f64) -> Option<Word> {
+		self.buffer.push(symbol);
+		match self.state {
+			State::Initial => {
+				if self.buffer.len() < PAGE_PART_SYMBOLS + SYNC_LEN { return None; }
+				let opt_polarity = sync_polarity(&self.buffer[..SYNC_LEN])
+					.filter(|is_inverse| sync_polarity(&self.buffer[PAGE_PART_SYMBOLS..]) == Some(*is_inverse));
+				match opt_polarity {
+					Some(is_inverse) => {
+						self.stats.preamble_detections += 1;
+						if let Some(last_is_inverse) = self.last_is_inverse {
+							if last_is_inverse != is_inverse { self.stats.polarity_flips += 1; }
+						}
+						self.last_is_inverse = Some(is_inverse);
+						self.state = State::Valid{ is_inverse };
+
+						// This part ends ten symbols back, so it's only kept if it's the even half of the next word
+						let part:Vec<f64> = self.buffer.drain(..PAGE_PART_SYMBOLS).collect();
+						self.pending_even = None;
+						self.page_part(&part, is_inverse);
+					},
+					None => { self.buffer.remove(0); },
+				}
+				None
+			},
+			State::Valid{ is_inverse } => {
+				if self.buffer.len() < PAGE_PART_SYMBOLS { return None; }
+				let part:Vec<f64> = self.buffer.drain(..).collect();
+				if sync_polarity(&part[..SYNC_LEN]) != Some(is_inverse) {
+					self.stats.preamble_false_detections += 1;
+					self.state = State::Initial;
+					self.pending_even = None;
+					self.buffer = part;
+					return None;
+				}
+				self.page_part(&part, is_inverse)
+			},
+		}
+	}
+
+	// Decodes one page part, including its sync pattern, and returns a word if it completes one
+	fn page_part(&mut self, part:&[f64], is_inverse:bool) -> Option<Word> {
+		// Positive soft symbols are true for the Viterbi decoder, which is logic 1, and the G2 symbols are inverted before transmission
+		let sign:f64 = if is_inverse { 1.0 } else { -1.0 };
+		let soft:Vec<f64> = inav::deinterleave(&part[SYNC_LEN..]).into_iter().enumerate()
+			.map(|(i, s)| if i % 2 == 0 { sign * s } else { -sign * s }).collect();
+		let (bits, _) = error_correction::decode(&soft);
+		let bits:Vec<bool> = bits[..inav::PAGE_PART_BITS - 6].to_vec();
+
+		if !bits[0] {
+			self.pending_even = Some(bits);
+			return None;
+		}
+		let even:Vec<bool> = self.pending_even.take()?;
+		match inav::word_from_page(&even, &bits).and_then(|word| Word::new(&word)) {
+			Ok(word) => {
+				self.stats.subframes_decoded += 1;
+				Some(word)
+			},
+			Err(_) => {
+				self.stats.crc_failures += 1;
+				None
+			},
+		}
+	}
+
+}
+
+impl Default for TelemetryDecoder {
+	fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+use crate::gnss::gps_l2c::tlm_decode::error_detection;
+#[cfg(test)]
+use crate::gnss::gps_l2c::tlm_decode::error_correction::cnav_fec;
+
+// Encodes, interleaves and modulates one page part the way the satellite does, with logic 0 as +1
+#[cfg(test)]
+pub(crate) fn modulate_page_part(bits:&[bool]) -> Vec<f64> {
+	let mut register:u8 = 0;
+	let mut encoded:Vec<bool> = vec![];
+	for b in bits.iter().chain([false; 6].iter()) {
+		register = (register >> 1) | if *b { 0x40 } else { 0 };
+		let (g1, g2) = cnav_fec(register);
+		encoded.push(g1);
+		encoded.push(!g2);
+	}
+	let mut interleaved:Vec<bool> = vec![false; inav::ENCODED_SYMBOLS];
+	for r in 0..8 {
+		for c in 0..30 { interleaved[r*30 + c] = encoded[c*8 + r]; }
+	}
+	SYNC_PATTERN.iter().chain(interleaved.iter()).map(|b| if *b { -1.0 } else { 1.0 }).collect()
+}
+
+// Both page parts for a word
+#[cfg(test)]
+pub(crate) fn modulate_word(word:&[bool]) -> Vec<f64> {
+	let mut even:Vec<bool> = vec![false; 114];
+	let mut odd:Vec<bool> = vec![false; 114];
+	odd[0] = true;
+	even[2..114].copy_from_slice(&word[..112]);
+	odd[2..18].copy_from_slice(&word[112..]);
+	let covered:Vec<bool> = even.iter().chain(odd[..82].iter()).cloned().collect();
+	odd[82..106].copy_from_slice(&error_detection::crc_24q(&covered));
+	modulate_page_part(&even).into_iter().chain(modulate_page_part(&odd)).collect()
+}
+
+#[test]
+fn test_decode_stream() {
+	use super::inav::encode_word;
+
+	let words:Vec<Vec<bool>> = (0..5).map(|i| encode_word(0, &[(2, 2), (0, 44), (0, 44), (1250, 12), (3600 + 2*i, 20)])).collect();
+
+	// Start partway into the first page, inverted, with some noise on the symbols
+	let symbols:Vec<f64> = words.iter().flat_map(|w| modulate_word(w)).skip(100).enumerate()
+		.map(|(i, s)| -s * (0.6 + 0.4 * ((i * 7919) % 13) as f64 / 13.0)).collect();
+
+	let mut decoder = TelemetryDecoder::new();
+	let mut decoded:Vec<(usize, Word)> = vec![];
+	for (i, s) in symbols.iter().enumerate() {
+		if let Some(word) = decoder.apply(*s) { decoded.push((i, word)); }
+	}
+
+	// The first word is lost to the partial page, and every other word comes out at the last symbol of its odd page part
+	assert_eq!(decoded.len(), 4);
+	for (k, (i, word)) in decoded.iter().enumerate() {
+		assert_eq!(*i + 100 + 1, (k + 2) * 2 * PAGE_PART_SYMBOLS);
+		assert_eq!(word.time_of_week(), Some(3602 + 2 * k as u32));
+	}
+	assert_eq!(decoder.stats.crc_failures, 0);
+	assert_eq!(decoder.stats.preamble_detections, 1);
+}
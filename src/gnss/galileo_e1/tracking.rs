@@ -0,0 +1,325 @@
+use std::collections::VecDeque;
+use std::f64::consts;
+
+use ::rustfft::num_complex::Complex;
+
+use crate::{Sample, DigSigProcErr as DSPErr};
+use crate::block::{BlockFunctionality, BlockResult};
+use crate::filters::{ScalarFilter, SecondOrderFIR};
+use crate::gnss::common::acquisition::AcquisitionResult;
+use crate::gnss::common::tracking::TrackReport;
+use crate::utils::IntegerClock;
+
+use super::{CHIP_RATE_HZ, CODE_LEN_CHIPS, CODE_PERIOD_SEC, SECONDARY_CODE_LEN};
+use super::signal_modulation::{boc_value, SECONDARY_CODE};
+
+// The lock test statistic is the pilot prompt power over the input power for each 4-ms code period, which has a mean of one
+// without a signal and 1 + C/N0 * 2 ms with one since the pilot carries half the power.  3.0 is about 30 dB-Hz and 2.0 about 27 dB-Hz.
+pub const TEST_STAT_THRESH_INITIAL_LOCK:f64 = 3.0;
+pub const TEST_STAT_THRESH_LOSS_OF_LOCK:f64 = 2.0;
+
+// Code periods allowed for finding the secondary code before giving up on the signal
+pub const MAX_SECONDARY_SYNC_CODE_PERIODS:usize = 500;
+
+// The early and late correlators sit on the main peak of the BOC(1,1) correlation, which is only a third of a chip wide, and the
+// very early and very late ones sit where the side peaks are when the prompt is on the main peak
+pub const EARLY_LATE_SPACING_CHIPS:f64 = 0.15;
+pub const VERY_EARLY_LATE_SPACING_CHIPS:f64 = 0.5;
+
+// Bump-jumping: code periods in a row where a very early or very late correlator has to beat the prompt before the code jumps
+// half a chip toward it
+pub const BUMP_JUMP_THRESHOLD:i32 = 8;
+
+// Gain of the code loop relative to the carrier loop
+const CODE_LOOP_GAIN:f64 = 0.25;
+
+// Weight of each code period in the running lock statistic, about 50 code periods
+const LOCK_STAT_ALPHA:f64 = 0.02;
+
+const ZERO:Complex<f64> = Complex{ re: 0.0, im: 0.0 };
+
+/// Tracks the E1B/E1C pair of one Galileo SV.  The code and carrier loops run on the pilot, and the BOC(1,1) ambiguity is
+/// handled by bump-jumping: the correlation has side peaks half a chip either side of the main one that an early-minus-late
+/// discriminator can settle on just as well, so the tracker watches a very early and very late correlator and moves the code
+/// over whenever one of them stays above the prompt.  Once the 25-chip secondary code is found on the pilot, the pilot sign
+/// also settles the carrier phase ambiguity, so the data prompt reported every code period has the transmitted polarity.
+pub struct Tracking<A: ScalarFilter> {
+	pub prn:usize,
+	pub state: TrackingState,
+	pub fs:f64,
+	data_code:Vec<i8>,
+	pilot_code:Vec<i8>,
+
+	last_acq_result:AcquisitionResult,
+
+	sv_tow_sec_inner:IntegerClock,
+	sv_tow_sec_outer:IntegerClock,
+
+	// Carrier and code
+	carrier: Complex<f64>,
+	carrier_inc: Complex<f64>,
+	carrier_dphase_rad: f64,
+	code_phase: f64,
+	code_dphase: f64,
+
+	// Accumulated carrier phase since carrier tracking last started and the number of times it has started
+	carrier_cycles: f64,
+	carrier_lock_count: usize,
+
+	carrier_filter: A,
+	code_filter: A,
+
+	// Used during summation over one code period; the first five are on the pilot
+	sum_very_early: Complex<f64>,
+	sum_early:      Complex<f64>,
+	sum_prompt:     Complex<f64>,
+	sum_late:       Complex<f64>,
+	sum_very_late:  Complex<f64>,
+	sum_data:       Complex<f64>,
+	input_signal_power: f64,
+
+	bump_jump_count: i32,
+	bump_jumps: usize,
+	pilot_prompts: VecDeque<f64>,
+	code_periods: usize,
+	lock_stat: f64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TrackingState {
+	SecondarySync,
+	Tracking{ secondary_idx:usize, is_inverse:bool },
+	LostLock,
+}
+
+impl<A:ScalarFilter> BlockFunctionality<AcquisitionResult, (), Sample, TrackReport> for Tracking<A> {
+
+	fn control(&mut self, acq_result:&AcquisitionResult) -> Result<(), &'static str> {
+		self.initialize(acq_result.doppler_hz);
+		self.last_acq_result = acq_result.clone();
+		Ok(())
+	}
+
+	fn apply(&mut self, sample:&Sample) -> BlockResult<TrackReport> {
+		if sample.idx < self.last_acq_result.sample_idx + self.last_acq_result.code_phase { return BlockResult::NotReady; }
+		self.sv_tow_sec_outer.inc();
+
+		// Increment the carrier and code phase
+		self.carrier *= self.carrier_inc;
+		self.carrier_cycles += self.carrier_dphase_rad / (2.0 * consts::PI);
+		self.code_phase += self.code_dphase;
+
+		// Remove the carrier from the new sample and accumulate the power sum
+		let x = sample.val * self.carrier;
+		self.input_signal_power += x.norm_sqr();
+
+		// Integrate the correlators, with early meaning the local code leads the prompt
+		let (pilot_code, code_phase) = (&self.pilot_code, self.code_phase);
+		let pilot = |offset:f64| boc_value(pilot_code, code_phase + offset) as f64;
+		self.sum_very_early += x * pilot(VERY_EARLY_LATE_SPACING_CHIPS);
+		self.sum_early      += x * pilot(EARLY_LATE_SPACING_CHIPS);
+		self.sum_prompt     += x * pilot(0.0);
+		self.sum_late       += x * pilot(-EARLY_LATE_SPACING_CHIPS);
+		self.sum_very_late  += x * pilot(-VERY_EARLY_LATE_SPACING_CHIPS);
+		self.sum_data       += x * boc_value(&self.data_code, self.code_phase) as f64;
+
+		if self.code_phase < CODE_LEN_CHIPS as f64 { return BlockResult::NotReady; }
+
+		// End of a code period.  The secondary code flips the pilot, so the carrier loop uses the Costas arctangent discriminator.
+		self.code_phase -= CODE_LEN_CHIPS as f64;
+		self.code_periods += 1;
+		self.sv_tow_sec_inner.inc();
+		self.sv_tow_sec_outer.reset(self.sv_tow_sec_inner.time());
+
+		let carrier_error = if self.sum_prompt.re == 0.0 { 0.0 } else { (self.sum_prompt.im / self.sum_prompt.re).atan() };
+		self.carrier_dphase_rad += self.carrier_filter.apply(carrier_error);
+		self.carrier_inc = Complex{ re: self.carrier_dphase_rad.cos(), im: -self.carrier_dphase_rad.sin() };
+		self.carrier = self.carrier / self.carrier.norm();
+
+		// Near the main peak the correlation falls off at three times the code error, which scales the normalized discriminator
+		let code_error:f64 = {
+			let e:f64 = self.sum_early.norm();
+			let l:f64 = self.sum_late.norm();
+			if l+e == 0.0 { 0.0 } else { (e-l) / (e+l) * (1.0 - 3.0*EARLY_LATE_SPACING_CHIPS) / 3.0 }
+		};
+		self.code_dphase += self.code_filter.apply(code_error);
+		self.sv_tow_sec_outer.set_clock_rate(CHIP_RATE_HZ / self.code_dphase);
+		self.bump_jump();
+
+		let test_stat:f64 = if self.input_signal_power > 0.0 { self.sum_prompt.norm_sqr() / self.input_signal_power } else { 0.0 };
+		self.lock_stat += LOCK_STAT_ALPHA * (test_stat - self.lock_stat);
+		self.pilot_prompts.push_back(self.sum_prompt.re);
+		if self.pilot_prompts.len() > SECONDARY_CODE_LEN { self.pilot_prompts.pop_front(); }
+
+		let (result, opt_next_state) = match self.state {
+			TrackingState::SecondarySync => {
+				// With the carrier phase right, the pilot prompt has the opposite sign of the secondary code chip
+				let matches = |is_inverse:bool| self.pilot_prompts.iter().zip(SECONDARY_CODE.iter()).all(|(p, c)| (*p > 0.0) == ((*c < 0) ^ is_inverse));
+				if self.pilot_prompts.len() == SECONDARY_CODE_LEN && self.lock_stat > TEST_STAT_THRESH_INITIAL_LOCK && (matches(false) || matches(true)) {
+					(BlockResult::NotReady, Some(TrackingState::Tracking{ secondary_idx: 0, is_inverse: matches(true) }))
+				} else if self.code_periods >= MAX_SECONDARY_SYNC_CODE_PERIODS {
+					(BlockResult::Err(DSPErr::LossOfLock), Some(TrackingState::LostLock))
+				} else { (BlockResult::NotReady, None) }
+			},
+			TrackingState::Tracking{ secondary_idx, is_inverse } => {
+				if self.lock_stat < TEST_STAT_THRESH_LOSS_OF_LOCK {
+					(BlockResult::Err(DSPErr::LossOfLock), Some(TrackingState::LostLock))
+				} else {
+					let sign:f64 = if is_inverse { -1.0 } else { 1.0 };
+					let v = TrackReport { id: self.prn, prompt_i: sign * self.sum_data.re, sample_idx: sample.idx,
+						test_stat: self.test_stat(), freq_hz: self.carrier_freq_hz() };
+					(BlockResult::Ready(v), Some(TrackingState::Tracking{ secondary_idx: (secondary_idx + 1) % SECONDARY_CODE_LEN, is_inverse }))
+				}
+			},
+			TrackingState::LostLock => (BlockResult::Err(DSPErr::LossOfLock), None),
+		};
+
+		// Reset the accumulators for the next code period
+		self.input_signal_power = 0.0;
+		self.sum_very_early = ZERO;
+		self.sum_early      = ZERO;
+		self.sum_prompt     = ZERO;
+		self.sum_late       = ZERO;
+		self.sum_very_late  = ZERO;
+		self.sum_data       = ZERO;
+
+		if let Some(next_state) = opt_next_state { self.state = next_state; }
+
+		result
+	}
+
+}
+
+impl<A: ScalarFilter> Tracking<A> {
+
+	pub fn carrier_freq_hz(&self) -> f64 { (self.carrier_dphase_rad * self.fs) / (2.0 * consts::PI) }
+	pub fn carrier_phase_rad(&self) -> f64 { self.carrier.arg() }
+	pub fn carrier_cycles(&self) -> f64 { self.carrier_cycles }
+	pub fn carrier_lock_count(&self) -> usize { self.carrier_lock_count }
+	pub fn has_carrier_lock(&self) -> bool { matches!(self.state, TrackingState::Tracking{ .. }) }
+	pub fn code_phase_samples(&self) -> f64 { self.code_phase * (self.fs / CHIP_RATE_HZ) }
+	pub fn code_phase_chips(&self) -> f64 { self.code_phase }
+	pub fn code_dphase(&self) -> f64 { self.code_dphase }
+	pub fn test_stat(&self) -> f64 { self.lock_stat }
+	pub fn bump_jumps(&self) -> usize { self.bump_jumps }
+
+	// The test statistic less its noise-only mean is the pilot signal to noise ratio in one code period
+	pub fn cn0_dbhz(&self) -> f64 { 10.0 * (2.0 * (self.lock_stat - 1.0).max(1.0e-3) / CODE_PERIOD_SEC).log10() }
+
+	pub fn sv_time_of_week(&self) -> f64 { self.sv_tow_sec_outer.time() }
+	pub fn reset_clock(&mut self, t:f64) {
+		self.sv_tow_sec_outer.reset(t);
+		self.sv_tow_sec_inner.reset(t);
+	}
+
+	// Moves the code half a chip toward a very early or very late correlator that keeps beating the prompt
+	fn bump_jump(&mut self) {
+		let (ve, p, vl) = (self.sum_very_early.norm(), self.sum_prompt.norm(), self.sum_very_late.norm());
+		if ve > p && ve > vl { self.bump_jump_count += 1; }
+		else if vl > p && vl > ve { self.bump_jump_count -= 1; }
+		else { self.bump_jump_count -= self.bump_jump_count.signum(); }
+
+		if self.bump_jump_count.abs() >= BUMP_JUMP_THRESHOLD {
+			self.code_phase += VERY_EARLY_LATE_SPACING_CHIPS * self.bump_jump_count.signum() as f64;
+			self.bump_jump_count = 0;
+			self.bump_jumps += 1;
+		}
+	}
+
+	pub fn initialize(&mut self, acq_freq_hz:f64) {
+		self.carrier            = Complex{ re: 1.0, im: 0.0};
+		self.carrier_dphase_rad = acq_freq_hz * 2.0 * consts::PI / self.fs;
+		self.carrier_inc        = Complex{ re: self.carrier_dphase_rad.cos(), im: -self.carrier_dphase_rad.sin() };
+		self.carrier_cycles     = 0.0;
+		self.carrier_lock_count += 1;
+
+		let radial_velocity_factor:f64 = (1.57542e9 + acq_freq_hz) / 1.57542e9;
+		self.code_phase = 0.0;
+		self.code_dphase = (radial_velocity_factor * CHIP_RATE_HZ) / self.fs;
+
+		self.carrier_filter.initialize();
+		self.code_filter.initialize();
+
+		self.input_signal_power = 0.0;
+		self.sum_very_early = ZERO;
+		self.sum_early      = ZERO;
+		self.sum_prompt     = ZERO;
+		self.sum_late       = ZERO;
+		self.sum_very_late  = ZERO;
+		self.sum_data       = ZERO;
+		self.bump_jump_count = 0;
+		self.pilot_prompts.clear();
+		self.code_periods = 0;
+		self.lock_stat    = 0.0;
+
+		self.state = TrackingState::SecondarySync;
+	}
+
+}
+
+pub fn new_default_tracker(prn:usize, data_code:Vec<i8>, pilot_code:Vec<i8>, acq_freq_hz:f64, fs:f64) -> Tracking<SecondOrderFIR> {
+	// Same carrier loop as the SBAS tracker, updated every 4-ms code period.  The code loop has a quarter of the gain since the
+	// discriminator is noisier on the narrow BOC(1,1) peak, and the code doesn't need the carrier loop's bandwidth.
+	let a0 = 0.0625 / CODE_PERIOD_SEC;
+	let a1 = -0.5   / CODE_PERIOD_SEC;
+	let a2 = 0.5    / CODE_PERIOD_SEC;
+
+	let mut trk = Tracking {
+		prn, state: TrackingState::LostLock, fs, data_code, pilot_code,
+		last_acq_result: AcquisitionResult::default(),
+		sv_tow_sec_inner: IntegerClock::new(1.0 / CODE_PERIOD_SEC),
+		sv_tow_sec_outer: IntegerClock::new(fs),
+		carrier: ZERO, carrier_inc: ZERO, carrier_dphase_rad: 0.0, code_phase: 0.0, code_dphase: 0.0,
+		carrier_cycles: 0.0, carrier_lock_count: 0,
+		carrier_filter: SecondOrderFIR::new(a0/fs, a1/fs, a2/fs),
+		code_filter:    SecondOrderFIR::new(CODE_LOOP_GAIN*a0/fs, CODE_LOOP_GAIN*a1/fs, CODE_LOOP_GAIN*a2/fs),
+		sum_very_early: ZERO, sum_early: ZERO, sum_prompt: ZERO, sum_late: ZERO, sum_very_late: ZERO, sum_data: ZERO,
+		input_signal_power: 0.0, bump_jump_count: 0, bump_jumps: 0, pilot_prompts: VecDeque::new(), code_periods: 0, lock_stat: 0.0,
+	};
+	trk.initialize(acq_freq_hz);
+	trk
+}
+
+#[test]
+fn test_bump_jumping() {
+	use rand::Rng;
+	use rand::SeedableRng;
+	use rand::rngs::StdRng;
+
+	let mut rng = StdRng::seed_from_u64(50);
+	let fs:f64 = 5.0e6;
+	let doppler_hz:f64 = -800.0;
+	let data_code:Vec<i8> = (0..CODE_LEN_CHIPS).map(|_| if rng.gen() { 1 } else { -1 }).collect();
+	let pilot_code:Vec<i8> = (0..CODE_LEN_CHIPS).map(|_| if rng.gen() { 1 } else { -1 }).collect();
+	let symbols:Vec<bool> = (0..320).map(|_| rng.gen()).collect();
+
+	// 40 dB-Hz with unit noise power per complex sample, starting close to the side peak half a chip behind the main one
+	let amplitude:f64 = (1.0e4 / fs).sqrt();
+	let mut trk = new_default_tracker(11, data_code.clone(), pilot_code.clone(), 0.0, fs);
+	trk.control(&AcquisitionResult{ doppler_hz: doppler_hz - 15.0, ..Default::default() }).unwrap();
+
+	let mut reported:Vec<f64> = vec![];
+	let mut last_chip:f64 = 0.0;
+	for idx in 0..(1.2 * fs) as usize {
+		let t:f64 = idx as f64 / fs;
+		let chip:f64 = t * CHIP_RATE_HZ * (1.0 + doppler_hz / 1.57542e9) + 0.72;
+		let period:usize = (chip / CODE_LEN_CHIPS as f64) as usize;
+		let data:f64 = boc_value(&data_code, chip) as f64 * if symbols[period] { 1.0 } else { -1.0 };
+		let pilot:f64 = boc_value(&pilot_code, chip) as f64 * SECONDARY_CODE[period % SECONDARY_CODE_LEN] as f64;
+		let phase:f64 = 2.0 * consts::PI * doppler_hz * t + 2.1;
+		let noise = Complex{ re: rng.gen::<f64>() - 0.5, im: rng.gen::<f64>() - 0.5 } * 12.0_f64.sqrt() / 2.0_f64.sqrt();
+		let val = Complex{ re: phase.cos(), im: phase.sin() } * amplitude * (data - pilot) / 2.0_f64.sqrt() + noise;
+		if let BlockResult::Ready(report) = trk.apply(&Sample{ val, idx }) { reported.push(report.prompt_i); }
+		last_chip = chip;
+	}
+
+	// The code ends up on the main peak, and the pilot resolves the polarity of the data symbols
+	assert!(trk.has_carrier_lock());
+	assert!(trk.bump_jumps() >= 1);
+	let code_error_chips:f64 = (last_chip.rem_euclid(CODE_LEN_CHIPS as f64) - trk.code_phase_chips() + 0.5 * CODE_LEN_CHIPS as f64)
+		.rem_euclid(CODE_LEN_CHIPS as f64) - 0.5 * CODE_LEN_CHIPS as f64;
+	assert!(code_error_chips.abs() < 0.1);
+	assert!((trk.cn0_dbhz() - 40.0).abs() < 2.0);
+	assert!(reported.len() > 150);
+	assert!((0..symbols.len() - reported.len()).any(|first| reported.iter().zip(symbols.iter().skip(first)).all(|(p, s)| (*p > 0.0) == *s)));
+}
@@ -8,6 +8,8 @@ use crate::block::block_tree_sync_static::acquire_and_track::AcquireAndTrack;
 
 use crate::filters::{SecondOrderFIR as FIR};
 use crate::gnss::common::acquisition::{two_stage_pcps::Acquisition, AcquisitionResult};
+use crate::gnss::common::hatch_filter::{CarrierMeasurement, HatchConfig, HatchFilter};
+use crate::gnss::common::nav_data_stats::NavDataStats;
 use crate::gnss::common::tracking::TrackReport;
 use crate::gnss::gps_l1_ca::{self, pvt};
 use crate::gnss::gps_l1_ca::telemetry_decode;
@@ -23,20 +25,31 @@ pub const C_METERS_PER_SEC:f64 = 2.99792458e8;    // [m/s] speed of light
 #[derive(Debug, Clone)]
 pub enum ChannelCommand {
 	Ionosphere,
+	NavDataStats,
 	Reset,
 }
 
 #[derive(Debug)]
 pub enum ChannelResponse {
 	Ionosphere(Option<pvt::ionosphere::Model>),
+	NavDataStats(NavDataStats),
 	Ack,
 }
 
+#[derive(Debug)]
+pub enum ObservationError {
+	NotTracking,
+	Coasting,						// Vector tracking is carrying the channel through a blockage, so there's nothing to measure
+	Ephemeris(pvt::ephemeris_store::EphemerisError),
+	Aiding(&'static str),
+}
+
 #[derive(Debug)]
 pub struct ChannelReport {
 	pub opt_subframe:Option<SF>,
-	pub opt_observation:Option<pvt::Observation>,
+	pub opt_observation:Option<Result<pvt::Observation, ObservationError>>,
 	pub new_ionosphere:bool,
+	pub opt_cutover:Option<pvt::ephemeris_store::Cutover>,
 }
 
 pub struct Channel {
@@ -50,9 +63,11 @@ pub struct Channel {
 	pub last_sf1:Option<subframe::subframe1::Body>,
 	pub last_sf2:Option<subframe::subframe2::Body>,
 	pub last_sf3:Option<subframe::subframe3::Body>,
-	pub ephemeris:Option<pvt::ephemeris::Ephemeris>,
+	pub ephemerides:pvt::ephemeris_store::EphemerisStore,
 	pub ionosphere:Option<pvt::ionosphere::Model>,
 	pub pvt_rate_samples:usize,
+	pub intermediate_freq_hz:f64,
+	pub hatch:HatchFilter,
 }
 
 impl BlockFunctionality<ChannelCommand, ChannelResponse, (Sample, f64), ChannelReport> for Channel {
@@ -60,6 +75,7 @@ impl BlockFunctionality<ChannelCommand, ChannelResponse, (Sample, f64), ChannelR
 	fn control(&mut self, c:&ChannelCommand) -> Result<ChannelResponse, &'static str> {
 		match c {
 			ChannelCommand::Ionosphere => Ok(ChannelResponse::Ionosphere(self.ionosphere.clone())),
+			ChannelCommand::NavDataStats => Ok(ChannelResponse::NavDataStats(self.nav_data_stats())),
 			ChannelCommand::Reset => {
 				// TODO: implement reset logic
 				Ok(ChannelResponse::Ack)
@@ -94,16 +110,20 @@ impl Channel {
 	// Read-only getter methods
 	pub fn carrier_freq_hz(&self) -> f64 { self.aat.trk.carrier_freq_hz() }
 	pub fn test_stat(&self) -> f64 { self.aat.trk.test_stat() }
+	pub fn input_power_db(&self) -> Option<f64> { self.aat.trk.input_power().map(|p| 10.0 * p.log10()) }
 
 	pub fn last_acq_doppler(&self) -> f64 { self.last_acq_doppler }
 	pub fn last_acq_test_stat(&self) -> f64 { self.last_acq_test_stat }
-	pub fn ephemeris(&self)  -> Option<pvt::ephemeris::Ephemeris> { self.ephemeris }
+	pub fn ephemeris(&self)  -> Option<pvt::ephemeris::Ephemeris> { self.ephemerides.current(self.prn).map(|stored| stored.ephemeris) }
 	pub fn ionosphere(&self) -> Option<pvt::ionosphere::Model> { self.ionosphere }
+	pub fn nav_data_stats(&self) -> NavDataStats { self.tlm.stats }
 
 	fn apply_tuple(&mut self, input:&(Sample, f64)) -> BlockResult<ChannelReport> {
 		let (s, tow_rcv) = input;
+		self.last_sample_idx = s.idx;
 
 		let mut new_ionosphere = false;
+		let mut opt_cutover = None;
 
 		match self.aat.apply(s) {
 			BlockResult::Ready(TrackReport { prompt_i, sample_idx, ..}) => {
@@ -111,7 +131,7 @@ impl Channel {
 				// bit_idx is the index of the last sample that made up this bit
 
 				// The tracker has a lock and produced a bit, so pass it into the telemetry decoder and match on the result
-				let opt_subframe:Option<SF> = match self.tlm.apply_sample((prompt_i > 0.0, sample_idx)) {
+				let opt_subframe:Option<SF> = match self.tlm.apply_soft_sample(prompt_i, sample_idx) {
 					telemetry_decode::TelemetryDecoderResult::Ok(sf, _, _) => {
 
 						self.aat.trk.reset_clock(sf.time_of_week() + (self.aat.trk.code_phase_samples()/self.fs));
@@ -124,14 +144,15 @@ impl Channel {
 
 								// If we just received subframe 3, we might have a new complete calendar and ephemeris ready
 								match (self.last_sf1, self.last_sf2) {
-									(Some(subframe::subframe1::Body{week_number, code_on_l2:_, ura_index:_, sv_health:_, iodc, t_gd, t_oc, a_f2, a_f1, a_f0}), 
+									(Some(subframe::subframe1::Body{week_number, code_on_l2:_, ura_index, sv_health, iodc, t_gd, t_oc, a_f2, a_f1, a_f0}), 
 									 Some(subframe::subframe2::Body{iode:iode2, crs, dn, m0, cuc, e, cus, sqrt_a, t_oe, fit_interval, aodo })) => {
 										if (iodc % 256) == (iode2 as u16) && iode2 == sf3.iode { 
 											let new_ephemeris = pvt::ephemeris::Ephemeris { week_number, t_gd, fit_interval, aodo,
 												t_oc:(t_oc as f64), a_f0, a_f1, a_f2, t_oe, sqrt_a, dn, m0, e, omega: sf3.omega, omega0: sf3.omega0, 
 												omega_dot: sf3.omega_dot, cus, cuc, crs, crc: sf3.crc, cis: sf3.cis, cic: sf3.cic, i0: sf3.i0, 
 												idot: sf3.idot, iodc };
-											self.ephemeris = Some(new_ephemeris);
+											opt_cutover = self.ephemerides.insert(self.prn, new_ephemeris, sv_health, ura_index);
+											if opt_cutover.is_some() { self.tlm.stats.ephemeris_changes += 1; }
 										}
 									},
 									(_, _) => {}
@@ -159,19 +180,19 @@ impl Channel {
 				};
 
 				let opt_observation = if s.idx % self.pvt_rate_samples == 0 {
-					self.opt_observation(*tow_rcv)
+					Some(self.observation(*tow_rcv))
 				} else {
 					None
 				};
 
-				BlockResult::Ready(ChannelReport{ opt_subframe, opt_observation, new_ionosphere })
+				BlockResult::Ready(ChannelReport{ opt_subframe, opt_observation, new_ionosphere, opt_cutover })
 
 			},
 			BlockResult::NotReady => {
 				// Even if the tracking block isn't ready, we might need to produce an observation
 				if s.idx % self.pvt_rate_samples == 0 {
 					BlockResult::Ready(ChannelReport{ opt_subframe: None, 
-						opt_observation: self.opt_observation(*tow_rcv), new_ionosphere: false })
+						opt_observation: Some(self.observation(*tow_rcv)), new_ionosphere: false, opt_cutover: None })
 				} else {
 					BlockResult::NotReady
 				}
@@ -181,34 +202,101 @@ impl Channel {
 
 	}
 
-	pub fn opt_observation(&self, rx_tow_sec:f64) -> Option<pvt::Observation> {
-		if self.aat.awaiting_acq { None } else {
-			if let Some(eph) = self.ephemeris {
-				// TODO: account for GPS week rollover possibility
-				// TODO: check for ephemeris validity time
-				// TODO: consider returning a Result where the Err describes the reason for not producing a position
-				let sv_tow_sec:f64 = self.aat.trk.sv_time_of_week();
-				let (pos_ecef, sv_clock) = eph.pos_and_clock(sv_tow_sec);
-				let carrier_freq_hz:f64 = self.aat.trk.carrier_freq_hz();
-				let pseudorange_m:f64 = (rx_tow_sec - sv_tow_sec + sv_clock - eph.t_gd) * C_METERS_PER_SEC;
-				let obs = pvt::Observation{ sv_id: self.prn, sv_tow_sec, pseudorange_m, pos_ecef, sv_clock, t_gd: eph.t_gd, carrier_freq_hz };
-				Some(obs)
-			} else { 
-				None
-			}
-		}		
+	pub fn observation(&mut self, rx_tow_sec:f64) -> Result<pvt::Observation, ObservationError> {
+		if self.aat.awaiting_acq { 
+			self.hatch.reset();
+			return Err(ObservationError::NotTracking); 
+		}
+
+		let opt_code_error_chips:Option<f64> = self.aat.trk.take_code_error_chips();
+		if self.aat.trk.is_coasting() {
+			self.hatch.reset();
+			return Err(ObservationError::Coasting);
+		}
+
+		let nco_obs = self.nco_observation(rx_tow_sec)?;
+		let corrections_m:f64 = (nco_obs.sv_clock - nco_obs.t_gd) * C_METERS_PER_SEC;
+
+		// With vector tracking the code NCO follows the navigation solution, so the discriminator output is the measurement
+		let code_m:f64 = match opt_code_error_chips {
+			Some(chips) if self.aat.trk.is_aided() => (nco_obs.pseudorange_m - corrections_m) - chips * C_METERS_PER_SEC / pvt::vector_tracking::CHIP_RATE_HZ,
+			_ => nco_obs.pseudorange_m - corrections_m,
+		};
+
+		// Smooth the code before the SV corrections so the carrier only has to follow the geometry and the receiver clock.  The
+		// receiver time of week can be steered in steps that the NCO never sees, so those steps are added to the carrier as well.
+		let wavelength_m:f64 = C_METERS_PER_SEC / pvt::L1_FREQ_HZ;
+		let clock_steering_m:f64 = (rx_tow_sec - (self.last_sample_idx as f64) / self.fs) * C_METERS_PER_SEC;
+		let opt_carrier_m:Option<f64> = if self.aat.trk.has_carrier_lock() {
+			Some(-wavelength_m * self.aat.trk.carrier_cycles() + clock_steering_m)
+		} else { None };
+		let smoothed_code_m:f64 = match opt_carrier_m {
+			Some(carrier_m) => self.hatch.apply(CarrierMeasurement{ t: rx_tow_sec, code_m, carrier_m,
+				range_rate_mps: -wavelength_m * nco_obs.doppler_hz, clock_steering_m, lock_count: self.aat.trk.carrier_lock_count() }),
+			None => {
+				self.hatch.reset();
+				code_m
+			},
+		};
+
+		Ok(pvt::Observation{ pseudorange_m: smoothed_code_m + corrections_m, pseudorange_raw_m: code_m + corrections_m,
+			smoothing_epochs: self.hatch.epochs(), carrier_phase_cycles: opt_carrier_m.map(|carrier_m| (carrier_m + corrections_m) / wavelength_m),
+			carrier_lock_count: self.aat.trk.carrier_lock_count(), ..nco_obs })
+	}
+
+	/// Vector tracking: steers the code NCO to the code phase and rate the navigation solution predicts for this SV, and holds
+	/// the predicted carrier if the signal fades.  Aiding is dropped if the solution is too uncertain to be of use.
+	pub fn apply_aiding(&mut self, soln:&pvt::ekf::NavigationSolution, rx_tow_sec:f64, opt_iono:Option<pvt::ionosphere::Model>,
+		solver:&pvt::SolverConfig, config:&pvt::vector_tracking::VectorTrackingConfig) -> Result<pvt::vector_tracking::ChannelAiding, ObservationError> {
+
+		if self.aat.awaiting_acq || !self.aat.trk.has_carrier_lock() { return Err(ObservationError::NotTracking); }
+
+		let nco_obs = self.nco_observation(rx_tow_sec)?;
+		match pvt::vector_tracking::predict(&nco_obs, soln, opt_iono, solver, config) {
+			Ok(aiding) => {
+				self.aat.trk.set_aiding(aiding.code_correction_chips, aiding.code_rate_chips_per_sec,
+					aiding.doppler_hz + self.intermediate_freq_hz, config.max_coast_sec);
+				Ok(aiding)
+			},
+			Err(e) => {
+				self.aat.trk.clear_aiding();
+				Err(ObservationError::Aiding(e))
+			},
+		}
+	}
+
+	// Observation implied by the code NCO alone, before the discriminator output or smoothing
+	fn nco_observation(&self, rx_tow_sec:f64) -> Result<pvt::Observation, ObservationError> {
+		// TODO: account for GPS week rollover possibility
+		let sv_tow_sec:f64 = self.aat.trk.sv_time_of_week();
+		let stored = self.ephemerides.get(self.prn, sv_tow_sec).map_err(ObservationError::Ephemeris)?;
+		let eph = stored.ephemeris;
+		let pvt::ephemeris::SvState{ pos_ecef, vel_ecef:sv_vel_ecef, clock:sv_clock, clock_drift:sv_clock_drift, .. } = eph.sv_state(sv_tow_sec);
+		let carrier_freq_hz:f64 = self.aat.trk.carrier_freq_hz();
+		let doppler_hz:f64 = carrier_freq_hz - self.intermediate_freq_hz;
+		let pseudorange_m:f64 = (rx_tow_sec - sv_tow_sec + sv_clock - eph.t_gd) * C_METERS_PER_SEC;
+
+		Ok(pvt::Observation{ sv_id: self.prn, sv_tow_sec, pseudorange_m, pseudorange_raw_m: pseudorange_m, smoothing_epochs: 0,
+			pos_ecef, sv_clock, t_gd: eph.t_gd, carrier_freq_hz, doppler_hz, sv_vel_ecef, sv_clock_drift, cn0_dbhz: self.aat.trk.cn0_dbhz(),
+			ura_m: stored.ura_m(), carrier_phase_cycles: None, carrier_lock_count: self.aat.trk.carrier_lock_count(), iodc: Some(eph.iodc) })
 	}
 }
 
-pub fn new_channel(prn:usize, fs:f64, test_stat_threshold:f64, pvt_rate_samples:usize) -> Channel { 
+/// Sets up a channel for one PRN.  The intermediate frequency is zero for complex baseband input.  Parity error correction is off
+/// with the default `ErrorCorrection`, so any word failing parity loses its whole subframe.
+pub fn new_channel(prn:usize, fs:f64, intermediate_freq_hz:f64, test_stat_threshold:f64, pvt_rate_samples:usize,
+	error_correction:telemetry_decode::ErrorCorrection) -> Channel { 
 	let symbol_i8:Vec<i8> = gps_l1_ca::signal_modulation::prn_int_sampled(prn, fs);
 	let symbol:Vec<Complex<f64>> = symbol_i8.into_iter().map(|x| Complex{ re: x as f64, im: 0.0 }).collect();
-	let acq = Acquisition::new(symbol, fs, prn, 9, 3, 50.0, test_stat_threshold, 8);
-	let trk = tracking::new_2nd_order_tracker(prn, 0.0, fs, 0.0, 0.0);
-	let tlm = telemetry_decode::TelemetryDecoder::new();
+	let mut acq = Acquisition::new(symbol, fs, prn, 9, 3, 50.0, test_stat_threshold, 8);
+	acq.set_center_freq_hz(intermediate_freq_hz);
+	let mut trk = tracking::new_2nd_order_tracker(prn, intermediate_freq_hz, fs, 0.0, 0.0);
+	trk.set_intermediate_freq_hz(intermediate_freq_hz);
+	let tlm = telemetry_decode::TelemetryDecoder::new_with_error_correction(error_correction);
 
 	let aat = AcquireAndTrack::new(acq, trk);
 
 	Channel { prn, fs, aat, tlm, last_acq_doppler:0.0, last_acq_test_stat: 0.0, last_sample_idx: 0, 
-		ephemeris: None, ionosphere: None, last_sf1: None, last_sf2: None, last_sf3: None, pvt_rate_samples }
+		ephemerides: pvt::ephemeris_store::EphemerisStore::new(), ionosphere: None, last_sf1: None, last_sf2: None, last_sf3: None, pvt_rate_samples,
+		intermediate_freq_hz, hatch: HatchFilter::new(HatchConfig::default()) }
 }
@@ -1,10 +1,16 @@
 
 pub mod channel;
 
+pub mod planner;
+
 pub mod pvt;
 
 pub mod signal_modulation;
 
+pub mod snapshot;
+
 pub mod telemetry_decode;
 
+pub mod telemetry_encode;
+
 pub mod tracking;
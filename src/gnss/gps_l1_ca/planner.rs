@@ -0,0 +1,177 @@
+
+use std::collections::HashMap;
+
+use serde::{Serialize, Deserialize};
+use nalgebra::base::{DMatrix, Vector3};
+
+use crate::gnss::gps_l1_ca::pvt::{C, L1_FREQ_HZ, Dop};
+use crate::gnss::gps_l1_ca::pvt::ephemeris::{Almanac, Ephemeris};
+use crate::utils::kinematics;
+
+const NOMINAL_ZENITH_RANGE_M:f64 = 20.2e6;
+const DOPPLER_RATE_DT_SEC:f64 = 0.5;
+
+#[derive(Debug, Clone, Copy)]
+pub struct PlannerConfig {
+	pub elevation_mask_radians: f64,
+	pub step_sec: f64,
+	pub cn0_zenith_dbhz: f64,			// Expected C/N0 for an SV straight overhead
+	pub antenna_rolloff_db: f64,		// Antenna gain at the horizon below its gain at zenith
+}
+
+impl Default for PlannerConfig {
+	fn default() -> Self { Self{ elevation_mask_radians: 5.0_f64.to_radians(), step_sec: 60.0, cn0_zenith_dbhz: 48.0, antenna_rolloff_db: 10.0 } }
+}
+
+/// Predicted geometry and signal for one SV as seen from a static receiver.  The Doppler includes the SV clock drift but not
+/// the receiver's, which is unknown until the receiver has a fix.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct SvPrediction {
+	pub prn: usize,
+	pub az_radians: f64,
+	pub el_radians: f64,
+	pub range_m: f64,
+	pub doppler_hz: f64,
+	pub doppler_rate_hz_per_sec: f64,
+	pub cn0_dbhz: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PlanEpoch {
+	pub tow_sec: f64,
+	pub visible: Vec<SvPrediction>,		// Above the elevation mask, in PRN order
+	pub dop: Option<Dop>,				// Only with at least four visible SVs
+}
+
+/// One SV's time above the elevation mask, to the resolution of the plan's step
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct Pass {
+	pub prn: usize,
+	pub rise_tow_sec: f64,
+	pub set_tow_sec: f64,
+	pub max_el_radians: f64,
+	pub min_doppler_hz: f64,
+	pub max_doppler_hz: f64,
+}
+
+/// Ephemerides from the healthy SVs in an almanac, for planning with the same functions
+pub fn from_almanacs(almanacs:&[Almanac]) -> HashMap<usize, Ephemeris> {
+	almanacs.iter().filter(|alm| alm.sv_health == 0).map(|alm| (alm.prn, alm.to_ephemeris())).collect()
+}
+
+// Range, line of sight and Doppler at GPS time t, ignoring the signal's time of flight, which is plenty for planning
+fn range_and_doppler(eph:&Ephemeris, p_rx:Vector3<f64>, t:f64) -> (f64, Vector3<f64>, f64) {
+	let ((x, y, z), _) = eph.pos_and_clock(t);
+	let ((vx, vy, vz), clock_drift) = eph.vel_and_clock_drift(t);
+	let p_r = Vector3::new(x, y, z) - p_rx;
+	let los_e = p_r.normalize();
+	let range_rate:f64 = Vector3::new(vx, vy, vz).dot(&los_e);
+	(p_r.norm(), los_e, (clock_drift*C - range_rate) * L1_FREQ_HZ / C)
+}
+
+pub fn predict(ephemerides:&HashMap<usize, Ephemeris>, rx_pos_ecef:(f64, f64, f64), tow_sec:f64, config:&PlannerConfig) -> PlanEpoch {
+	let p_rx = Vector3::new(rx_pos_ecef.0, rx_pos_ecef.1, rx_pos_ecef.2);
+	let rx_wgs84 = kinematics::ecef_to_wgs84(rx_pos_ecef.0, rx_pos_ecef.1, rx_pos_ecef.2);
+
+	let mut prns:Vec<usize> = ephemerides.keys().copied().collect();
+	prns.sort_unstable();
+
+	let mut los:Vec<Vector3<f64>> = vec![];
+	let visible:Vec<SvPrediction> = prns.into_iter().filter_map(|prn| {
+		let eph = &ephemerides[&prn];
+		let (range_m, los_e, doppler_hz) = range_and_doppler(eph, p_rx, tow_sec);
+		let (az_radians, el_radians) = kinematics::az_el(rx_wgs84.latitude, rx_wgs84.longitude, rx_wgs84.height_above_ellipsoid, los_e);
+		if el_radians < config.elevation_mask_radians { return None; }
+
+		let (_, _, doppler_before) = range_and_doppler(eph, p_rx, tow_sec - DOPPLER_RATE_DT_SEC);
+		let (_, _, doppler_after)  = range_and_doppler(eph, p_rx, tow_sec + DOPPLER_RATE_DT_SEC);
+		let doppler_rate_hz_per_sec:f64 = (doppler_after - doppler_before) / (2.0 * DOPPLER_RATE_DT_SEC);
+
+		// Free-space loss relative to an SV overhead, plus a simple antenna pattern that falls off toward the horizon
+		let cn0_dbhz:f64 = config.cn0_zenith_dbhz - 20.0*(range_m / NOMINAL_ZENITH_RANGE_M).log10()
+			- config.antenna_rolloff_db * (1.0 - el_radians.sin());
+
+		los.push(los_e);
+		Some(SvPrediction{ prn, az_radians, el_radians, range_m, doppler_hz, doppler_rate_hz_per_sec, cn0_dbhz })
+	}).collect();
+
+	let dop:Option<Dop> = if los.len() >= 4 {
+		let h = DMatrix::from_fn(los.len(), 4, |i, j| if j < 3 { -los[i][j] } else { 1.0 });
+		Dop::new(&h, rx_pos_ecef)
+	} else { None };
+
+	PlanEpoch{ tow_sec, visible, dop }
+}
+
+/// Predictions every step from the start time through the end of the span.  Times past the end of the week are fine as long as
+/// the ephemerides are referenced to the starting week.
+pub fn plan(ephemerides:&HashMap<usize, Ephemeris>, rx_pos_ecef:(f64, f64, f64), start_tow_sec:f64, duration_sec:f64,
+	config:&PlannerConfig) -> Vec<PlanEpoch> {
+
+	let n:usize = (duration_sec / config.step_sec).floor() as usize + 1;
+	(0..n).map(|i| predict(ephemerides, rx_pos_ecef, start_tow_sec + (i as f64)*config.step_sec, config)).collect()
+}
+
+/// Splits a plan into passes, in order of rise time.  A pass that's already in progress at the start of the plan or still in
+/// progress at the end is cut off there.
+pub fn passes(plan:&[PlanEpoch]) -> Vec<Pass> {
+	let mut open:HashMap<usize, Pass> = HashMap::new();
+	let mut closed:Vec<Pass> = vec![];
+
+	for epoch in plan {
+		for sv in epoch.visible.iter() {
+			let pass = open.entry(sv.prn).or_insert(Pass{ prn: sv.prn, rise_tow_sec: epoch.tow_sec, set_tow_sec: epoch.tow_sec,
+				max_el_radians: sv.el_radians, min_doppler_hz: sv.doppler_hz, max_doppler_hz: sv.doppler_hz });
+			pass.set_tow_sec = epoch.tow_sec;
+			pass.max_el_radians = pass.max_el_radians.max(sv.el_radians);
+			pass.min_doppler_hz = pass.min_doppler_hz.min(sv.doppler_hz);
+			pass.max_doppler_hz = pass.max_doppler_hz.max(sv.doppler_hz);
+		}
+
+		let set:Vec<usize> = open.keys().copied().filter(|prn| !epoch.visible.iter().any(|sv| sv.prn == *prn)).collect();
+		for prn in set { closed.extend(open.remove(&prn)); }
+	}
+
+	closed.extend(open.values().copied());
+	closed.sort_by(|a, b| a.rise_tow_sec.partial_cmp(&b.rise_tow_sec).unwrap_or(std::cmp::Ordering::Equal).then(a.prn.cmp(&b.prn)));
+	closed
+}
+
+/// Doppler range to search during acquisition over the whole plan.  The margin has to cover the receiver oscillator's
+/// frequency error, which is usually much larger than the prediction error.
+pub fn doppler_search_limits(plan:&[PlanEpoch], margin_hz:f64) -> Option<(f64, f64)> {
+	let dopplers = plan.iter().flat_map(|epoch| epoch.visible.iter().map(|sv| sv.doppler_hz));
+	let (min, max) = dopplers.fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), d| (lo.min(d), hi.max(d)));
+	if min.is_finite() { Some((min - margin_hz, max + margin_hz)) } else { None }
+}
+
+#[test]
+fn test_visibility_plan() {
+	let ephemerides:HashMap<usize, Ephemeris> = (0..24).map(|i| (i + 1, crate::gnss::gps_l1_ca::pvt::ephemeris::test_ephemeris(-1.0 + ((i/4) as f64)/3.0, -1.0 + ((i%4) as f64)/2.0 + ((i/4) as f64)/12.0))).collect();
+	let p = kinematics::wgs84_to_ecef(0.7, -1.4, 300.0);
+	let rx_pos_ecef = (p[0], p[1], p[2]);
+
+	let config = PlannerConfig::default();
+	let plan = plan(&ephemerides, rx_pos_ecef, 3600.0, 12.0*3600.0, &config);
+	assert_eq!(plan.len(), 721);
+	assert!(plan.iter().all(|epoch| epoch.visible.iter().all(|sv| sv.el_radians >= config.elevation_mask_radians)));
+	assert!(plan.iter().any(|epoch| epoch.dop.is_some()));
+
+	// Doppler is the negative range rate, so it should match the change in range between steps
+	for prn in 1..=24 {
+		let ranges:Vec<(f64, SvPrediction)> = plan.iter().filter_map(|epoch| epoch.visible.iter().find(|sv| sv.prn == prn).map(|sv| (epoch.tow_sec, *sv))).collect();
+		for w in ranges.windows(2).filter(|w| w[1].0 - w[0].0 == config.step_sec) {
+			let range_rate:f64 = (w[1].1.range_m - w[0].1.range_m) / config.step_sec;
+			let doppler_mid:f64 = 0.5 * (w[0].1.doppler_hz + w[1].1.doppler_hz) - 2.0e-11 * L1_FREQ_HZ;
+			assert!((doppler_mid + range_rate * L1_FREQ_HZ / C).abs() < 5.0);
+			assert!((w[1].1.doppler_hz - w[0].1.doppler_hz - w[0].1.doppler_rate_hz_per_sec * config.step_sec).abs() < 5.0);
+		}
+	}
+
+	let passes = passes(&plan);
+	assert!(!passes.is_empty());
+	assert!(passes.iter().all(|p| p.set_tow_sec >= p.rise_tow_sec && p.min_doppler_hz <= p.max_doppler_hz));
+	let (lo, hi) = doppler_search_limits(&plan, 500.0).unwrap();
+	assert!(passes.iter().all(|p| p.min_doppler_hz - 500.0 >= lo && p.max_doppler_hz + 500.0 <= hi));
+	assert!(lo > -6000.0 && hi < 6000.0);
+}
@@ -0,0 +1,127 @@
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use serde::{Serialize, Deserialize};
+use nalgebra::base::Vector4;
+
+use super::{C, GnssFix, Observation, SolverConfig, ionosphere, timing};
+
+/// Observations from one receiver at one epoch, tagged with the receiver time of week after clock steering
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ObservationEpoch {
+	pub tow_sec: f64,
+	pub observations: Vec<Observation>,
+}
+
+/// Reads a JSON array of epochs, as written by the receiver example
+pub fn read_log<R: Read>(reader:R) -> Result<Vec<ObservationEpoch>, &'static str> {
+	let mut epochs:Vec<ObservationEpoch> = serde_json::from_reader(reader).map_err(|_| "Unable to parse observation log JSON")?;
+	epochs.sort_by(|a, b| a.tow_sec.partial_cmp(&b.tow_sec).unwrap_or(std::cmp::Ordering::Equal));
+	Ok(epochs)
+}
+
+/// Pseudorange correction for one SV, to be added to the rover's pseudorange
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct Correction {
+	pub prn: usize,
+	pub tow_sec: f64,
+	pub prc_m: f64,
+	pub rrc_mps: f64,			// Rate of change of the correction, or zero until there are two base epochs on the same ephemeris to difference
+	pub iodc: Option<u16>,		// Issue of data of the ephemeris the base used, which the rover has to be using too
+}
+
+impl Correction {
+
+	pub fn at(&self, tow_sec:f64) -> f64 { self.prc_m + self.rrc_mps * (tow_sec - self.tow_sec) }
+
+}
+
+/// Corrections from one base station epoch.  Everything the solver config models is left out of the correction, so the rover
+/// has to solve with the same config.  The base clock bias is common to every SV and drops out with the rover's clock bias, so
+/// it's removed here to keep the corrections small.
+pub fn corrections(epoch:&ObservationEpoch, base_pos_ecef:(f64, f64, f64), opt_iono:Option<ionosphere::Model>,
+	config:&SolverConfig) -> Result<Vec<Correction>, &'static str> {
+
+	let clock = timing::solve_time(&epoch.observations, base_pos_ecef, epoch.tow_sec, opt_iono, config)?;
+	let x = Vector4::new(base_pos_ecef.0, base_pos_ecef.1, base_pos_ecef.2, clock.clock_bias_sec * C);
+	Ok(epoch.observations.iter()
+		.filter(|obs| clock.sv_ids.contains(&obs.sv_id))
+		.map(|obs| Correction{ prn: obs.sv_id, tow_sec: epoch.tow_sec, prc_m: -obs.complete(x, opt_iono, config).residual, rrc_mps: 0.0, iodc: obs.iodc })
+		.collect())
+}
+
+/// Keeps the latest correction for each SV from a stream of base station epochs and applies them to rover observations
+#[derive(Debug, Clone)]
+pub struct Dgps {
+	pub base_pos_ecef: (f64, f64, f64),
+	pub config: SolverConfig,
+	pub max_age_sec: f64,
+	latest: HashMap<usize, Correction>,
+}
+
+impl Dgps {
+
+	pub fn new(base_pos_ecef:(f64, f64, f64), config:SolverConfig, max_age_sec:f64) -> Self {
+		Self{ base_pos_ecef, config, max_age_sec, latest: HashMap::new() }
+	}
+
+	pub fn latest(&self, prn:usize) -> Option<Correction> { self.latest.get(&prn).copied() }
+
+	/// Forgets the correction for one SV, e.g. when the base lost track of it, so the next one doesn't get a rate
+	pub fn remove(&mut self, prn:usize) { self.latest.remove(&prn); }
+
+	/// Returns the number of SVs corrected by this epoch.  The orbit and clock errors change at an ephemeris cutover on the base,
+	/// so the correction rate starts over then.
+	pub fn add_base_epoch(&mut self, epoch:&ObservationEpoch, opt_iono:Option<ionosphere::Model>) -> Result<usize, &'static str> {
+		let new_corrections = corrections(epoch, self.base_pos_ecef, opt_iono, &self.config)?;
+		for mut correction in new_corrections.iter().copied() {
+			if let Some(prev) = self.latest.get(&correction.prn).filter(|prev| prev.iodc == correction.iodc) {
+				let dt:f64 = correction.tow_sec - prev.tow_sec;
+				if dt > 0.0 && dt <= self.max_age_sec { correction.rrc_mps = (correction.prc_m - prev.prc_m) / dt; }
+			}
+			self.latest.insert(correction.prn, correction);
+		}
+		Ok(new_corrections.len())
+	}
+
+	/// Corrected copies of the rover observations that have a current correction from the same ephemeris; the rest are left out
+	pub fn correct(&self, rover_obs:&[Observation], tow_sec:f64) -> Vec<Observation> {
+		rover_obs.iter().filter_map(|obs| {
+			let correction = self.latest.get(&obs.sv_id).filter(|c| (tow_sec - c.tow_sec).abs() <= self.max_age_sec && c.iodc == obs.iodc)?;
+			let prc_m:f64 = correction.at(tow_sec);
+			Some(Observation{ pseudorange_m: obs.pseudorange_m + prc_m, pseudorange_raw_m: obs.pseudorange_raw_m + prc_m, ..*obs })
+		}).collect()
+	}
+
+	pub fn solve_position_and_time(&self, rover_obs:&[Observation], x0:Vector4<f64>, tow_sec:f64, current_rx_time:f64,
+		opt_iono:Option<ionosphere::Model>) -> Result<(GnssFix, Vector4<f64>), &'static str> {
+		super::solve_position_and_time(self.correct(rover_obs, tow_sec), x0, current_rx_time, opt_iono, &self.config)
+	}
+
+}
+
+/// Differential fixes for every rover epoch, using the base epochs up to and including the rover's time
+pub fn post_process(base:&[ObservationEpoch], rover:&[ObservationEpoch], base_pos_ecef:(f64, f64, f64), opt_iono:Option<ionosphere::Model>,
+	config:&SolverConfig, max_age_sec:f64) -> Vec<GnssFix> {
+
+	let mut dgps = Dgps::new(base_pos_ecef, *config, max_age_sec);
+	let mut base_iter = base.iter().peekable();
+	let mut x = Vector4::zeros();
+	let mut fixes:Vec<GnssFix> = vec![];
+
+	for rover_epoch in rover {
+		while let Some(base_epoch) = base_iter.peek() {
+			if base_epoch.tow_sec > rover_epoch.tow_sec { break; }
+			let _ = dgps.add_base_epoch(base_epoch, opt_iono);
+			base_iter.next();
+		}
+
+		if let Ok((fix, x_next)) = dgps.solve_position_and_time(&rover_epoch.observations, x, rover_epoch.tow_sec, rover_epoch.tow_sec, opt_iono) {
+			x = Vector4::new(x_next[0], x_next[1], x_next[2], 0.0);
+			fixes.push(fix);
+		}
+	}
+
+	fixes
+}
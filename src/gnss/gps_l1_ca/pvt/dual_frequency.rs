@@ -0,0 +1,76 @@
+
+use serde::{Serialize, Deserialize};
+use nalgebra::base::Vector4;
+
+use crate::gnss::gps_l2c::channel::Observation as L2cObservation;
+use crate::gnss::gps_l2c::tlm_decode::message_decode::type30;
+
+use super::{C, L1_FREQ_HZ, L2_FREQ_HZ, GnssFix, Observation, SolverConfig};
+
+// Ratio of the squared L1 and L2 frequencies, which is also the ratio of the ionospheric delays on L2 and L1
+pub const GAMMA_12:f64 = (L1_FREQ_HZ / L2_FREQ_HZ) * (L1_FREQ_HZ / L2_FREQ_HZ);
+
+// Ionospheric group delay in meters is 40.3 * TEC / f^2, with TEC in electrons per square meter
+const IONO_COEFF:f64 = 40.3;
+const TECU:f64 = 1.0e16;
+
+/// L1 C/A and L2C pseudoranges for the same SV at the same receive time
+#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+pub struct DualFrequencyObservation {
+	pub l1: Observation,				// Pseudorange already corrected for the SV clock and T_GD, as for single-frequency solutions
+	pub l2c_pseudorange_m: f64,			// Corrected for the SV clock, but not for any group delay
+	pub isc_l1ca: f64,					// [sec] Inter-signal corrections from CNAV message type 30, or zero if not yet received
+	pub isc_l2c: f64,					// [sec]
+}
+
+impl DualFrequencyObservation {
+
+	pub fn new(l1:Observation, l2c_pseudorange_m:f64, opt_clock:Option<&type30::Body>) -> Self {
+		let (isc_l1ca, isc_l2c) = match opt_clock {
+			Some(body) => (body.isc_l1ca as f64, body.isc_l2c as f64),
+			None => (0.0, 0.0),
+		};
+		Self{ l1, l2c_pseudorange_m, isc_l1ca, isc_l2c }
+	}
+
+	/// L1 C/A pseudorange with the group delay correction c*(T_GD - ISC_L1CA) from IS-GPS-200K, 30.3.3.3.1.1.1
+	pub fn l1ca_pseudorange_m(&self) -> f64 { self.l1.pseudorange_m + C*self.isc_l1ca }
+
+	/// L2C pseudorange with the group delay correction c*(T_GD - ISC_L2C) from the same section
+	pub fn l2c_pseudorange_m(&self) -> f64 { self.l2c_pseudorange_m - C*(self.l1.t_gd - self.isc_l2c) }
+
+	// The ionospheric delay on L2 is gamma_12 times the delay on L1, so this combination cancels it to first order
+	pub fn iono_free_pseudorange_m(&self) -> f64 { (self.l2c_pseudorange_m() - GAMMA_12*self.l1ca_pseudorange_m()) / (1.0 - GAMMA_12) }
+
+	/// Estimated ionospheric group delay on L1 [m]
+	pub fn l1_iono_delay_m(&self) -> f64 { (self.l2c_pseudorange_m() - self.l1ca_pseudorange_m()) / (GAMMA_12 - 1.0) }
+
+	/// Estimated slant total electron content along the line of sight [TECU]
+	pub fn slant_tec_tecu(&self) -> f64 { self.l1_iono_delay_m() * L1_FREQ_HZ.powi(2) / (IONO_COEFF * TECU) }
+
+	pub fn iono_free_observation(&self) -> Observation { Observation{ pseudorange_m: self.iono_free_pseudorange_m(), ..self.l1 } }
+
+}
+
+/// Pairs L1 C/A observations with the L2C observations from the same epoch, using the type 30 message each L2C channel last
+/// decoded when there is one.  The L2C pseudoranges get the SV clock correction from the L1 ephemeris.  SVs without both
+/// signals are left out.
+pub fn pair_observations(l1_obs:&[Observation], l2c_obs:&[L2cObservation]) -> Vec<DualFrequencyObservation> {
+	l1_obs.iter()
+		.filter_map(|obs| l2c_obs.iter().find(|l2c| l2c.prn == obs.sv_id)
+			.map(|l2c| DualFrequencyObservation::new(*obs, l2c.pseudorange_m + C*obs.sv_clock, l2c.clock.as_ref())))
+		.collect()
+}
+
+/// Position and time from the ionosphere-free combination, with the slant TEC of each SV in the fix
+pub fn solve_position_and_time(obs_this_soln:Vec<DualFrequencyObservation>, x0:Vector4<f64>, current_rx_time:f64,
+	config:&SolverConfig) -> Result<(GnssFix, Vector4<f64>), &'static str> {
+
+	// The combination already removes the ionosphere, so the broadcast model would only add error
+	let iono_free_config = SolverConfig{ ionosphere: false, ..*config };
+	let iono_free_obs:Vec<Observation> = obs_this_soln.iter().map(|obs| obs.iono_free_observation()).collect();
+	let (mut fix, x) = super::solve_position_and_time(iono_free_obs, x0, current_rx_time, None, &iono_free_config)?;
+
+	fix.slant_tec_tecu = obs_this_soln.iter().map(|obs| (obs.l1.sv_id, obs.slant_tec_tecu())).collect();
+	Ok((fix, x))
+}
@@ -0,0 +1,261 @@
+
+use serde::{Serialize, Deserialize};
+use nalgebra::base::{DMatrix, DVector, Matrix3, Vector3, Vector4};
+
+use crate::utils::kinematics;
+
+use super::{C, L1_FREQ_HZ, GnssFix, Observation, SolverConfig, ionosphere};
+
+/// Preset process noise for the kind of motion the receiver is expected to undergo
+#[derive(Debug, Clone, Copy)]
+pub enum Dynamics {
+	Static,
+	Pedestrian,
+	Vehicle,
+	Custom{ psd_h:f64, psd_v:f64 },
+}
+
+impl Dynamics {
+
+	/// Horizontal and vertical power spectral densities of the white noise driving the highest-order kinematic state,
+	/// so [m^2/s^3] for a constant-velocity model or [m^2/s^5] when acceleration is estimated
+	pub fn psd(&self) -> (f64, f64) {
+		match self {
+			Dynamics::Static => (1.0e-4, 1.0e-4),
+			Dynamics::Pedestrian => (1.0, 0.1),
+			Dynamics::Vehicle => (10.0, 1.0),
+			Dynamics::Custom{ psd_h, psd_v } => (*psd_h, *psd_v),
+		}
+	}
+
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EkfConfig {
+	pub dynamics: Dynamics,
+	pub estimate_acceleration: bool,
+	pub clock_h0: f64,				// Allan variance coefficients of the receiver oscillator
+	pub clock_h_2: f64,
+	pub doppler_sigma_mps: f64,		// Pseudorange sigmas come from the solver's weight model
+	pub gate_sigma: Option<f64>,	// Measurements whose innovation is larger than this many sigma are rejected
+	pub solver: SolverConfig,		// Corrections applied to each measurement and configuration of the initial least squares fix
+}
+
+impl Default for EkfConfig {
+	fn default() -> Self {
+		Self{ dynamics: Dynamics::Vehicle, estimate_acceleration: false, clock_h0: 2.0e-19, clock_h_2: 2.0e-20, doppler_sigma_mps: 1.0,
+			gate_sigma: Some(5.0), solver: SolverConfig::default() }
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NavigationSolution {
+	pub current_rx_time: f64,
+	pub pos_ecef: (f64, f64, f64),
+	pub vel_ecef: (f64, f64, f64),
+	pub acc_ecef: Option<(f64, f64, f64)>,
+	pub clock_bias_m: f64,
+	pub clock_drift: f64,				// [sec/sec]
+	pub h_accuracy_m: f64,				// [m] 1-sigma
+	pub v_accuracy_m: f64,				// [m] 1-sigma
+	pub pseudoranges_used: usize,		// Zero when the solution is a pure propagation
+	pub dopplers_used: usize,
+	pub rejected_sv_ids: Vec<usize>,	// SVs with at least one measurement that failed the innovation gate
+}
+
+/// Extended Kalman filter for position, velocity, optionally acceleration, clock bias and clock drift, all in ECEF.  The
+/// filter is initialized from a least squares fix and after that it processes whatever pseudoranges and Dopplers are
+/// available, propagating through epochs that don't have enough SVs for a snapshot solution.
+pub struct NavigationFilter {
+	pub config: EkfConfig,
+	x: DVector<f64>,			// Position, velocity, [acceleration], clock bias [m] and clock drift [m/s]
+	p: DMatrix<f64>,
+	opt_t: Option<f64>,			// Receiver time of the state, or None before initialization
+}
+
+impl NavigationFilter {
+
+	pub fn new(config:EkfConfig) -> Self {
+		let n:usize = if config.estimate_acceleration { 11 } else { 8 };
+		Self{ config, x: DVector::zeros(n), p: DMatrix::zeros(n, n), opt_t: None }
+	}
+
+	pub fn is_initialized(&self) -> bool { self.opt_t.is_some() }
+
+	pub fn reset(&mut self) { self.opt_t = None; }
+
+	// Index of the clock bias, which follows the kinematic states
+	fn idx_clock(&self) -> usize { self.x.len() - 2 }
+
+	/// Accounts for a change the caller made to the receiver clock, e.g. steering the time of week by the estimated bias
+	pub fn shift_clock_bias(&mut self, delta_m:f64) {
+		let i = self.idx_clock();
+		self.x[i] += delta_m;
+	}
+
+	pub fn update(&mut self, obs_this_soln:&[Observation], current_rx_time:f64, opt_iono:Option<ionosphere::Model>) -> Result<NavigationSolution, &'static str> {
+		let (pseudoranges_used, dopplers_used, rejected_sv_ids) = match self.opt_t {
+			None => self.initialize(obs_this_soln, current_rx_time, opt_iono)?,
+			Some(t) => {
+				self.propagate(current_rx_time - t);
+				self.measurement_update(obs_this_soln, opt_iono)
+			},
+		};
+		self.opt_t = Some(current_rx_time);
+
+		if !self.x.iter().all(|a| a.is_finite()) {
+			self.reset();
+			return Err("Filter state is infinite");
+		}
+
+		let k:usize = self.idx_clock();
+		let cov_e = Matrix3::from_fn(|i, j| self.p[(i,j)]);
+		let wgs84 = kinematics::ecef_to_wgs84(self.x[0], self.x[1], self.x[2]);
+		let dcm = kinematics::dcm_we(wgs84.latitude, wgs84.longitude);
+		let cov_enu = dcm * cov_e * dcm.transpose();
+
+		Ok(NavigationSolution{ current_rx_time,
+			pos_ecef: (self.x[0], self.x[1], self.x[2]),
+			vel_ecef: (self.x[3], self.x[4], self.x[5]),
+			acc_ecef: if self.config.estimate_acceleration { Some((self.x[6], self.x[7], self.x[8])) } else { None },
+			clock_bias_m: self.x[k],
+			clock_drift: self.x[k+1] / C,
+			h_accuracy_m: (cov_enu[(0,0)] + cov_enu[(1,1)]).sqrt(),
+			v_accuracy_m: cov_enu[(2,2)].sqrt(),
+			pseudoranges_used, dopplers_used, rejected_sv_ids })
+	}
+
+	// Starts the filter from a snapshot least squares fix, with loose uncertainty on the states that fix doesn't provide
+	fn initialize(&mut self, obs_this_soln:&[Observation], current_rx_time:f64, opt_iono:Option<ionosphere::Model>) -> Result<(usize, usize, Vec<usize>), &'static str> {
+		let (fix, x):(GnssFix, Vector4<f64>) = super::solve_position_and_time(obs_this_soln.to_vec(), Vector4::zeros(), current_rx_time, opt_iono, &self.config.solver)?;
+		let k:usize = self.idx_clock();
+
+		self.x.fill(0.0);
+		self.p.fill(0.0);
+		let pos_clock_idx:[usize; 4] = [0, 1, 2, k];
+		for (i, ii) in pos_clock_idx.iter().enumerate() {
+			self.x[*ii] = x[i];
+			for (j, jj) in pos_clock_idx.iter().enumerate() { self.p[(*ii,*jj)] = fix.covariance[i][j]; }
+		}
+
+		let rate_var:f64 = match &fix.velocity {
+			Some(vel) => {
+				self.x[3] = vel.vel_ecef.0;
+				self.x[4] = vel.vel_ecef.1;
+				self.x[5] = vel.vel_ecef.2;
+				self.x[k+1] = vel.clock_drift * C;
+				10.0_f64.powi(2)
+			},
+			None => 100.0_f64.powi(2),
+		};
+		for i in 3..6 { self.p[(i,i)] = rate_var; }
+		self.p[(k+1,k+1)] = rate_var;
+		if self.config.estimate_acceleration { for i in 6..9 { self.p[(i,i)] = 1.0; } }
+
+		let dopplers_used:usize = if fix.velocity.is_some() { fix.observations.len() } else { 0 };
+		let excluded_sv_ids:Vec<usize> = fix.raim.map(|r| r.excluded_sv_ids).unwrap_or_default();
+		Ok((fix.observations.len(), dopplers_used, excluded_sv_ids))
+	}
+
+	fn propagate(&mut self, dt:f64) {
+		if dt <= 0.0 { return; }
+		let n:usize = self.x.len();
+		let k:usize = self.idx_clock();
+		let order:usize = if self.config.estimate_acceleration { 3 } else { 2 };
+
+		let mut phi = DMatrix::identity(n, n);
+		for a in 0..order {
+			for b in (a+1)..order {
+				let coeff:f64 = dt.powi((b - a) as i32) / (1..=(b - a)).product::<usize>() as f64;
+				for i in 0..3 { phi[(3*a+i, 3*b+i)] = coeff; }
+			}
+		}
+		phi[(k,k+1)] = dt;
+
+		// Kinematic process noise is the integrated white noise on the highest-order state, with its horizontal and vertical
+		// parts rotated from ENU into ECEF
+		let (psd_h, psd_v) = self.config.dynamics.psd();
+		let wgs84 = kinematics::ecef_to_wgs84(self.x[0], self.x[1], self.x[2]);
+		let dcm = kinematics::dcm_we(wgs84.latitude, wgs84.longitude);
+		let spatial:Matrix3<f64> = dcm.transpose() * Matrix3::from_diagonal(&Vector3::new(psd_h, psd_h, psd_v)) * dcm;
+
+		let mut q = DMatrix::zeros(n, n);
+		for a in 0..order {
+			for b in 0..order {
+				// Integral of dt^(m-1-a) dt^(m-1-b) / ((m-1-a)! (m-1-b)!) for m states per axis
+				let (pa, pb) = ((order - 1 - a) as i32, (order - 1 - b) as i32);
+				let fact = |p:i32| (1..=p).product::<i32>() as f64;
+				let coeff:f64 = dt.powi(pa + pb + 1) / ((pa + pb + 1) as f64 * fact(pa) * fact(pb));
+				for i in 0..3 { for j in 0..3 { q[(3*a+i, 3*b+j)] = coeff * spatial[(i,j)]; } }
+			}
+		}
+
+		// Two-state clock model from the oscillator's Allan variance coefficients
+		let s_f:f64 = 0.5 * self.config.clock_h0 * C * C;
+		let s_g:f64 = 2.0 * std::f64::consts::PI.powi(2) * self.config.clock_h_2 * C * C;
+		q[(k,k)] = s_f*dt + s_g*dt.powi(3)/3.0;
+		q[(k,k+1)] = s_g*dt.powi(2)/2.0;
+		q[(k+1,k)] = q[(k,k+1)];
+		q[(k+1,k+1)] = s_g*dt;
+
+		self.x = &phi * &self.x;
+		self.p = &phi * &self.p * phi.transpose() + q;
+	}
+
+	// Sequential scalar updates, relinearizing about the latest state for each measurement
+	fn measurement_update(&mut self, obs_this_soln:&[Observation], opt_iono:Option<ionosphere::Model>) -> (usize, usize, Vec<usize>) {
+		let n:usize = self.x.len();
+		let k:usize = self.idx_clock();
+		let solver = self.config.solver;
+		let (mut pseudoranges_used, mut dopplers_used, mut rejected_sv_ids) = (0, 0, vec![]);
+
+		for obs in obs_this_soln {
+			let x4 = Vector4::new(self.x[0], self.x[1], self.x[2], self.x[k]);
+			let completed = obs.complete(x4, opt_iono, &solver);
+			if completed.el_radians < solver.elevation_mask_radians { continue; }
+			let mut accepted:bool = true;
+
+			let mut h = DVector::zeros(n);
+			for j in 0..3 { h[j] = -completed.p_r_e_norm[j]; }
+			h[k] = 1.0;
+			let r:f64 = solver.weight_model.sigma_m(obs, &completed).powi(2);
+			if self.scalar_update(&h, completed.residual, r) { pseudoranges_used += 1; } else { accepted = false; }
+
+			// Same model as the least squares velocity solution, linearized about the updated state
+			let x4 = Vector4::new(self.x[0], self.x[1], self.x[2], self.x[k]);
+			let los_e = Vector3::from_column_slice(&obs.complete(x4, opt_iono, &solver).p_r_e_norm);
+			let v_sv_e = Vector3::new(obs.sv_vel_ecef.0, obs.sv_vel_ecef.1, obs.sv_vel_ecef.2);
+			let v_rx_e = Vector3::new(self.x[3], self.x[4], self.x[5]);
+			let range_rate:f64 = -obs.doppler_hz * C / L1_FREQ_HZ + obs.sv_clock_drift * C;
+			let predicted:f64 = (v_sv_e - v_rx_e).dot(&los_e) + self.x[k+1];
+
+			let mut h = DVector::zeros(n);
+			for j in 0..3 { h[3+j] = -los_e[j]; }
+			h[k+1] = 1.0;
+			if self.scalar_update(&h, range_rate - predicted, self.config.doppler_sigma_mps.powi(2)) { dopplers_used += 1; } else { accepted = false; }
+
+			if !accepted { rejected_sv_ids.push(obs.sv_id); }
+		}
+
+		(pseudoranges_used, dopplers_used, rejected_sv_ids)
+	}
+
+	// Returns false if the innovation failed the gate, in which case the state is left alone
+	fn scalar_update(&mut self, h:&DVector<f64>, innovation:f64, r:f64) -> bool {
+		let ph:DVector<f64> = &self.p * h;
+		let s:f64 = h.dot(&ph) + r;
+		if let Some(gate_sigma) = self.config.gate_sigma {
+			if innovation.powi(2) > gate_sigma.powi(2) * s { return false; }
+		}
+
+		let gain:DVector<f64> = ph / s;
+		self.x += &gain * innovation;
+
+		// Joseph form keeps the covariance symmetric and positive definite
+		let n:usize = self.x.len();
+		let i_kh = DMatrix::identity(n, n) - &gain * h.transpose();
+		self.p = &i_kh * &self.p * i_kh.transpose() + &gain * gain.transpose() * r;
+		true
+	}
+
+}
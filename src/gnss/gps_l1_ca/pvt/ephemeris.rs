@@ -5,6 +5,8 @@ use std::f64::consts;
 
 use self::serde::{Serialize, Deserialize};
 
+use crate::gnss::gps_l1_ca::telemetry_decode::subframe::{Subframe, SubframeBody, subframe4, subframe5};
+
 pub const MU:f64 = 3.986005e14;              // [m^3/s^2] WGS-84 value of the earth's gravitational constant
 pub const F:f64 = -4.442807633e-10;			 // [sec/root-meter]
 
@@ -12,6 +14,16 @@ pub const F:f64 = -4.442807633e-10;			 // [sec/root-meter]
 // acceleration, so I changed the name to match the more common convention
 pub const OMEGA_E:f64 = 7.2921151467e-5;     // [rad/s] WGS-84 value of the earth's rotation rate
 
+/// SV position, velocity and clock at the time of transmission, in the ECEF frame at that time
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct SvState {
+	pub t_gps: f64,						// [sec] GPS system time of transmission
+	pub pos_ecef: (f64, f64, f64),		// [m]
+	pub vel_ecef: (f64, f64, f64),		// [m/s]
+	pub clock: f64,						// [sec] SV clock offset, including the relativistic correction
+	pub clock_drift: f64,				// [sec/sec]
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
 pub struct Ephemeris {
 	pub week_number:u16, pub t_gd:f64,	  pub aodo: u8,    pub fit_interval:bool,
@@ -23,16 +35,70 @@ pub struct Ephemeris {
 	pub iodc: u16,
 }
 
+/// Reduced-precision orbit and clock from subframes 4 and 5, in the same units as the ephemeris.  It's good to a few kilometers
+/// for weeks after t_oa, which is plenty for planning and acquisition but not for positioning.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct Almanac {
+	pub prn: usize,      pub week_number: u16, pub sv_health: u8,
+	pub t_oa: f64,       pub e: f64,      pub delta_i: f64, pub omega_dot: f64,
+	pub sqrt_a: f64,     pub omega0: f64, pub omega: f64,   pub m0: f64,
+	pub af0: f64,        pub af1: f64,
+}
+
+impl Almanac {
+
+	/// Almanac from a subframe 4 or 5 page that carries one.  The week number is broadcast separately, in subframe 5 page 25.
+	pub fn from_subframe(sf:&Subframe, week_number:u16) -> Option<Self> {
+		let (sv_id, page) = match sf.body {
+			SubframeBody::Subframe4(body) => match body.page {
+				subframe4::Page::AlmanacData{e, t_oa, delta_i, omega_dot, sv_health, sqrt_a, omega0, omega, m0, af0, af1} =>
+					(body.sv_id, (e, t_oa, delta_i, omega_dot, sv_health, sqrt_a, omega0, omega, m0, af0, af1)),
+				_ => return None,
+			},
+			SubframeBody::Subframe5(body) => match body.page {
+				subframe5::Page::AlmanacData{e, t_oa, delta_i, omega_dot, sv_health, sqrt_a, omega0, omega, m0, af0, af1} =>
+					(body.sv_id, (e, t_oa, delta_i, omega_dot, sv_health, sqrt_a, omega0, omega, m0, af0, af1)),
+				_ => return None,
+			},
+			_ => return None,
+		};
+		let (e, t_oa, delta_i, omega_dot, sv_health, sqrt_a, omega0, omega, m0, af0, af1) = page;
+		Some(Self{ prn: sv_id as usize, week_number, sv_health, t_oa: t_oa as f64, e, delta_i, omega_dot, sqrt_a, omega0, omega, m0, af0, af1 })
+	}
+
+	/// The almanac inclination is relative to 0.30 semicircles, and everything it doesn't carry is zero
+	pub fn to_ephemeris(&self) -> Ephemeris {
+		Ephemeris{ week_number: self.week_number, t_gd: 0.0, aodo: 0, fit_interval: false,
+			t_oc: self.t_oa, a_f0: self.af0, a_f1: self.af1, a_f2: 0.0,
+			t_oe: self.t_oa, sqrt_a: self.sqrt_a, dn: 0.0, m0: self.m0,
+			e: self.e, omega: self.omega, omega0: self.omega0, omega_dot: self.omega_dot,
+			cus: 0.0, cuc: 0.0, crs: 0.0, crc: 0.0,
+			cis: 0.0, cic: 0.0, i0: 0.30 + self.delta_i, idot: 0.0,
+			iodc: 0 }
+	}
+
+}
+
 impl Ephemeris {
 
 	// Correction factor between the SV clock and GPS system time
 	pub fn dt_sv(&self, t:f64) -> f64 { self.a_f0 + self.a_f1*(t - self.t_oc) + self.a_f2*(t - self.t_oc).powi(2) }
 
+	/// SV state for a signal transmitted at SV time t_sv.  The orbit and clock equations are evaluated at GPS system time,
+	/// which is found from t_sv by iterating on the clock correction as described in IS-GPS-200K, 20.3.3.3.3.1.  The
+	/// relativistic term depends on the eccentric anomaly at that time, so it's included by evaluating the orbit there.
+	pub fn sv_state(&self, t_sv:f64) -> SvState {
+		let mut t_gps:f64 = t_sv;
+		for _ in 0..2 { t_gps = t_sv - self.dt_sv(t_gps); }
+
+		let (pos_ecef, clock) = self.pos_and_clock(t_gps);
+		let (vel_ecef, clock_drift) = self.vel_and_clock_drift(t_gps);
+		SvState{ t_gps, pos_ecef, vel_ecef, clock, clock_drift }
+	}
+
 	pub fn pos_and_clock(&self, t:f64) -> ((f64, f64, f64), f64) {
-		// Note: this is the time without the relativistic correction because we need the eccentric anomaly, which
-		// we haven't calculated yet, but this is a good approximation.  Also, we should use t in these equations instead
-		// of t_sv but for this purpose, t_sv is a good approximation to t.  The GPS ICD also mentions this issue and
-		// recommends this approximation.
+		// Note: t should be GPS system time rather than t_sv; sv_state takes care of that conversion, but t_sv is a good
+		// enough approximation for most other purposes.  The GPS ICD also mentions this issue and recommends this approximation.
 
 		// Note: Code commented above each line is Python code used to rapid-prototype this algorithm
 
@@ -111,8 +177,63 @@ impl Ephemeris {
 	    // Relativistic correction to transmission time
 		let dt_r:f64 = F * self.e * self.sqrt_a * ek.sin();
 		
-		((x_k, y_k, z_k), self.a_f0 + (self.a_f1 * tk) + (self.a_f2 * tk.powi(2)) + dt_r)
+		((x_k, y_k, z_k), self.dt_sv(t) + dt_r)
+
+	}
+
+	/// SV velocity in ECEF coordinates and the SV clock drift [sec/sec], using the velocity equations that accompany
+	/// the position algorithm in IS-GPS-200K, Table 20-IV
+	pub fn vel_and_clock_drift(&self, t:f64) -> ((f64, f64, f64), f64) {
+		let a:f64 = self.sqrt_a.powi(2);
+		let n:f64 = (MU / a.powi(3)).sqrt() + (self.dn * consts::PI);
+		let tk:f64 = t - self.t_oe;
+		let mk:f64 = (self.m0 * consts::PI) + n*tk;
+
+		let mut ek:f64 = mk;
+		for _ in 0..10 { ek = ek - (ek - self.e*ek.sin() - mk)/(1.0 - self.e*ek.cos()); }
+
+		let nu_k:f64 = ((1.0 - self.e.powi(2)).sqrt() * ek.sin()).atan2(ek.cos() - self.e);
+		let phi_k:f64 = nu_k + (self.omega * consts::PI);
+		let (sin_2phi, cos_2phi) = (2.0*phi_k).sin_cos();
+
+		let u_k:f64 = phi_k + self.cus*sin_2phi + self.cuc*cos_2phi;
+		let r_k:f64 = a*(1.0 - self.e*ek.cos()) + self.crs*sin_2phi + self.crc*cos_2phi;
+		let i_k:f64 = (self.i0 * consts::PI) + self.cis*sin_2phi + self.cic*cos_2phi + (self.idot * consts::PI)*tk;
+		let omega_k:f64 = (self.omega0 * consts::PI) + ((self.omega_dot * consts::PI) - OMEGA_E)*tk - OMEGA_E*self.t_oe;
+
+		// Time derivatives of the quantities above
+		let ek_dot:f64 = n / (1.0 - self.e*ek.cos());
+		let nu_k_dot:f64 = ek_dot * (1.0 - self.e.powi(2)).sqrt() / (1.0 - self.e*ek.cos());
+		let u_k_dot:f64 = nu_k_dot * (1.0 + 2.0*(self.cus*cos_2phi - self.cuc*sin_2phi));
+		let r_k_dot:f64 = self.e*a*ek_dot*ek.sin() + 2.0*nu_k_dot*(self.crs*cos_2phi - self.crc*sin_2phi);
+		let i_k_dot:f64 = (self.idot * consts::PI) + 2.0*nu_k_dot*(self.cis*cos_2phi - self.cic*sin_2phi);
+		let omega_k_dot:f64 = (self.omega_dot * consts::PI) - OMEGA_E;
+
+		// Position and velocity in the orbital plane
+		let x_kp:f64 = r_k * u_k.cos();
+		let y_kp:f64 = r_k * u_k.sin();
+		let x_kp_dot:f64 = r_k_dot*u_k.cos() - r_k*u_k_dot*u_k.sin();
+		let y_kp_dot:f64 = r_k_dot*u_k.sin() + r_k*u_k_dot*u_k.cos();
+
+		let (sin_om, cos_om) = omega_k.sin_cos();
+		let (sin_i, cos_i) = i_k.sin_cos();
 
-	} 
+		let vx:f64 = -x_kp*omega_k_dot*sin_om + x_kp_dot*cos_om - y_kp_dot*sin_om*cos_i - y_kp*(omega_k_dot*cos_om*cos_i - i_k_dot*sin_om*sin_i);
+		let vy:f64 =  x_kp*omega_k_dot*cos_om + x_kp_dot*sin_om + y_kp_dot*cos_om*cos_i - y_kp*(omega_k_dot*sin_om*cos_i + i_k_dot*cos_om*sin_i);
+		let vz:f64 =  y_kp_dot*sin_i + y_kp*i_k_dot*cos_i;
+
+		// Derivative of the clock correction in pos_and_clock, including the relativistic term
+		let dt_r_dot:f64 = F * self.e * self.sqrt_a * ek.cos() * ek_dot;
+
+		((vx, vy, vz), self.a_f1 + 2.0*self.a_f2*(t - self.t_oc) + dt_r_dot)
+	}
+
+}
 
+/// Circular-ish orbit for tests, spread around the sky by varying the right ascension and mean anomaly
+#[cfg(test)]
+pub fn test_ephemeris(omega0:f64, m0:f64) -> Ephemeris {
+	Ephemeris{ week_number: 100, t_gd: 0.0, aodo: 0, fit_interval: false, t_oc: 3600.0, a_f0: 1.0e-5, a_f1: 2.0e-11, a_f2: 1.0e-18,
+		t_oe: 3600.0, sqrt_a: 5153.6, dn: 1.4e-9, m0, e: 0.012, omega: 0.3, omega0, omega_dot: -2.6e-9, cus: 8.0e-6, cuc: -3.0e-6, 
+		crs: 40.0, crc: 200.0, cis: 1.0e-7, cic: -5.0e-8, i0: 0.31, idot: 1.0e-10, iodc: 12 }
 }
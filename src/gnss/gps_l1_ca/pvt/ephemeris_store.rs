@@ -0,0 +1,191 @@
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Serialize, Deserialize};
+
+use super::ephemeris::Ephemeris;
+
+const HALF_WEEK_SEC:f64 = 302400.0;
+
+// Older issues of data are kept around briefly so observations near a cutover can still use the set that was valid at the time
+const MAX_ISSUES_PER_PRN:usize = 3;
+
+// Enough to cover a few days of two-hourly uploads on every SV without growing for as long as the receiver runs
+const MAX_CUTOVERS:usize = 1000;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct StoredEphemeris {
+	pub ephemeris: Ephemeris,
+	pub sv_health: u8,
+	pub ura_index: u8,
+}
+
+impl StoredEphemeris {
+
+	pub fn iode(&self) -> u8 { (self.ephemeris.iodc % 256) as u8 }
+
+	// The six-bit health word from subframe 1 is zero when all navigation data and signals are healthy
+	pub fn is_healthy(&self) -> bool { self.sv_health == 0 }
+
+	/// Nominal user range accuracy [m] for the URA index (IS-GPS-200K, 20.3.3.3.1.3), or None if the SV doesn't provide one
+	pub fn ura_m(&self) -> Option<f64> {
+		match self.ura_index {
+			0 => Some(2.4), 1 => Some(3.4), 2 => Some(4.85), 3 => Some(6.85), 4 => Some(9.65), 5 => Some(13.65),
+			n @ 6..=14 => Some(24.0 * 2.0_f64.powi(n as i32 - 6)),
+			_ => None,
+		}
+	}
+
+	/// Curve fit interval in hours, based on the fit interval flag and IODC (IS-GPS-200K, Table 20-XII)
+	pub fn fit_interval_hours(&self) -> f64 {
+		if !self.ephemeris.fit_interval { 4.0 } else {
+			match self.ephemeris.iodc {
+				240..=247 => 8.0,
+				248..=255 | 496 => 14.0,
+				497..=503 | 1021..=1023 => 26.0,
+				_ => 6.0,
+			}
+		}
+	}
+
+	/// Seconds from t_oe to the given GPS time of week, accounting for the beginning or end of week crossover
+	pub fn age_sec(&self, tow_sec:f64) -> f64 {
+		let mut tk:f64 = tow_sec - self.ephemeris.t_oe;
+		if tk > HALF_WEEK_SEC { tk -= 2.0*HALF_WEEK_SEC; }
+		if tk < -HALF_WEEK_SEC { tk += 2.0*HALF_WEEK_SEC; }
+		tk
+	}
+
+	// The fit interval is centered on t_oe
+	pub fn is_valid_at(&self, tow_sec:f64) -> bool { self.age_sec(tow_sec).abs() <= self.fit_interval_hours() * 1800.0 }
+
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct Cutover {
+	pub prn: usize,
+	pub old_iode: u8,
+	pub new_iode: u8,
+	pub new_t_oe: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum EphemerisError {
+	NotAvailable{ prn:usize },
+	Unhealthy{ prn:usize, iode:u8, sv_health:u8 },
+	Expired{ prn:usize, iode:u8, t_oe:f64, age_sec:f64, fit_interval_hours:f64 },
+}
+
+/// Holds broadcast ephemerides keyed by PRN and IODE and hands out only the ones that are valid and healthy at a given time
+#[derive(Debug, Default, Clone)]
+pub struct EphemerisStore {
+	entries: HashMap<usize, BTreeMap<u8, StoredEphemeris>>,
+	current: HashMap<usize, u8>,
+	cutovers: Vec<Cutover>,
+}
+
+impl EphemerisStore {
+
+	pub fn new() -> Self { Self::default() }
+
+	/// Adds a newly decoded ephemeris.  Returns the cutover if this ephemeris has a different IODE than the current one for this PRN.
+	pub fn insert(&mut self, prn:usize, ephemeris:Ephemeris, sv_health:u8, ura_index:u8) -> Option<Cutover> {
+		let new = StoredEphemeris{ ephemeris, sv_health, ura_index };
+		let new_iode:u8 = new.iode();
+
+		let opt_cutover = match self.current.insert(prn, new_iode) {
+			Some(old_iode) if old_iode != new_iode => Some(Cutover{ prn, old_iode, new_iode, new_t_oe: ephemeris.t_oe }),
+			_ => None,
+		};
+
+		let issues = self.entries.entry(prn).or_default();
+		issues.insert(new_iode, new);
+
+		// Drop the issues that are furthest in the past relative to the new one, which is current and so always kept
+		while issues.len() > MAX_ISSUES_PER_PRN {
+			let opt_oldest:Option<u8> = issues.iter().filter(|(iode, _)| **iode != new_iode)
+				.max_by(|a, b| a.1.age_sec(ephemeris.t_oe).partial_cmp(&b.1.age_sec(ephemeris.t_oe)).unwrap_or(std::cmp::Ordering::Equal))
+				.map(|(iode, _)| *iode);
+			match opt_oldest {
+				Some(oldest) => { issues.remove(&oldest); },
+				None => break,
+			}
+		}
+
+		if let Some(cutover) = opt_cutover {
+			if self.cutovers.len() >= MAX_CUTOVERS { self.cutovers.remove(0); }
+			self.cutovers.push(cutover);
+		}
+		opt_cutover
+	}
+
+	/// Most recently received ephemeris for this PRN, whether or not it's currently usable
+	pub fn current(&self, prn:usize) -> Option<StoredEphemeris> {
+		let iode:u8 = *self.current.get(&prn)?;
+		self.entries.get(&prn)?.get(&iode).cloned()
+	}
+
+	pub fn get_by_iode(&self, prn:usize, iode:u8) -> Option<StoredEphemeris> {
+		self.entries.get(&prn)?.get(&iode).cloned()
+	}
+
+	/// Returns the healthy ephemeris whose fit interval covers the given time of week with t_oe closest to it, or the reason there isn't one.
+	/// An SV whose current issue is unhealthy is unusable, even if an older issue still says otherwise.
+	pub fn get(&self, prn:usize, tow_sec:f64) -> Result<StoredEphemeris, EphemerisError> {
+		let current:StoredEphemeris = self.current(prn).ok_or(EphemerisError::NotAvailable{ prn })?;
+		if !current.is_healthy() { return Err(EphemerisError::Unhealthy{ prn, iode: current.iode(), sv_health: current.sv_health }); }
+		let issues = self.entries.get(&prn).ok_or(EphemerisError::NotAvailable{ prn })?;
+
+		let opt_best = issues.values()
+			.filter(|eph| eph.is_healthy() && eph.is_valid_at(tow_sec))
+			.min_by(|a, b| a.age_sec(tow_sec).abs().partial_cmp(&b.age_sec(tow_sec).abs()).unwrap_or(std::cmp::Ordering::Equal));
+
+		match opt_best {
+			Some(eph) => Ok(*eph),
+			None => Err(EphemerisError::Expired{ prn, iode: current.iode(), t_oe: current.ephemeris.t_oe,
+				age_sec: current.age_sec(tow_sec), fit_interval_hours: current.fit_interval_hours() }),
+		}
+	}
+
+	/// The most recent cutovers seen by this store, up to a fixed number of them, oldest first
+	pub fn cutovers(&self) -> &[Cutover] { &self.cutovers }
+
+}
+
+#[test]
+fn test_ephemeris_store() {
+	let eph = |iodc:u16, t_oe:f64| Ephemeris{ week_number: 100, t_gd: 0.0, aodo: 0, fit_interval: false, t_oc: t_oe, a_f0: 0.0, a_f1: 0.0,
+		a_f2: 0.0, t_oe, sqrt_a: 5153.0, dn: 0.0, m0: 0.0, e: 0.01, omega: 0.0, omega0: 0.0, omega_dot: 0.0, cus: 0.0, cuc: 0.0, crs: 0.0,
+		crc: 0.0, cis: 0.0, cic: 0.0, i0: 0.3, idot: 0.0, iodc };
+
+	let mut store = EphemerisStore::new();
+	assert!(matches!(store.get(5, 0.0), Err(EphemerisError::NotAvailable{ prn: 5 })));
+
+	// Four hour fit interval, so valid for two hours on either side of t_oe, including across the end of the week
+	assert!(store.insert(5, eph(10, 600.0), 0, 0).is_none());
+	assert_eq!(store.get(5, 7800.0).unwrap().ephemeris.iodc, 10);
+	assert_eq!(store.get(5, 604000.0).unwrap().ephemeris.iodc, 10);
+	assert!(matches!(store.get(5, 7900.0), Err(EphemerisError::Expired{ iode: 10, .. })));
+
+	// The old issue stays available for times closer to its own t_oe
+	let cutover = store.insert(5, eph(11, 7800.0), 0, 0).unwrap();
+	assert_eq!((cutover.old_iode, cutover.new_iode), (10, 11));
+	assert_eq!(store.get(5, 1000.0).unwrap().ephemeris.iodc, 10);
+	assert_eq!(store.get(5, 7000.0).unwrap().ephemeris.iodc, 11);
+	assert_eq!(store.cutovers().len(), 1);
+
+	// An issue with an earlier t_oe than the ones already stored still becomes current and pushes out another one
+	store.insert(5, eph(12, 15000.0), 0, 0);
+	store.insert(5, eph(13, 0.0), 0, 0);
+	assert_eq!(store.current(5).unwrap().ephemeris.iodc, 13);
+	assert!(store.get_by_iode(5, 10).is_none());
+	assert_eq!(store.cutovers().len(), 3);
+
+	store.insert(7, eph(3, 600.0), 0b100000, 0);
+	assert!(matches!(store.get(7, 600.0), Err(EphemerisError::Unhealthy{ prn: 7, iode: 3, sv_health: 32 })));
+
+	// Once the current issue is set unhealthy, an older healthy issue that would otherwise still cover the time isn't used
+	store.insert(9, eph(20, 600.0), 0, 0);
+	store.insert(9, eph(21, 1200.0), 0b100000, 0);
+	assert!(matches!(store.get(9, 600.0), Err(EphemerisError::Unhealthy{ prn: 9, iode: 21, .. })));
+}
@@ -0,0 +1,369 @@
+
+use std::io::{BufRead, BufReader, Read};
+
+use serde::{Serialize, Deserialize};
+use nalgebra::base::{DMatrix, DVector, Matrix3, Vector3};
+
+use crate::utils::kinematics;
+
+use super::GnssFix;
+
+const MU:f64 = 3.986004418e14;				// [m^3/s^2] WGS-84 earth's gravitational constant
+const J2:f64 = 1.082627e-3;					// WGS-84 second zonal harmonic
+const N_STATES:usize = 15;
+
+// Offsets of the error states: position, velocity, attitude, accelerometer bias and gyro bias, three each, all in ECEF
+const POS:usize = 0;
+const VEL:usize = 3;
+const ATT:usize = 6;
+const BA:usize = 9;
+const BG:usize = 12;
+
+/// One accelerometer and gyro sample in the forward-right-down body frame
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct ImuSample {
+	pub t_sec: f64,
+	pub accel_mps2: (f64, f64, f64),		// [m/s^2] specific force
+	pub gyro_radps: (f64, f64, f64),		// [rad/s] angular rate relative to inertial space
+}
+
+/// Reads IMU samples from CSV with one sample per line as t, ax, ay, az, gx, gy, gz.  Blank lines, lines starting with '#'
+/// and a header line before the first sample are skipped.
+pub fn read_imu_csv<R: Read>(reader:R) -> Result<Vec<ImuSample>, &'static str> {
+	let mut samples:Vec<ImuSample> = vec![];
+	for line in BufReader::new(reader).lines() {
+		let line = line.map_err(|_| "Unable to read IMU CSV")?;
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') { continue; }
+
+		let fields:Result<Vec<f64>, _> = line.split(',').map(|s| s.trim().parse::<f64>()).collect();
+		match fields {
+			Ok(v) if v.len() == 7 => samples.push(ImuSample{ t_sec: v[0], accel_mps2: (v[1], v[2], v[3]), gyro_radps: (v[4], v[5], v[6]) }),
+			Ok(_) => return Err("IMU CSV lines should have seven fields"),
+			Err(_) if samples.is_empty() => continue,
+			Err(_) => return Err("Unable to parse IMU CSV"),
+		}
+	}
+	Ok(samples)
+}
+
+/// Reads a JSON array of `ImuSample`
+pub fn read_imu_json<R: Read>(reader:R) -> Result<Vec<ImuSample>, &'static str> {
+	serde_json::from_reader(reader).map_err(|_| "Unable to parse IMU JSON")
+}
+
+/// Whether the samples look like the platform was still, i.e. neither sensor varies by more than the configured amount on any
+/// axis.  At least two samples are needed to tell.
+pub fn is_stationary(samples:&[ImuSample], config:&InsConfig) -> bool {
+	if samples.len() < 2 { return false; }
+	let n:f64 = samples.len() as f64;
+	let sigma = |x:&dyn Fn(&ImuSample) -> Vector3<f64>| {
+		let mean:Vector3<f64> = samples.iter().map(x).fold(Vector3::zeros(), |a, b| a + b) / n;
+		let var:Vector3<f64> = samples.iter().map(|s| (x(s) - mean).component_mul(&(x(s) - mean))).fold(Vector3::zeros(), |a, b| a + b) / (n - 1.0);
+		var.map(|v| v.sqrt()).max()
+	};
+	sigma(&|s| Vector3::new(s.accel_mps2.0, s.accel_mps2.1, s.accel_mps2.2)) <= config.max_level_accel_sigma_mps2 &&
+		sigma(&|s| Vector3::new(s.gyro_radps.0, s.gyro_radps.1, s.gyro_radps.2)) <= config.max_level_gyro_sigma_radps
+}
+
+/// Roll and pitch from the mean specific force over samples taken while the platform is still.  Heading isn't observable this
+/// way with anything short of a navigation-grade gyro.
+pub fn level(samples:&[ImuSample]) -> Option<(f64, f64)> {
+	if samples.is_empty() { return None; }
+	let n:f64 = samples.len() as f64;
+	let f:Vector3<f64> = samples.iter().map(|s| Vector3::new(s.accel_mps2.0, s.accel_mps2.1, s.accel_mps2.2)).fold(Vector3::zeros(), |a, b| a + b) / n;
+	Some(((-f[1]).atan2(-f[2]), f[0].atan2((f[1]*f[1] + f[2]*f[2]).sqrt())))
+}
+
+/// Gravity in ECEF [m/s^2], gravitation to J2 plus the centrifugal term, so a specific force of minus this holds a point
+/// still on the rotating earth
+pub fn gravity_ecef(p:&Vector3<f64>) -> Vector3<f64> {
+	let r:f64 = p.norm();
+	let k:f64 = 1.5 * J2 * (kinematics::WGS84_SEMI_MAJOR_AXIS_METERS / r).powi(2);
+	let z_sq:f64 = (p[2] / r).powi(2);
+	let w_sq:f64 = kinematics::OMEGA_E.powi(2);
+	let gm:f64 = -MU / r.powi(3);
+	Vector3::new(gm * (1.0 + k*(1.0 - 5.0*z_sq)) * p[0] + w_sq * p[0],
+		         gm * (1.0 + k*(1.0 - 5.0*z_sq)) * p[1] + w_sq * p[1],
+		         gm * (1.0 + k*(3.0 - 5.0*z_sq)) * p[2])
+}
+
+fn skew(v:&Vector3<f64>) -> Matrix3<f64> {
+	Matrix3::new( 0.0,  -v[2],  v[1],
+		          v[2],  0.0,  -v[0],
+		         -v[1],  v[0],  0.0)
+}
+
+// Rotation matrix for a rotation vector
+fn rotation(theta:&Vector3<f64>) -> Matrix3<f64> {
+	let angle:f64 = theta.norm();
+	let k = skew(theta);
+	if angle < 1.0e-12 { return Matrix3::identity() + k; }
+	Matrix3::identity() + k * (angle.sin() / angle) + k * k * ((1.0 - angle.cos()) / angle.powi(2))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct InsConfig {
+	pub accel_noise_psd: f64,				// [(m/s^2)^2/Hz] velocity random walk
+	pub gyro_noise_psd: f64,				// [(rad/s)^2/Hz] angle random walk
+	pub accel_bias_psd: f64,				// [(m/s^3)^2/Hz] accelerometer bias random walk
+	pub gyro_bias_psd: f64,					// [(rad/s^2)^2/Hz] gyro bias random walk
+	pub initial_tilt_sigma_radians: f64,	// Roll and pitch uncertainty after leveling
+	pub initial_heading_sigma_radians: f64,
+	pub initial_accel_bias_sigma_mps2: f64,
+	pub initial_gyro_bias_sigma_radps: f64,
+	pub velocity_sigma_mps: f64,			// GNSS velocity error, since fixes don't carry a velocity covariance
+	pub gate_sigma: Option<f64>,			// Fixes whose normalized innovation is larger than this many sigma are rejected
+	pub min_alignment_speed_mps: f64,		// Speed at which the GNSS track gives the initial heading
+	pub max_level_accel_sigma_mps2: f64,	// Leveling waits for a second of IMU data with less spread than these on every axis
+	pub max_level_gyro_sigma_radps: f64,
+}
+
+impl Default for InsConfig {
+	// Roughly an automotive MEMS unit
+	fn default() -> Self {
+		Self{ accel_noise_psd: 1.0e-4, gyro_noise_psd: 1.0e-8, accel_bias_psd: 1.0e-7, gyro_bias_psd: 1.0e-11,
+			initial_tilt_sigma_radians: 2.0_f64.to_radians(), initial_heading_sigma_radians: 5.0_f64.to_radians(),
+			initial_accel_bias_sigma_mps2: 0.1, initial_gyro_bias_sigma_radps: 1.0e-3, velocity_sigma_mps: 0.5,
+			gate_sigma: Some(5.0), min_alignment_speed_mps: 3.0, max_level_accel_sigma_mps2: 0.1, max_level_gyro_sigma_radps: 0.5_f64.to_radians() }
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InsSolution {
+	pub t_sec: f64,
+	pub pos_ecef: (f64, f64, f64),
+	pub vel_ecef: (f64, f64, f64),
+	pub vel_ned: (f64, f64, f64),
+	pub roll_radians: f64,
+	pub pitch_radians: f64,
+	pub yaw_radians: f64,					// [rad] clockwise from true north in [0, 2*pi)
+	pub accel_bias_mps2: (f64, f64, f64),
+	pub gyro_bias_radps: (f64, f64, f64),
+	pub h_accuracy_m: f64,					// [m] 1-sigma
+	pub v_accuracy_m: f64,					// [m] 1-sigma
+	pub sec_since_fix: f64,					// Time since the last accepted GNSS update
+}
+
+/// Strapdown inertial navigation in ECEF, loosely coupled to GNSS through an error-state Kalman filter.  The filter carries
+/// errors in position, velocity, attitude and both sensor biases; each accepted fix is folded back into the whole-state
+/// solution and the errors start over at zero.  Between fixes, and through outages, the solution is the IMU's alone.
+pub struct InsFilter {
+	pub config: InsConfig,
+	pos: Vector3<f64>,
+	vel: Vector3<f64>,
+	c_be: Matrix3<f64>,				// Body to ECEF
+	accel_bias: Vector3<f64>,
+	gyro_bias: Vector3<f64>,
+	p: DMatrix<f64>,
+	opt_t: Option<f64>,				// IMU time of the state, or None before initialization
+	t_fix: f64,
+}
+
+impl InsFilter {
+
+	pub fn new(config:InsConfig) -> Self {
+		Self{ config, pos: Vector3::zeros(), vel: Vector3::zeros(), c_be: Matrix3::identity(), accel_bias: Vector3::zeros(),
+			gyro_bias: Vector3::zeros(), p: DMatrix::zeros(N_STATES, N_STATES), opt_t: None, t_fix: 0.0 }
+	}
+
+	pub fn is_initialized(&self) -> bool { self.opt_t.is_some() }
+
+	pub fn reset(&mut self) { self.opt_t = None; }
+
+	/// Starts the filter at a fix, with the attitude from leveling and a heading from wherever the caller has one
+	pub fn initialize(&mut self, fix:&GnssFix, t_sec:f64, roll:f64, pitch:f64, yaw:f64) {
+		self.pos = Vector3::new(fix.pos_ecef.0, fix.pos_ecef.1, fix.pos_ecef.2);
+		self.vel = fix.velocity.map(|v| Vector3::new(v.vel_ecef.0, v.vel_ecef.1, v.vel_ecef.2)).unwrap_or_else(Vector3::zeros);
+		let wgs84 = kinematics::ecef_to_wgs84(self.pos[0], self.pos[1], self.pos[2]);
+		let c_ne = kinematics::dcm_ne(wgs84.latitude, wgs84.longitude);
+		self.c_be = c_ne.transpose() * kinematics::dcm_from_euler(roll, pitch, yaw);
+		self.accel_bias = Vector3::zeros();
+		self.gyro_bias = Vector3::zeros();
+
+		self.p.fill(0.0);
+		for i in 0..3 { for j in 0..3 { self.p[(POS+i, POS+j)] = fix.covariance[i][j]; } }
+		let vel_var:f64 = if fix.velocity.is_some() { self.config.velocity_sigma_mps.powi(2) } else { 10.0_f64.powi(2) };
+		for i in 0..3 {
+			self.p[(VEL+i, VEL+i)] = vel_var;
+			self.p[(BA+i, BA+i)] = self.config.initial_accel_bias_sigma_mps2.powi(2);
+			self.p[(BG+i, BG+i)] = self.config.initial_gyro_bias_sigma_radps.powi(2);
+		}
+		// Attitude uncertainty is set up in NED, where tilt and heading separate, then rotated into ECEF
+		let (tilt, heading) = (self.config.initial_tilt_sigma_radians.powi(2), self.config.initial_heading_sigma_radians.powi(2));
+		let p_att:Matrix3<f64> = c_ne.transpose() * Matrix3::from_diagonal(&Vector3::new(tilt, tilt, heading)) * c_ne;
+		for i in 0..3 { for j in 0..3 { self.p[(ATT+i, ATT+j)] = p_att[(i,j)]; } }
+
+		self.opt_t = Some(t_sec);
+		self.t_fix = t_sec;
+	}
+
+	/// Mechanizes one IMU sample, taking the sample's rates as constant since the previous one
+	pub fn propagate(&mut self, imu:&ImuSample) -> Result<InsSolution, &'static str> {
+		let t:f64 = self.opt_t.ok_or("INS isn't initialized")?;
+		let dt:f64 = imu.t_sec - t;
+		if dt > 0.0 {
+			let f_b = Vector3::new(imu.accel_mps2.0, imu.accel_mps2.1, imu.accel_mps2.2) - self.accel_bias;
+			let w_b = Vector3::new(imu.gyro_radps.0, imu.gyro_radps.1, imu.gyro_radps.2) - self.gyro_bias;
+			let w_ie = Vector3::new(0.0, 0.0, kinematics::OMEGA_E);
+
+			// Attitude turns with the body relative to inertial space, less the earth's rotation under it
+			let c_be_next:Matrix3<f64> = rotation(&(-w_ie * dt)) * self.c_be * rotation(&(w_b * dt));
+			let f_e:Vector3<f64> = 0.5 * (self.c_be + c_be_next) * f_b;
+			let vel_next:Vector3<f64> = self.vel + (f_e + gravity_ecef(&self.pos) - 2.0 * w_ie.cross(&self.vel)) * dt;
+			self.pos += 0.5 * (self.vel + vel_next) * dt;
+			self.vel = vel_next;
+			self.c_be = c_be_next;
+
+			self.propagate_covariance(&f_e, dt);
+			self.opt_t = Some(imu.t_sec);
+		}
+
+		if !(self.pos.iter().chain(self.vel.iter()).all(|a| a.is_finite())) {
+			self.reset();
+			return Err("INS state is infinite");
+		}
+		Ok(self.solution())
+	}
+
+	fn propagate_covariance(&mut self, f_e:&Vector3<f64>, dt:f64) {
+		let omega_ie = skew(&Vector3::new(0.0, 0.0, kinematics::OMEGA_E));
+		let r:f64 = self.pos.norm();
+		let u:Vector3<f64> = self.pos / r;
+		let gravity_gradient:Matrix3<f64> = -(MU / r.powi(3)) * (Matrix3::identity() - 3.0 * u * u.transpose());
+
+		let mut f = DMatrix::zeros(N_STATES, N_STATES);
+		let mut set = |row:usize, col:usize, m:&Matrix3<f64>| { for i in 0..3 { for j in 0..3 { f[(row+i, col+j)] = m[(i,j)]; } } };
+		set(POS, VEL, &Matrix3::identity());
+		set(VEL, POS, &gravity_gradient);
+		set(VEL, VEL, &(-2.0 * omega_ie));
+		set(VEL, ATT, &(-skew(f_e)));
+		set(VEL, BA, &(-self.c_be));
+		set(ATT, ATT, &(-omega_ie));
+		set(ATT, BG, &(-self.c_be));
+
+		let phi = DMatrix::identity(N_STATES, N_STATES) + f * dt;
+		let mut q = DMatrix::zeros(N_STATES, N_STATES);
+		for i in 0..3 {
+			q[(VEL+i, VEL+i)] = self.config.accel_noise_psd * dt;
+			q[(ATT+i, ATT+i)] = self.config.gyro_noise_psd * dt;
+			q[(BA+i, BA+i)] = self.config.accel_bias_psd * dt;
+			q[(BG+i, BG+i)] = self.config.gyro_bias_psd * dt;
+		}
+		self.p = &phi * &self.p * phi.transpose() + q;
+	}
+
+	/// Corrects the solution with a fix's position and, if it has one, velocity.  The fix time is on the IMU's clock and
+	/// should be within a sample of the state; the difference is taken up by the velocity.  Returns false if the fix failed the
+	/// gate, in which case the state is left alone.
+	pub fn update(&mut self, fix:&GnssFix, t_sec:f64) -> Result<bool, &'static str> {
+		let t:f64 = self.opt_t.ok_or("INS isn't initialized")?;
+		let m:usize = if fix.velocity.is_some() { 6 } else { 3 };
+
+		let p_predicted:Vector3<f64> = self.pos + self.vel * (t_sec - t);
+		let mut z = DVector::zeros(m);
+		let mut h = DMatrix::zeros(m, N_STATES);
+		let mut r = DMatrix::zeros(m, m);
+		for i in 0..3 {
+			z[i] = [fix.pos_ecef.0, fix.pos_ecef.1, fix.pos_ecef.2][i] - p_predicted[i];
+			h[(i, POS+i)] = 1.0;
+			for j in 0..3 { r[(i,j)] = fix.covariance[i][j]; }
+		}
+		if let Some(v) = fix.velocity {
+			for i in 0..3 {
+				z[3+i] = [v.vel_ecef.0, v.vel_ecef.1, v.vel_ecef.2][i] - self.vel[i];
+				h[(3+i, VEL+i)] = 1.0;
+				r[(3+i, 3+i)] = self.config.velocity_sigma_mps.powi(2);
+			}
+		}
+
+		let s:DMatrix<f64> = &h * &self.p * h.transpose() + &r;
+		let s_inv:DMatrix<f64> = s.try_inverse().ok_or("Innovation covariance is singular")?;
+		if let Some(gate_sigma) = self.config.gate_sigma {
+			let nis:f64 = (z.transpose() * &s_inv * &z)[(0,0)];
+			if nis > gate_sigma.powi(2) * (m as f64) { return Ok(false); }
+		}
+
+		let k:DMatrix<f64> = &self.p * h.transpose() * s_inv;
+		let dx:DVector<f64> = &k * z;
+
+		// Joseph form keeps the covariance symmetric and positive definite
+		let i_kh = DMatrix::identity(N_STATES, N_STATES) - &k * &h;
+		self.p = &i_kh * &self.p * i_kh.transpose() + &k * r * k.transpose();
+
+		// Fold the estimated errors into the solution; the error states are zero again afterwards
+		let block = |i:usize| Vector3::new(dx[i], dx[i+1], dx[i+2]);
+		self.pos += block(POS);
+		self.vel += block(VEL);
+		self.c_be = rotation(&block(ATT)) * self.c_be;
+		self.accel_bias += block(BA);
+		self.gyro_bias += block(BG);
+		self.t_fix = t_sec;
+		Ok(true)
+	}
+
+	pub fn solution(&self) -> InsSolution {
+		let wgs84 = kinematics::ecef_to_wgs84(self.pos[0], self.pos[1], self.pos[2]);
+		let c_ne = kinematics::dcm_ne(wgs84.latitude, wgs84.longitude);
+		let (roll_radians, pitch_radians, yaw_radians) = kinematics::euler_from_dcm(&(c_ne * self.c_be));
+		let vel_ned:Vector3<f64> = c_ne * self.vel;
+
+		let cov_e = Matrix3::from_fn(|i, j| self.p[(POS+i, POS+j)]);
+		let dcm = kinematics::dcm_we(wgs84.latitude, wgs84.longitude);
+		let cov_enu = dcm * cov_e * dcm.transpose();
+		let t_sec:f64 = self.opt_t.unwrap_or(self.t_fix);
+
+		InsSolution{ t_sec,
+			pos_ecef: (self.pos[0], self.pos[1], self.pos[2]),
+			vel_ecef: (self.vel[0], self.vel[1], self.vel[2]),
+			vel_ned: (vel_ned[0], vel_ned[1], vel_ned[2]),
+			roll_radians, pitch_radians, yaw_radians,
+			accel_bias_mps2: (self.accel_bias[0], self.accel_bias[1], self.accel_bias[2]),
+			gyro_bias_radps: (self.gyro_bias[0], self.gyro_bias[1], self.gyro_bias[2]),
+			h_accuracy_m: (cov_enu[(0,0)] + cov_enu[(1,1)]).sqrt(),
+			v_accuracy_m: cov_enu[(2,2)].sqrt(),
+			sec_since_fix: t_sec - self.t_fix }
+	}
+
+}
+
+/// Post-processes an IMU log against a run of fixes, returning a solution for every IMU sample from alignment on.  Fix times
+/// are the receiver's sample times plus `time_offset_sec` to put them on the IMU's clock.  The filter aligns at the first fix
+/// it can: roll and pitch come from leveling over the latest second of IMU data before a fix where the platform was still,
+/// and heading from `initial_yaw` if given or else the GNSS track once the platform is moving faster than the configured speed.
+pub fn run(imu:&[ImuSample], fixes:&[GnssFix], time_offset_sec:f64, initial_yaw:Option<f64>, config:InsConfig) -> Vec<InsSolution> {
+	let mut filter = InsFilter::new(config);
+	let mut solutions:Vec<InsSolution> = vec![];
+	let mut fixes = fixes.iter().map(|fix| (fix.current_rx_time + time_offset_sec, fix)).peekable();
+	let mut opt_level:Option<(f64, f64)> = None;
+
+	for (idx, sample) in imu.iter().enumerate() {
+		while let Some((t_fix, fix)) = fixes.peek().copied() {
+			if t_fix > sample.t_sec { break; }
+			fixes.next();
+
+			if filter.is_initialized() {
+				let _ = filter.update(fix, t_fix);
+				continue;
+			}
+
+			// Leveling while moving would take the acceleration for tilt, so hold on to the last level taken while still
+			let still:Vec<ImuSample> = imu[..idx].iter().filter(|s| s.t_sec >= t_fix - 1.0).copied().collect();
+			if is_stationary(&still, &config) { opt_level = level(&still); }
+
+			let yaw:Option<f64> = initial_yaw.or_else(|| fix.velocity.filter(|v| v.speed_mps >= config.min_alignment_speed_mps).map(|v| v.heading_radians));
+			if let (Some(yaw), Some((roll, pitch))) = (yaw, opt_level) {
+				filter.initialize(fix, t_fix, roll, pitch, yaw);
+			}
+		}
+
+		if filter.is_initialized() {
+			match filter.propagate(sample) {
+				Ok(soln) => solutions.push(soln),
+				Err(_) => filter.reset(),
+			}
+		}
+	}
+
+	solutions
+}
@@ -0,0 +1,183 @@
+
+use std::collections::HashMap;
+
+use serde::{Serialize, Deserialize};
+use nalgebra::base::Vector3;
+
+use super::{C, L1_FREQ_HZ, GnssFix, Observation};
+use super::ephemeris::{Almanac, Ephemeris};
+
+const HALF_WEEK_SEC:f64 = 302400.0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct IntegrityConfig {
+	pub min_cn0_spread_db: f64,				// C/N0 standard deviation below which the SVs look like they come from one transmitter
+	pub min_svs_for_cn0: usize,
+	pub max_clock_jump_m: f64,				// Change in the receiver clock bias beyond what the clock drift accounts for
+	pub max_tow_jump_sec: f64,				// Change in one SV's time of transmission beyond the change in GPS time
+	pub max_pseudorange_doppler_mps: f64,	// Pseudorange rate against the Doppler, after removing what's common to every SV
+	pub max_almanac_distance_m: f64,
+	pub max_power_rise_db: f64,				// Input power above its baseline
+	pub power_baseline_epochs: usize,
+	pub max_epoch_gap_sec: f64,				// Epoch-to-epoch checks start over after a longer gap
+}
+
+impl Default for IntegrityConfig {
+	fn default() -> Self {
+		Self{ min_cn0_spread_db: 1.0, min_svs_for_cn0: 5, max_clock_jump_m: 300.0, max_tow_jump_sec: 1.0e-3, max_pseudorange_doppler_mps: 50.0,
+			max_almanac_distance_m: 50.0e3, max_power_rise_db: 6.0, power_baseline_epochs: 20, max_epoch_gap_sec: 5.0 }
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum Alert {
+	UniformCn0{ std_dev_db:f64, sv_count:usize },
+	ClockJump{ jump_m:f64 },
+	TowJump{ sv_id:usize, jump_sec:f64 },
+	PseudorangeDopplerMismatch{ sv_id:usize, mismatch_mps:f64 },
+	EphemerisAlmanacMismatch{ sv_id:usize, distance_m:f64 },
+	InputPowerRise{ power_db:f64, baseline_db:f64 },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct IntegrityEvent {
+	pub tow_sec: f64,
+	pub alert: Alert,
+}
+
+#[derive(Debug, Clone)]
+struct Epoch {
+	rx_time: f64,
+	gps_tow_sec: f64,
+	clock_drift: Option<f64>,
+	observations: HashMap<usize, Observation>,
+}
+
+fn wrap_week(dt:f64) -> f64 {
+	if dt > HALF_WEEK_SEC { dt - 2.0*HALF_WEEK_SEC } else if dt < -HALF_WEEK_SEC { dt + 2.0*HALF_WEEK_SEC } else { dt }
+}
+
+fn median(mut v:Vec<f64>) -> f64 {
+	v.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+	let n = v.len();
+	if n % 2 == 1 { v[n/2] } else { 0.5 * (v[n/2 - 1] + v[n/2]) }
+}
+
+/// Checks that the signals are consistent with each other and with what's already known about them.  None of the checks
+/// proves spoofing or interference on its own; they flag conditions that are unusual for a clean sky.
+#[derive(Debug, Clone)]
+pub struct IntegrityMonitor {
+	pub config: IntegrityConfig,
+	almanacs: HashMap<usize, Almanac>,
+	checked_iodc: HashMap<usize, u16>,
+	last: Option<Epoch>,
+	power_baseline_db: Option<f64>,
+	power_count: usize,
+}
+
+impl IntegrityMonitor {
+
+	pub fn new(config:IntegrityConfig) -> Self {
+		Self{ config, almanacs: HashMap::new(), checked_iodc: HashMap::new(), last: None, power_baseline_db: None, power_count: 0 }
+	}
+
+	/// A new almanac means ephemerides already checked against the old one get checked again
+	pub fn set_almanac(&mut self, almanac:Almanac) {
+		self.checked_iodc.remove(&almanac.prn);
+		self.almanacs.insert(almanac.prn, almanac);
+	}
+
+	/// Epoch checks on a fix.  The GPS time is the receiver's estimate of the time of the fix, i.e. with the clock bias removed.
+	pub fn check_fix(&mut self, fix:&GnssFix, gps_tow_sec:f64) -> Vec<IntegrityEvent> {
+		let mut alerts:Vec<Alert> = vec![];
+		let observations:HashMap<usize, Observation> = fix.observations.iter().map(|(obs, _)| (obs.sv_id, *obs)).collect();
+
+		// Real signals arrive at different elevations through different antenna gains, so their C/N0 varies by several dB
+		let n:usize = observations.len();
+		if n >= self.config.min_svs_for_cn0 {
+			let mean:f64 = observations.values().map(|obs| obs.cn0_dbhz).sum::<f64>() / (n as f64);
+			let std_dev_db:f64 = (observations.values().map(|obs| (obs.cn0_dbhz - mean).powi(2)).sum::<f64>() / ((n - 1) as f64)).sqrt();
+			if std_dev_db < self.config.min_cn0_spread_db { alerts.push(Alert::UniformCn0{ std_dev_db, sv_count: n }); }
+		}
+
+		let this = Epoch{ rx_time: fix.current_rx_time, gps_tow_sec, clock_drift: fix.velocity.map(|v| v.clock_drift), observations };
+
+		if let Some(last) = self.last.as_ref() {
+			let dt:f64 = this.rx_time - last.rx_time;
+			if dt > 0.0 && dt <= self.config.max_epoch_gap_sec {
+				// The clock bias is the receiver time minus GPS time, so its rate is the drift
+				let drift:f64 = match (this.clock_drift, last.clock_drift) { (Some(a), Some(b)) => 0.5*(a + b), (Some(a), None) | (None, Some(a)) => a, _ => 0.0 };
+				let dgps_sec:f64 = wrap_week(this.gps_tow_sec - last.gps_tow_sec);
+				let jump_m:f64 = (dgps_sec - dt*(1.0 - drift)) * C;
+				if jump_m.abs() > self.config.max_clock_jump_m { alerts.push(Alert::ClockJump{ jump_m }); }
+
+				let mut common:Vec<usize> = this.observations.keys().copied().filter(|sv_id| last.observations.contains_key(sv_id)).collect();
+				common.sort_unstable();
+
+				for sv_id in common.iter() {
+					let jump_sec:f64 = wrap_week(this.observations[sv_id].sv_tow_sec - last.observations[sv_id].sv_tow_sec) - dgps_sec;
+					if jump_sec.abs() > self.config.max_tow_jump_sec { alerts.push(Alert::TowJump{ sv_id: *sv_id, jump_sec }); }
+				}
+
+				// Code and carrier should agree on the range rate.  Clock steering and the receiver clock drift move every
+				// pseudorange the same way, so compare each SV against the median.
+				if common.len() >= 3 {
+					let mismatches:Vec<(usize, f64)> = common.iter().map(|sv_id| {
+						let (a, b) = (&last.observations[sv_id], &this.observations[sv_id]);
+						let code_rate:f64 = (b.pseudorange_raw_m - a.pseudorange_raw_m) / dt;
+						let carrier_rate:f64 = 0.5 * ((-a.doppler_hz*C/L1_FREQ_HZ + a.sv_clock_drift*C) + (-b.doppler_hz*C/L1_FREQ_HZ + b.sv_clock_drift*C));
+						(*sv_id, code_rate - carrier_rate)
+					}).collect();
+					let common_mps:f64 = median(mismatches.iter().map(|(_, m)| *m).collect());
+					for (sv_id, m) in mismatches {
+						let mismatch_mps:f64 = m - common_mps;
+						if mismatch_mps.abs() > self.config.max_pseudorange_doppler_mps { alerts.push(Alert::PseudorangeDopplerMismatch{ sv_id, mismatch_mps }); }
+					}
+				}
+			}
+		}
+
+		self.last = Some(this);
+		alerts.into_iter().map(|alert| IntegrityEvent{ tow_sec: gps_tow_sec, alert }).collect()
+	}
+
+	/// Compares an ephemeris against the almanac for the same SV, once per IODC.  Returns None if there's no almanac, the
+	/// ephemeris was already checked, or they agree.
+	pub fn check_ephemeris(&mut self, prn:usize, eph:&Ephemeris, tow_sec:f64) -> Option<IntegrityEvent> {
+		let almanac = self.almanacs.get(&prn)?;
+		if self.checked_iodc.get(&prn) == Some(&eph.iodc) { return None; }
+		self.checked_iodc.insert(prn, eph.iodc);
+
+		// Each set of parameters is referenced to its own week, so express the time relative to its reference time
+		let ((xa, ya, za), _) = almanac.to_ephemeris().pos_and_clock(almanac.t_oa + wrap_week(tow_sec - almanac.t_oa));
+		let ((xe, ye, ze), _) = eph.pos_and_clock(eph.t_oe + wrap_week(tow_sec - eph.t_oe));
+		let distance_m:f64 = (Vector3::new(xa, ya, za) - Vector3::new(xe, ye, ze)).norm();
+		if distance_m > self.config.max_almanac_distance_m {
+			Some(IntegrityEvent{ tow_sec, alert: Alert::EphemerisAlmanacMismatch{ sv_id: prn, distance_m } })
+		} else { None }
+	}
+
+	/// Compares the input power [dB] against a baseline averaged over the first epochs and then tracked slowly while nothing
+	/// is flagged.  Without AGC in front of the ADC, a jammer or a spoofer strong enough to take over tracking raises it.
+	pub fn check_input_power(&mut self, tow_sec:f64, power_db:f64) -> Option<IntegrityEvent> {
+		let n:f64 = self.config.power_baseline_epochs.max(1) as f64;
+		match self.power_baseline_db {
+			Some(baseline_db) if self.power_count >= self.config.power_baseline_epochs => {
+				if power_db - baseline_db > self.config.max_power_rise_db {
+					return Some(IntegrityEvent{ tow_sec, alert: Alert::InputPowerRise{ power_db, baseline_db } });
+				}
+				self.power_baseline_db = Some(baseline_db + (power_db - baseline_db) / n);
+			},
+			Some(baseline_db) => {
+				self.power_count += 1;
+				self.power_baseline_db = Some(baseline_db + (power_db - baseline_db) / (self.power_count as f64));
+			},
+			None => {
+				self.power_count = 1;
+				self.power_baseline_db = Some(power_db);
+			},
+		}
+		None
+	}
+
+}
@@ -46,12 +46,12 @@ impl Model {
 
 		let f_iono:f64 = 1.0 + 16.0*(0.53 - el_semicircles).powi(3);			// []
 		let x:f64 = {															// [radians]
-			let per_p:f64 = self.beta0 + self.beta1*phi_m + self.beta2*phi_m.powi(2) + self.beta3.powi(3);
+			let per_p:f64 = self.beta0 + self.beta1*phi_m + self.beta2*phi_m.powi(2) + self.beta3*phi_m.powi(3);
 			let per:f64 = if per_p < 72000.0 { 72000.0 } else { per_p };
 			(2.0 * consts::PI*(t_lcl - 50400.0)) / per
 		};
 		let amp:f64 = {
-			let ans:f64 = self.alpha0 + self.alpha1*phi_m + self.alpha2*phi_m.powi(2) + self.alpha3.powi(3);
+			let ans:f64 = self.alpha0 + self.alpha1*phi_m + self.alpha2*phi_m.powi(2) + self.alpha3*phi_m.powi(3);
 			if ans < 0.0 { 0.0 } else { ans }
 		};
 
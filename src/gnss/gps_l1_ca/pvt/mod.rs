@@ -2,15 +2,33 @@
 use serde::{Serialize, Deserialize};
 use nalgebra::base::{Matrix3, DMatrix, Vector3, Vector4, DVector};
 
-use crate::utils::kinematics;
+use crate::gnss::galileo_e1;
+use crate::utils::{geodesy, geoid, kinematics};
 
 pub const C:f64 = 2.99792458e8;					 // [m/s] speed of light
+pub const L1_FREQ_HZ:f64 = 1.57542e9;			 // [Hz] L1 carrier frequency
+pub const L2_FREQ_HZ:f64 = 1.22760e9;			 // [Hz] L2 carrier frequency
 
 const MAX_ITER:usize = 10;
 const SV_COUNT_THRESHOLD:usize = 5;
 
+pub mod dgps;
+pub mod dual_frequency;
+pub mod ekf;
 pub mod ephemeris;
+pub mod ephemeris_store;
+pub mod ins;
+pub mod integrity;
 pub mod ionosphere;
+pub mod raim;
+pub mod rtk;
+pub mod sbas;
+pub mod timing;
+pub mod troposphere;
+pub mod vector_tracking;
+
+#[cfg(test)]
+mod tests;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GnssFix {
@@ -18,6 +36,97 @@ pub struct GnssFix {
 	pub residual_norm:f64,
 	pub current_rx_time: f64,
 	pub observations:Vec<(Observation, CompletedObservation)>,
+	pub velocity:Option<Velocity>,
+	pub covariance:[[f64; 4]; 4],		// [m^2] ECEF position and clock bias (as a range)
+	pub h_accuracy_m:f64,				// [m] 1-sigma
+	pub v_accuracy_m:f64,				// [m] 1-sigma
+	pub dop:Dop,
+	pub masked_sv_ids:Vec<usize>,		// SVs left out of the solution for being below the elevation mask
+	pub raim:Option<raim::RaimReport>,
+	pub slant_tec_tecu:Vec<(usize, f64)>,	// Per-SV total electron content, only available from dual-frequency solutions
+	pub height_msl_m:f64,				// [m] above the coarse embedded geoid
+	pub enu_error_m:Option<(f64, f64, f64)>,	// [m] east, north and up from the configured reference position
+	#[serde(default)]
+	pub inter_system_bias_m:Option<f64>,		// [m] Galileo clock bias less the GPS one, only estimated when both are in the solution
+}
+
+/// Dilution of precision, from the unweighted geometry of the final solution
+#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+pub struct Dop {
+	pub gdop:f64,
+	pub pdop:f64,
+	pub hdop:f64,
+	pub vdop:f64,
+	pub tdop:f64,
+}
+
+impl Dop {
+
+	pub fn new(h:&DMatrix<f64>, pos_ecef:(f64, f64, f64)) -> Option<Dop> {
+		let q = (h.transpose() * h).try_inverse()?;
+		let q_enu = enu_covariance(&q, pos_ecef);
+		// Only the GPS clock counts toward the time DOPs when there's also an inter-system bias in the solution
+		Some(Dop{ gdop: (0..4).map(|i| q[(i,i)]).sum::<f64>().sqrt(), pdop: (q[(0,0)] + q[(1,1)] + q[(2,2)]).sqrt(), hdop: (q_enu[(0,0)] + q_enu[(1,1)]).sqrt(),
+			vdop: q_enu[(2,2)].sqrt(), tdop: q[(3,3)].sqrt() })
+	}
+
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+pub struct Velocity {
+	pub vel_ecef:(f64, f64, f64),		// [m/s]
+	pub vel_ned:(f64, f64, f64),		// [m/s]
+	pub speed_mps:f64,					// [m/s] horizontal speed over the ground
+	pub heading_radians:f64,			// [rad] direction of horizontal motion, clockwise from true north in [0, 2*pi)
+	pub clock_drift:f64,				// [sec/sec] receiver clock drift
+}
+
+/// Models for the 1-sigma pseudorange error used to weight each observation
+#[derive(Debug, Clone, Copy)]
+pub enum WeightModel {
+	/// Every observation gets the same error
+	Unweighted{ sigma_m:f64 },
+	/// sigma^2 = a^2 + b^2/sin^2(el), with elevation floored at five degrees
+	Elevation{ a_m:f64, b_m:f64 },
+	/// sigma^2 = a^2 + b^2 * 10^(-C/N0 / 10), with C/N0 in dB-Hz
+	Cn0{ a_m:f64, b_m:f64 },
+	/// User range accuracy broadcast in subframe 1, or the fallback if the SV doesn't provide one
+	Ura{ fallback_m:f64 },
+}
+
+impl WeightModel {
+
+	pub fn sigma_m(&self, obs:&Observation, completed:&CompletedObservation) -> f64 {
+		match *self {
+			WeightModel::Unweighted{ sigma_m } => sigma_m,
+			WeightModel::Elevation{ a_m, b_m } => {
+				// The elevation is meaningless until the position estimate gets near the surface, so treat the SV as overhead until then
+				let el:f64 = if completed.el_radians.is_finite() { completed.el_radians.max(5.0_f64.to_radians()) } else { std::f64::consts::FRAC_PI_2 };
+				(a_m.powi(2) + (b_m / el.sin()).powi(2)).sqrt()
+			},
+			WeightModel::Cn0{ a_m, b_m } => (a_m.powi(2) + b_m.powi(2) * 10.0_f64.powf(-obs.cn0_dbhz / 10.0)).sqrt(),
+			WeightModel::Ura{ fallback_m } => obs.ura_m.unwrap_or(fallback_m),
+		}
+	}
+
+}
+
+/// Options controlling which corrections and models the solver applies
+#[derive(Debug, Clone, Copy)]
+pub struct SolverConfig {
+	pub ionosphere: bool,		// Only has an effect when a Klobuchar model is available
+	pub troposphere: bool,
+	pub sagnac: bool,
+	pub weight_model: WeightModel,
+	pub elevation_mask_radians: f64,
+	pub raim: Option<raim::RaimConfig>,
+	pub reference_ecef: Option<(f64, f64, f64)>,	// Known receiver position to report fix errors against
+}
+
+impl Default for SolverConfig {
+	fn default() -> Self { 
+		Self{ ionosphere: true, troposphere: true, sagnac: true, weight_model: WeightModel::Unweighted{ sigma_m: 5.0 }, elevation_mask_radians: 0.0, raim: None, reference_ecef: None } 
+	}
 }
 
 // This struct is populated by the tracking and telemetry decoding modules and only depends on SV state
@@ -25,29 +134,60 @@ pub struct GnssFix {
 pub struct Observation {
 	pub sv_id: usize,
 	pub sv_tow_sec: f64,
-	pub pseudorange_m: f64,
-	pub pos_ecef: (f64, f64, f64),
+	pub pseudorange_m: f64,				// Carrier-smoothed when smoothing_epochs > 1
+	pub pseudorange_raw_m: f64,			// Code-only pseudorange with the same corrections applied
+	pub smoothing_epochs: usize,
+	pub pos_ecef: (f64, f64, f64),		// SV position at transmission, in the ECEF frame at that time
 	pub sv_clock: f64,
 	pub t_gd: f64,
 	pub carrier_freq_hz: f64,
+	pub doppler_hz: f64,				// Carrier frequency relative to the IF
+	pub sv_vel_ecef: (f64, f64, f64),
+	pub sv_clock_drift: f64,
+	pub cn0_dbhz: f64,
+	pub ura_m: Option<f64>,
+	#[serde(default)]
+	pub carrier_phase_cycles: Option<f64>,	// Accumulated carrier phase with the same sign and corrections as the pseudorange
+	#[serde(default)]
+	pub carrier_lock_count: usize,			// Changes whenever the accumulated carrier phase starts over
+	#[serde(default)]
+	pub iodc: Option<u16>,					// Issue of data of the ephemeris behind pos_ecef and sv_clock
 }
 
 // A CompletedObservation contains data the depends on the observer state in addition to the SV state
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CompletedObservation {
-	residual: f64,
+	pub residual: f64,
 	p_r_mag: f64,
 	p_r_e_norm: Vec<f64>,
-	az_radians: f64,
-	el_radians: f64,
-	iono_delay: f64,
+	pub az_radians: f64,
+	pub el_radians: f64,
+	iono_delay_m: Option<f64>,		// None if the correction wasn't applied
+	tropo_delay_m: Option<f64>,
+	sagnac_correction_m: Option<f64>,
+	transit_time_sec: f64,
+}
+
+impl CompletedObservation {
+
+	pub fn iono_delay_m(&self) -> Option<f64> { self.iono_delay_m }
+	pub fn tropo_delay_m(&self) -> Option<f64> { self.tropo_delay_m }
+	pub fn sagnac_correction_m(&self) -> Option<f64> { self.sagnac_correction_m }
+	pub fn transit_time_sec(&self) -> f64 { self.transit_time_sec }
+
 }
 
 impl Observation {
 	
-	pub fn complete(&self, x:Vector4<f64>, opt_iono:Option<ionosphere::Model>) -> CompletedObservation {
+	pub fn complete(&self, x:Vector4<f64>, opt_iono:Option<ionosphere::Model>, config:&SolverConfig) -> CompletedObservation {
 		let p_ob_e = Vector3::new(x[0], x[1], x[2]);
-		let p_sv_e = Vector3::new(self.pos_ecef.0, self.pos_ecef.1, self.pos_ecef.2);
+		let p_sv_tx_e = Vector3::new(self.pos_ecef.0, self.pos_ecef.1, self.pos_ecef.2);
+		let (p_sv_e, transit_time_sec) = if config.sagnac { 
+			sagnac_rotation(p_sv_tx_e, p_ob_e) 
+		} else { 
+			(p_sv_tx_e, (p_sv_tx_e - p_ob_e).norm() / C) 
+		};
+		let sagnac_correction_m:Option<f64> = if config.sagnac { Some((p_sv_e - p_ob_e).norm() - (p_sv_tx_e - p_ob_e).norm()) } else { None };
 		
 		// Position of the SV relative to the observer
 		let p_r_e = p_sv_e - p_ob_e;
@@ -56,85 +196,250 @@ impl Observation {
 
 		// Transformation from ECEF to NED
 		let obs_wgs84 = kinematics::ecef_to_wgs84(x[0], x[1], x[2]);
-		let dcm_ne = match obs_wgs84 {
-			kinematics::PositionWGS84 { latitude:phi, longitude:lam, height_above_ellipsoid:_ } => {
-				Matrix3::new(-phi.sin()*lam.cos(), -phi.sin()*lam.sin(),  phi.cos(),
-					         -lam.sin(),            lam.cos(),            0.0,
-					         -phi.cos()*lam.cos(), -phi.cos()*lam.sin(), -phi.sin())
-			}
-		};
+		let dcm_ne = kinematics::dcm_ne(obs_wgs84.latitude, obs_wgs84.longitude);
 
 		// Vector from the observer to the SV in the NED frame
 		let p_r_n = dcm_ne * p_r_e;
 
 		let r_horizontal:f64 = (p_r_n[(0,0)].powi(2) + p_r_n[(1,0)].powi(2)).sqrt();
-		let az_radians:f64 = p_r_n[(1,0)].atan2(p_r_n[(0,0)]);
+		let az_radians:f64 = p_r_n[(1,0)].atan2(p_r_n[(0,0)]).rem_euclid(2.0 * std::f64::consts::PI);
 		let el_radians:f64 = (-p_r_n[(2,0)]).atan2(r_horizontal);
 
-		// Compute ionospheric delay; recorded for testing, but not applied to the pseudorange yet
-		let iono_delay:f64 = match opt_iono {
-			Some(iono) => iono.delay(az_radians, el_radians, obs_wgs84.latitude, obs_wgs84.longitude, self.sv_tow_sec),
-			None => 0.0
+		let near_surface:bool = is_near_surface(&x);
+
+		let iono_delay_m:Option<f64> = match opt_iono {
+			Some(iono) if config.ionosphere && near_surface => 
+				Some(iono.delay(az_radians, el_radians, obs_wgs84.latitude, obs_wgs84.longitude, self.sv_tow_sec) * C),
+			_ => None
 		};
 
-		let residual = self.pseudorange_m - p_r_mag - x[3];
+		let tropo_delay_m:Option<f64> = if config.troposphere && near_surface {
+			Some(troposphere::delay(el_radians, obs_wgs84.latitude, obs_wgs84.height_above_ellipsoid))
+		} else { None };
+
+		let residual = self.pseudorange_m - p_r_mag - x[3] - iono_delay_m.unwrap_or(0.0) - tropo_delay_m.unwrap_or(0.0);
 		let p_r_e_norm_vec:Vec<f64> = (0..3).map(|j| p_r_e_norm[j] ).collect();
-		CompletedObservation{ residual, p_r_mag, p_r_e_norm: p_r_e_norm_vec, az_radians, el_radians, iono_delay }
+		CompletedObservation{ residual, p_r_mag, p_r_e_norm: p_r_e_norm_vec, az_radians, el_radians, iono_delay_m, tropo_delay_m,
+			sagnac_correction_m, transit_time_sec }
 	}
 
 }
 
-pub fn solve_position_and_time(obs_this_soln:Vec<Observation>, x0:Vector4<f64>, current_rx_time:f64, opt_iono:Option<ionosphere::Model>) -> Result<(GnssFix, Vector4<f64>), &'static str> {
-	// TODO: make other time corrections (ionosphere, etc) 
+/// Rotates an SV position from the ECEF frame at transmission into the ECEF frame at reception to account for the earth's
+/// rotation while the signal is in transit.  The transit time depends on the rotated position, so this iterates until it
+/// settles.  Returns the rotated position and the transit time.
+pub fn sagnac_rotation(p_sv_tx_e:Vector3<f64>, p_ob_e:Vector3<f64>) -> (Vector3<f64>, f64) {
+	let mut transit_time_sec:f64 = (p_sv_tx_e - p_ob_e).norm() / C;
+	let mut p_sv_e = p_sv_tx_e;
+	for _ in 0..5 {
+		let (sin_theta, cos_theta) = (ephemeris::OMEGA_E * transit_time_sec).sin_cos();
+		p_sv_e = Matrix3::new( cos_theta, sin_theta, 0.0,
+							  -sin_theta, cos_theta, 0.0,
+							   0.0,       0.0,       1.0) * p_sv_tx_e;
+		let next_transit_time_sec:f64 = (p_sv_e - p_ob_e).norm() / C;
+		let converged:bool = (next_transit_time_sec - transit_time_sec).abs() < 1.0e-12;
+		transit_time_sec = next_transit_time_sec;
+		if converged { break; }
+	}
+	(p_sv_e, transit_time_sec)
+}
 
-	if obs_this_soln.len() >= SV_COUNT_THRESHOLD {
-		let n = obs_this_soln.len();
+// Rotates the position part of an ECEF covariance into the local ENU frame at the given position
+fn enu_covariance(covariance:&DMatrix<f64>, pos_ecef:(f64, f64, f64)) -> Matrix3<f64> {
+	let wgs84 = kinematics::ecef_to_wgs84(pos_ecef.0, pos_ecef.1, pos_ecef.2);
+	let dcm = kinematics::dcm_we(wgs84.latitude, wgs84.longitude);
+	let cov_e = Matrix3::from_fn(|i, j| covariance[(i,j)]);
+	dcm * cov_e * dcm.transpose()
+}
 
-		let mut x = x0.clone();
-		let mut v = DVector::from_element(n, 0.0);
+// Atmospheric corrections and elevations only make sense once the position estimate is somewhere near the surface of the earth
+fn is_near_surface(x:&Vector4<f64>) -> bool {
+	let h:f64 = kinematics::ecef_to_wgs84(x[0], x[1], x[2]).height_above_ellipsoid;
+	h.is_finite() && h > -1.0e3
+}
 
-		// Try to solve for position
-		for _ in 0..MAX_ITER {
+// Galileo observations share the receiver clock with GPS but not the system time, so a solution with both has an extra
+// inter-system bias state, which is the Galileo clock bias less the GPS one.  Covers the GGTO when it hasn't been broadcast
+// yet, and any receiver delay between the two signals.
+fn has_inter_system_bias(obs:&[Observation]) -> bool {
+	let galileo_count:usize = obs.iter().filter(|o| galileo_e1::is_galileo_sv_id(o.sv_id)).count();
+	galileo_count > 0 && galileo_count < obs.len()
+}
 
-			let mut h = DMatrix::from_element(n, 4, 0.0);
+// The inter-system bias that applies to one observation
+fn inter_system_bias_m(obs:&Observation, opt_isb_m:Option<f64>) -> f64 {
+	match opt_isb_m {
+		Some(isb_m) if galileo_e1::is_galileo_sv_id(obs.sv_id) => isb_m,
+		_ => 0.0,
+	}
+}
 
-			for (i, ob) in obs_this_soln.iter().map(|obs| obs.complete(x, opt_iono)).enumerate() {
+// Converged state of the iterative weighted least squares
+struct Iteration {
+	x: Vector4<f64>,
+	isb_m: Option<f64>,		// Only estimated with a mix of GPS and Galileo observations
+	v: DVector<f64>,		// Residuals from the last iteration
+	h: DMatrix<f64>,		// Geometry matrix
+	w: DMatrix<f64>,		// Weight matrix
+	q: DMatrix<f64>,		// (H^T W H)^-1
+}
 
-				v[i] = ob.residual;
-				for j in 0..3 { h[(i,j)] = -ob.p_r_e_norm[j]; }
-				h[(i,3)] = 1.0;
-			
-			}
+fn iterate(obs_this_soln:&[Observation], x0:Vector4<f64>, opt_iono:Option<ionosphere::Model>, config:&SolverConfig) -> Result<Iteration, &'static str> {
+	let n = obs_this_soln.len();
+	let mut opt_isb_m:Option<f64> = if has_inter_system_bias(obs_this_soln) { Some(0.0) } else { None };
+	let n_states:usize = if opt_isb_m.is_some() { 5 } else { 4 };
+	if n <= n_states { return Err("Not enough observations"); }
+
+	let mut x = x0;
+	let mut v = DVector::from_element(n, 0.0);
+
+	// Try to solve for position
+	for _ in 0..MAX_ITER {
+
+		let mut h = DMatrix::from_element(n, n_states, 0.0);
+		let mut w = DMatrix::from_element(n, n, 0.0);
 
+		for (i, (obs, ob)) in obs_this_soln.iter().map(|obs| (obs, obs.complete(x, opt_iono, config))).enumerate() {
 
-			if let Some(q) = (h.tr_mul(&h)).try_inverse() {
-				let dx = q * h.tr_mul(&v);
+			v[i] = ob.residual - inter_system_bias_m(obs, opt_isb_m);
+			for j in 0..3 { h[(i,j)] = -ob.p_r_e_norm[j]; }
+			h[(i,3)] = 1.0;
+			if opt_isb_m.is_some() && galileo_e1::is_galileo_sv_id(obs.sv_id) { h[(i,4)] = 1.0; }
+			w[(i,i)] = config.weight_model.sigma_m(obs, &ob).powi(-2);
+		
+		}
 
-				x = x + dx.clone();
+		if let Some(q) = (h.transpose() * &w * &h).try_inverse() {
+			let dx = &q * h.transpose() * &w * &v;
 
-				if dx.norm() < 1.0e-4 { 
+			x += dx.fixed_rows::<nalgebra::U4>(0);
+			if let Some(isb_m) = opt_isb_m.as_mut() { *isb_m += dx[4]; }
 
-					// The iterative least squares method has converged
-					if x.iter().chain(v.iter()).all(|a| a.is_finite()) {
-						// Return the fix regardless of the residual norm and let the calling scope determine whether it's good enough
-						let observations:Vec<(Observation, CompletedObservation)> = obs_this_soln.iter().map(|obs| (*obs, obs.complete(x, opt_iono))).collect();
-						let fix = GnssFix{pos_ecef:(x[0], x[1], x[2]), residual_norm:v.norm(), current_rx_time, observations };
-						return Ok((fix, x))
-					}
+			if dx.norm() < 1.0e-4 { 
 
-					return Err("Solution and/or residual is infinite");
+				// The iterative least squares method has converged
+				if x.iter().chain(v.iter()).chain(opt_isb_m.iter()).all(|a| a.is_finite()) {
+					return Ok(Iteration{ x, isb_m: opt_isb_m, v, h, w, q });
 				}
 
-			} else { 
-				// If we get a non-invertible matrix, just return None
-				return Err("Non-invertible matrix");
+				return Err("Solution and/or residual is infinite");
 			}
 
+		} else { 
+			// If we get a non-invertible matrix, just return None
+			return Err("Non-invertible matrix");
 		}
 
 	}
 
-	Err("Not enough observations")
+	Err("Solution did not converge")
 }
 
+pub fn solve_position_and_time(obs_this_soln:Vec<Observation>, x0:Vector4<f64>, current_rx_time:f64, opt_iono:Option<ionosphere::Model>, 
+	config:&SolverConfig) -> Result<(GnssFix, Vector4<f64>), &'static str> {
+
+	if obs_this_soln.len() < SV_COUNT_THRESHOLD { return Err("Not enough observations"); }
+
+	// Elevations are needed to apply the mask, so if the initial guess isn't good enough to compute them, get a preliminary
+	// solution with every observation first
+	let x_mask:Vector4<f64> = if is_near_surface(&x0) { x0 } else { iterate(&obs_this_soln, x0, opt_iono, config)?.x };
+	let (obs_used, obs_masked):(Vec<Observation>, Vec<Observation>) = obs_this_soln.iter()
+		.partition(|obs| obs.complete(x_mask, opt_iono, config).el_radians >= config.elevation_mask_radians);
+	if obs_used.len() < SV_COUNT_THRESHOLD { return Err("Not enough observations above the elevation mask"); }
+
+	let mut obs_used = obs_used;
+	let mut it:Iteration = iterate(&obs_used, x_mask, opt_iono, config)?;
+
+	// Fault detection and exclusion: while the residuals fail the chi-square test, drop the observation with the largest
+	// normalized residual, as long as enough remain to keep testing the solution
+	let opt_raim:Option<raim::RaimReport> = match config.raim {
+		Some(raim_config) => {
+			let mut excluded_sv_ids:Vec<usize> = vec![];
+			loop {
+				let check = raim::check(&it, &raim_config);
+				if check.report.passed || excluded_sv_ids.len() >= raim_config.max_exclusions || obs_used.len() <= it.h.ncols() + 1 {
+					break Some(raim::RaimReport{ excluded_sv_ids, ..check.report });
+				}
+				excluded_sv_ids.push(obs_used.remove(check.worst_idx).sv_id);
+				it = iterate(&obs_used, it.x, opt_iono, config)?;
+			}
+		},
+		None => None,
+	};
+
+	let Iteration{ x, isb_m, v, h, w, q } = it;
+	let n = obs_used.len();
+
+	// Return the fix regardless of the residual norm and let the calling scope determine whether it's good enough
+	let observations:Vec<(Observation, CompletedObservation)> = obs_used.iter().map(|obs| {
+		let mut ob = obs.complete(x, opt_iono, config);
+		ob.residual -= inter_system_bias_m(obs, isb_m);
+		(*obs, ob)
+	}).collect();
+	let velocity:Option<Velocity> = solve_velocity_and_clock_drift(&obs_used, (x[0], x[1], x[2])).ok();
+
+	// Scale the covariance up, but never down, if the residuals are larger than the weight model predicts
+	let variance_factor:f64 = (v.dot(&(&w * &v)) / ((n - h.ncols()) as f64)).max(1.0);
+	let covariance = q * variance_factor;
+	let cov_enu = enu_covariance(&covariance, (x[0], x[1], x[2]));
+	let (h_accuracy_m, v_accuracy_m) = ((cov_enu[(0,0)] + cov_enu[(1,1)]).sqrt(), cov_enu[(2,2)].sqrt());
+
+	let mut cov_array:[[f64; 4]; 4] = [[0.0; 4]; 4];
+	for (i, row) in cov_array.iter_mut().enumerate() {
+		for (j, c) in row.iter_mut().enumerate() { *c = covariance[(i,j)]; }
+	}
+
+	let dop:Dop = Dop::new(&h, (x[0], x[1], x[2])).ok_or("Non-invertible matrix")?;
+	let masked_sv_ids:Vec<usize> = obs_masked.iter().map(|obs| obs.sv_id).collect();
 
+	let pos = kinematics::ecef_to_wgs84(x[0], x[1], x[2]);
+	let height_msl_m:f64 = geoid::height_msl_m(pos.latitude, pos.longitude, pos.height_above_ellipsoid);
+	let enu_error_m:Option<(f64, f64, f64)> = config.reference_ecef.map(|r| {
+		let enu = geodesy::LocalFrame::from_ecef(Vector3::new(r.0, r.1, r.2)).ecef_to_enu(Vector3::new(x[0], x[1], x[2]));
+		(enu[0], enu[1], enu[2])
+	});
+
+	let fix = GnssFix{pos_ecef:(x[0], x[1], x[2]), residual_norm:v.norm(), current_rx_time, observations, velocity, 
+		covariance: cov_array, h_accuracy_m, v_accuracy_m, dop, masked_sv_ids, raim: opt_raim, slant_tec_tecu: vec![], height_msl_m, enu_error_m,
+		inter_system_bias_m: isb_m };
+	Ok((fix, x))
+}
+
+/// Least-squares velocity and clock drift from the Doppler measurements, given a receiver position
+pub fn solve_velocity_and_clock_drift(obs_this_soln:&[Observation], pos_ecef:(f64, f64, f64)) -> Result<Velocity, &'static str> {
+	let n = obs_this_soln.len();
+	if n < 4 { return Err("Not enough observations"); }
+
+	let p_ob_e = Vector3::new(pos_ecef.0, pos_ecef.1, pos_ecef.2);
+	let mut h = DMatrix::from_element(n, 4, 0.0);
+	let mut y = DVector::from_element(n, 0.0);
+
+	for (i, obs) in obs_this_soln.iter().enumerate() {
+		// Same line of sight as the pseudorange, i.e. with the SV position rotated into the frame at reception
+		let v_sv_e = Vector3::new(obs.sv_vel_ecef.0, obs.sv_vel_ecef.1, obs.sv_vel_ecef.2);
+		let (p_sv_e, _) = sagnac_rotation(Vector3::new(obs.pos_ecef.0, obs.pos_ecef.1, obs.pos_ecef.2), p_ob_e);
+		let los_e = (p_sv_e - p_ob_e).normalize();
+
+		// A positive Doppler shift means the range is shrinking; the pseudorange rate corrected for the SV clock drift is
+		// (v_sv - v_rx).los + c*clock_drift, so moving the known SV term to the left-hand side leaves a linear system
+		let range_rate:f64 = -obs.doppler_hz * C / L1_FREQ_HZ + obs.sv_clock_drift * C;
+		y[i] = range_rate - v_sv_e.dot(&los_e);
+		for j in 0..3 { h[(i,j)] = -los_e[j]; }
+		h[(i,3)] = 1.0;
+	}
+
+	let q = (h.tr_mul(&h)).try_inverse().ok_or("Non-invertible matrix")?;
+	let x = q * h.tr_mul(&y);
+	if !x.iter().all(|a| a.is_finite()) { return Err("Solution is infinite"); }
+
+	let vel_e = Vector3::new(x[0], x[1], x[2]);
+	let obs_wgs84 = kinematics::ecef_to_wgs84(pos_ecef.0, pos_ecef.1, pos_ecef.2);
+	let vel_enu = kinematics::dcm_we(obs_wgs84.latitude, obs_wgs84.longitude) * vel_e;
+	let vel_ned = (vel_enu[1], vel_enu[0], -vel_enu[2]);
+
+	let mut heading_radians:f64 = vel_ned.1.atan2(vel_ned.0);
+	if heading_radians < 0.0 { heading_radians += 2.0 * std::f64::consts::PI; }
+
+	Ok(Velocity{ vel_ecef: (x[0], x[1], x[2]), vel_ned, speed_mps: (vel_ned.0.powi(2) + vel_ned.1.powi(2)).sqrt(), 
+		heading_radians, clock_drift: x[3] / C })
+}
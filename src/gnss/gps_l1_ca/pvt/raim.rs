@@ -0,0 +1,167 @@
+
+use serde::{Serialize, Deserialize};
+use nalgebra::base::{DMatrix, DVector, Vector3};
+
+use crate::utils::kinematics;
+
+use super::Iteration;
+
+/// Parameters for residual-based RAIM with fault detection and exclusion
+#[derive(Debug, Clone, Copy)]
+pub struct RaimConfig {
+	pub p_fa: f64,				// Probability of false alarm, which sets the chi-square threshold
+	pub p_md: f64,				// Probability of missed detection, which sets the protection levels
+	pub max_exclusions: usize,
+}
+
+impl Default for RaimConfig {
+	fn default() -> Self { Self{ p_fa: 1.0e-5, p_md: 1.0e-3, max_exclusions: 1 } }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RaimReport {
+	pub test_statistic: f64,		// Sum of squared normalized residuals
+	pub threshold: f64,
+	pub dof: usize,
+	pub hpl_m: f64,
+	pub vpl_m: f64,
+	pub passed: bool,
+	pub excluded_sv_ids: Vec<usize>,
+}
+
+// Outcome of testing one solution
+pub(super) struct Check {
+	pub report: RaimReport,
+	pub worst_idx: usize,		// Observation with the largest normalized residual
+}
+
+pub(super) fn check(it:&Iteration, config:&RaimConfig) -> Check {
+	let n:usize = it.v.len();
+	let dof:usize = n - it.h.ncols();
+
+	// Normalize everything by the measurement sigmas so the residuals are unitless
+	let w_sqrt = it.w.map(|x| x.sqrt());
+	let h_norm = &w_sqrt * &it.h;
+	let v_norm = &w_sqrt * &it.v;
+	let s_norm = &it.q * h_norm.transpose();
+	let residual_projection = DMatrix::identity(n, n) - &h_norm * &s_norm;
+	let v_post:DVector<f64> = &residual_projection * v_norm;
+
+	let test_statistic:f64 = v_post.norm_squared();
+	let threshold:f64 = chi_squared_quantile(1.0 - config.p_fa, dof);
+
+	// A bias on observation i that pushes the test statistic's noncentrality to lambda causes a position error of
+	// |S_i| * sqrt(lambda / (1 - P_ii)); the protection level is that error for the worst observation at the lambda
+	// that would be missed with probability p_md
+	let wgs84 = kinematics::ecef_to_wgs84(it.x[0], it.x[1], it.x[2]);
+	let dcm = kinematics::dcm_we(wgs84.latitude, wgs84.longitude);
+	let pbias:f64 = noncentrality_for_missed_detection(threshold, dof, config.p_md).sqrt();
+
+	let (mut h_slope, mut v_slope, mut worst_idx, mut worst_normalized_residual) = (0.0_f64, 0.0_f64, 0, 0.0);
+	for i in 0..n {
+		let redundancy:f64 = residual_projection[(i,i)].max(1.0e-12);
+		let s_enu = dcm * Vector3::new(s_norm[(0,i)], s_norm[(1,i)], s_norm[(2,i)]);
+		h_slope = h_slope.max((s_enu[0].powi(2) + s_enu[1].powi(2)).sqrt() / redundancy.sqrt());
+		v_slope = v_slope.max(s_enu[2].abs() / redundancy.sqrt());
+
+		let normalized_residual:f64 = v_post[i].abs() / redundancy.sqrt();
+		if normalized_residual > worst_normalized_residual {
+			worst_normalized_residual = normalized_residual;
+			worst_idx = i;
+		}
+	}
+
+	let report = RaimReport{ test_statistic, threshold, dof, hpl_m: h_slope * pbias, vpl_m: v_slope * pbias,
+		passed: test_statistic <= threshold, excluded_sv_ids: vec![] };
+	Check{ report, worst_idx }
+}
+
+// Natural log of the gamma function (Lanczos approximation)
+fn ln_gamma(x:f64) -> f64 {
+	const COEFFS:[f64; 6] = [76.18009172947146, -86.50532032941677, 24.01409824083091, -1.231739572450155, 0.1208650973866179e-2, -0.5395239384953e-5];
+	let tmp:f64 = x + 5.5 - (x + 0.5)*(x + 5.5).ln();
+	let ser:f64 = COEFFS.iter().enumerate().fold(1.000000000190015, |acc, (j, c)| acc + c / (x + 1.0 + j as f64));
+	-tmp + (2.5066282746310005 * ser / x).ln()
+}
+
+// Regularized lower incomplete gamma function P(a, x), using the series for small x and the continued fraction otherwise
+fn gamma_p(a:f64, x:f64) -> f64 {
+	if x <= 0.0 { return 0.0; }
+	let ln_prefix:f64 = -x + a*x.ln() - ln_gamma(a);
+	if x < a + 1.0 {
+		let (mut ap, mut del) = (a, 1.0 / a);
+		let mut sum:f64 = del;
+		for _ in 0..1000 {
+			ap += 1.0;
+			del *= x / ap;
+			sum += del;
+			if del.abs() < sum.abs() * 1.0e-15 { break; }
+		}
+		sum * ln_prefix.exp()
+	} else {
+		let tiny:f64 = 1.0e-300;
+		let mut b:f64 = x + 1.0 - a;
+		let mut c:f64 = 1.0 / tiny;
+		let mut d:f64 = 1.0 / b;
+		let mut h:f64 = d;
+		for i in 1..1000 {
+			let an:f64 = -(i as f64) * (i as f64 - a);
+			b += 2.0;
+			d = an*d + b;
+			if d.abs() < tiny { d = tiny; }
+			c = b + an/c;
+			if c.abs() < tiny { c = tiny; }
+			d = 1.0 / d;
+			let del:f64 = d*c;
+			h *= del;
+			if (del - 1.0).abs() < 1.0e-15 { break; }
+		}
+		1.0 - ln_prefix.exp() * h
+	}
+}
+
+pub fn chi_squared_cdf(x:f64, dof:usize) -> f64 { gamma_p(0.5 * dof as f64, 0.5 * x) }
+
+// Noncentral chi-square CDF as a Poisson-weighted sum of central chi-square CDFs
+pub fn noncentral_chi_squared_cdf(x:f64, dof:usize, lambda:f64) -> f64 {
+	let half_lambda:f64 = 0.5 * lambda;
+	let mut ans:f64 = 0.0;
+	let mut ln_weight:f64 = -half_lambda;
+	for j in 0..10000 {
+		let weight:f64 = ln_weight.exp();
+		ans += weight * gamma_p(0.5 * dof as f64 + j as f64, 0.5 * x);
+		if (j as f64) > half_lambda && weight < 1.0e-15 { break; }
+		ln_weight += half_lambda.ln() - ((j + 1) as f64).ln();
+	}
+	ans
+}
+
+// Solves f(x) = target by bisection for a monotonic f over [lo, hi]
+fn bisect<F: Fn(f64) -> f64>(f:F, target:f64, mut lo:f64, mut hi:f64, increasing:bool) -> f64 {
+	for _ in 0..200 {
+		let mid:f64 = 0.5 * (lo + hi);
+		if (f(mid) < target) == increasing { lo = mid; } else { hi = mid; }
+	}
+	0.5 * (lo + hi)
+}
+
+pub fn chi_squared_quantile(p:f64, dof:usize) -> f64 {
+	bisect(|x| chi_squared_cdf(x, dof), p, 0.0, 1.0e3 + 100.0 * dof as f64, true)
+}
+
+// Noncentrality at which a test with the given threshold misses the fault with probability p_md
+fn noncentrality_for_missed_detection(threshold:f64, dof:usize, p_md:f64) -> f64 {
+	bisect(|lambda| noncentral_chi_squared_cdf(threshold, dof, lambda), p_md, 0.0, 1.0e3 + 10.0 * threshold, false)
+}
+
+#[test]
+fn test_chi_squared() {
+	// Reference values from standard tables
+	assert!((chi_squared_quantile(0.95, 1) - 3.841).abs() < 1.0e-3);
+	assert!((chi_squared_quantile(0.99, 5) - 15.086).abs() < 1.0e-3);
+	assert!((chi_squared_quantile(1.0 - 1.0e-5, 2) - 23.026).abs() < 1.0e-3);
+	assert!((noncentral_chi_squared_cdf(chi_squared_quantile(0.9, 3), 3, 0.0) - 0.9).abs() < 1.0e-9);
+	// A unit bias on a single degree of freedom is a shifted normal distribution
+	let x:f64 = 1.96_f64.powi(2);
+	assert!((noncentral_chi_squared_cdf(x, 1, 1.0) - 0.8299).abs() < 1.0e-3);
+}
@@ -0,0 +1,311 @@
+
+use std::collections::HashMap;
+
+use serde::{Serialize, Deserialize};
+use nalgebra::base::{DMatrix, DVector, Vector3, Vector4};
+
+use crate::gnss::common::lambda;
+use crate::utils::kinematics;
+
+use super::{C, L1_FREQ_HZ, SV_COUNT_THRESHOLD, Observation, SolverConfig, ionosphere};
+use super::dgps::{Dgps, ObservationEpoch};
+
+const WAVELENGTH_M:f64 = C / L1_FREQ_HZ;
+const INITIAL_POSITION_SIGMA_M:f64 = 10.0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RtkConfig {
+	pub code_sigma_m: f64,				// Undifferenced, for each receiver
+	pub phase_sigma_m: f64,
+	pub position_psd: f64,				// [m^2/s] Random walk on the rover position; zero for a static survey
+	pub ratio_threshold: f64,			// Second-best over best squared distance needed to accept the integer ambiguities
+	pub max_age_sec: f64,				// Oldest base epoch to use for a rover epoch
+	pub solver: SolverConfig,			// Models applied at both receivers
+}
+
+impl Default for RtkConfig {
+	fn default() -> Self {
+		Self{ code_sigma_m: 1.0, phase_sigma_m: 0.005, position_psd: 0.0, ratio_threshold: 3.0, max_age_sec: 30.0, solver: SolverConfig::default() }
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RtkSolution {
+	pub tow_sec: f64,
+	pub pos_ecef: (f64, f64, f64),				// Fixed if the ambiguities passed the ratio test, otherwise float
+	pub baseline_ecef: (f64, f64, f64),			// Rover minus base
+	pub baseline_enu: (f64, f64, f64),			// East, north and up at the base
+	pub float_baseline_ecef: (f64, f64, f64),
+	pub fixed: bool,
+	pub ratio: f64,
+	pub sigma_m: f64,							// 1-sigma of the float position, as the root sum of squares over the three axes
+	pub reference_sv: usize,
+	pub sv_ids: Vec<usize>,
+}
+
+// The carrier phase as a range, so it goes through the same corrections as the code
+fn carrier_observation(obs:&Observation) -> Option<Observation> {
+	obs.carrier_phase_cycles.map(|cycles| Observation{ pseudorange_m: cycles * WAVELENGTH_M, pseudorange_raw_m: cycles * WAVELENGTH_M, ..*obs })
+}
+
+// Code and carrier at the rover with the base's measurements of the same SV subtracted, along with the lock counts at the base
+// and rover that the single-difference ambiguity belongs to
+#[derive(Debug, Clone, Copy)]
+struct SingleDifference {
+	code: Observation,
+	carrier: Observation,
+	locks: (usize, usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Ambiguity {
+	prn: usize,
+	locks: (usize, usize),
+}
+
+/// Float baseline from double-differenced code and carrier with a Kalman filter, with the integer ambiguities resolved by LAMBDA
+/// at every epoch.  The state is the rover position and a single-difference ambiguity [cycles] for each SV.  The single
+/// differences carry a common term from both receiver clocks, so only their differences are integers, but that term drops out
+/// of every double difference.
+pub struct RtkFilter {
+	pub base_pos_ecef: (f64, f64, f64),
+	pub config: RtkConfig,
+	code: Dgps,
+	carrier: Dgps,
+	base_locks: HashMap<usize, usize>,
+	unrated: Vec<usize>,
+	ambiguities: Vec<Ambiguity>,
+	x: DVector<f64>,
+	p: DMatrix<f64>,
+	opt_t: Option<f64>,
+}
+
+impl RtkFilter {
+
+	pub fn new(base_pos_ecef:(f64, f64, f64), config:RtkConfig) -> Self {
+		Self{ base_pos_ecef, config, code: Dgps::new(base_pos_ecef, config.solver, config.max_age_sec),
+			carrier: Dgps::new(base_pos_ecef, config.solver, config.max_age_sec), base_locks: HashMap::new(), unrated: vec![], ambiguities: vec![],
+			x: DVector::zeros(3), p: DMatrix::zeros(3, 3), opt_t: None }
+	}
+
+	pub fn is_initialized(&self) -> bool { self.opt_t.is_some() }
+
+	pub fn reset(&mut self) {
+		self.opt_t = None;
+		self.ambiguities.clear();
+		self.x = DVector::zeros(3);
+		self.p = DMatrix::zeros(3, 3);
+	}
+
+	/// Returns the number of SVs with carrier corrections from this epoch
+	pub fn add_base_epoch(&mut self, epoch:&ObservationEpoch, opt_iono:Option<ionosphere::Model>) -> Result<usize, &'static str> {
+		let carrier_obs:Vec<Observation> = epoch.observations.iter().filter_map(carrier_observation).collect();
+		let mut restarted:Vec<usize> = vec![];
+		for obs in carrier_obs.iter() {
+			// The phase started over, so the correction can't be differenced against the last one
+			if self.base_locks.insert(obs.sv_id, obs.carrier_lock_count) != Some(obs.carrier_lock_count) {
+				self.carrier.remove(obs.sv_id);
+				restarted.push(obs.sv_id);
+			}
+		}
+
+		// A new or restarted phase also shifts the base clock estimate, which every other correction rate picks up for one
+		// epoch.  That's common to those SVs, so it drops out as long as the ones without a rate are left out until the next epoch.
+		self.unrated = if restarted.len() < carrier_obs.len() { restarted } else { vec![] };
+		self.code.add_base_epoch(epoch, opt_iono)?;
+		self.carrier.add_base_epoch(&ObservationEpoch{ tow_sec: epoch.tow_sec, observations: carrier_obs }, opt_iono)
+	}
+
+	pub fn update(&mut self, rover_epoch:&ObservationEpoch, opt_iono:Option<ionosphere::Model>) -> Result<RtkSolution, &'static str> {
+		let tow_sec:f64 = rover_epoch.tow_sec;
+		let solver = self.config.solver;
+
+		let mut sds:Vec<SingleDifference> = rover_epoch.observations.iter().filter(|obs| !self.unrated.contains(&obs.sv_id)).filter_map(|obs| {
+			let base_lock:usize = *self.base_locks.get(&obs.sv_id)?;
+			let code = self.code.correct(&[*obs], tow_sec).pop()?;
+			let carrier = self.carrier.correct(&[carrier_observation(obs)?], tow_sec).pop()?;
+			Some(SingleDifference{ code, carrier, locks: (base_lock, obs.carrier_lock_count) })
+		}).collect();
+		if sds.len() < SV_COUNT_THRESHOLD { return Err("Not enough SVs with code and carrier at both receivers"); }
+
+		match self.opt_t {
+			None => {
+				let (fix, _) = super::solve_position_and_time(sds.iter().map(|sd| sd.code).collect(), Vector4::zeros(), tow_sec, opt_iono, &solver)?;
+				self.x = DVector::from_vec(vec![fix.pos_ecef.0, fix.pos_ecef.1, fix.pos_ecef.2]);
+				self.p = DMatrix::identity(3, 3) * INITIAL_POSITION_SIGMA_M.powi(2);
+				self.ambiguities.clear();
+			},
+			Some(t) => for i in 0..3 { self.p[(i,i)] += self.config.position_psd * (tow_sec - t).abs(); },
+		}
+
+		let x4 = Vector4::new(self.x[0], self.x[1], self.x[2], 0.0);
+		sds.retain(|sd| sd.code.complete(x4, opt_iono, &solver).el_radians >= solver.elevation_mask_radians);
+		if sds.len() < SV_COUNT_THRESHOLD { return Err("Not enough SVs above the elevation mask"); }
+
+		self.update_ambiguity_states(&sds);
+		let reference:usize = self.measurement_update(&sds, opt_iono)?;
+		self.opt_t = Some(tow_sec);
+
+		let float_pos = Vector3::new(self.x[0], self.x[1], self.x[2]);
+		let (pos, fixed, ratio) = match self.resolve(&sds, reference) {
+			Some((fixed_pos, ratio)) if ratio >= self.config.ratio_threshold => (fixed_pos, true, ratio),
+			Some((_, ratio)) => (float_pos, false, ratio),
+			None => (float_pos, false, 0.0),
+		};
+
+		let base = Vector3::new(self.base_pos_ecef.0, self.base_pos_ecef.1, self.base_pos_ecef.2);
+		let base_wgs84 = kinematics::ecef_to_wgs84(base[0], base[1], base[2]);
+		let baseline_enu = kinematics::dcm_we(base_wgs84.latitude, base_wgs84.longitude) * (pos - base);
+		Ok(RtkSolution{ tow_sec,
+			pos_ecef: (pos[0], pos[1], pos[2]),
+			baseline_ecef: (pos[0] - base[0], pos[1] - base[1], pos[2] - base[2]),
+			baseline_enu: (baseline_enu[0], baseline_enu[1], baseline_enu[2]),
+			float_baseline_ecef: (float_pos[0] - base[0], float_pos[1] - base[1], float_pos[2] - base[2]),
+			fixed, ratio,
+			sigma_m: (self.p[(0,0)] + self.p[(1,1)] + self.p[(2,2)]).sqrt(),
+			reference_sv: sds[reference].code.sv_id,
+			sv_ids: sds.iter().map(|sd| sd.code.sv_id).collect() })
+	}
+
+	// Index in the state of the ambiguity for this SV
+	fn idx_ambiguity(&self, prn:usize) -> usize { 3 + self.ambiguities.iter().position(|amb| amb.prn == prn).unwrap() }
+
+	// Drops the ambiguities of SVs that are gone or whose phase started over at either receiver and adds any new ones.  New
+	// ambiguities come from code minus carrier, relative to one that's already in the state so the common term matches.
+	fn update_ambiguity_states(&mut self, sds:&[SingleDifference]) {
+		let code_minus_carrier = |sd:&SingleDifference| (sd.carrier.pseudorange_m - sd.code.pseudorange_m) / WAVELENGTH_M;
+		let kept:Vec<usize> = (0..self.ambiguities.len())
+			.filter(|k| sds.iter().any(|sd| Ambiguity{ prn: sd.code.sv_id, locks: sd.locks } == self.ambiguities[*k]))
+			.collect();
+		let added:Vec<&SingleDifference> = sds.iter()
+			.filter(|sd| !kept.iter().any(|k| self.ambiguities[*k].prn == sd.code.sv_id))
+			.collect();
+
+		// Anchor for the new ambiguities, as an index into the old state and its code minus carrier now
+		let opt_anchor:Option<(usize, f64)> = kept.first().map(|k| {
+			let sd = sds.iter().find(|sd| sd.code.sv_id == self.ambiguities[*k].prn).unwrap();
+			(3 + k, code_minus_carrier(sd))
+		});
+		let new_var:f64 = 2.0 * (self.config.code_sigma_m.powi(2) + self.config.phase_sigma_m.powi(2)) / WAVELENGTH_M.powi(2);
+
+		// Each new state is either a copy of an old one, the anchor plus an independent offset, or independent altogether
+		let n:usize = 3 + kept.len() + added.len();
+		let source:Vec<(Option<usize>, f64)> = (0..3).map(|i| (Some(i), 0.0))
+			.chain(kept.iter().map(|k| (Some(3 + k), 0.0)))
+			.chain(added.iter().map(|sd| match opt_anchor {
+				Some((anchor_idx, anchor_cmc)) => (Some(anchor_idx), code_minus_carrier(sd) - anchor_cmc),
+				None => (None, code_minus_carrier(sd)),
+			}))
+			.collect();
+		let is_added = |i:usize| i >= 3 + kept.len();
+
+		let x = DVector::from_fn(n, |i, _| match source[i] { (Some(j), offset) => self.x[j] + offset, (None, value) => value });
+		let p = DMatrix::from_fn(n, n, |i, j| {
+			let shared:f64 = match (source[i].0, source[j].0) { (Some(a), Some(b)) => self.p[(a,b)], _ => 0.0 };
+			if i == j && is_added(i) { shared + new_var } else { shared }
+		});
+
+		self.ambiguities = kept.iter().map(|k| self.ambiguities[*k])
+			.chain(added.iter().map(|sd| Ambiguity{ prn: sd.code.sv_id, locks: sd.locks }))
+			.collect();
+		self.x = x;
+		self.p = p;
+	}
+
+	// Double differences against the highest SV, all in one update since they share the reference.  Returns the reference.
+	fn measurement_update(&mut self, sds:&[SingleDifference], opt_iono:Option<ionosphere::Model>) -> Result<usize, &'static str> {
+		let n:usize = self.x.len();
+		let x4 = Vector4::new(self.x[0], self.x[1], self.x[2], 0.0);
+		let solver = self.config.solver;
+		let completed:Vec<(f64, f64, Vector3<f64>, f64)> = sds.iter().map(|sd| {
+			let code = sd.code.complete(x4, opt_iono, &solver);
+			let carrier = sd.carrier.complete(x4, opt_iono, &solver);
+			(code.residual, carrier.residual, Vector3::from_column_slice(&code.p_r_e_norm), code.el_radians)
+		}).collect();
+		if completed.iter().any(|(code, carrier, _, el)| !(code.is_finite() && carrier.is_finite() && el.is_finite())) { return Err("Non-finite single difference"); }
+		let reference:usize = (0..sds.len()).max_by(|a, b| completed[*a].3.total_cmp(&completed[*b].3)).ok_or("No single differences")?;
+		let idx_ref:usize = self.idx_ambiguity(sds[reference].code.sv_id);
+
+		let others:Vec<usize> = (0..sds.len()).filter(|i| *i != reference).collect();
+		let m:usize = others.len();
+		let mut h = DMatrix::zeros(2*m, n);
+		let mut v = DVector::zeros(2*m);
+		for (row, i) in others.iter().enumerate() {
+			let idx_i:usize = self.idx_ambiguity(sds[*i].code.sv_id);
+			let d_los = completed[*i].2 - completed[reference].2;
+			for j in 0..3 {
+				h[(row,j)]   = -d_los[j];
+				h[(m+row,j)] = -d_los[j];
+			}
+			h[(m+row,idx_i)]   =  WAVELENGTH_M;
+			h[(m+row,idx_ref)] = -WAVELENGTH_M;
+			v[row]   = completed[*i].0 - completed[reference].0;
+			v[m+row] = (completed[*i].1 - WAVELENGTH_M*self.x[idx_i]) - (completed[reference].1 - WAVELENGTH_M*self.x[idx_ref]);
+		}
+
+		// Each single difference has twice the undifferenced variance, and the reference is shared by every double difference
+		let mut r = DMatrix::zeros(2*m, 2*m);
+		for (offset, sigma) in [(0, self.config.code_sigma_m), (m, self.config.phase_sigma_m)].iter() {
+			for i in 0..m { for j in 0..m {
+				r[(offset+i,offset+j)] = 2.0 * sigma.powi(2) * if i == j { 2.0 } else { 1.0 };
+			}}
+		}
+
+		let s = &h * &self.p * h.transpose() + &r;
+		let k = &self.p * h.transpose() * s.try_inverse().ok_or("Non-invertible matrix")?;
+		let i_kh = DMatrix::identity(n, n) - &k * &h;
+		self.x += &k * v;
+		self.p = &i_kh * &self.p * i_kh.transpose() + &k * r * k.transpose();
+		if !self.x.iter().all(|a| a.is_finite()) {
+			self.reset();
+			return Err("Filter state is infinite");
+		}
+
+		Ok(reference)
+	}
+
+	// Fixed position and the ratio test statistic, or None if the search fails
+	fn resolve(&self, sds:&[SingleDifference], reference:usize) -> Option<(Vector3<f64>, f64)> {
+		let n:usize = self.x.len();
+		let idx_ref:usize = self.idx_ambiguity(sds[reference].code.sv_id);
+		let others:Vec<usize> = (0..sds.len()).filter(|i| *i != reference).collect();
+		let mut a = DMatrix::zeros(others.len(), n);
+		for (row, i) in others.iter().enumerate() {
+			a[(row,self.idx_ambiguity(sds[*i].code.sv_id))] = 1.0;
+			a[(row,idx_ref)] = -1.0;
+		}
+
+		let a_float = &a * &self.x;
+		let q_a = &a * &self.p * a.transpose();
+		let candidates = lambda::integer_least_squares(&a_float, &q_a, 2).ok()?;
+		if candidates.len() < 2 { return None; }
+		let ratio:f64 = if candidates[0].1 > 0.0 { candidates[1].1 / candidates[0].1 } else { f64::INFINITY };
+
+		// Condition the position on the fixed double-difference ambiguities
+		let q_xa = self.p.rows(0, 3) * a.transpose();
+		let dx = q_xa * q_a.try_inverse()? * (a_float - &candidates[0].0);
+		Some((Vector3::new(self.x[0] - dx[0], self.x[1] - dx[1], self.x[2] - dx[2]), ratio))
+	}
+
+}
+
+/// Baselines for every rover epoch, using the base epochs up to and including the rover's time
+pub fn post_process(base:&[ObservationEpoch], rover:&[ObservationEpoch], base_pos_ecef:(f64, f64, f64), opt_iono:Option<ionosphere::Model>,
+	config:&RtkConfig) -> Vec<RtkSolution> {
+
+	let mut filter = RtkFilter::new(base_pos_ecef, *config);
+	let mut base_iter = base.iter().peekable();
+	let mut solutions:Vec<RtkSolution> = vec![];
+
+	for rover_epoch in rover {
+		while let Some(base_epoch) = base_iter.peek() {
+			if base_epoch.tow_sec > rover_epoch.tow_sec { break; }
+			let _ = filter.add_base_epoch(base_epoch, opt_iono);
+			base_iter.next();
+		}
+
+		if let Ok(soln) = filter.update(rover_epoch, opt_iono) { solutions.push(soln); }
+	}
+
+	solutions
+}
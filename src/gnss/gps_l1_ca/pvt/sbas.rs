@@ -0,0 +1,284 @@
+
+use std::collections::HashMap;
+
+use serde::{Serialize, Deserialize};
+use nalgebra::base::{Vector3, Vector4};
+
+use crate::gnss::sbas_l1::message::{self, Message, MessageBody, FastCorrections, LongTermCorrection, GeoNavigation, IgpDelay};
+use crate::utils::kinematics;
+
+use super::{C, GnssFix, Observation, SolverConfig, ionosphere, is_near_surface};
+
+const SEC_PER_DAY:f64 = 86400.0;
+
+// Ionospheric pierce points are computed on a thin shell at this height
+const EARTH_RADIUS_M:f64 = 6378.1363e3;
+const SHELL_HEIGHT_M:f64 = 350.0e3;
+
+/// How long each kind of SBAS data stays usable after it's received
+#[derive(Debug, Clone, Copy)]
+pub struct SbasConfig {
+	pub max_fast_age_sec: f64,
+	pub max_long_term_age_sec: f64,
+	pub max_iono_age_sec: f64,
+}
+
+impl Default for SbasConfig {
+	fn default() -> Self { Self{ max_fast_age_sec: 18.0, max_long_term_age_sec: 360.0, max_iono_age_sec: 600.0 } }
+}
+
+/// Latest fast correction for one SV
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct FastCorrection {
+	pub tow_sec: f64,			// Time of applicability, taken as the time the message was received
+	pub prc_m: f64,
+	pub rrc_mps: f64,			// Difference of the last two corrections, or zero until there are two
+	pub udrei: u8,
+	pub iodf: u8,
+}
+
+impl FastCorrection {
+
+	pub fn at(&self, tow_sec:f64) -> f64 { self.prc_m + self.rrc_mps * (tow_sec - self.tow_sec) }
+
+}
+
+/// Applies the corrections broadcast by an SBAS GEO to GPS observations.  Messages go in as they're decoded, and only SVs with
+/// a current fast correction and a usable UDREI come out of correct().  The long-term corrections are applied when there's
+/// one for the ephemeris the observation was computed from.  Only IGP bands 0 through 8 are supported, so above about 75
+/// degrees latitude the Klobuchar model is used instead of the grid.
+#[derive(Debug, Clone)]
+pub struct Sbas {
+	pub config: SbasConfig,
+	pub geo: Option<GeoNavigation>,
+	mask: Option<(u8, Vec<usize>)>,				// IODP and the PRN in each mask slot
+	fast: HashMap<usize, FastCorrection>,
+	degradation: Option<(u8, f64, Vec<u8>)>,	// IODP, system latency, and degradation factor indicator per slot
+	long_term: HashMap<usize, (f64, LongTermCorrection)>,
+	covariance: HashMap<usize, [[f64; 4]; 4]>,
+	igp_masks: HashMap<u8, (u8, Vec<bool>)>,	// IODI and mask by band
+	igp_delays: HashMap<(i32, i32), (f64, IgpDelay)>,	// Time received and delay by IGP latitude and longitude [deg]
+}
+
+impl Sbas {
+
+	pub fn new(config:SbasConfig) -> Self {
+		Self{ config, geo: None, mask: None, fast: HashMap::new(), degradation: None, long_term: HashMap::new(),
+			covariance: HashMap::new(), igp_masks: HashMap::new(), igp_delays: HashMap::new() }
+	}
+
+	pub fn prn_mask(&self) -> Option<&[usize]> { self.mask.as_ref().map(|(_, prns)| prns.as_slice()) }
+	pub fn fast_correction(&self, prn:usize) -> Option<FastCorrection> { self.fast.get(&prn).copied() }
+	pub fn long_term_correction(&self, prn:usize) -> Option<LongTermCorrection> { self.long_term.get(&prn).map(|(_, ltc)| *ltc) }
+	pub fn num_igp_delays(&self) -> usize { self.igp_delays.len() }
+
+	// PRN in a mask slot, counting from one, as long as the message was built against the current mask
+	fn slot_prn(&self, iodp:u8, slot:usize) -> Option<usize> {
+		match &self.mask {
+			Some((mask_iodp, prns)) if *mask_iodp == iodp && slot > 0 => prns.get(slot - 1).copied(),
+			_ => None,
+		}
+	}
+
+	pub fn add_message(&mut self, msg:&Message, tow_sec:f64) {
+		match &msg.body {
+			MessageBody::DontUse => {
+				// The GEO's data isn't to be relied on, so start over
+				*self = Self::new(self.config);
+			},
+			MessageBody::PrnMask{ prns, iodp } => {
+				if self.mask.as_ref().map(|(mask_iodp, _)| mask_iodp) != Some(iodp) {
+					self.mask = Some((*iodp, prns.clone()));
+					self.fast.clear();
+					self.long_term.clear();
+					self.covariance.clear();
+				}
+			},
+			MessageBody::FastCorrections(fc) => self.add_fast_corrections(fc, tow_sec),
+			MessageBody::MixedCorrections{ fast, long_term } => {
+				self.add_fast_corrections(fast, tow_sec);
+				self.add_long_term_corrections(long_term, tow_sec);
+			},
+			MessageBody::Integrity{ iodf, udrei } => {
+				for (slot_idx, new_udrei) in udrei.iter().enumerate() {
+					let block:usize = slot_idx / message::FAST_CORRECTIONS_PER_BLOCK;
+					let prn:Option<usize> = self.mask.as_ref().and_then(|(_, prns)| prns.get(slot_idx).copied());
+					if let Some(fc) = prn.and_then(|prn| self.fast.get_mut(&prn)) {
+						// An IODF of 3 is an alarm that applies to whatever was sent before
+						if block < 4 && (iodf[block] == 3 || iodf[block] == fc.iodf) { fc.udrei = *new_udrei; }
+					}
+				}
+			},
+			MessageBody::FastCorrectionDegradation{ latency_sec, iodp, ai } => self.degradation = Some((*iodp, *latency_sec, ai.clone())),
+			MessageBody::GeoNavigation(geo) => self.geo = Some(*geo),
+			MessageBody::IgpMask{ band, iodi, mask, .. } => {
+				if self.igp_masks.get(band).map(|(mask_iodi, _)| mask_iodi) != Some(iodi) {
+					// Delays from the old mask can't be trusted to still line up with the IGPs
+					if let Some(igps) = message::band_igps(*band) { self.igp_delays.retain(|latlon, _| !igps.contains(latlon)); }
+					self.igp_masks.insert(*band, (*iodi, mask.clone()));
+				}
+			},
+			MessageBody::IonosphericDelays{ band, block, iodi, delays } => {
+				let igps:Vec<(i32, i32)> = match (self.igp_masks.get(band), message::band_igps(*band)) {
+					(Some((mask_iodi, mask)), Some(igps)) if mask_iodi == iodi => igps.into_iter().zip(mask.iter()).filter(|(_, b)| **b).map(|(latlon, _)| latlon).collect(),
+					_ => return,
+				};
+
+				// Each block covers the next 15 IGPs that are set in the mask
+				for (latlon, opt_delay) in igps.into_iter().skip(message::IGPS_PER_BLOCK * (*block as usize)).zip(delays.iter()) {
+					match opt_delay {
+						Some(delay) => { self.igp_delays.insert(latlon, (tow_sec, *delay)); },
+						None        => { self.igp_delays.remove(&latlon); },
+					}
+				}
+			},
+			MessageBody::LongTermCorrections(long_term) => self.add_long_term_corrections(long_term, tow_sec),
+			MessageBody::ClockEphemerisCovariance(entries) => {
+				for entry in entries.iter() {
+					if let Some(prn) = self.slot_prn(entry.iodp, entry.slot) { self.covariance.insert(prn, entry.covariance_m2); }
+				}
+			},
+			MessageBody::Unknown => {},
+		}
+	}
+
+	fn add_fast_corrections(&mut self, fc:&FastCorrections, tow_sec:f64) {
+		for (i, (prc_m, udrei)) in fc.prc_m.iter().zip(fc.udrei.iter()).enumerate() {
+			let prn:usize = match self.slot_prn(fc.iodp, fc.first_slot + i) { Some(prn) => prn, None => continue };
+			let mut new_fc = FastCorrection{ tow_sec, prc_m: *prc_m, rrc_mps: 0.0, udrei: *udrei, iodf: fc.iodf };
+			if let Some(prev) = self.fast.get(&prn) {
+				let dt:f64 = tow_sec - prev.tow_sec;
+				if dt > 0.0 && dt <= self.config.max_fast_age_sec { new_fc.rrc_mps = (prc_m - prev.prc_m) / dt; }
+			}
+			self.fast.insert(prn, new_fc);
+		}
+	}
+
+	fn add_long_term_corrections(&mut self, long_term:&[LongTermCorrection], tow_sec:f64) {
+		for ltc in long_term.iter() {
+			if let Some(prn) = self.slot_prn(ltc.iodp, ltc.slot) { self.long_term.insert(prn, (tow_sec, *ltc)); }
+		}
+	}
+
+	// Degradation of a fast correction with age, from the factor and latency in message type 7
+	fn fast_degradation_m(&self, prn:usize, age_sec:f64) -> f64 {
+		let slot_idx:Option<usize> = self.mask.as_ref().and_then(|(_, prns)| prns.iter().position(|x| *x == prn));
+		match (&self.degradation, slot_idx) {
+			(Some((iodp, latency_sec, ai)), Some(idx)) if self.mask.as_ref().map(|(mask_iodp, _)| mask_iodp) == Some(iodp) =>
+				ai.get(idx).map(|a| message::degradation_factor_mps2(*a) * (age_sec + latency_sec).powi(2) / 2.0).unwrap_or(0.0),
+			_ => 0.0,
+		}
+	}
+
+	/// Vertical delay [m] and its variance [m^2] at a point, interpolated from the surrounding IGPs.  Cells of 5 degrees
+	/// are used where all four corners are available and 10 degrees otherwise.
+	pub fn vertical_delay_m(&self, latitude_deg:f64, longitude_deg:f64, tow_sec:f64) -> Option<(f64, f64)> {
+		let lookup = |lat:i32, lon:i32| -> Option<IgpDelay> {
+			let lon:i32 = (lon + 180).rem_euclid(360) - 180;
+			self.igp_delays.get(&(lat, lon)).filter(|(t, _)| (tow_sec - t).abs() <= self.config.max_iono_age_sec).map(|(_, delay)| *delay)
+		};
+
+		[5, 10].iter().find_map(|spacing| {
+			// Rows of the 10-degree grid are offset by 5 degrees, which keeps 65 and 75 as grid latitudes
+			let (lat_offset, max_lat):(i32, f64) = if *spacing == 5 { (0, 55.0) } else { (5, 85.0) };
+			if latitude_deg.abs() > max_lat { return None; }
+			let lat1:i32 = (((latitude_deg - lat_offset as f64) / *spacing as f64).floor() as i32) * spacing + lat_offset;
+			let lon1:i32 = ((longitude_deg / *spacing as f64).floor() as i32) * spacing;
+			let y:f64 = (latitude_deg  - lat1 as f64) / *spacing as f64;
+			let x:f64 = (longitude_deg - lon1 as f64) / *spacing as f64;
+
+			let corners = [(lat1, lon1, (1.0-x)*(1.0-y)), (lat1, lon1+spacing, x*(1.0-y)), (lat1+spacing, lon1+spacing, x*y), (lat1+spacing, lon1, (1.0-x)*y)];
+			let mut delay_m:f64 = 0.0;
+			let mut variance_m2:f64 = 0.0;
+			for (lat, lon, w) in corners.iter() {
+				let igp = lookup(*lat, *lon)?;
+				delay_m += w * igp.vertical_delay_m;
+				variance_m2 += w * message::give_variance_m2(igp.givei)?;
+			}
+			Some((delay_m, variance_m2))
+		})
+	}
+
+	// Slant delay [m] and its variance [m^2] along the line of sight at the given azimuth and elevation
+	fn slant_delay_m(&self, pos:&kinematics::PositionWGS84, az_radians:f64, el_radians:f64, tow_sec:f64) -> Option<(f64, f64)> {
+		// Pierce point and obliquity factor on the shell
+		let ratio:f64 = EARTH_RADIUS_M / (EARTH_RADIUS_M + SHELL_HEIGHT_M);
+		let psi:f64 = std::f64::consts::FRAC_PI_2 - el_radians - (ratio * el_radians.cos()).asin();
+		let lat_pp:f64 = (pos.latitude.sin() * psi.cos() + pos.latitude.cos() * psi.sin() * az_radians.cos()).asin();
+		let lon_pp:f64 = pos.longitude + (psi.sin() * az_radians.sin() / lat_pp.cos()).asin();
+		let obliquity:f64 = (1.0 - (ratio * el_radians.cos()).powi(2)).powf(-0.5);
+
+		self.vertical_delay_m(lat_pp.to_degrees(), lon_pp.to_degrees(), tow_sec)
+			.map(|(delay_m, variance_m2)| (obliquity * delay_m, obliquity.powi(2) * variance_m2))
+	}
+
+	/// Corrected copies of the observations that have a current fast correction.  With a receiver position, the ionospheric
+	/// delay from the grid, or from the Klobuchar model where the grid doesn't reach, is taken out of the pseudoranges too, so
+	/// the result should be solved with the solver's own ionosphere correction turned off.  The URA of each observation is
+	/// replaced with the SBAS error bound, so it works with the URA weight model.
+	pub fn correct(&self, obs:&[Observation], tow_sec:f64, opt_pos_ecef:Option<(f64, f64, f64)>, opt_iono:Option<ionosphere::Model>) -> Vec<Observation> {
+		let opt_pos = opt_pos_ecef.map(|p| (p, kinematics::ecef_to_wgs84(p.0, p.1, p.2)));
+		obs.iter().filter_map(|o| {
+			let fc = self.fast.get(&o.sv_id).filter(|fc| (tow_sec - fc.tow_sec).abs() <= self.config.max_fast_age_sec)?;
+			let udre_variance_m2:f64 = message::udre_variance_m2(fc.udrei)?;
+			let mut corrected = Observation{ ..*o };
+			let mut correction_m:f64 = fc.at(tow_sec);
+
+			// Long-term corrections only apply to the ephemeris they were computed for
+			let ltc = self.long_term.get(&o.sv_id)
+				.filter(|(t, ltc)| (tow_sec - t).abs() <= self.config.max_long_term_age_sec && o.iodc.map(|iodc| (iodc & 0xFF) as u8) == Some(ltc.iode))
+				.map(|(_, ltc)| ltc);
+			if let Some(ltc) = ltc {
+				let dt:f64 = match ltc.t0_sec {
+					Some(t0_sec) => ((tow_sec - t0_sec) % SEC_PER_DAY + 1.5 * SEC_PER_DAY) % SEC_PER_DAY - 0.5 * SEC_PER_DAY,
+					None => 0.0,
+				};
+				corrected.pos_ecef = (o.pos_ecef.0 + ltc.dpos_m.0 + ltc.dvel_mps.0 * dt, o.pos_ecef.1 + ltc.dpos_m.1 + ltc.dvel_mps.1 * dt,
+					o.pos_ecef.2 + ltc.dpos_m.2 + ltc.dvel_mps.2 * dt);
+				let dclock_sec:f64 = ltc.dclock_sec + ltc.dclock_rate * dt;
+				corrected.sv_clock += dclock_sec;
+				corrected.sv_clock_drift += ltc.dclock_rate;
+				correction_m += dclock_sec * C;
+			}
+
+			// The covariance from message type 28 scales the UDRE to the user's geometry
+			let mut udre_scale:f64 = 1.0;
+			let mut uire_variance_m2:f64 = 0.0;
+			if let Some((p, pos)) = &opt_pos {
+				let los = (Vector3::new(corrected.pos_ecef.0, corrected.pos_ecef.1, corrected.pos_ecef.2) - Vector3::new(p.0, p.1, p.2)).normalize();
+				if let Some(cov) = self.covariance.get(&o.sv_id) {
+					let i = [los[0], los[1], los[2], 1.0];
+					udre_scale = (0..4).map(|j| (0..4).map(|k| i[j] * cov[j][k] * i[k]).sum::<f64>()).sum::<f64>().max(0.0).sqrt();
+				}
+
+				let (az_radians, el_radians) = kinematics::az_el(pos.latitude, pos.longitude, pos.height_above_ellipsoid, los);
+				match (self.slant_delay_m(pos, az_radians, el_radians, tow_sec), opt_iono) {
+					(Some((delay_m, variance_m2)), _) => {
+						correction_m -= delay_m;
+						uire_variance_m2 = variance_m2;
+					},
+					(None, Some(iono)) => correction_m -= iono.delay(az_radians, el_radians, pos.latitude, pos.longitude, o.sv_tow_sec) * C,
+					(None, None) => {},
+				}
+			}
+
+			let flt_m:f64 = udre_variance_m2.sqrt() * udre_scale + self.fast_degradation_m(o.sv_id, tow_sec - fc.tow_sec);
+			corrected.pseudorange_m     += correction_m;
+			corrected.pseudorange_raw_m += correction_m;
+			corrected.ura_m = Some((flt_m.powi(2) + uire_variance_m2).sqrt());
+			Some(corrected)
+		}).collect()
+	}
+
+	/// Solves with the SBAS corrections.  Without a starting position near the surface, a first solution with the Klobuchar
+	/// model provides the pierce points for the ionospheric grid.
+	pub fn solve_position_and_time(&self, obs:&[Observation], x0:Vector4<f64>, tow_sec:f64, current_rx_time:f64,
+		opt_iono:Option<ionosphere::Model>, config:&SolverConfig) -> Result<(GnssFix, Vector4<f64>), &'static str> {
+
+		let x_apriori:Vector4<f64> = if is_near_surface(&x0) { x0 }
+			else { super::solve_position_and_time(self.correct(obs, tow_sec, None, None), x0, current_rx_time, opt_iono, config)?.1 };
+		let corrected = self.correct(obs, tow_sec, Some((x_apriori[0], x_apriori[1], x_apriori[2])), opt_iono);
+		super::solve_position_and_time(corrected, x_apriori, current_rx_time, None, &SolverConfig{ ionosphere: false, ..*config })
+	}
+
+}
@@ -0,0 +1,652 @@
+use nalgebra::base::Vector3;
+
+use super::*;
+use super::ephemeris::test_ephemeris;
+
+#[test]
+fn sv_velocity_matches_position_derivative() {
+	let eph = test_ephemeris(0.2, -0.4);
+	let t:f64 = 5000.0;
+	let dt:f64 = 0.01;
+	let ((x0, y0, z0), clk0) = eph.pos_and_clock(t - dt);
+	let ((x1, y1, z1), clk1) = eph.pos_and_clock(t + dt);
+	let ((vx, vy, vz), drift) = eph.vel_and_clock_drift(t);
+	assert!(((x1 - x0)/(2.0*dt) - vx).abs() < 1.0e-3);
+	assert!(((y1 - y0)/(2.0*dt) - vy).abs() < 1.0e-3);
+	assert!(((z1 - z0)/(2.0*dt) - vz).abs() < 1.0e-3);
+	assert!(((clk1 - clk0)/(2.0*dt) - drift).abs() < 1.0e-14);
+}
+
+#[test]
+fn doppler_velocity_solution() {
+	// Receiver on the equator at the prime meridian heading northeast and climbing
+	let p_rx = Vector3::new(kinematics::WGS84_SEMI_MAJOR_AXIS_METERS, 0.0, 0.0);
+	let v_rx = Vector3::new(1.0, 10.0, 10.0);
+	let clock_drift:f64 = 2.0e-7;
+
+	let t:f64 = 5000.0;
+	let obs:Vec<Observation> = (0..6).map(|i| {
+		let eph = test_ephemeris(-0.1 + 0.08*(i as f64), -0.4 + 0.25*(i as f64));
+		let (pos_ecef, sv_clock) = eph.pos_and_clock(t);
+		let (sv_vel_ecef, sv_clock_drift) = eph.vel_and_clock_drift(t);
+		let los = (sagnac_rotation(Vector3::new(pos_ecef.0, pos_ecef.1, pos_ecef.2), p_rx).0 - p_rx).normalize();
+		let range_rate:f64 = (Vector3::new(sv_vel_ecef.0, sv_vel_ecef.1, sv_vel_ecef.2) - v_rx).dot(&los) + C*(clock_drift - sv_clock_drift);
+		let doppler_hz:f64 = -range_rate * L1_FREQ_HZ / C;
+		Observation{ sv_id: i+1, sv_tow_sec: t, pseudorange_m: 2.0e7, pseudorange_raw_m: 2.0e7, smoothing_epochs: 0, pos_ecef, sv_clock, t_gd: 0.0, carrier_freq_hz: doppler_hz,
+			doppler_hz, sv_vel_ecef, sv_clock_drift, cn0_dbhz: 45.0, ura_m: None,
+			carrier_phase_cycles: None, carrier_lock_count: 0, iodc: None }
+	}).collect();
+
+	let vel = solve_velocity_and_clock_drift(&obs, (p_rx[0], p_rx[1], p_rx[2])).unwrap();
+	assert!((vel.vel_ecef.0 - v_rx[0]).abs() < 1.0e-6 && (vel.vel_ecef.1 - v_rx[1]).abs() < 1.0e-6 && (vel.vel_ecef.2 - v_rx[2]).abs() < 1.0e-6);
+	assert!((vel.vel_ned.0 - 10.0).abs() < 1.0e-6 && (vel.vel_ned.1 - 10.0).abs() < 1.0e-6 && (vel.vel_ned.2 + 1.0).abs() < 1.0e-6);
+	assert!((vel.speed_mps - 200.0_f64.sqrt()).abs() < 1.0e-6);
+	assert!((vel.heading_radians - std::f64::consts::FRAC_PI_4).abs() < 1.0e-9);
+	assert!((vel.clock_drift - clock_drift).abs() < 1.0e-14);
+}
+
+// Receiver in mid-latitudes with SVs at the given azimuths and elevations [deg] and a clock bias of 1 ms
+fn sky(az_el_deg:&[(f64, f64)]) -> (Vector3<f64>, Vec<Observation>) {
+	let (lat, lon, h):(f64, f64, f64) = (0.7, -1.4, 300.0);
+	let n:f64 = kinematics::WGS84_SEMI_MAJOR_AXIS_METERS / (1.0 - 0.00669438*lat.sin().powi(2)).sqrt();
+	let p_rx = Vector3::new((n + h)*lat.cos()*lon.cos(), (n + h)*lat.cos()*lon.sin(), (n*(1.0 - 0.00669438) + h)*lat.sin());
+	let dcm_ew = kinematics::dcm_we(lat, lon).transpose();
+
+	let obs = az_el_deg.iter().enumerate().map(|(i, (az, el))| {
+		let (az, el) = (az.to_radians(), el.to_radians());
+		let los_e = dcm_ew * Vector3::new(el.cos()*az.sin(), el.cos()*az.cos(), el.sin());
+		let p_sv = p_rx + los_e * 2.2e7;
+		Observation{ sv_id: i+1, sv_tow_sec: 5000.0, pseudorange_m: 2.2e7 + 1.0e-3*C, pseudorange_raw_m: 2.2e7 + 1.0e-3*C, smoothing_epochs: 0,
+			pos_ecef: (p_sv[0], p_sv[1], p_sv[2]), sv_clock: 0.0, 
+			t_gd: 0.0, carrier_freq_hz: 0.0, doppler_hz: 0.0, sv_vel_ecef: (0.0, 0.0, 0.0), sv_clock_drift: 0.0, 
+			cn0_dbhz: 45.0, ura_m: Some(2.4), carrier_phase_cycles: None, carrier_lock_count: 0, iodc: None }
+	}).collect();
+	(p_rx, obs)
+}
+
+#[test]
+fn troposphere_correction() {
+	// Roughly 2.4 m at zenith at sea level, and about ten times that at five degrees elevation
+	let (hydrostatic, wet) = troposphere::zenith_delays(0.7, 0.0);
+	assert!((hydrostatic + wet - 2.45).abs() < 0.1);
+	assert!((troposphere::mapping(5.0_f64.to_radians()) - 10.0).abs() < 0.5);
+
+	let (p_rx, mut obs) = sky(&[(0.0, 85.0), (45.0, 30.0), (130.0, 15.0), (200.0, 50.0), (270.0, 10.0), (320.0, 25.0)]);
+	let wgs84 = kinematics::ecef_to_wgs84(p_rx[0], p_rx[1], p_rx[2]);
+	for ob in obs.iter_mut() {
+		let el:f64 = kinematics::az_el(wgs84.latitude, wgs84.longitude, wgs84.height_above_ellipsoid, 
+			(Vector3::new(ob.pos_ecef.0, ob.pos_ecef.1, ob.pos_ecef.2) - p_rx).normalize()).1;
+		ob.pseudorange_m += troposphere::delay(el, wgs84.latitude, wgs84.height_above_ellipsoid);
+	}
+
+	let position_error = |config:&SolverConfig| {
+		let (fix, _) = solve_position_and_time(obs.clone(), Vector4::zeros(), 0.0, None, config).unwrap();
+		let err = (Vector3::new(fix.pos_ecef.0, fix.pos_ecef.1, fix.pos_ecef.2) - p_rx).norm();
+		(err, fix.observations[0].1.tropo_delay_m())
+	};
+
+	// sky() gives SV positions in the frame at reception, so there's nothing for the Sagnac correction to do
+	let no_sagnac = SolverConfig{ sagnac: false, ..Default::default() };
+	let (err, tropo) = position_error(&no_sagnac);
+	assert!(err < 1.0e-3 && tropo.is_some());
+	let (err, tropo) = position_error(&SolverConfig{ troposphere: false, ..no_sagnac });
+	assert!(err > 1.0 && tropo.is_none());
+}
+
+#[test]
+fn sagnac_correction() {
+	// sky() gives SV positions in the frame at reception, so rotate them back to where they were in the frame at transmission
+	let (p_rx, mut obs) = sky(&[(0.0, 85.0), (45.0, 30.0), (130.0, 15.0), (200.0, 50.0), (270.0, 10.0), (320.0, 25.0)]);
+	for ob in obs.iter_mut() {
+		let p_sv_e = Vector3::new(ob.pos_ecef.0, ob.pos_ecef.1, ob.pos_ecef.2);
+		let (sin_theta, cos_theta) = (ephemeris::OMEGA_E * (p_sv_e - p_rx).norm() / C).sin_cos();
+		ob.pos_ecef = (cos_theta*p_sv_e[0] - sin_theta*p_sv_e[1], sin_theta*p_sv_e[0] + cos_theta*p_sv_e[1], p_sv_e[2]);
+	}
+
+	let position_error = |config:&SolverConfig| {
+		let (fix, _) = solve_position_and_time(obs.clone(), Vector4::zeros(), 0.0, None, config).unwrap();
+		(Vector3::new(fix.pos_ecef.0, fix.pos_ecef.1, fix.pos_ecef.2) - p_rx).norm()
+	};
+
+	let no_tropo = SolverConfig{ troposphere: false, ..Default::default() };
+	assert!(position_error(&no_tropo) < 1.0e-3);
+	assert!(position_error(&SolverConfig{ sagnac: false, ..no_tropo }) > 1.0);
+}
+
+#[test]
+fn sv_state_at_gps_time() {
+	// The clock correction is about 10 us here, so the orbit has to be evaluated tens of millimeters away from t_sv
+	let eph = test_ephemeris(0.2, -0.4);
+	let t_sv:f64 = 5000.0;
+	let state = eph.sv_state(t_sv);
+	assert!((state.t_gps + eph.dt_sv(state.t_gps) - t_sv).abs() < 1.0e-15);
+	assert_eq!(state.pos_ecef, eph.pos_and_clock(state.t_gps).0);
+	let relativistic:f64 = state.clock - eph.dt_sv(state.t_gps);
+	assert!(relativistic.abs() > 1.0e-10 && relativistic.abs() < 1.0e-7);
+}
+
+#[test]
+fn weighted_solution_and_accuracy() {
+	// A 10 m error on the lowest SV should matter less once low elevations are deweighted
+	let (p_rx, mut obs) = sky(&[(0.0, 85.0), (45.0, 30.0), (130.0, 15.0), (200.0, 50.0), (270.0, 5.0), (320.0, 25.0)]);
+	obs[4].pseudorange_m += 10.0;
+
+	let solve = |obs:&Vec<Observation>, weight_model:WeightModel| {
+		let config = SolverConfig{ troposphere: false, sagnac: false, weight_model, ..Default::default() };
+		let (fix, _) = solve_position_and_time(obs.clone(), Vector4::zeros(), 0.0, None, &config).unwrap();
+		((Vector3::new(fix.pos_ecef.0, fix.pos_ecef.1, fix.pos_ecef.2) - p_rx).norm(), fix)
+	};
+
+	let (err_unweighted, fix_unweighted) = solve(&obs, WeightModel::Unweighted{ sigma_m: 1.0 });
+	let (err_elevation, _) = solve(&obs, WeightModel::Elevation{ a_m: 0.3, b_m: 0.3 });
+	assert!(err_elevation < 0.5 * err_unweighted);
+
+	// Accuracy comes from the covariance, which is symmetric and inflated by the residuals since they exceed 1 m
+	let cov = fix_unweighted.covariance;
+	assert!((0..4).all(|i| (0..4).all(|j| (cov[i][j] - cov[j][i]).abs() < 1.0e-6)));
+	let position_variance:f64 = cov[0][0] + cov[1][1] + cov[2][2];
+	assert!((fix_unweighted.h_accuracy_m.powi(2) + fix_unweighted.v_accuracy_m.powi(2) - position_variance).abs() < 1.0e-6);
+	assert!(fix_unweighted.h_accuracy_m > 1.0 && fix_unweighted.v_accuracy_m > 1.0);
+
+	// With consistent measurements, the URA model gives accuracy on the order of the URA itself
+	obs[4].pseudorange_m -= 10.0;
+	let (_, fix_ura) = solve(&obs, WeightModel::Ura{ fallback_m: 100.0 });
+	assert!(fix_ura.h_accuracy_m > 2.4 && fix_ura.h_accuracy_m < 10.0);
+}
+
+#[test]
+fn dop_and_elevation_mask() {
+	let (_, obs) = sky(&[(0.0, 85.0), (45.0, 30.0), (130.0, 15.0), (200.0, 50.0), (270.0, 10.0), (320.0, 25.0)]);
+	let config = SolverConfig{ troposphere: false, sagnac: false, ..Default::default() };
+
+	let (fix, x) = solve_position_and_time(obs.clone(), Vector4::zeros(), 0.0, None, &config).unwrap();
+	let dop = fix.dop;
+	assert!((dop.hdop.powi(2) + dop.vdop.powi(2) - dop.pdop.powi(2)).abs() < 1.0e-9);
+	assert!((dop.pdop.powi(2) + dop.tdop.powi(2) - dop.gdop.powi(2)).abs() < 1.0e-9);
+	assert!(dop.hdop > 0.5 && dop.hdop < dop.vdop && dop.pdop < 5.0);
+	assert!(fix.masked_sv_ids.is_empty());
+	let (_, completed) = &fix.observations[4];
+	assert!((completed.az_radians - 270.0_f64.to_radians()).abs() < 1.0e-6 && (completed.el_radians - 10.0_f64.to_radians()).abs() < 1.0e-6);
+
+	// The same mask should apply whether or not the initial guess is good enough to compute elevations from
+	let masked = SolverConfig{ elevation_mask_radians: 12.0_f64.to_radians(), ..config };
+	for x0 in [Vector4::zeros(), x].iter() {
+		let (fix_masked, _) = solve_position_and_time(obs.clone(), *x0, 0.0, None, &masked).unwrap();
+		assert_eq!(fix_masked.masked_sv_ids, vec![5]);
+		assert_eq!(fix_masked.observations.len(), 5);
+		assert!(fix_masked.dop.pdop > dop.pdop);
+	}
+
+	let too_high = SolverConfig{ elevation_mask_radians: 20.0_f64.to_radians(), ..config };
+	assert!(solve_position_and_time(obs, Vector4::zeros(), 0.0, None, &too_high).is_err());
+}
+
+#[test]
+fn raim_fault_detection_and_exclusion() {
+	let (p_rx, mut obs) = sky(&[(0.0, 85.0), (45.0, 30.0), (130.0, 15.0), (200.0, 50.0), (270.0, 10.0), (320.0, 25.0), (100.0, 60.0)]);
+	let raim = SolverConfig{ troposphere: false, sagnac: false, raim: Some(raim::RaimConfig::default()), ..Default::default() };
+	let error = |fix:&GnssFix| (Vector3::new(fix.pos_ecef.0, fix.pos_ecef.1, fix.pos_ecef.2) - p_rx).norm();
+
+	let (fix, _) = solve_position_and_time(obs.clone(), Vector4::zeros(), 0.0, None, &raim).unwrap();
+	let report = fix.raim.clone().unwrap();
+	assert!(report.passed && report.excluded_sv_ids.is_empty() && report.dof == 3);
+	assert!(report.hpl_m > fix.h_accuracy_m && report.vpl_m > fix.v_accuracy_m);
+
+	// A 100 m error on one SV is detected, excluded, and the remaining SVs pass
+	obs[3].pseudorange_m += 100.0;
+	let (fix, _) = solve_position_and_time(obs.clone(), Vector4::zeros(), 0.0, None, &raim).unwrap();
+	let report = fix.raim.clone().unwrap();
+	assert!(report.passed && report.dof == 2);
+	assert_eq!(report.excluded_sv_ids, vec![4]);
+	assert!(error(&fix) < 1.0e-3);
+
+	// Without exclusion the fault is only flagged
+	let no_exclusion = SolverConfig{ raim: Some(raim::RaimConfig{ max_exclusions: 0, ..Default::default() }), ..raim };
+	let (fix, _) = solve_position_and_time(obs, Vector4::zeros(), 0.0, None, &no_exclusion).unwrap();
+	assert!(error(&fix) > 1.0);
+	let report = fix.raim.clone().unwrap();
+	assert!(!report.passed && report.test_statistic > report.threshold && report.excluded_sv_ids.is_empty());
+}
+
+#[test]
+fn ekf_tracks_through_outages_and_gates_outliers() {
+	// SVs fixed in space around a receiver moving east at 10 m/s with a drifting clock
+	let (p0, sky_obs) = sky(&[(0.0, 85.0), (45.0, 30.0), (130.0, 15.0), (200.0, 50.0), (270.0, 10.0), (320.0, 25.0)]);
+	let wgs84 = kinematics::ecef_to_wgs84(p0[0], p0[1], p0[2]);
+	let v_rx = kinematics::dcm_we(wgs84.latitude, wgs84.longitude).transpose() * Vector3::new(10.0, 0.0, 0.0);
+	let clock_drift:f64 = 1.0e-7;
+	let epoch = |t:f64, sv_count:usize| -> (Vector3<f64>, Vec<Observation>) {
+		let p_rx = p0 + v_rx * t;
+		let obs = sky_obs.iter().take(sv_count).map(|o| {
+			let los = (Vector3::new(o.pos_ecef.0, o.pos_ecef.1, o.pos_ecef.2) - p_rx).normalize();
+			let range_rate:f64 = -v_rx.dot(&los) + C*clock_drift;
+			let pseudorange_m:f64 = (Vector3::new(o.pos_ecef.0, o.pos_ecef.1, o.pos_ecef.2) - p_rx).norm() + 1.0e-3*C + C*clock_drift*t;
+			Observation{ pseudorange_m, doppler_hz: -range_rate * L1_FREQ_HZ / C, ..*o }
+		}).collect();
+		(p_rx, obs)
+	};
+
+	for estimate_acceleration in [false, true].iter() {
+		let solver = SolverConfig{ troposphere: false, sagnac: false, ..Default::default() };
+		let mut filter = ekf::NavigationFilter::new(ekf::EkfConfig{ estimate_acceleration: *estimate_acceleration, solver, ..Default::default() });
+		assert!(filter.update(&epoch(0.0, 3).1, 0.0, None).is_err());
+		for i in 0..20 {
+			let t:f64 = i as f64;
+			let (_, mut obs) = epoch(t, 6);
+			if i == 15 { obs[2].pseudorange_m += 200.0; }
+			let soln = filter.update(&obs, t, None).unwrap();
+			assert_eq!(soln.rejected_sv_ids, if i == 15 { vec![3] } else { vec![] });
+		}
+
+		// Three SVs aren't enough for a snapshot fix, but the filter keeps going, and so does pure propagation with none
+		for (i, sv_count) in [3, 3, 0, 0].iter().enumerate() {
+			let t:f64 = 20.0 + i as f64;
+			let (p_rx, obs) = epoch(t, *sv_count);
+			let soln = filter.update(&obs, t, None).unwrap();
+			assert_eq!(soln.pseudoranges_used, *sv_count);
+			assert!((Vector3::new(soln.pos_ecef.0, soln.pos_ecef.1, soln.pos_ecef.2) - p_rx).norm() < 1.0);
+			assert!((Vector3::new(soln.vel_ecef.0, soln.vel_ecef.1, soln.vel_ecef.2) - v_rx).norm() < 0.1);
+			assert!((soln.clock_drift - clock_drift).abs() < 1.0e-9);
+		}
+	}
+}
+
+#[test]
+fn dual_frequency_iono_free_solution() {
+	use crate::gnss::gps_l2c::channel::Observation as L2cObservation;
+
+	let (p_rx, obs) = sky(&[(0.0, 85.0), (45.0, 30.0), (130.0, 15.0), (200.0, 50.0), (270.0, 10.0), (320.0, 25.0)]);
+	let config = SolverConfig{ troposphere: false, sagnac: false, ..Default::default() };
+	let (isc_l1ca, isc_l2c) = (1.5e-9, -2.0e-9);
+	let gamma:f64 = (L1_FREQ_HZ / L2_FREQ_HZ).powi(2);
+
+	// Each SV gets its own L1 ionospheric delay, clock and T_GD.  IS-GPS-200K 30.3.3.3.1.1.1 corrects each signal with
+	// dt_SV - T_GD + ISC, so the raw measurements carry the opposite of that.
+	let iono_m:Vec<f64> = (0..obs.len()).map(|i| 3.0 + 2.5*(i as f64)).collect();
+	let sv_clock:Vec<f64> = (0..obs.len()).map(|i| 1.0e-4 * (i as f64) - 2.0e-4).collect();
+	let t_gd:Vec<f64> = (0..obs.len()).map(|i| -4.0e-9 + 3.0e-9*(i as f64)).collect();
+	let raw_l1:Vec<f64> = (0..obs.len()).map(|i| obs[i].pseudorange_m + iono_m[i] - C*(sv_clock[i] - t_gd[i] + isc_l1ca)).collect();
+	let raw_l2c:Vec<f64> = (0..obs.len()).map(|i| obs[i].pseudorange_m + gamma*iono_m[i] - C*(sv_clock[i] - t_gd[i] + isc_l2c)).collect();
+
+	// L1 observations come out of the channel corrected for the SV clock and T_GD
+	let l1_obs:Vec<Observation> = (0..obs.len()).map(|i| Observation{ pseudorange_m: raw_l1[i] + C*(sv_clock[i] - t_gd[i]),
+		sv_clock: sv_clock[i], t_gd: t_gd[i], ..obs[i] }).collect();
+	let l2c_obs:Vec<L2cObservation> = (1..obs.len()).map(|i| L2cObservation{ prn: obs[i].sv_id, sv_tow_sec: obs[i].sv_tow_sec,
+		pseudorange_m: raw_l2c[i], carrier_freq_hz: 0.0, clock: None }).collect();
+
+	let mut dual_obs = dual_frequency::pair_observations(&l1_obs, &l2c_obs);
+	assert_eq!(dual_obs.len(), obs.len() - 1);
+	for o in dual_obs.iter_mut() {
+		o.isc_l1ca = isc_l1ca;
+		o.isc_l2c = isc_l2c;
+	}
+
+	// The combination from the ICD: (PR_L2C - gamma*PR_L1CA + c*(ISC_L2C - gamma*ISC_L1CA)) / (1 - gamma) - c*T_GD, with both
+	// pseudoranges corrected for the SV clock
+	for (o, i) in dual_obs.iter().zip(1..) {
+		let (pr_l1ca, pr_l2c) = (raw_l1[i] + C*sv_clock[i], raw_l2c[i] + C*sv_clock[i]);
+		let icd:f64 = (pr_l2c - gamma*pr_l1ca + C*(isc_l2c - gamma*isc_l1ca)) / (1.0 - gamma) - C*t_gd[i];
+		assert!((o.iono_free_pseudorange_m() - icd).abs() < 1.0e-6, "SV {}", o.l1.sv_id);
+		assert!((o.iono_free_pseudorange_m() - obs[i].pseudorange_m).abs() < 1.0e-6, "SV {}", o.l1.sv_id);
+		assert!((o.l1_iono_delay_m() - iono_m[i]).abs() < 1.0e-6, "SV {}", o.l1.sv_id);
+	}
+
+	let (fix, _) = dual_frequency::solve_position_and_time(dual_obs, Vector4::zeros(), 0.0, &config).unwrap();
+	assert!((Vector3::new(fix.pos_ecef.0, fix.pos_ecef.1, fix.pos_ecef.2) - p_rx).norm() < 1.0e-3);
+	for ((sv_id, tec), iono) in fix.slant_tec_tecu.iter().zip(iono_m.iter().skip(1)) {
+		assert!((tec - iono * L1_FREQ_HZ.powi(2) / 40.3e16).abs() < 1.0e-6, "SV {}", sv_id);
+	}
+
+	// The same L1 pseudoranges on their own are biased by the ionosphere
+	let (fix, _) = solve_position_and_time(l1_obs, Vector4::zeros(), 0.0, None, &config).unwrap();
+	assert!((Vector3::new(fix.pos_ecef.0, fix.pos_ecef.1, fix.pos_ecef.2) - p_rx).norm() > 1.0);
+	assert!(fix.slant_tec_tecu.is_empty());
+}
+
+#[test]
+fn timing_mode_and_self_survey() {
+	let (p_rx, obs) = sky(&[(0.0, 85.0), (45.0, 30.0), (130.0, 15.0), (200.0, 50.0), (270.0, 10.0), (320.0, 25.0)]);
+	let config = SolverConfig{ troposphere: false, sagnac: false, raim: Some(raim::RaimConfig::default()), ..Default::default() };
+	let pos = (p_rx[0], p_rx[1], p_rx[2]);
+
+	// A single SV is enough from a known position, but there's no way to check it
+	let soln = timing::solve_time(&obs[..1], pos, 0.0, None, &config).unwrap();
+	assert!((soln.clock_bias_sec - 1.0e-3).abs() < 1.0e-12 && soln.clock_drift.unwrap().abs() < 1.0e-12);
+	assert_eq!((soln.integrity.dof, soln.integrity.passed), (0, None));
+	assert!((soln.integrity.sigma_sec * C - 5.0).abs() < 1.0e-9);
+
+	let mut faulty = obs.clone();
+	faulty[2].pseudorange_m += 100.0;
+	let soln = timing::solve_time(&faulty, pos, 0.0, None, &config).unwrap();
+	assert_eq!((soln.integrity.dof, soln.integrity.passed), (5, Some(false)));
+	assert!(soln.integrity.sigma_sec * C > 5.0 / 6.0_f64.sqrt());
+
+	// Survey for three epochs, then hold the averaged position and keep going with a single SV
+	let mut receiver = timing::TimingReceiver::new(timing::TimingMode::SelfSurvey(timing::SelfSurvey::new(3)), config);
+	for _ in 0..3 {
+		let soln = receiver.update(obs.clone(), 0.0, None).unwrap();
+		assert!(soln.surveying && soln.integrity.passed == Some(true));
+	}
+	match receiver.mode {
+		timing::TimingMode::PositionHold{ pos_ecef } => assert!((Vector3::new(pos_ecef.0, pos_ecef.1, pos_ecef.2) - p_rx).norm() < 1.0e-3),
+		_ => panic!("Survey should be complete"),
+	}
+	let soln = receiver.update(obs[..1].to_vec(), 0.0, None).unwrap();
+	assert!(!soln.surveying && (soln.clock_bias_sec - 1.0e-3).abs() < 1.0e-12);
+}
+
+#[test]
+fn vector_tracking_prediction() {
+	let (p_rx, sky_obs) = sky(&[(0.0, 85.0), (45.0, 30.0), (130.0, 15.0)]);
+	let wgs84 = kinematics::ecef_to_wgs84(p_rx[0], p_rx[1], p_rx[2]);
+	let v_rx = kinematics::dcm_we(wgs84.latitude, wgs84.longitude).transpose() * Vector3::new(10.0, 0.0, 0.0);
+	let soln = ekf::NavigationSolution{ current_rx_time: 0.0, pos_ecef: (p_rx[0], p_rx[1], p_rx[2]), vel_ecef: (v_rx[0], v_rx[1], v_rx[2]),
+		acc_ecef: None, clock_bias_m: 1.0e-3*C, clock_drift: 1.0e-7, h_accuracy_m: 5.0, v_accuracy_m: 5.0, pseudoranges_used: 0,
+		dopplers_used: 0, rejected_sv_ids: vec![] };
+	let solver = SolverConfig{ troposphere: false, sagnac: false, ..Default::default() };
+	let config = vector_tracking::VectorTrackingConfig::default();
+	let chip_m:f64 = C / vector_tracking::CHIP_RATE_HZ;
+
+	// An NCO running a tenth of a chip long needs to be pulled in, and a large error is fed back a bit at a time
+	for (offset_chips, expected_chips) in [(0.1, 0.1), (-0.2, -0.2), (3.0, 0.5)].iter() {
+		let obs = Observation{ pseudorange_m: sky_obs[1].pseudorange_m + offset_chips*chip_m, sv_vel_ecef: (0.0, 100.0, 0.0), ..sky_obs[1] };
+		let aiding = vector_tracking::predict(&obs, &soln, None, &solver, &config).unwrap();
+		assert!((aiding.code_correction_chips - expected_chips).abs() < 1.0e-6);
+
+		let los = (Vector3::new(obs.pos_ecef.0, obs.pos_ecef.1, obs.pos_ecef.2) - p_rx).normalize();
+		let range_rate:f64 = (Vector3::new(0.0, 100.0, 0.0) - v_rx).dot(&los) + C*1.0e-7;
+		assert!((aiding.doppler_hz + range_rate * L1_FREQ_HZ / C).abs() < 1.0e-6);
+		assert!((aiding.code_rate_chips_per_sec / vector_tracking::CHIP_RATE_HZ - 1.0 - aiding.doppler_hz / L1_FREQ_HZ).abs() < 1.0e-12);
+	}
+
+	// Too uncertain a solution would only drag the channels off
+	let poor = ekf::NavigationSolution{ h_accuracy_m: 100.0, ..soln };
+	assert!(vector_tracking::predict(&sky_obs[0], &poor, None, &solver, &config).is_err());
+}
+
+#[test]
+fn dgps_corrections() {
+	// Base and rover 2 km apart with errors common to both that grow over time, e.g. from the ionosphere
+	let (p_base, sky_obs) = sky(&[(0.0, 85.0), (45.0, 30.0), (130.0, 15.0), (200.0, 50.0), (270.0, 10.0), (320.0, 25.0)]);
+	let p_rover = p_base + Vector3::new(1200.0, -1000.0, 1200.0);
+	let sv_error_m = |i:usize, t:f64| 10.0 * (i as f64) - 25.0 + 0.5 * t * (i as f64);
+	let epoch = |p_rx:Vector3<f64>, t:f64, clock_bias_m:f64| dgps::ObservationEpoch{ tow_sec: 5000.0 + t, observations: sky_obs.iter().enumerate().map(|(i, o)| {
+		let pseudorange_m:f64 = (Vector3::new(o.pos_ecef.0, o.pos_ecef.1, o.pos_ecef.2) - p_rx).norm() + clock_bias_m + sv_error_m(i, t);
+		Observation{ pseudorange_m, pseudorange_raw_m: pseudorange_m, ..*o }
+	}).collect() };
+
+	let config = SolverConfig{ troposphere: false, sagnac: false, ..Default::default() };
+	let base_pos = (p_base[0], p_base[1], p_base[2]);
+	let base:Vec<dgps::ObservationEpoch> = (0..5).map(|i| epoch(p_base, 10.0*(i as f64), 300.0)).collect();
+	let rover:Vec<dgps::ObservationEpoch> = (0..10).map(|i| epoch(p_rover, 5.0 + 5.0*(i as f64), -700.0)).collect();
+	let error_m = |fix:&GnssFix| (Vector3::new(fix.pos_ecef.0, fix.pos_ecef.1, fix.pos_ecef.2) - p_rover).norm();
+
+	let (uncorrected, _) = solve_position_and_time(rover[0].observations.clone(), Vector4::zeros(), rover[0].tow_sec, None, &config).unwrap();
+	assert!(error_m(&uncorrected) > 10.0);
+
+	// Post-processed, the correction rate carries each rover epoch past the last base epoch
+	let fixes = dgps::post_process(&base, &rover, base_pos, None, &config, 30.0);
+	assert_eq!(fixes.len(), rover.len());
+	for fix in fixes.iter().skip(1) { assert!(error_m(fix) < 0.1); }
+
+	// Streaming, old corrections are dropped along with the SVs that needed them
+	let mut streaming = dgps::Dgps::new(base_pos, config, 30.0);
+	assert_eq!(streaming.add_base_epoch(&base[0], None).unwrap(), 6);
+	assert_eq!(streaming.correct(&rover[0].observations, rover[0].tow_sec).len(), 6);
+	assert!(streaming.correct(&rover[0].observations, rover[0].tow_sec + 60.0).is_empty());
+	assert_eq!(streaming.latest(1).unwrap().rrc_mps, 0.0);
+	streaming.add_base_epoch(&base[1], None).unwrap();
+
+	// The first SV's error is constant, but the mean over all of them grows at 1.25 m/s and ends up in the base clock bias
+	assert!((streaming.latest(1).unwrap().rrc_mps - 1.25).abs() < 1.0e-6);
+	let (fix, _) = streaming.solve_position_and_time(&rover[3].observations, Vector4::zeros(), rover[3].tow_sec, rover[3].tow_sec, None).unwrap();
+	assert!(error_m(&fix) < 0.1);
+
+	// After an ephemeris cutover on the base, the rate starts over and the rover has to be on the new issue too
+	let with_iodc = |epoch:&dgps::ObservationEpoch, iodc:u16| dgps::ObservationEpoch{ tow_sec: epoch.tow_sec,
+		observations: epoch.observations.iter().map(|o| Observation{ iodc: Some(iodc), ..*o }).collect() };
+	streaming.add_base_epoch(&with_iodc(&base[2], 7), None).unwrap();
+	assert_eq!(streaming.latest(1).unwrap().rrc_mps, 0.0);
+	assert!(streaming.correct(&rover[4].observations, rover[4].tow_sec).is_empty());
+	assert!(streaming.correct(&with_iodc(&rover[4], 6).observations, rover[4].tow_sec).is_empty());
+	assert_eq!(streaming.correct(&with_iodc(&rover[4], 7).observations, rover[4].tow_sec).len(), 6);
+	streaming.add_base_epoch(&with_iodc(&base[3], 7), None).unwrap();
+	assert!((streaming.latest(1).unwrap().rrc_mps - 1.25).abs() < 1.0e-6);
+}
+
+#[test]
+fn rtk_baseline() {
+	// Rover 300 m from the base, both drifting clocks, epochs 0.3 s apart and a few decimeters of code noise
+	let (p_base, sky_obs) = sky(&[(0.0, 85.0), (45.0, 30.0), (130.0, 15.0), (200.0, 50.0), (270.0, 20.0), (320.0, 35.0), (100.0, 60.0), (170.0, 25.0)]);
+	let p_rover = p_base + Vector3::new(-150.0, 200.0, 170.0);
+	let wavelength_m:f64 = C / L1_FREQ_HZ;
+	let noise = |k:usize, i:usize| 0.3 * ((k * 7919 + i * 104_729) as f64).sin();
+	let epoch = |p_rx:Vector3<f64>, k:usize, t:f64, clock_bias_m:f64, ambiguity:&dyn Fn(usize) -> (f64, usize)| dgps::ObservationEpoch{
+		tow_sec: 5000.0 + t,
+		observations: sky_obs.iter().enumerate().map(|(i, o)| {
+			let range_m:f64 = (Vector3::new(o.pos_ecef.0, o.pos_ecef.1, o.pos_ecef.2) - p_rx).norm() + clock_bias_m;
+			let (n, carrier_lock_count) = ambiguity(i);
+			Observation{ pseudorange_m: range_m + noise(k, i), pseudorange_raw_m: range_m + noise(k, i),
+				carrier_phase_cycles: Some(range_m / wavelength_m + n), carrier_lock_count, ..*o }
+		}).collect() };
+
+	// The base loses lock on one SV halfway through and comes back with a different ambiguity
+	let base:Vec<dgps::ObservationEpoch> = (0..60).map(|k| epoch(p_base, k, k as f64, 300.0 + 50.0*(k as f64),
+		&|i| if i == 3 && k >= 30 { (-77.0, 2) } else { (1000.0 + 37.0*(i as f64), 1) })).collect();
+	let rover:Vec<dgps::ObservationEpoch> = (0..60).map(|k| epoch(p_rover, k + 1000, (k as f64) + 0.3, -700.0 - 20.0*(k as f64),
+		&|i| (-500.0 + 11.0*(i as f64), 1))).collect();
+
+	let config = rtk::RtkConfig{ code_sigma_m: 0.3, solver: SolverConfig{ troposphere: false, sagnac: false, ..Default::default() }, ..Default::default() };
+	let solutions = rtk::post_process(&base, &rover, (p_base[0], p_base[1], p_base[2]), None, &config);
+	assert_eq!(solutions.len(), rover.len());
+
+	let baseline_error_m = |baseline:(f64, f64, f64)| (Vector3::new(baseline.0, baseline.1, baseline.2) - (p_rover - p_base)).norm();
+	for (k, soln) in solutions.iter().enumerate() {
+		assert!(soln.fixed && soln.ratio >= config.ratio_threshold);
+		assert!(baseline_error_m(soln.baseline_ecef) < 0.01);
+
+		// The SV the base just lost is left out for an epoch while its correction catches up
+		assert_eq!(soln.sv_ids.len(), if k == 30 { 7 } else { 8 });
+	}
+
+	let enu = solutions[59].baseline_enu;
+	assert!(((enu.0.powi(2) + enu.1.powi(2) + enu.2.powi(2)).sqrt() - (p_rover - p_base).norm()).abs() < 0.01);
+}
+
+#[test]
+fn integrity_monitor() {
+	use integrity::{Alert, IntegrityConfig, IntegrityMonitor};
+
+	let config = SolverConfig{ troposphere: false, sagnac: false, ..Default::default() };
+	let (_, obs) = sky(&[(0.0, 85.0), (45.0, 30.0), (130.0, 15.0), (200.0, 50.0), (270.0, 20.0), (320.0, 35.0)]);
+	let epoch = |rx_time:f64, cn0:&dyn Fn(usize) -> f64, pr_offset:&dyn Fn(usize) -> f64| {
+		let obs:Vec<Observation> = obs.iter().map(|o| Observation{ sv_tow_sec: o.sv_tow_sec + rx_time, cn0_dbhz: cn0(o.sv_id),
+			pseudorange_m: o.pseudorange_m + pr_offset(o.sv_id), pseudorange_raw_m: o.pseudorange_raw_m + pr_offset(o.sv_id), ..*o }).collect();
+		solve_position_and_time(obs, Vector4::zeros(), rx_time, None, &config).unwrap().0
+	};
+	let alerts = |events:Vec<integrity::IntegrityEvent>| events.into_iter().map(|e| e.alert).collect::<Vec<Alert>>();
+
+	// Identical C/N0 from every SV, then a realistic spread
+	let mut monitor = IntegrityMonitor::new(IntegrityConfig::default());
+	let uniform = alerts(monitor.check_fix(&epoch(0.0, &|_| 45.0, &|_| 0.0), 5000.0));
+	assert!(matches!(uniform[..], [Alert::UniformCn0{ sv_count: 6, .. }]));
+	let spread = |sv_id:usize| 38.0 + 2.0 * sv_id as f64;
+	assert!(monitor.check_fix(&epoch(0.5, &spread, &|_| 0.0), 5000.5).is_empty());
+
+	// One SV's code moves 100 m without any Doppler to match, then the time of the fix jumps by 10 us
+	let mismatch = alerts(monitor.check_fix(&epoch(1.0, &spread, &|sv_id| if sv_id == 3 { 100.0 } else { 0.0 }), 5001.0));
+	assert!(matches!(mismatch[..], [Alert::PseudorangeDopplerMismatch{ sv_id: 3, mismatch_mps }] if (mismatch_mps - 200.0).abs() < 1.0e-6));
+	let jump = alerts(monitor.check_fix(&epoch(1.5, &spread, &|sv_id| if sv_id == 3 { 100.0 } else { 0.0 }), 5001.50001));
+	assert!(matches!(jump[..], [Alert::ClockJump{ jump_m }] if (jump_m - 1.0e-5 * C).abs() < 1.0e-3));
+
+	// Ephemerides against the almanac, only once per IODC
+	let eph = test_ephemeris(0.2, -0.4);
+	let almanac = ephemeris::Almanac{ prn: 7, week_number: 100, sv_health: 0, t_oa: eph.t_oe, e: eph.e, delta_i: eph.i0 - 0.30,
+		omega_dot: eph.omega_dot, sqrt_a: eph.sqrt_a, omega0: eph.omega0, omega: eph.omega, m0: eph.m0, af0: eph.a_f0, af1: eph.a_f1 };
+	assert!(monitor.check_ephemeris(7, &eph, 5000.0).is_none());
+	monitor.set_almanac(almanac);
+	assert!(monitor.check_ephemeris(7, &eph, 5000.0).is_none());
+	let moved = ephemeris::Ephemeris{ m0: eph.m0 + 0.01, iodc: 13, ..eph };
+	assert!(matches!(monitor.check_ephemeris(7, &moved, 5000.0), Some(integrity::IntegrityEvent{ alert: Alert::EphemerisAlmanacMismatch{ sv_id: 7, .. }, .. })));
+	assert!(monitor.check_ephemeris(7, &moved, 5000.0).is_none());
+
+	// Input power rising 10 dB over its baseline
+	for k in 0..20 { assert!(monitor.check_input_power(k as f64, 30.0 + 0.1 * (k % 3) as f64).is_none()); }
+	assert!(monitor.check_input_power(21.0, 31.0).is_none());
+	assert!(matches!(monitor.check_input_power(22.0, 40.0), Some(integrity::IntegrityEvent{ alert: Alert::InputPowerRise{ .. }, .. })));
+}
+
+#[test]
+fn ins_through_outage() {
+	use super::ins::{ImuSample, InsConfig, InsFilter};
+
+	// Vehicle heading 30 degrees at 10 m/s in a straight line, level and with constant attitude in ECEF, so the IMU sees
+	// gravity, Coriolis and the earth's rotation plus its biases
+	let (lat, lon, yaw):(f64, f64, f64) = (0.7, -1.4, 30.0_f64.to_radians());
+	let c_ne = kinematics::dcm_ne(lat, lon);
+	let c_be = c_ne.transpose() * kinematics::dcm_from_euler(0.0, 0.0, yaw);
+	let p0 = kinematics::wgs84_to_ecef(lat, lon, 100.0);
+	let v = c_ne.transpose() * Vector3::new(10.0*yaw.cos(), 10.0*yaw.sin(), 0.0);
+	let w_ie = Vector3::new(0.0, 0.0, kinematics::OMEGA_E);
+	let (accel_bias, gyro_bias) = (Vector3::new(0.05, -0.03, 0.02), Vector3::new(2.0e-4, -1.0e-4, 3.0e-4));
+
+	let imu = |t:f64| {
+		let f_b = c_be.transpose() * (-ins::gravity_ecef(&(p0 + v*t)) + 2.0*w_ie.cross(&v)) + accel_bias;
+		let w_b = c_be.transpose() * w_ie + gyro_bias;
+		ImuSample{ t_sec: t, accel_mps2: (f_b[0], f_b[1], f_b[2]), gyro_radps: (w_b[0], w_b[1], w_b[2]) }
+	};
+	let fix = |t:f64| {
+		let p = p0 + v*t;
+		let mut covariance = [[0.0; 4]; 4];
+		for (i, row) in covariance.iter_mut().enumerate() { row[i] = 4.0; }
+		GnssFix{ pos_ecef: (p[0], p[1], p[2]), residual_norm: 0.0, current_rx_time: t, observations: vec![],
+			velocity: Some(Velocity{ vel_ecef: (v[0], v[1], v[2]), vel_ned: (10.0*yaw.cos(), 10.0*yaw.sin(), 0.0), speed_mps: 10.0,
+				heading_radians: yaw, clock_drift: 0.0 }),
+			covariance, h_accuracy_m: 2.8, v_accuracy_m: 2.0, dop: Dop{ gdop: 2.0, pdop: 1.7, hdop: 1.0, vdop: 1.4, tdop: 0.9 },
+			masked_sv_ids: vec![], raim: None, slant_tec_tecu: vec![], height_msl_m: 100.0, enu_error_m: None, inter_system_bias_m: None }
+	};
+
+	// Aided for two minutes with a heading that's off by two degrees, then 20 seconds without fixes
+	let samples:Vec<ImuSample> = (1..=14000).map(|i| imu(0.01 * (i as f64))).collect();
+	let fixes:Vec<GnssFix> = (1..=120).map(|i| fix(i as f64)).collect();
+	let solutions = ins::run(&samples, &fixes, 0.0, Some(yaw + 2.0_f64.to_radians()), InsConfig::default());
+	assert_eq!(solutions.len(), 14000 - 99);
+
+	// Swerving for the first ten seconds holds off leveling, and so alignment, until the second before the first fix after it
+	let swerving:Vec<ImuSample> = samples.iter().map(|s| if s.t_sec < 10.0 {
+		ImuSample{ accel_mps2: (s.accel_mps2.0, s.accel_mps2.1 + 2.0*(3.0*s.t_sec).sin(), s.accel_mps2.2), ..*s } } else { *s }).collect();
+	assert!(!ins::is_stationary(&swerving[..100], &InsConfig::default()));
+	assert_eq!(ins::run(&swerving, &fixes, 0.0, Some(yaw), InsConfig::default()).len(), 14000 - 1099);
+
+	let error = |s:&ins::InsSolution| (Vector3::new(s.pos_ecef.0, s.pos_ecef.1, s.pos_ecef.2) - (p0 + v*s.t_sec)).norm();
+	let aided = solutions.iter().find(|s| (s.t_sec - 120.0).abs() < 1.0e-6).unwrap();
+	assert!(error(aided) < 1.0);
+	// Without turns or acceleration, horizontal accelerometer bias can't be told from tilt and heading isn't observable at all,
+	// but the vertical bias is and the tilt takes up the rest
+	assert!((aided.accel_bias_mps2.2 - accel_bias[2]).abs() < 5.0e-3);
+	assert!(aided.roll_radians.abs() < 0.5_f64.to_radians() && aided.pitch_radians.abs() < 0.5_f64.to_radians());
+	let last = solutions.last().unwrap();
+	assert!((last.sec_since_fix - 20.0).abs() < 1.0e-6);
+	assert!(error(last) < 1.0);
+
+	// Fixes far from the solution fail the gate
+	let mut filter = InsFilter::new(InsConfig::default());
+	filter.initialize(&fix(0.0), 0.0, 0.0, 0.0, yaw);
+	let mut bad = fix(0.0);
+	bad.pos_ecef.0 += 500.0;
+	assert_eq!(filter.update(&bad, 0.0), Ok(false));
+	assert_eq!(filter.update(&fix(0.0), 0.0), Ok(true));
+}
+
+#[test]
+fn sbas_corrections() {
+	use crate::gnss::sbas_l1::message::{self, Message, MessageBody, FastCorrections, LongTermCorrection, IgpDelay};
+
+	// Errors that grow over time on every SV, a long-term correction that fixes the second SV's orbit and clock, and a
+	// uniform 4 m of vertical ionospheric delay
+	let (p_rx, sky_obs) = sky(&[(0.0, 85.0), (45.0, 30.0), (130.0, 15.0), (200.0, 50.0), (270.0, 10.0), (320.0, 25.0)]);
+	let sv_error_m = |i:usize, t:f64| 10.0 * (i as f64) - 25.0 + 0.5 * t * (i as f64);
+	let dpos_m:(f64, f64, f64) = (3.0, -2.0, 1.0);
+	let dclock_sec:f64 = 20.0 * 2.0_f64.powi(-31);
+	let obliquity = |el_deg:f64| (1.0 - (6378.1363e3 / 6728.1363e3 * el_deg.to_radians().cos()).powi(2)).powf(-0.5);
+	let els:Vec<f64> = vec![85.0, 30.0, 15.0, 50.0, 10.0, 25.0];
+	let obs:Vec<Observation> = sky_obs.iter().enumerate().map(|(i, o)| {
+		let mut pseudorange_m:f64 = (Vector3::new(o.pos_ecef.0, o.pos_ecef.1, o.pos_ecef.2) - p_rx).norm() + 500.0 + sv_error_m(i, 10.0) + 4.0 * obliquity(els[i]);
+		let mut pos_ecef = o.pos_ecef;
+		if i == 1 {
+			pos_ecef = (pos_ecef.0 - dpos_m.0, pos_ecef.1 - dpos_m.1, pos_ecef.2 - dpos_m.2);
+			pseudorange_m -= dclock_sec * C;
+		}
+		Observation{ pseudorange_m, pseudorange_raw_m: pseudorange_m, pos_ecef, iodc: Some(0x1A5), ..*o }
+	}).collect();
+
+	let mut sbas = sbas::Sbas::new(sbas::SbasConfig::default());
+	let fast = |t:f64| Message{ type_id: 2, body: MessageBody::FastCorrections(FastCorrections{ first_slot: 1, iodf: 0, iodp: 1,
+		prc_m: (0..6).map(|i| -sv_error_m(i, t)).collect(), udrei: vec![5; 6] }) };
+	sbas.add_message(&fast(0.0), 5000.0);
+	assert!(sbas.fast_correction(1).is_none());
+	sbas.add_message(&Message{ type_id: 1, body: MessageBody::PrnMask{ prns: (1..=6).collect(), iodp: 1 } }, 5000.0);
+	sbas.add_message(&fast(0.0), 5000.0);
+	sbas.add_message(&fast(6.0), 5006.0);
+	assert!((sbas.fast_correction(3).unwrap().rrc_mps + 1.0).abs() < 1.0e-9);
+	sbas.add_message(&Message{ type_id: 25, body: MessageBody::LongTermCorrections(vec![LongTermCorrection{ slot: 2, iode: 0xA5, iodp: 1,
+		dpos_m, dclock_sec, dvel_mps: (0.0, 0.0, 0.0), dclock_rate: 0.0, t0_sec: None }]) }, 5006.0);
+	for band in 0..=8 {
+		let n:usize = message::band_igps(band).unwrap().len();
+		sbas.add_message(&Message{ type_id: 18, body: MessageBody::IgpMask{ num_bands: 9, band, iodi: 2, mask: vec![true; n] } }, 5000.0);
+		for block in 0..(n + 14) / 15 {
+			sbas.add_message(&Message{ type_id: 26, body: MessageBody::IonosphericDelays{ band, block: block as u8, iodi: 2,
+				delays: vec![Some(IgpDelay{ vertical_delay_m: 4.0, givei: 3 }); 15] } }, 5000.0);
+		}
+	}
+	assert_eq!(sbas.num_igp_delays(), 8 * 201 + 200);
+	let (delay_m, variance_m2) = sbas.vertical_delay_m(40.1, -80.2, 5010.0).unwrap();
+	assert!((delay_m - 4.0).abs() < 1.0e-9 && (variance_m2 - 0.1331).abs() < 1.0e-9);
+
+	let config = SolverConfig{ troposphere: false, sagnac: false, ..Default::default() };
+	let error_m = |fix:&GnssFix| (Vector3::new(fix.pos_ecef.0, fix.pos_ecef.1, fix.pos_ecef.2) - p_rx).norm();
+	let (uncorrected, _) = solve_position_and_time(obs.clone(), Vector4::zeros(), 5010.0, None, &config).unwrap();
+	assert!(error_m(&uncorrected) > 10.0);
+
+	let (fix, _) = sbas.solve_position_and_time(&obs, Vector4::zeros(), 5010.0, 5010.0, None, &config).unwrap();
+	assert!(error_m(&fix) < 0.01);
+	let corrected = sbas.correct(&obs, 5010.0, Some(fix.pos_ecef), None);
+	assert!(corrected.iter().all(|o| o.ura_m.unwrap() > 0.8315_f64.sqrt()));
+
+	// Old fast corrections, or an ephemeris the long-term correction wasn't made for, aren't used
+	assert!(sbas.correct(&obs, 5030.0, None, None).is_empty());
+	let other_iodc:Vec<Observation> = obs.iter().map(|o| Observation{ iodc: Some(0x1A6), ..*o }).collect();
+	let (fix, _) = sbas.solve_position_and_time(&other_iodc, Vector4::zeros(), 5010.0, 5010.0, None, &config).unwrap();
+	assert!(error_m(&fix) > 0.1);
+}
+
+#[test]
+fn galileo_inter_system_bias() {
+	use crate::gnss::galileo_e1;
+
+	// Three of the seven SVs are Galileo, with their pseudoranges offset by a 30 ns bias
+	let (p_rx, mut obs) = sky(&[(0.0, 85.0), (45.0, 30.0), (130.0, 15.0), (200.0, 50.0), (270.0, 10.0), (320.0, 25.0), (100.0, 60.0)]);
+	let isb_m:f64 = 30.0e-9 * C;
+	for o in obs.iter_mut().skip(4) {
+		o.sv_id = galileo_e1::sv_id(o.sv_id);
+		o.pseudorange_m += isb_m;
+	}
+	let config = SolverConfig{ troposphere: false, sagnac: false, raim: Some(raim::RaimConfig::default()), ..Default::default() };
+	let error_m = |fix:&GnssFix| (Vector3::new(fix.pos_ecef.0, fix.pos_ecef.1, fix.pos_ecef.2) - p_rx).norm();
+
+	let (fix, x) = solve_position_and_time(obs.clone(), Vector4::zeros(), 0.0, None, &config).unwrap();
+	assert!(error_m(&fix) < 1.0e-3);
+	assert!((x[3] - 1.0e-3*C).abs() < 1.0e-3);
+	assert!((fix.inter_system_bias_m.unwrap() - isb_m).abs() < 1.0e-3);
+	assert!(fix.observations.iter().all(|(_, completed)| completed.residual.abs() < 1.0e-3));
+	let report = fix.raim.unwrap();
+	assert!(report.passed && report.dof == 2);
+
+	// With a single system there's no bias to estimate, and Galileo alone just has a larger clock bias
+	let galileo_only:Vec<Observation> = obs.iter().map(|o| if galileo_e1::is_galileo_sv_id(o.sv_id) { *o }
+		else { Observation{ sv_id: galileo_e1::sv_id(o.sv_id), pseudorange_m: o.pseudorange_m + isb_m, ..*o } }).collect();
+	let (fix, x) = solve_position_and_time(galileo_only, Vector4::zeros(), 0.0, None, &config).unwrap();
+	assert!(fix.inter_system_bias_m.is_none() && error_m(&fix) < 1.0e-3);
+	assert!((x[3] - (1.0e-3*C + isb_m)).abs() < 1.0e-3);
+	assert_eq!(fix.raim.unwrap().dof, 3);
+}
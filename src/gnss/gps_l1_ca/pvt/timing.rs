@@ -0,0 +1,153 @@
+
+use serde::{Serialize, Deserialize};
+use nalgebra::base::{Vector3, Vector4};
+
+use super::{C, L1_FREQ_HZ, Observation, SolverConfig, ionosphere, raim, sagnac_rotation};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct TimingIntegrity {
+	pub sigma_sec: f64,					// 1-sigma uncertainty of the clock bias, inflated if the residuals are larger than the weights predict
+	pub residual_rms_m: f64,			// Weighted RMS of the pseudorange residuals about the clock bias
+	pub dof: usize,						// One less than the number of SVs
+	pub test_statistic: f64,
+	pub threshold: f64,
+	pub passed: Option<bool>,			// None when there's only one SV, so there's nothing to check it against
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TimingSolution {
+	pub current_rx_time: f64,
+	pub pos_ecef: (f64, f64, f64),		// Position the clock was solved at
+	pub clock_bias_sec: f64,			// Receiver clock minus GPS time
+	pub clock_drift: Option<f64>,		// [sec/sec]
+	pub sv_ids: Vec<usize>,
+	pub surveying: bool,				// True while the position comes from a fix rather than a surveyed or held position
+	pub integrity: TimingIntegrity,
+}
+
+/// Receiver clock bias and drift from a known position, which needs as few as one SV
+pub fn solve_time(obs_this_soln:&[Observation], pos_ecef:(f64, f64, f64), current_rx_time:f64, opt_iono:Option<ionosphere::Model>,
+	config:&SolverConfig) -> Result<TimingSolution, &'static str> {
+
+	// With the clock term at zero, each residual is that SV's measurement of the clock bias
+	let x = Vector4::new(pos_ecef.0, pos_ecef.1, pos_ecef.2, 0.0);
+	let completed:Vec<(&Observation, super::CompletedObservation)> = obs_this_soln.iter()
+		.map(|obs| (obs, obs.complete(x, opt_iono, config)))
+		.filter(|(_, ob)| ob.el_radians >= config.elevation_mask_radians)
+		.collect();
+	if completed.is_empty() { return Err("No observations above the elevation mask"); }
+
+	let weights:Vec<f64> = completed.iter().map(|(obs, ob)| config.weight_model.sigma_m(obs, ob).powi(-2)).collect();
+	let sum_weights:f64 = weights.iter().sum();
+	let bias_m:f64 = completed.iter().zip(weights.iter()).map(|((_, ob), w)| w * ob.residual).sum::<f64>() / sum_weights;
+	if !bias_m.is_finite() { return Err("Solution is infinite"); }
+
+	let n:usize = completed.len();
+	let dof:usize = n - 1;
+	let test_statistic:f64 = completed.iter().zip(weights.iter()).map(|((_, ob), w)| w * (ob.residual - bias_m).powi(2)).sum();
+	let (threshold, passed) = match config.raim {
+		Some(raim_config) if dof > 0 => {
+			let threshold:f64 = raim::chi_squared_quantile(1.0 - raim_config.p_fa, dof);
+			(threshold, Some(test_statistic <= threshold))
+		},
+		_ => (f64::INFINITY, None),
+	};
+	let variance_factor:f64 = if dof > 0 { (test_statistic / dof as f64).max(1.0) } else { 1.0 };
+	let integrity = TimingIntegrity{ sigma_sec: (variance_factor / sum_weights).sqrt() / C, residual_rms_m: (test_statistic / sum_weights).sqrt(),
+		dof, test_statistic, threshold, passed };
+
+	// The receiver is stationary in ECEF, so each Doppler is the SV's motion along the line of sight plus the clock drift
+	let p_ob_e = Vector3::new(pos_ecef.0, pos_ecef.1, pos_ecef.2);
+	let drift_mps:f64 = completed.iter().map(|(obs, _)| {
+		let (p_sv_e, _) = sagnac_rotation(Vector3::new(obs.pos_ecef.0, obs.pos_ecef.1, obs.pos_ecef.2), p_ob_e);
+		let los_e = (p_sv_e - p_ob_e).normalize();
+		let range_rate:f64 = -obs.doppler_hz * C / L1_FREQ_HZ + obs.sv_clock_drift * C;
+		range_rate - Vector3::new(obs.sv_vel_ecef.0, obs.sv_vel_ecef.1, obs.sv_vel_ecef.2).dot(&los_e)
+	}).sum::<f64>() / n as f64;
+
+	Ok(TimingSolution{ current_rx_time, pos_ecef, clock_bias_sec: bias_m / C, clock_drift: if drift_mps.is_finite() { Some(drift_mps / C) } else { None },
+		sv_ids: completed.iter().map(|(obs, _)| obs.sv_id).collect(), surveying: false, integrity })
+}
+
+/// Averages position fixes until there are enough of them to hold the position for timing
+#[derive(Debug, Clone)]
+pub struct SelfSurvey {
+	pub target_fixes: usize,
+	count: usize,
+	sum: Vector3<f64>,
+	sum_sq: Vector3<f64>,
+}
+
+impl SelfSurvey {
+
+	pub fn new(target_fixes:usize) -> Self { Self{ target_fixes, count: 0, sum: Vector3::zeros(), sum_sq: Vector3::zeros() } }
+
+	pub fn add(&mut self, pos_ecef:(f64, f64, f64)) {
+		let p = Vector3::new(pos_ecef.0, pos_ecef.1, pos_ecef.2);
+		self.count += 1;
+		self.sum += p;
+		self.sum_sq += p.component_mul(&p);
+	}
+
+	pub fn count(&self) -> usize { self.count }
+	pub fn is_complete(&self) -> bool { self.count >= self.target_fixes }
+
+	pub fn mean(&self) -> Option<(f64, f64, f64)> {
+		if self.count == 0 { return None; }
+		let m = self.sum / self.count as f64;
+		Some((m[0], m[1], m[2]))
+	}
+
+	/// Standard deviation [m] of the fixes averaged so far, as the root sum of squares over the three axes
+	pub fn scatter_m(&self) -> Option<f64> {
+		if self.count < 2 { return None; }
+		let n:f64 = self.count as f64;
+		let m = self.sum / n;
+		let var = (self.sum_sq / n - m.component_mul(&m)) * (n / (n - 1.0));
+		Some(var.iter().map(|v| v.max(0.0)).sum::<f64>().sqrt())
+	}
+
+}
+
+#[derive(Debug, Clone)]
+pub enum TimingMode {
+	SelfSurvey(SelfSurvey),
+	PositionHold{ pos_ecef:(f64, f64, f64) },
+}
+
+/// Produces a clock solution every epoch, either from a full fix while a self-survey is running or from the held position
+/// afterwards.  The mode switches to position hold once the survey is complete.
+#[derive(Debug, Clone)]
+pub struct TimingReceiver {
+	pub mode: TimingMode,
+	pub config: SolverConfig,
+	x_last: Vector4<f64>,
+}
+
+impl TimingReceiver {
+
+	pub fn new(mode:TimingMode, config:SolverConfig) -> Self { Self{ mode, config, x_last: Vector4::zeros() } }
+
+	pub fn update(&mut self, obs_this_soln:Vec<Observation>, current_rx_time:f64, opt_iono:Option<ionosphere::Model>) -> Result<TimingSolution, &'static str> {
+		let (pos_ecef, surveying) = match &mut self.mode {
+			TimingMode::PositionHold{ pos_ecef } => (*pos_ecef, false),
+			TimingMode::SelfSurvey(survey) => {
+				let (fix, x) = super::solve_position_and_time(obs_this_soln.clone(), self.x_last, current_rx_time, opt_iono, &self.config)?;
+				self.x_last = Vector4::new(x[0], x[1], x[2], 0.0);
+				survey.add(fix.pos_ecef);
+				(fix.pos_ecef, true)
+			},
+		};
+
+		let soln = solve_time(&obs_this_soln, pos_ecef, current_rx_time, opt_iono, &self.config)?;
+
+		if let TimingMode::SelfSurvey(survey) = &self.mode {
+			if survey.is_complete() {
+				if let Some(pos_ecef) = survey.mean() { self.mode = TimingMode::PositionHold{ pos_ecef }; }
+			}
+		}
+
+		Ok(TimingSolution{ surveying, ..soln })
+	}
+
+}
@@ -0,0 +1,34 @@
+
+// Saastamoinen tropospheric delay using a standard atmosphere, with the Black and Eisner mapping function
+
+const TEMPERATURE_SEA_LEVEL_C:f64 = 15.0;
+const RELATIVE_HUMIDITY:f64 = 0.7;
+const MIN_HEIGHT_M:f64 = -1.0e3;		// Same bound as the solver uses for a position being near the surface
+const MAX_HEIGHT_M:f64 = 1.0e4;
+
+/// Zenith hydrostatic and wet delays [m] at the given latitude [rad] and height above the ellipsoid [m]
+pub fn zenith_delays(latitude_radians:f64, height_m:f64) -> (f64, f64) {
+	let h:f64 = height_m.max(0.0);
+
+	// Standard atmosphere
+	let pressure_hpa:f64 = 1013.25 * (1.0 - 2.2557e-5*h).powf(5.2568);
+	let temperature_k:f64 = TEMPERATURE_SEA_LEVEL_C - 6.5e-3*h + 273.16;
+	let water_vapor_hpa:f64 = 6.108 * RELATIVE_HUMIDITY * ((17.15*temperature_k - 4684.0) / (temperature_k - 38.45)).exp();
+
+	let hydrostatic:f64 = 0.0022768 * pressure_hpa / (1.0 - 0.00266*(2.0*latitude_radians).cos() - 0.00028*h/1.0e3);
+	let wet:f64 = 0.002277 * (1255.0/temperature_k + 0.05) * water_vapor_hpa;
+	(hydrostatic, wet)
+}
+
+/// Ratio of the slant delay to the zenith delay; unlike 1/sin(el), this stays bounded near the horizon
+pub fn mapping(el_radians:f64) -> f64 {
+	1.001 / (0.002001 + el_radians.sin().powi(2)).sqrt()
+}
+
+/// Slant tropospheric delay [m].  Zero for SVs below the horizon and for positions outside the troposphere (which
+/// includes the poor position estimates during the first few iterations of a fix).
+pub fn delay(el_radians:f64, latitude_radians:f64, height_m:f64) -> f64 {
+	if el_radians <= 0.0 || !(MIN_HEIGHT_M..=MAX_HEIGHT_M).contains(&height_m) { return 0.0; }
+	let (hydrostatic, wet) = zenith_delays(latitude_radians, height_m);
+	(hydrostatic + wet) * mapping(el_radians)
+}
@@ -0,0 +1,50 @@
+
+use serde::{Serialize, Deserialize};
+use nalgebra::base::{Vector3, Vector4};
+
+use super::{C, L1_FREQ_HZ, Observation, SolverConfig, ionosphere};
+use super::ekf::NavigationSolution;
+
+pub const CHIP_RATE_HZ:f64 = 1.023e6;
+
+#[derive(Debug, Clone, Copy)]
+pub struct VectorTrackingConfig {
+	pub max_h_accuracy_m: f64,				// Channels are left to track on their own while the solution is less certain than this
+	pub max_code_correction_chips: f64,		// Largest code phase step fed back to a channel in one update
+	pub max_coast_sec: f64,					// How long an aided channel keeps going after its signal drops below the lock threshold
+}
+
+impl Default for VectorTrackingConfig {
+	fn default() -> Self { Self{ max_h_accuracy_m: 30.0, max_code_correction_chips: 0.5, max_coast_sec: 5.0 } }
+}
+
+/// Code and carrier predicted for one channel from the navigation solution
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct ChannelAiding {
+	pub prn: usize,
+	pub code_correction_chips: f64,			// Positive when the predicted code is ahead of the channel's code NCO
+	pub code_rate_chips_per_sec: f64,
+	pub doppler_hz: f64,
+}
+
+/// Predicts the code phase and Doppler for one channel.  The observation should be built from the channel's code NCO alone,
+/// i.e. with the SV state at the NCO's time of transmission and a pseudorange that doesn't include the latest discriminator
+/// output, so the residual against the solution is how far the NCO has to move.
+pub fn predict(obs:&Observation, soln:&NavigationSolution, opt_iono:Option<ionosphere::Model>, solver:&SolverConfig,
+	config:&VectorTrackingConfig) -> Result<ChannelAiding, &'static str> {
+
+	if soln.h_accuracy_m > config.max_h_accuracy_m { return Err("Navigation solution is too uncertain to aid tracking"); }
+
+	let x = Vector4::new(soln.pos_ecef.0, soln.pos_ecef.1, soln.pos_ecef.2, soln.clock_bias_m);
+	let completed = obs.complete(x, opt_iono, solver);
+	let code_correction_chips:f64 = (completed.residual / C * CHIP_RATE_HZ).max(-config.max_code_correction_chips).min(config.max_code_correction_chips);
+
+	// Same Doppler model as the navigation filter, solved for the Doppler rather than the range rate
+	let los_e = Vector3::from_column_slice(&completed.p_r_e_norm);
+	let v_sv_e = Vector3::new(obs.sv_vel_ecef.0, obs.sv_vel_ecef.1, obs.sv_vel_ecef.2);
+	let v_rx_e = Vector3::new(soln.vel_ecef.0, soln.vel_ecef.1, soln.vel_ecef.2);
+	let range_rate:f64 = (v_sv_e - v_rx_e).dot(&los_e) + C*(soln.clock_drift - obs.sv_clock_drift);
+	let doppler_hz:f64 = -range_rate * L1_FREQ_HZ / C;
+
+	Ok(ChannelAiding{ prn: obs.sv_id, code_correction_chips, code_rate_chips_per_sec: CHIP_RATE_HZ * (1.0 + doppler_hz / L1_FREQ_HZ), doppler_hz })
+}
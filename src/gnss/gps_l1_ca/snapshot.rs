@@ -0,0 +1,184 @@
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use serde::{Serialize, Deserialize};
+use nalgebra::base::{DMatrix, DVector, Vector3};
+
+use crate::Sample;
+use crate::gnss::common::acquisition::{AcquisitionResult, basic_pcps};
+use crate::gnss::gps_l1_ca::{pvt, signal_modulation};
+use crate::gnss::gps_l1_ca::pvt::ephemeris::Ephemeris;
+
+const CODE_PERIOD_SEC:f64 = 1.0e-3;
+const MAX_ITER:usize = 20;
+const SV_COUNT_THRESHOLD:usize = 5;			// Position, clock bias, and the coarse time error
+
+/// Sub-millisecond timing of one SV's code, measured as the delay from the first sample of the snapshot until the next
+/// code epoch arrives
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct CodePhase {
+	pub prn: usize,
+	pub delay_sec: f64,				// In [0, 1 ms)
+	pub doppler_hz: f64,
+	pub test_statistic: f64,
+}
+
+impl CodePhase {
+
+	pub fn from_acquisition(acq:&AcquisitionResult, fs:f64, first_sample_idx:usize) -> Self {
+		// The correlation peak is code_phase samples into the block of mf_len samples that ends at sample_idx
+		let epoch_idx:f64 = (acq.sample_idx + 1 + acq.code_phase) as f64 - acq.mf_len as f64;
+		let delay_sec:f64 = ((epoch_idx - first_sample_idx as f64) / fs).rem_euclid(CODE_PERIOD_SEC);
+		Self{ prn: acq.id, delay_sec, doppler_hz: acq.doppler_hz, test_statistic: acq.test_statistic() }
+	}
+
+}
+
+/// Runs acquisition for each PRN over every whole code period in the snapshot and keeps the strongest result for each one
+/// that passes the threshold
+pub fn acquire(samples:&[Sample], fs:f64, prns:&[usize], doppler_max_hz:f64, doppler_step_hz:f64, test_statistic_threshold:f64) -> Vec<CodePhase> {
+	let first_sample_idx:usize = match samples.first() { Some(s) => s.idx, None => return vec![] };
+	let n_freqs:usize = (2.0 * doppler_max_hz / doppler_step_hz).round() as usize + 1;
+	let doppler_freqs:Vec<f64> = (0..n_freqs).map(|i| -doppler_max_hz + (i as f64)*doppler_step_hz).collect();
+
+	prns.iter().filter_map(|prn| {
+		let symbol = signal_modulation::prn_complex_sampled(*prn, fs);
+		let mut acq = basic_pcps::Acquisition::new(symbol, fs, *prn, test_statistic_threshold, doppler_freqs.clone());
+
+		let mut opt_best:Option<AcquisitionResult> = None;
+		for s in samples {
+			acq.provide_sample(s).ok()?;
+			if let Ok(Some(result)) = acq.block_for_result() {
+				if opt_best.as_ref().map(|best| result.test_statistic() > best.test_statistic()).unwrap_or(true) { opt_best = Some(result); }
+			}
+		}
+
+		opt_best.map(|best| CodePhase::from_acquisition(&best, fs, first_sample_idx))
+	}).collect()
+}
+
+/// Reads ephemerides in the JSON format the ephemerides example writes (keyed by PRN, week number, then IODC) and keeps the
+/// one for each PRN with t_oe closest to the given time
+pub fn load_ephemerides<R: Read>(reader:R, week_number:u16, tow_sec:f64) -> Result<HashMap<usize, Ephemeris>, &'static str> {
+	let data:HashMap<usize, HashMap<u16, HashMap<u16, Ephemeris>>> = serde_json::from_reader(reader).map_err(|_| "Unable to parse ephemerides JSON")?;
+
+	Ok(data.into_iter().filter_map(|(prn, weeks)| {
+		let closest = weeks.get(&week_number)?.values()
+			.min_by(|a, b| (a.t_oe - tow_sec).abs().partial_cmp(&(b.t_oe - tow_sec).abs()).unwrap_or(std::cmp::Ordering::Equal))?;
+		Some((prn, *closest))
+	}).collect())
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SnapshotFix {
+	pub pos_ecef: (f64, f64, f64),
+	pub clock_bias_m: f64,				// Includes the whole-millisecond part of the pseudoranges that's common to every SV
+	pub tow_sec: f64,					// GPS time of week of the first sample
+	pub time_correction_sec: f64,		// Applied to the a-priori time
+	pub sv_ids: Vec<usize>,
+	pub residual_norm: f64,
+}
+
+// Range [m], line of sight, range rate [m/s] and SV clock [sec] for a stationary receiver at GPS time t_rx
+fn predict(eph:&Ephemeris, p_rx:Vector3<f64>, t_rx:f64) -> (f64, Vector3<f64>, f64, f64) {
+	let mut transit_time_sec:f64 = 0.075;
+	let mut ans = (0.0, Vector3::zeros(), 0.0, 0.0);
+	for _ in 0..3 {
+		let ((x, y, z), clock) = eph.pos_and_clock(t_rx - transit_time_sec);
+		let ((vx, vy, vz), _) = eph.vel_and_clock_drift(t_rx - transit_time_sec);
+		let (p_sv_e, next_transit_time_sec) = pvt::sagnac_rotation(Vector3::new(x, y, z), p_rx);
+		let range:f64 = (p_sv_e - p_rx).norm();
+		let los_e = (p_sv_e - p_rx) / range;
+		ans = (range, los_e, Vector3::new(vx, vy, vz).dot(&los_e), clock);
+		transit_time_sec = next_transit_time_sec;
+	}
+	ans
+}
+
+/// Coarse-time navigation: solves for position, clock bias and the error in the a-priori time from sub-millisecond code
+/// phases alone.  The whole milliseconds of each pseudorange are reconstructed from the a-priori position and time relative
+/// to the closest SV, which works as long as the a-priori position is within roughly 100 km and the time within a couple of
+/// minutes.  The time error is observable through the SVs' range rates, so this needs one more SV than a normal fix.
+pub fn solve(code_phases:&[CodePhase], ephemerides:&HashMap<usize, Ephemeris>, apriori_pos_ecef:(f64, f64, f64),
+	apriori_tow_sec:f64) -> Result<SnapshotFix, &'static str> {
+
+	let usable:Vec<(CodePhase, Ephemeris)> = code_phases.iter().filter_map(|cp| ephemerides.get(&cp.prn).map(|eph| (*cp, *eph))).collect();
+	let n:usize = usable.len();
+	if n < SV_COUNT_THRESHOLD { return Err("Not enough SVs with ephemerides"); }
+
+	let p0 = Vector3::new(apriori_pos_ecef.0, apriori_pos_ecef.1, apriori_pos_ecef.2);
+	let code_period_m:f64 = CODE_PERIOD_SEC * pvt::C;
+
+	// Whole milliseconds for each SV relative to the reference SV, whose own whole milliseconds end up in the clock bias
+	let predicted:Vec<f64> = usable.iter().map(|(_, eph)| {
+		let (range, _, _, clock) = predict(eph, p0, apriori_tow_sec);
+		range - clock*pvt::C
+	}).collect();
+	let fractional:Vec<f64> = usable.iter().map(|(cp, _)| cp.delay_sec * pvt::C).collect();
+	let reference:usize = (0..n).min_by(|a, b| predicted[*a].partial_cmp(&predicted[*b]).unwrap_or(std::cmp::Ordering::Equal)).unwrap_or(0);
+	let reference_offset_m:f64 = predicted[reference] - fractional[reference] - (predicted[reference] - fractional[reference]).div_euclid(code_period_m) * code_period_m;
+	let pseudoranges:Vec<f64> = (0..n).map(|i| {
+		let whole_ms:f64 = ((predicted[i] - fractional[i] - reference_offset_m) / code_period_m).round();
+		fractional[i] + whole_ms * code_period_m
+	}).collect();
+
+	// State is position, clock bias [m] and the correction to the a-priori time [sec]
+	let mut x = DVector::from_vec(vec![p0[0], p0[1], p0[2], 0.0, 0.0]);
+	let mut v = DVector::from_element(n, 0.0);
+	for _ in 0..MAX_ITER {
+		let p_rx = Vector3::new(x[0], x[1], x[2]);
+		let mut h = DMatrix::from_element(n, 5, 0.0);
+		for (i, (_, eph)) in usable.iter().enumerate() {
+			let (range, los_e, range_rate, clock) = predict(eph, p_rx, apriori_tow_sec + x[4]);
+			v[i] = pseudoranges[i] - (range - clock*pvt::C + x[3]);
+			for j in 0..3 { h[(i,j)] = -los_e[j]; }
+			h[(i,3)] = 1.0;
+			h[(i,4)] = range_rate;
+		}
+
+		let dx = (h.transpose() * &h).try_inverse().ok_or("Non-invertible matrix")? * h.transpose() * &v;
+		x += &dx;
+		if !x.iter().all(|a| a.is_finite()) { return Err("Solution is infinite"); }
+
+		if dx.rows(0, 4).norm() < 1.0e-3 && dx[4].abs() < 1.0e-6 {
+			return Ok(SnapshotFix{ pos_ecef: (x[0], x[1], x[2]), clock_bias_m: x[3], tow_sec: apriori_tow_sec + x[4],
+				time_correction_sec: x[4], sv_ids: usable.iter().map(|(cp, _)| cp.prn).collect(), residual_norm: v.norm() });
+		}
+	}
+
+	Err("Solution did not converge")
+}
+
+#[test]
+fn test_coarse_time_solution() {
+	let p_rx:Vector3<f64> = crate::utils::kinematics::wgs84_to_ecef(0.7, -1.4, 300.0);
+
+	// Six planes of eight SVs, keeping the ones more than 10 degrees above the horizon
+	let t_true:f64 = 5000.25;
+	let rx_clock_sec:f64 = 3.7e-4;
+	let mut ephemerides:HashMap<usize, Ephemeris> = HashMap::new();
+	let mut code_phases:Vec<CodePhase> = vec![];
+	for plane in 0..6 {
+		for slot in 0..8 {
+			let prn:usize = 1 + 8*plane + slot;
+			let e = pvt::ephemeris::test_ephemeris(-1.0 + (plane as f64)/3.0, -1.0 + (slot as f64)/4.0 + (plane as f64)/24.0);
+			let (range, los_e, _, clock) = predict(&e, p_rx, t_true);
+			if los_e.dot(&p_rx.normalize()) < 10.0_f64.to_radians().sin() { continue; }
+			ephemerides.insert(prn, e);
+			let delay_sec:f64 = (range/pvt::C - clock + rx_clock_sec - t_true).rem_euclid(CODE_PERIOD_SEC);
+			code_phases.push(CodePhase{ prn, delay_sec, doppler_hz: 0.0, test_statistic: 1.0 });
+		}
+	}
+	assert!(code_phases.len() >= SV_COUNT_THRESHOLD);
+
+	// A-priori position 20 km off and time a minute off
+	let p_apriori = p_rx + Vector3::new(12.0e3, -10.0e3, 12.0e3);
+	let fix = solve(&code_phases, &ephemerides, (p_apriori[0], p_apriori[1], p_apriori[2]), t_true + 60.0).unwrap();
+	assert!((Vector3::new(fix.pos_ecef.0, fix.pos_ecef.1, fix.pos_ecef.2) - p_rx).norm() < 0.1);
+	assert!((fix.tow_sec - t_true).abs() < 1.0e-3);
+	assert!(fix.residual_norm < 1.0e-3);
+
+	// Too few SVs to solve for the time error as well
+	assert!(solve(&code_phases[..4], &ephemerides, (p_apriori[0], p_apriori[1], p_apriori[2]), t_true).is_err());
+}
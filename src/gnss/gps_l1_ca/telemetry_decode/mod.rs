@@ -5,6 +5,7 @@ use std::collections::VecDeque;
 
 use crate::DigSigProcErr;
 use crate::block::{BlockFunctionality, BlockResult};
+use crate::gnss::common::nav_data_stats::NavDataStats;
 use crate::gnss::common::tracking::TrackReport;
 
 /*	GPS Telemetry Decoding Pipeline:
@@ -20,50 +21,134 @@ mod preamble_detector;
 
 pub mod subframe;
 
-fn parity_check(word:&Vec<bool>, last_D29:bool, last_D30:bool) -> bool {
+// Computes the six parity bits D25 through D30 for the 24 source data bits d1 through d24 (IS-GPS-200K, Table 20-XIV)
+pub(crate) fn parity_bits(d:&[bool], last_D29:bool, last_D30:bool) -> [bool; 6] {
+	[last_D29 ^ d[0] ^ d[1] ^ d[2] ^ d[4] ^ d[5] ^ d[9]  ^ d[10] ^ d[11] ^ d[12] ^ d[13] ^ d[16] ^ d[17] ^ d[19] ^ d[22],
+	 last_D30 ^ d[1] ^ d[2] ^ d[3] ^ d[5] ^ d[6] ^ d[10] ^ d[11] ^ d[12] ^ d[13] ^ d[14] ^ d[17] ^ d[18] ^ d[20] ^ d[23],
+	 last_D29 ^ d[0] ^ d[2] ^ d[3] ^ d[4] ^ d[6] ^ d[7]  ^ d[11] ^ d[12] ^ d[13] ^ d[14] ^ d[15] ^ d[18] ^ d[19] ^ d[21],
+	 last_D30 ^ d[1] ^ d[3] ^ d[4] ^ d[5] ^ d[7] ^ d[8]  ^ d[12] ^ d[13] ^ d[14] ^ d[15] ^ d[16] ^ d[19] ^ d[20] ^ d[22],
+	 last_D30 ^ d[0] ^ d[2] ^ d[4] ^ d[5] ^ d[6] ^ d[8]  ^ d[9]  ^ d[13] ^ d[14] ^ d[15] ^ d[16] ^ d[17] ^ d[20] ^ d[21] ^ d[23],
+	 last_D29 ^ d[2] ^ d[4] ^ d[5] ^ d[7] ^ d[8] ^ d[9]  ^ d[10] ^ d[12] ^ d[14] ^ d[18] ^ d[21] ^ d[22] ^ d[23]]
+}
+
+fn parity_check(word:&[bool], last_D29:bool, last_D30:bool) -> bool {
 	if word.len() != 30 { panic!("Word length must be 30 bits"); }
 
-	let d:Vec<bool> = word.iter().take(24).map(|b| b ^ last_D30).collect();
+	let mut d:[bool; 24] = [false; 24];
+	for (d_i, b) in d.iter_mut().zip(word.iter()) { *d_i = b ^ last_D30; }
 
-	let mut parity:Vec<bool> = vec![];
-	parity.push(last_D29 ^ d[0] ^ d[1] ^ d[2] ^ d[4] ^ d[5] ^ d[9]  ^ d[10] ^ d[11] ^ d[12] ^ d[13] ^ d[16] ^ d[17] ^ d[19] ^ d[22]);
-	parity.push(last_D30 ^ d[1] ^ d[2] ^ d[3] ^ d[5] ^ d[6] ^ d[10] ^ d[11] ^ d[12] ^ d[13] ^ d[14] ^ d[17] ^ d[18] ^ d[20] ^ d[23]);
-	parity.push(last_D29 ^ d[0] ^ d[2] ^ d[3] ^ d[4] ^ d[6] ^ d[7]  ^ d[11] ^ d[12] ^ d[13] ^ d[14] ^ d[15] ^ d[18] ^ d[19] ^ d[21]);
-	parity.push(last_D30 ^ d[1] ^ d[3] ^ d[4] ^ d[5] ^ d[7] ^ d[8]  ^ d[12] ^ d[13] ^ d[14] ^ d[15] ^ d[16] ^ d[19] ^ d[20] ^ d[22]);
-	parity.push(last_D30 ^ d[0] ^ d[2] ^ d[4] ^ d[5] ^ d[6] ^ d[8]  ^ d[9]  ^ d[13] ^ d[14] ^ d[15] ^ d[16] ^ d[17] ^ d[20] ^ d[21] ^ d[23]);
-	parity.push(last_D29 ^ d[2] ^ d[4] ^ d[5] ^ d[7] ^ d[8] ^ d[9]  ^ d[10] ^ d[12] ^ d[14] ^ d[18] ^ d[21] ^ d[22] ^ d[23]);
+	let parity:[bool; 6] = parity_bits(&d, last_D29, last_D30);
 
 	word.iter().skip(24).zip(parity.iter()).map(|(a,b)| a == b).fold(true, |a,b| a & b)
 }
 
-fn data_recover(subframe:[bool; SUBFRAME_SIZE_W_PARITY_BITS]) -> Result<[bool; SUBFRAME_SIZE_DATA_ONLY_BITS], DigSigProcErr> {
+/// Controls how hard the decoder tries to recover subframes with parity failures.  The default disables all correction, so any
+/// word failing parity causes the whole subframe to be rejected.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ErrorCorrection {
+	/// Maximum number of words per subframe that may be corrected; subframes needing more than this are rejected.  Zero disables correction.
+	pub max_corrected_words: usize,
+	/// Number of weakest soft bits in a word to consider for double-bit flips once single-bit flips fail.  Values below two disable
+	/// double-bit correction, as does feeding the decoder hard bits through `apply_sample`.
+	pub double_bit_candidates: usize,
+	/// Accept subframes with uncorrectable words as long as the TLM, HOW and every word the subframe's contents depend on are valid
+	pub word_level_acceptance: bool,
+}
+
+// Tries to correct a word that fails parity by flipping one bit, then by flipping pairs of the weakest bits in order of increasing
+// combined magnitude.  The (32,26) Hamming code has a minimum distance of four, so a single-bit correction is unambiguous, while a
+// double-bit correction is only a best guess and is why it's restricted to bits the tracker was unsure about.
+fn correct_word(word:&mut [bool], soft:&[Option<f64>], last_D29:bool, last_D30:bool, double_bit_candidates:usize) -> bool {
+	for i in 0..30 {
+		word[i] = !word[i];
+		if parity_check(word, last_D29, last_D30) { return true; }
+		word[i] = !word[i];
+	}
+
+	if double_bit_candidates >= 2 {
+		if let Some(magnitudes) = soft.iter().cloned().collect::<Option<Vec<f64>>>() {
+			let mut order:Vec<usize> = (0..30).collect();
+			order.sort_by(|a, b| magnitudes[*a].partial_cmp(&magnitudes[*b]).unwrap_or(std::cmp::Ordering::Equal));
+			order.truncate(double_bit_candidates);
+
+			let mut pairs:Vec<(usize, usize)> = order.iter().enumerate()
+				.flat_map(|(n, a)| order.iter().skip(n+1).map(move |b| (*a, *b))).collect();
+			pairs.sort_by(|(a0, b0), (a1, b1)| (magnitudes[*a0] + magnitudes[*b0]).partial_cmp(&(magnitudes[*a1] + magnitudes[*b1])).unwrap_or(std::cmp::Ordering::Equal));
+
+			for (a, b) in pairs {
+				word[a] = !word[a];
+				word[b] = !word[b];
+				if parity_check(word, last_D29, last_D30) { return true; }
+				word[a] = !word[a];
+				word[b] = !word[b];
+			}
+		}
+	}
+
+	false
+}
+
+// Outcome of checking, and possibly correcting, all ten words in a subframe
+struct WordCheck {
+	parity_failures: usize,
+	words_corrected: usize,
+	failed_words: u16,		// Bitmask over word indices of the words that still fail parity
+}
+
+fn check_words(subframe:&mut [bool; SUBFRAME_SIZE_W_PARITY_BITS], soft:&[Option<f64>], ec:&ErrorCorrection) -> WordCheck {
+	let mut ans = WordCheck{ parity_failures: 0, words_corrected: 0, failed_words: 0 };
+	for word_idx in 0..10 {
+		// Working forward through the subframe means any correction to D29 or D30 is already in place when checking the next word
+		let (last_D29, last_D30) = if word_idx == 0 { (false, false) } else { (subframe[(30*word_idx)-2], subframe[(30*word_idx)-1]) };
+		let range = (30*word_idx)..(30*word_idx + 30);
+		if !parity_check(&subframe[range.clone()], last_D29, last_D30) {
+			ans.parity_failures += 1;
+			if ans.words_corrected < ec.max_corrected_words && correct_word(&mut subframe[range.clone()], &soft[range], last_D29, last_D30, ec.double_bit_candidates) {
+				ans.words_corrected += 1;
+			} else {
+				ans.failed_words |= 1 << word_idx;
+			}
+		}
+	}
+	ans
+}
+
+// Bitmask over word indices of the words needed to decode this subframe.  The TLM, HOW and word 3 (which identifies the page in
+// subframes 4 and 5) are always needed; subframe 1 words 4 through 6 and the reserved pages of subframe 4 carry nothing we decode.
+fn needed_words(data:&[bool; SUBFRAME_SIZE_DATA_ONLY_BITS]) -> u16 {
+	let subframe_id:u8 = (data[43] as u8) << 2 | (data[44] as u8) << 1 | (data[45] as u8);
+	let sv_id:u8 = data[50..56].iter().fold(0, |acc, b| (acc << 1) | (*b as u8));
+	match (subframe_id, sv_id) {
+		(1, _) => 0b11_1100_0111,
+		(4, 25..=32) | (4, 52) | (4, 55) | (4, 56) | (4, 63) => 0b11_1111_1111,
+		(4, _) => 0b00_0000_0111,
+		(_, _) => 0b11_1111_1111,
+	}
+}
+
+// Returns the actual data without the parity bits; the data bits of any word still failing parity are cleared
+fn data_recover(subframe:&[bool; SUBFRAME_SIZE_W_PARITY_BITS], failed_words:u16) -> [bool; SUBFRAME_SIZE_DATA_ONLY_BITS] {
 	let mut ans:[bool; SUBFRAME_SIZE_DATA_ONLY_BITS] = [false; SUBFRAME_SIZE_DATA_ONLY_BITS];
 
-	if !parity_check(&subframe[  0..30 ].to_vec(), false,         false) ||
-	   !parity_check(&subframe[ 30..60 ].to_vec(), subframe[28],  subframe[29]) ||
-	   !parity_check(&subframe[ 60..90 ].to_vec(), subframe[58],  subframe[59]) ||
-	   !parity_check(&subframe[ 90..120].to_vec(), subframe[88],  subframe[89]) ||
-	   !parity_check(&subframe[120..150].to_vec(), subframe[118], subframe[119]) ||
-	   !parity_check(&subframe[150..180].to_vec(), subframe[148], subframe[149]) ||
-	   !parity_check(&subframe[180..210].to_vec(), subframe[178], subframe[179]) ||
-	   !parity_check(&subframe[210..240].to_vec(), subframe[208], subframe[209]) ||
-	   !parity_check(&subframe[240..270].to_vec(), subframe[238], subframe[239]) ||
-	   !parity_check(&subframe[270..300].to_vec(), subframe[268], subframe[269])
-	   { return Err(DigSigProcErr::InvalidTelemetryData("Bad parity check")); }
-
-	for bit_idx in 0..24 { ans[bit_idx] = subframe[bit_idx]; }
+	ans[0..24].copy_from_slice(&subframe[0..24]);
 	for sf_idx in 1..10 {
 		for bit_idx in 0..24 { ans[(24*sf_idx)+bit_idx] = subframe[(30*sf_idx)+bit_idx] ^ subframe[(30*sf_idx)-1]; }
 	}
+	for sf_idx in (0..10).filter(|i| failed_words & (1 << i) != 0) {
+		for b in ans[(24*sf_idx)..(24*sf_idx + 24)].iter_mut() { *b = false; }
+	}
 
-	Ok(ans)
+	ans
 }
 
 pub struct TelemetryDecoder {
 	detector: preamble_detector::PreambleDetector,
-	detection_buffer:VecDeque<(bool, usize)>,
+	detection_buffer:VecDeque<(bool, usize, Option<f64>)>,
 	state: TelemetryDecoderState,
-	idx_buffer: VecDeque<usize>
+	idx_buffer: VecDeque<usize>,
+	last_inverse_sense: Option<bool>,
+	pub error_correction: ErrorCorrection,
+	pub stats: NavDataStats,
 }
 
 impl BlockFunctionality<(), bool, TrackReport, (usize, subframe::Subframe, usize)> for TelemetryDecoder {
@@ -73,8 +158,7 @@ impl BlockFunctionality<(), bool, TrackReport, (usize, subframe::Subframe, usize
 	}
 
 	fn apply(&mut self, input:&TrackReport) -> BlockResult<(usize, subframe::Subframe, usize)> {
-		let bit = (input.prompt_i > 0.0, input.sample_idx);
-		match self.apply_sample(bit) {
+		match self.apply_soft_sample(input.prompt_i, input.sample_idx) {
 			TelemetryDecoderResult::NotReady => BlockResult::NotReady,
 			TelemetryDecoderResult::Err(e)   => BlockResult::Err(e),
 			TelemetryDecoderResult::Ok(sf, _, last_idx) => BlockResult::Ready((input.id, sf, last_idx))
@@ -95,9 +179,17 @@ impl TelemetryDecoder {
 		TelemetryDecoder{ detector: preamble_detector::new_preamble_detector(), 
 						  detection_buffer: VecDeque::new(),
 						  state: TelemetryDecoderState::LookingForPreamble,
-						  idx_buffer: VecDeque::new() }
+						  idx_buffer: VecDeque::new(),
+						  last_inverse_sense: None,
+						  error_correction: Default::default(),
+						  stats: Default::default() }
+	}
+
+	pub fn new_with_error_correction(error_correction:ErrorCorrection) -> TelemetryDecoder {
+		TelemetryDecoder{ error_correction, ..Self::new() }
 	}
 
+	// Note that the stats and the last known polarity aren't cleared here so that they cover the whole life of the decoder
 	pub fn initialize(&mut self) {
 		self.detector.initialize();
 		self.detection_buffer.clear();
@@ -108,7 +200,16 @@ impl TelemetryDecoder {
 	/// Takes a bit tuple in the form of a boolean representing a bit and a usize representing the sample index where this symbol ended.
 	/// Returns a TelemetryDecoderResult
 	pub fn apply_sample(&mut self, bit:(bool, usize)) -> TelemetryDecoderResult {
-		self.idx_buffer.push_back(bit.1);
+		self.apply_bit(bit.0, bit.1, None)
+	}
+
+	/// Same as `apply_sample`, but takes the prompt correlator value itself so its magnitude can guide double-bit error correction
+	pub fn apply_soft_sample(&mut self, prompt_i:f64, sample_idx:usize) -> TelemetryDecoderResult {
+		self.apply_bit(prompt_i > 0.0, sample_idx, Some(prompt_i.abs()))
+	}
+
+	fn apply_bit(&mut self, bit:bool, sample_idx:usize, opt_magnitude:Option<f64>) -> TelemetryDecoderResult {
+		self.idx_buffer.push_back(sample_idx);
 		if self.idx_buffer.len() == 3 {
 			let d_idx0 = self.idx_buffer[1] - self.idx_buffer[0];
 			let d_idx1 = self.idx_buffer[2] - self.idx_buffer[1];
@@ -125,12 +226,17 @@ impl TelemetryDecoder {
 
 		match self.state {
 			TelemetryDecoderState::LookingForPreamble => {
-				self.detector.apply(bit.0);
-				self.detection_buffer.push_back(bit);
+				self.detector.apply(bit);
+				self.detection_buffer.push_back((bit, sample_idx, opt_magnitude));
 				match (self.detector.get_result(), self.detector.is_inverse_sense()) {
 					(Ok(bit_locations), Ok(is_inverse_sense)) => {
 						// Preamble detected
-						self.state = TelemetryDecoderState::DecodingSubframes{ is_inverse_sense };
+						self.stats.preamble_detections += 1;
+						if let Some(last_inverse_sense) = self.last_inverse_sense {
+							if last_inverse_sense != is_inverse_sense { self.stats.polarity_flips += 1; }
+						}
+						self.last_inverse_sense = Some(is_inverse_sense);
+						self.state = TelemetryDecoderState::DecodingSubframes{ is_inverse_sense, subframes_since_detection: 0 };
 
 						// Drop bits to get to the start of the next subframe
 						for _ in 0..bit_locations { self.detection_buffer.pop_front(); }
@@ -145,34 +251,64 @@ impl TelemetryDecoder {
 					}
 				}
 			},
-			TelemetryDecoderState::DecodingSubframes{ is_inverse_sense } => {
-				self.detection_buffer.push_back(bit);
+			TelemetryDecoderState::DecodingSubframes{ is_inverse_sense, subframes_since_detection } => {
+				self.detection_buffer.push_back((bit, sample_idx, opt_magnitude));
 
 				if self.detection_buffer.len() >= SUBFRAME_SIZE_W_PARITY_BITS {
 					let mut next_subframe = [false; SUBFRAME_SIZE_W_PARITY_BITS];
+					let mut soft = [None; SUBFRAME_SIZE_W_PARITY_BITS];
 					let last_idx:usize = match self.detection_buffer.get(SUBFRAME_SIZE_W_PARITY_BITS-1) {
-						Some((_, idx)) => *idx,
+						Some((_, idx, _)) => *idx,
 						None => panic!("Thought we had enough bits in the buffer, but didn't")
 					};
 
 					// Unload the detection buffer
 					for i in 0..SUBFRAME_SIZE_W_PARITY_BITS {
 						match self.detection_buffer.pop_front() {
-							Some((b, _)) => next_subframe[i] = b ^ is_inverse_sense,
+							Some((b, _, m)) => {
+								next_subframe[i] = b ^ is_inverse_sense;
+								soft[i] = m;
+							},
 							None => return TelemetryDecoderResult::Err(DigSigProcErr::InvalidTelemetryData("Not enough bits in detection_buffer")),
 						}
 					}
 
-					// Parity-check the whole subframe and return the actual data without the parity bits
-					match data_recover(next_subframe) {
+					let check = check_words(&mut next_subframe, &soft, &self.error_correction);
+					self.stats.words_checked += 10;
+					self.stats.word_parity_failures += check.parity_failures;
+					self.stats.words_corrected += check.words_corrected;
+
+					// Return the actual data without the parity bits if every word passed the parity check, or if only words the subframe
+					// doesn't depend on failed and word-level acceptance is enabled
+					let data = data_recover(&next_subframe, check.failed_words);
+					let recovered = if check.failed_words == 0 { Ok(data) } 
+						else if self.error_correction.word_level_acceptance && (check.failed_words & needed_words(&data)) == 0 {
+							self.stats.subframes_partially_accepted += 1;
+							Ok(data)
+						}
+						else { Err(DigSigProcErr::InvalidTelemetryData("Bad parity check")) };
+
+					match recovered {
 						Ok(bits) => {
 							// If the bits passed the parity check, try to actually decode the data
 							match subframe::decode(bits) {
-								Ok(sf) => TelemetryDecoderResult::Ok(sf, bits, last_idx),
+								Ok(sf) => {
+									self.stats.subframes_decoded += 1;
+									self.state = TelemetryDecoderState::DecodingSubframes{ is_inverse_sense, subframes_since_detection: subframes_since_detection + 1 };
+									TelemetryDecoderResult::Ok(sf, bits, last_idx)
+								},
 								Err(e) => TelemetryDecoderResult::Err(e)		
 							}
 						},
-						Err(e) => TelemetryDecoderResult::Err(e)
+						Err(e) => {
+							if subframes_since_detection == 0 {
+								// The preamble and first word passed, but the rest of the subframe didn't, so this was most 
+								// likely a false detection; go back to looking for the preamble
+								self.stats.preamble_false_detections += 1;
+								self.initialize();
+							}
+							TelemetryDecoderResult::Err(e)
+						}
 					}
 
 				} else { TelemetryDecoderResult::NotReady }
@@ -182,7 +318,8 @@ impl TelemetryDecoder {
 	}
 }
 
+#[derive(Debug, Clone, Copy)]
 enum TelemetryDecoderState {
 	LookingForPreamble,
-	DecodingSubframes{ is_inverse_sense:bool },
+	DecodingSubframes{ is_inverse_sense:bool, subframes_since_detection:usize },
 }
\ No newline at end of file
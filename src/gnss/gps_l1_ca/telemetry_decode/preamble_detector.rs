@@ -1,50 +1,47 @@
 
-use std::collections::VecDeque;
-
 use crate::DigSigProcErr;
 
 const POS_PATTERN:[bool; 8] = [true,  false, false, false, true,  false, true,  true ];
 const NEG_PATTERN:[bool; 8] = [false, true,  true,  true,  false, true,  false, false];
 
 pub struct PreambleDetector {
-	buffer:VecDeque<bool>,
+	buffer:[bool; 30],			// The last 30 bits, oldest first
+	buffered:usize,
 	current_bit:usize,
 	inverse_sense:Option<bool>,
 	preamble_location:Option<usize>,
 }
 
 pub fn new_preamble_detector() -> PreambleDetector {
-	PreambleDetector{ buffer: VecDeque::new(), current_bit: 0, inverse_sense: None, preamble_location: None }
+	PreambleDetector{ buffer: [false; 30], buffered: 0, current_bit: 0, inverse_sense: None, preamble_location: None }
 }
 
 impl PreambleDetector {
 
 	pub fn initialize(&mut self) {
-		self.buffer.clear();
+		self.buffered = 0;
 		self.current_bit = 0;
 		self.inverse_sense = None;
 		self.preamble_location = None;
 	}
 
 	pub fn apply(&mut self, b:bool) {
-		self.buffer.push_back(b);
+		self.buffer.copy_within(1.., 0);
+		self.buffer[29] = b;
+		self.buffered = (self.buffered + 1).min(30);
 		self.current_bit += 1;
 
-		// Limit the buffer size to 30
-		while self.buffer.len() > 30 { self.buffer.pop_front().unwrap(); }
-
 		// TODO: consider adding a flag to indicate whether the preamble has been lost after being found the first time
-		if self.buffer.len() == 30 {
-			let first_eight:Vec<bool> = self.buffer.iter().map(|b| *b).take(8).collect();
-			if first_eight == POS_PATTERN {
-				let whole_word:Vec<bool> = self.buffer.iter().map(|b| *b).collect();
-				if super::parity_check(&whole_word, false, false) {
+		if self.buffered == 30 {
+			if self.buffer[..8] == POS_PATTERN {
+				if super::parity_check(&self.buffer, false, false) {
 					self.inverse_sense = Some(false);
 					self.preamble_location = Some((self.current_bit - 30)%300);
 				}
 			} 
-			else if first_eight == NEG_PATTERN {
-				let whole_word:Vec<bool> = self.buffer.iter().map(|b| !b).collect();
+			else if self.buffer[..8] == NEG_PATTERN {
+				let mut whole_word:[bool; 30] = self.buffer;
+				for x in whole_word.iter_mut() { *x = !*x; }
 				if super::parity_check(&whole_word, false, false) {
 					self.inverse_sense = Some(true);
 					self.preamble_location = Some((self.current_bit - 30)%300);
@@ -2,7 +2,7 @@
 use ::serde::{Serialize, Deserialize};
 
 use crate::DigSigProcErr;
-use crate::utils::bools_to_int;
+use crate::utils::{bools_to_int, int_to_bools};
 
 pub mod subframe1;
 pub mod subframe2;
@@ -10,14 +10,16 @@ pub mod subframe3;
 pub mod subframe4;
 pub mod subframe5;
 
-#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+pub const PREAMBLE:[bool; 8] = [true, false, false, false, true, false, true, true];
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
 pub struct Subframe {
 	pub time_of_week_truncated:u32,
 	pub subframe_id:u8,
 	pub body:SubframeBody,
 }
 
-#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
 pub enum SubframeBody {
 	Subframe1(subframe1::Body),
 	Subframe2(subframe2::Body),
@@ -46,4 +48,53 @@ pub fn decode(bits:[bool; 240]) -> Result<Subframe, DigSigProcErr> {
 	};
 
 	Ok(Subframe{ time_of_week_truncated, subframe_id, body })
-}
\ No newline at end of file
+}
+
+// Inverse of decode; the TLM message, integrity, alert and anti-spoof flags aren't stored in a Subframe, so they're left clear
+pub fn encode(sf:&Subframe) -> Result<[bool; 240], DigSigProcErr> {
+	let mut bits:[bool; 240] = [false; 240];
+
+	bits[0..8].copy_from_slice(&PREAMBLE);
+	int_to_bools::from_unsigned(sf.time_of_week_truncated as i64, &mut bits[24..41])?;
+	int_to_bools::from_unsigned(sf.subframe_id as i64,            &mut bits[43..46])?;
+
+	match &sf.body {
+		SubframeBody::Subframe1(body) => body.write(&mut bits)?,
+		SubframeBody::Subframe2(body) => body.write(&mut bits)?,
+		SubframeBody::Subframe3(body) => body.write(&mut bits)?,
+		SubframeBody::Subframe4(body) => body.write(&mut bits)?,
+		SubframeBody::Subframe5(body) => body.write(&mut bits)?,
+	}
+
+	Ok(bits)
+}
+
+#[test]
+fn test_field_positions() {
+	// Bit positions straight from IS-GPS-200K, 20.3.3.4 and 20.3.3.5, rather than from the encoder
+	let with_bits = |fields:&[(usize, &str)]| {
+		let mut bits = [false; 240];
+		for (start, s) in fields.iter() { for (i, c) in s.chars().enumerate() { bits[start + i] = c == '1'; } }
+		bits
+	};
+
+	// Subframe 2 ends with the fit interval flag and the 5-bit AODO, then two spare bits
+	let sf2 = subframe2::Body::new(&with_bits(&[(232, "110001"), (238, "11")])).unwrap();
+	assert_eq!((sf2.fit_interval, sf2.aodo), (true, 17));
+
+	// Page 18 has DN ahead of delta_t_LSF
+	match subframe4::Body::new(&with_bits(&[(50, "111000"), (208, "00000111"), (216, "11111110")])).unwrap().page {
+		subframe4::Page::Page18{ dn, delta_t_LSF, .. } => assert_eq!((dn, delta_t_LSF), (7, -2)),
+		page => panic!("Expected page 18, got {:?}", page),
+	}
+
+	// Page 25 is SV ID 63 in subframe 4 and 51 in subframe 5
+	assert!(matches!(subframe4::Body::new(&with_bits(&[(50, "111111")])).unwrap().page, subframe4::Page::Page25{ .. }));
+	assert!(matches!(subframe5::Body::new(&with_bits(&[(50, "110011")])).unwrap().page, subframe5::Page::Page25{ .. }));
+
+	// Almanac af1 has an LSB of 2^-38 seconds per second
+	match subframe5::Body::new(&with_bits(&[(50, "000011"), (224, "00000000001")])).unwrap().page {
+		subframe5::Page::AlmanacData{ af1, .. } => assert_eq!(af1, 2.0_f64.powi(-38)),
+		page => panic!("Expected almanac data, got {:?}", page),
+	}
+}
@@ -2,9 +2,9 @@
 use ::serde::{Serialize, Deserialize};
 
 use crate::DigSigProcErr;
-use crate::utils::bools_to_int;
+use crate::utils::{bools_to_int, int_to_bools};
 
-#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
 #[allow(non_camel_case_types)]
 pub enum CodeOnL2 {
 	Reserved,
@@ -12,7 +12,7 @@ pub enum CodeOnL2 {
 	CA_Code,
 }
 
-#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
 pub struct Body {
 	pub week_number:u16, 
 	pub code_on_l2:CodeOnL2, 
@@ -49,4 +49,25 @@ impl Body {
 		Ok(Body{ week_number, code_on_l2, ura_index, sv_health, iodc, t_gd, t_oc, a_f2, a_f1, a_f0 })		
 	}
 
-}
\ No newline at end of file
+	pub fn write(&self, bits:&mut [bool; 240]) -> Result<(), DigSigProcErr> {
+		int_to_bools::from_unsigned(self.week_number as i64, &mut bits[48..58])?;
+		let (b58, b59) = match self.code_on_l2 {
+			CodeOnL2::Reserved => (false, false),
+			CodeOnL2::P_Code   => (false, true ),
+			CodeOnL2::CA_Code  => (true,  false),
+		};
+		bits[58] = b58;
+		bits[59] = b59;
+		int_to_bools::from_unsigned(self.ura_index as i64, &mut bits[60..64])?;
+		int_to_bools::from_unsigned(self.sv_health as i64, &mut bits[64..70])?;
+		int_to_bools::from_unsigned((self.iodc >> 8) as i64, &mut bits[70..72])?;
+		int_to_bools::from_unsigned((self.iodc % 256) as i64, &mut bits[168..176])?;
+		int_to_bools::from_signed(  int_to_bools::scaled(self.t_gd, (2.0_f64).powi(-31)), &mut bits[160..168])?;
+		int_to_bools::from_unsigned((self.t_oc / 16) as i64, &mut bits[176..192])?;
+		int_to_bools::from_signed(  int_to_bools::scaled(self.a_f2, (2.0_f64).powi(-55)), &mut bits[192..200])?;
+		int_to_bools::from_signed(  int_to_bools::scaled(self.a_f1, (2.0_f64).powi(-43)), &mut bits[200..216])?;
+		int_to_bools::from_signed(  int_to_bools::scaled(self.a_f0, (2.0_f64).powi(-31)), &mut bits[216..238])?;
+		Ok(())
+	}
+
+}
@@ -2,10 +2,10 @@
 use ::serde::{Serialize, Deserialize};
 
 use crate::DigSigProcErr;
-use crate::utils::bools_to_int;
+use crate::utils::{bools_to_int, int_to_bools};
 
 // TODO: think about whether Copy and Clone are really necessary
-#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
 pub struct Body {
 	pub iode:u8, 
 	pub crs:f64, 
@@ -33,9 +33,24 @@ impl Body {
 		let cus:f64    = (bools_to_int::to_i16(&bits[168..184])? as f64) * (2.0_f64).powi(-29);
 		let sqrt_a:f64 = (bools_to_int::to_u32(&bits[184..216])? as f64) * (2.0_f64).powi(-19);
 		let t_oe:f64   = (bools_to_int::to_u16(&bits[216..232])? as f64) * (2.0_f64).powi(4);
-		let fit_interval:bool = bits[233];
-		let aodo:u8    =  bools_to_int::to_u8( &bits[234..239])?;
+		let fit_interval:bool = bits[232];
+		let aodo:u8    =  bools_to_int::to_u8( &bits[233..238])?;
 		Ok(Body{ iode, crs, dn, m0, cuc, e, cus, sqrt_a, t_oe, fit_interval, aodo })
 	}
 
-}
\ No newline at end of file
+	pub fn write(&self, bits:&mut [bool; 240]) -> Result<(), DigSigProcErr> {
+		int_to_bools::from_unsigned(self.iode as i64,                                    &mut bits[ 48..56 ])?;
+		int_to_bools::from_signed(  int_to_bools::scaled(self.crs,    (2.0_f64).powi(-5)),  &mut bits[ 56..72 ])?;
+		int_to_bools::from_signed(  int_to_bools::scaled(self.dn,     (2.0_f64).powi(-43)), &mut bits[ 72..88 ])?;
+		int_to_bools::from_signed(  int_to_bools::scaled(self.m0,     (2.0_f64).powi(-31)), &mut bits[ 88..120])?;
+		int_to_bools::from_signed(  int_to_bools::scaled(self.cuc,    (2.0_f64).powi(-29)), &mut bits[120..136])?;
+		int_to_bools::from_unsigned(int_to_bools::scaled(self.e,      (2.0_f64).powi(-33)), &mut bits[136..168])?;
+		int_to_bools::from_signed(  int_to_bools::scaled(self.cus,    (2.0_f64).powi(-29)), &mut bits[168..184])?;
+		int_to_bools::from_unsigned(int_to_bools::scaled(self.sqrt_a, (2.0_f64).powi(-19)), &mut bits[184..216])?;
+		int_to_bools::from_unsigned(int_to_bools::scaled(self.t_oe,   (2.0_f64).powi(4)),   &mut bits[216..232])?;
+		bits[232] = self.fit_interval;
+		int_to_bools::from_unsigned(self.aodo as i64,                                    &mut bits[233..238])?;
+		Ok(())
+	}
+
+}
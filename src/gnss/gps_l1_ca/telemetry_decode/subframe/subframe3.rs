@@ -2,10 +2,10 @@
 use ::serde::{Serialize, Deserialize};
 
 use crate::DigSigProcErr;
-use crate::utils::bools_to_int;
+use crate::utils::{bools_to_int, int_to_bools};
 
 // TODO: think about whether Copy and Clone are really necessary
-#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
 pub struct Body {
 	pub cic:f64, 
 	pub omega0:f64, 
@@ -33,4 +33,17 @@ impl Body {
 		Ok(Body{ cic, omega0, cis, i0, crc, omega, omega_dot, iode, idot })		
 	}
 
-}
\ No newline at end of file
+	pub fn write(&self, bits:&mut [bool; 240]) -> Result<(), DigSigProcErr> {
+		int_to_bools::from_signed(  int_to_bools::scaled(self.cic,       (2.0_f64).powi(-29)), &mut bits[ 48..64 ])?;
+		int_to_bools::from_signed(  int_to_bools::scaled(self.omega0,    (2.0_f64).powi(-31)), &mut bits[ 64..96 ])?;
+		int_to_bools::from_signed(  int_to_bools::scaled(self.cis,       (2.0_f64).powi(-29)), &mut bits[ 96..112])?;
+		int_to_bools::from_signed(  int_to_bools::scaled(self.i0,        (2.0_f64).powi(-31)), &mut bits[112..144])?;
+		int_to_bools::from_signed(  int_to_bools::scaled(self.crc,       (2.0_f64).powi(-5)),  &mut bits[144..160])?;
+		int_to_bools::from_signed(  int_to_bools::scaled(self.omega,     (2.0_f64).powi(-31)), &mut bits[160..192])?;
+		int_to_bools::from_signed(  int_to_bools::scaled(self.omega_dot, (2.0_f64).powi(-43)), &mut bits[192..216])?;
+		int_to_bools::from_unsigned(self.iode as i64,                                       &mut bits[216..224])?;
+		int_to_bools::from_signed(  int_to_bools::scaled(self.idot,      (2.0_f64).powi(-43)), &mut bits[224..238])?;
+		Ok(())
+	}
+
+}
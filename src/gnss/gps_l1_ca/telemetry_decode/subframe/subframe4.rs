@@ -2,21 +2,21 @@
 use ::serde::{Serialize, Deserialize};
 
 use crate::DigSigProcErr;
-use crate::utils::bools_to_int;
+use crate::utils::{bools_to_int, int_to_bools};
 
-#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
 pub struct Body {
 	pub data_id:u8, 
 	pub sv_id:u8, 
 	pub page:Page
 }
 
-#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
 pub enum Page {
 	AlmanacData{e:f64, t_oa:u32, delta_i:f64, omega_dot:f64, sv_health:u8, sqrt_a:f64, omega0:f64, omega:f64, m0:f64, af0:f64, af1:f64},
 	NavigationMessageCorrectionTable{availability:u8, erd:[u8; 30]},
 	SpecialMessages([u8; 22]),
-	Page18{ alpha0:f64, alpha1:f64, alpha2:f64, alpha3:f64, beta0:f64, beta1:f64, beta2:f64, beta3:f64, a1:f64, a0:f64, t_ot:u32, wn_t:u8, delta_t_LS:i8, wn_LSF:u8, delta_t_LSF:i8 },
+	Page18{ alpha0:f64, alpha1:f64, alpha2:f64, alpha3:f64, beta0:f64, beta1:f64, beta2:f64, beta3:f64, a1:f64, a0:f64, t_ot:u32, wn_t:u8, delta_t_LS:i8, wn_LSF:u8, dn:u8, delta_t_LSF:i8 },
 	Page25{ antispoof_and_config:[u8; 32], sv_health:[u8; 8] },
 	Reserved,
 }
@@ -38,7 +38,7 @@ impl Body {
 				let omega:f64     = (bools_to_int::to_i32(&bits[168..192])? as f64) * (2.0_f64).powi(-23);
 				let m0:f64        = (bools_to_int::to_i32(&bits[192..216])? as f64) * (2.0_f64).powi(-23);
 				let af0:f64       = (bools_to_int::to_i16(&[&bits[216..224], &bits[235..238]].concat())? as f64) * (2.0_f64).powi(-20);
-				let af1:f64       = (bools_to_int::to_i32(&bits[224..235])? as f64) * (2.0_f64).powi(-18);
+				let af1:f64       = (bools_to_int::to_i32(&bits[224..235])? as f64) * (2.0_f64).powi(-38);
 				Page::AlmanacData{e, t_oa, delta_i, omega_dot, sv_health, sqrt_a, omega0, omega, m0, af0, af1}
 			},
 			52 => {
@@ -71,11 +71,12 @@ impl Body {
 				let wn_t:u8        =  bools_to_int::to_u8(&bits[184..192])?;
 				let delta_t_LS:i8  =  bools_to_int::to_i8(&bits[192..200])?;
 				let wn_LSF:u8      =  bools_to_int::to_u8(&bits[200..208])?;  
-				let delta_t_LSF:i8 =  bools_to_int::to_i8(&bits[208..216])?;
+				let dn:u8          =  bools_to_int::to_u8(&bits[208..216])?;
+				let delta_t_LSF:i8 =  bools_to_int::to_i8(&bits[216..224])?;
 
-				Page::Page18{ alpha0, alpha1, alpha2, alpha3, beta0, beta1, beta2, beta3, a1, a0, t_ot, wn_t, delta_t_LS, wn_LSF, delta_t_LSF }
+				Page::Page18{ alpha0, alpha1, alpha2, alpha3, beta0, beta1, beta2, beta3, a1, a0, t_ot, wn_t, delta_t_LS, wn_LSF, dn, delta_t_LSF }
 			},
-			62 => {
+			63 => {
 				let mut antispoof_and_config:[u8; 32] = [0; 32];
 				for i in 0..32 {
 					antispoof_and_config[i] = bools_to_int::to_u8(&bits[(56+(i*4))..(60+(i*4))])?;
@@ -93,4 +94,67 @@ impl Body {
 
 	}
 
-}
\ No newline at end of file
+	pub fn write(&self, bits:&mut [bool; 240]) -> Result<(), DigSigProcErr> {
+		int_to_bools::from_unsigned(self.data_id as i64, &mut bits[48..50])?;
+		int_to_bools::from_unsigned(self.sv_id as i64,   &mut bits[50..56])?;
+		match &self.page {
+			Page::AlmanacData{e, t_oa, delta_i, omega_dot, sv_health, sqrt_a, omega0, omega, m0, af0, af1} => {
+				int_to_bools::from_unsigned(int_to_bools::scaled(*e,         (2.0_f64).powi(-21)), &mut bits[ 56..72 ])?;
+				int_to_bools::from_unsigned((*t_oa / 2_u32.pow(12)) as i64,                     &mut bits[ 72..80 ])?;
+				int_to_bools::from_signed(  int_to_bools::scaled(*delta_i,   (2.0_f64).powi(-19)), &mut bits[ 80..96 ])?;
+				int_to_bools::from_signed(  int_to_bools::scaled(*omega_dot, (2.0_f64).powi(-38)), &mut bits[ 96..112])?;
+				int_to_bools::from_unsigned(*sv_health as i64,                                  &mut bits[112..120])?;
+				int_to_bools::from_unsigned(int_to_bools::scaled(*sqrt_a,    (2.0_f64).powi(-11)), &mut bits[120..144])?;
+				int_to_bools::from_signed(  int_to_bools::scaled(*omega0,    (2.0_f64).powi(-23)), &mut bits[144..168])?;
+				int_to_bools::from_signed(  int_to_bools::scaled(*omega,     (2.0_f64).powi(-23)), &mut bits[168..192])?;
+				int_to_bools::from_signed(  int_to_bools::scaled(*m0,        (2.0_f64).powi(-23)), &mut bits[192..216])?;
+				// The 11-bit af0 is split into 8 MSBs before af1 and 3 LSBs after it
+				let mut af0_bits:[bool; 11] = [false; 11];
+				int_to_bools::from_signed(  int_to_bools::scaled(*af0,       (2.0_f64).powi(-20)), &mut af0_bits)?;
+				bits[216..224].copy_from_slice(&af0_bits[0..8]);
+				bits[235..238].copy_from_slice(&af0_bits[8..11]);
+				int_to_bools::from_signed(  int_to_bools::scaled(*af1,       (2.0_f64).powi(-38)), &mut bits[224..235])?;
+			},
+			Page::NavigationMessageCorrectionTable{availability, erd} => {
+				int_to_bools::from_unsigned(*availability as i64, &mut bits[56..58])?;
+				for (i, x) in erd.iter().enumerate() {
+					int_to_bools::from_unsigned(*x as i64, &mut bits[(58+(i*6))..(64+(i*6))])?;
+				}
+			},
+			Page::SpecialMessages(message) => {
+				for (i, x) in message.iter().enumerate() {
+					int_to_bools::from_unsigned(*x as i64, &mut bits[(56+(i*8))..(64+(i*8))])?;
+				}
+			},
+			Page::Page18{ alpha0, alpha1, alpha2, alpha3, beta0, beta1, beta2, beta3, a1, a0, t_ot, wn_t, delta_t_LS, wn_LSF, dn, delta_t_LSF } => {
+				int_to_bools::from_signed(  int_to_bools::scaled(*alpha0, (2.0_f64).powi(-30)), &mut bits[ 56..64 ])?;
+				int_to_bools::from_signed(  int_to_bools::scaled(*alpha1, (2.0_f64).powi(-27)), &mut bits[ 64..72 ])?;
+				int_to_bools::from_signed(  int_to_bools::scaled(*alpha2, (2.0_f64).powi(-24)), &mut bits[ 72..80 ])?;
+				int_to_bools::from_signed(  int_to_bools::scaled(*alpha3, (2.0_f64).powi(-24)), &mut bits[ 80..88 ])?;
+				int_to_bools::from_signed(  int_to_bools::scaled(*beta0,  (2.0_f64).powi(11)),  &mut bits[ 88..96 ])?;
+				int_to_bools::from_signed(  int_to_bools::scaled(*beta1,  (2.0_f64).powi(14)),  &mut bits[ 96..104])?;
+				int_to_bools::from_signed(  int_to_bools::scaled(*beta2,  (2.0_f64).powi(16)),  &mut bits[104..112])?;
+				int_to_bools::from_signed(  int_to_bools::scaled(*beta3,  (2.0_f64).powi(16)),  &mut bits[112..120])?;
+				int_to_bools::from_signed(  int_to_bools::scaled(*a1,     (2.0_f64).powi(-50)), &mut bits[120..144])?;
+				int_to_bools::from_signed(  int_to_bools::scaled(*a0,     (2.0_f64).powi(-30)), &mut bits[144..176])?;
+				int_to_bools::from_unsigned((*t_ot / 2_u32.pow(12)) as i64,                  &mut bits[176..184])?;
+				int_to_bools::from_unsigned(*wn_t as i64,                                    &mut bits[184..192])?;
+				int_to_bools::from_signed(  *delta_t_LS as i64,                              &mut bits[192..200])?;
+				int_to_bools::from_unsigned(*wn_LSF as i64,                                  &mut bits[200..208])?;
+				int_to_bools::from_unsigned(*dn as i64,                                      &mut bits[208..216])?;
+				int_to_bools::from_signed(  *delta_t_LSF as i64,                             &mut bits[216..224])?;
+			},
+			Page::Page25{ antispoof_and_config, sv_health } => {
+				for (i, x) in antispoof_and_config.iter().enumerate() {
+					int_to_bools::from_unsigned(*x as i64, &mut bits[(56+(i*4))..(60+(i*4))])?;
+				}
+				for (i, x) in sv_health.iter().enumerate() {
+					int_to_bools::from_unsigned(*x as i64, &mut bits[(186+(i*6))..(192+(i*6))])?;
+				}
+			},
+			Page::Reserved => {},
+		}
+		Ok(())
+	}
+
+}
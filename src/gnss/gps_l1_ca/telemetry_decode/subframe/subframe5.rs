@@ -2,16 +2,16 @@
 use ::serde::{Serialize, Deserialize};
 
 use crate::DigSigProcErr;
-use crate::utils::bools_to_int;
+use crate::utils::{bools_to_int, int_to_bools};
 
-#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
 pub struct Body {
 	pub data_id:u8, 
 	pub sv_id:u8, 
 	pub page:Page
 }
 
-#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
 pub enum Page {
 	AlmanacData{e:f64, t_oa:u32, delta_i:f64, omega_dot:f64, sv_health:u8, sqrt_a:f64, omega0:f64, omega:f64, m0:f64, af0:f64, af1:f64},
 	Page25{t_oa:u32, WN_a:u8, sv_health:[u8; 24]},
@@ -34,10 +34,10 @@ impl Body {
 				let omega:f64     = (bools_to_int::to_i32(&bits[168..192])? as f64) * (2.0_f64).powi(-23);
 				let m0:f64        = (bools_to_int::to_i32(&bits[192..216])? as f64) * (2.0_f64).powi(-23);
 				let af0:f64       = (bools_to_int::to_i16(&[&bits[216..224], &bits[235..238]].concat())? as f64) * (2.0_f64).powi(-20);
-				let af1:f64       = (bools_to_int::to_i32(&bits[224..235])? as f64) * (2.0_f64).powi(-18);
+				let af1:f64       = (bools_to_int::to_i32(&bits[224..235])? as f64) * (2.0_f64).powi(-38);
 				Page::AlmanacData{e, t_oa, delta_i, omega_dot, sv_health, sqrt_a, omega0, omega, m0, af0, af1}
 			},
-			25 => {
+			51 => {
 				let t_oa:u32 = bools_to_int::to_u32(&bits[56..64])? * 2_u32.pow(12);
 				let WN_a:u8  = bools_to_int::to_u8(&bits[64..72])?;
 				let mut sv_health:[u8; 24] = [0; 24];
@@ -51,4 +51,36 @@ impl Body {
 		Ok(Body{ data_id, sv_id, page })		
 	}
 
-}
\ No newline at end of file
+	pub fn write(&self, bits:&mut [bool; 240]) -> Result<(), DigSigProcErr> {
+		int_to_bools::from_unsigned(self.data_id as i64, &mut bits[48..50])?;
+		int_to_bools::from_unsigned(self.sv_id as i64,   &mut bits[50..56])?;
+		match &self.page {
+			Page::AlmanacData{e, t_oa, delta_i, omega_dot, sv_health, sqrt_a, omega0, omega, m0, af0, af1} => {
+				int_to_bools::from_unsigned(int_to_bools::scaled(*e,         (2.0_f64).powi(-21)), &mut bits[ 56..72 ])?;
+				int_to_bools::from_unsigned((*t_oa / 2_u32.pow(12)) as i64,                     &mut bits[ 72..80 ])?;
+				int_to_bools::from_signed(  int_to_bools::scaled(*delta_i,   (2.0_f64).powi(-19)), &mut bits[ 80..96 ])?;
+				int_to_bools::from_signed(  int_to_bools::scaled(*omega_dot, (2.0_f64).powi(-38)), &mut bits[ 96..112])?;
+				int_to_bools::from_unsigned(*sv_health as i64,                                  &mut bits[112..120])?;
+				int_to_bools::from_unsigned(int_to_bools::scaled(*sqrt_a,    (2.0_f64).powi(-11)), &mut bits[120..144])?;
+				int_to_bools::from_signed(  int_to_bools::scaled(*omega0,    (2.0_f64).powi(-23)), &mut bits[144..168])?;
+				int_to_bools::from_signed(  int_to_bools::scaled(*omega,     (2.0_f64).powi(-23)), &mut bits[168..192])?;
+				int_to_bools::from_signed(  int_to_bools::scaled(*m0,        (2.0_f64).powi(-23)), &mut bits[192..216])?;
+				// The 11-bit af0 is split into 8 MSBs before af1 and 3 LSBs after it
+				let mut af0_bits:[bool; 11] = [false; 11];
+				int_to_bools::from_signed(  int_to_bools::scaled(*af0,       (2.0_f64).powi(-20)), &mut af0_bits)?;
+				bits[216..224].copy_from_slice(&af0_bits[0..8]);
+				bits[235..238].copy_from_slice(&af0_bits[8..11]);
+				int_to_bools::from_signed(  int_to_bools::scaled(*af1,       (2.0_f64).powi(-38)), &mut bits[224..235])?;
+			},
+			Page::Page25{t_oa, WN_a, sv_health} => {
+				int_to_bools::from_unsigned((*t_oa / 2_u32.pow(12)) as i64, &mut bits[56..64])?;
+				int_to_bools::from_unsigned(*WN_a as i64,                   &mut bits[64..72])?;
+				for (i, x) in sv_health.iter().enumerate() {
+					int_to_bools::from_unsigned(*x as i64, &mut bits[(72+(i*6))..(78+(i*6))])?;
+				}
+			},
+		}
+		Ok(())
+	}
+
+}
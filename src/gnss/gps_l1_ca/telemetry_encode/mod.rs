@@ -0,0 +1,74 @@
+#![allow(non_snake_case)]
+
+use crate::DigSigProcErr;
+use crate::gnss::gps_l1_ca::pvt::ephemeris::Ephemeris;
+use crate::gnss::gps_l1_ca::telemetry_decode::parity_bits;
+use crate::gnss::gps_l1_ca::telemetry_decode::subframe::{self, Subframe, SubframeBody};
+use crate::gnss::gps_l1_ca::telemetry_decode::subframe::{subframe1, subframe2, subframe3};
+
+/*	GPS Telemetry Encoding Pipeline (the inverse of telemetry_decode):
+	- Subframe encode
+	- Parity encode
+*/
+
+#[cfg(test)]
+mod tests;
+
+const SUBFRAME_SIZE_W_PARITY_BITS:usize = 300;
+const SUBFRAME_SIZE_DATA_ONLY_BITS:usize = 240;
+
+/// Adds the parity bits to a subframe and applies the D30 inversion from the previous word.  Bits 23 and 24 of words 2 and 10 are
+/// non-information bits chosen so that D29 and D30 of those words come out zero (IS-GPS-200K, section 20.3.5.2), so the first word
+/// of every subframe can be encoded and decoded as if the previous D29 and D30 were zero.
+pub fn parity_encode(data:&[bool; SUBFRAME_SIZE_DATA_ONLY_BITS]) -> [bool; SUBFRAME_SIZE_W_PARITY_BITS] {
+	let mut ans:[bool; SUBFRAME_SIZE_W_PARITY_BITS] = [false; SUBFRAME_SIZE_W_PARITY_BITS];
+
+	let mut last_D29:bool = false;
+	let mut last_D30:bool = false;
+
+	for word_idx in 0..10 {
+		let mut d:[bool; 24] = [false; 24];
+		d.copy_from_slice(&data[(24*word_idx)..(24*word_idx + 24)]);
+
+		let mut parity:[bool; 6] = parity_bits(&d, last_D29, last_D30);
+		if word_idx == 1 || word_idx == 9 {
+			for t in 0..4 {
+				d[22] = t & 2 != 0;
+				d[23] = t & 1 != 0;
+				parity = parity_bits(&d, last_D29, last_D30);
+				if !parity[4] && !parity[5] { break; }
+			}
+		}
+
+		for bit_idx in 0..24 { ans[(30*word_idx) + bit_idx] = d[bit_idx] ^ last_D30; }
+		for bit_idx in 0..6  { ans[(30*word_idx) + 24 + bit_idx] = parity[bit_idx]; }
+
+		last_D29 = parity[4];
+		last_D30 = parity[5];
+	}
+
+	ans
+}
+
+/// Encodes a subframe into the 300 bits that would be transmitted, including parity
+pub fn encode(sf:&Subframe) -> Result<[bool; SUBFRAME_SIZE_W_PARITY_BITS], DigSigProcErr> {
+	let data = subframe::encode(sf)?;
+	Ok(parity_encode(&data))
+}
+
+/// Splits an ephemeris into subframes 1, 2, and 3.  The TOW count in each HOW is the truncated time of week at the start of the next
+/// subframe, so the three subframes carry tow_truncated, tow_truncated+1 and tow_truncated+2.  The IODE is the 8 LSBs of the IODC.
+pub fn ephemeris_subframes(eph:&Ephemeris, ura_index:u8, sv_health:u8, tow_truncated:u32) -> [Subframe; 3] {
+	let iode:u8 = (eph.iodc % 256) as u8;
+
+	let sf1 = subframe1::Body{ week_number: eph.week_number, code_on_l2: subframe1::CodeOnL2::CA_Code, ura_index, sv_health,
+		iodc: eph.iodc, t_gd: eph.t_gd, t_oc: eph.t_oc as u32, a_f2: eph.a_f2, a_f1: eph.a_f1, a_f0: eph.a_f0 };
+	let sf2 = subframe2::Body{ iode, crs: eph.crs, dn: eph.dn, m0: eph.m0, cuc: eph.cuc, e: eph.e, cus: eph.cus, sqrt_a: eph.sqrt_a,
+		t_oe: eph.t_oe, fit_interval: eph.fit_interval, aodo: eph.aodo };
+	let sf3 = subframe3::Body{ cic: eph.cic, omega0: eph.omega0, cis: eph.cis, i0: eph.i0, crc: eph.crc, omega: eph.omega,
+		omega_dot: eph.omega_dot, iode, idot: eph.idot };
+
+	[Subframe{ time_of_week_truncated: tow_truncated,   subframe_id: 1, body: SubframeBody::Subframe1(sf1) },
+	 Subframe{ time_of_week_truncated: tow_truncated+1, subframe_id: 2, body: SubframeBody::Subframe2(sf2) },
+	 Subframe{ time_of_week_truncated: tow_truncated+2, subframe_id: 3, body: SubframeBody::Subframe3(sf3) }]
+}
@@ -0,0 +1,126 @@
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use crate::gnss::gps_l1_ca::pvt::ephemeris::Ephemeris;
+use crate::gnss::gps_l1_ca::telemetry_decode::{ErrorCorrection, TelemetryDecoder, TelemetryDecoderResult};
+use crate::gnss::gps_l1_ca::telemetry_decode::subframe::{Subframe, SubframeBody, subframe4, subframe5};
+
+// Random value that fits in a field of the given width and scale factor
+fn unsigned<R: Rng>(rng:&mut R, n:u32, lsb:f64) -> f64 { (rng.gen_range(0, 1i64 << n) as f64) * lsb }
+fn signed<R: Rng>(rng:&mut R, n:u32, lsb:f64) -> f64 { (rng.gen_range(-(1i64 << (n-1)), 1i64 << (n-1)) as f64) * lsb }
+
+fn random_ephemeris<R: Rng>(rng:&mut R) -> Ephemeris {
+	Ephemeris{ 
+		week_number: rng.gen_range(0, 1024), t_gd: signed(rng, 8, 2.0_f64.powi(-31)), aodo: rng.gen_range(0, 32), fit_interval: rng.gen(),
+		t_oc: unsigned(rng, 16, 16.0), a_f0: signed(rng, 22, 2.0_f64.powi(-31)), a_f1: signed(rng, 16, 2.0_f64.powi(-43)), a_f2: signed(rng, 8, 2.0_f64.powi(-55)),
+		t_oe: unsigned(rng, 16, 16.0), sqrt_a: unsigned(rng, 32, 2.0_f64.powi(-19)), dn: signed(rng, 16, 2.0_f64.powi(-43)), m0: signed(rng, 32, 2.0_f64.powi(-31)),
+		e: unsigned(rng, 32, 2.0_f64.powi(-33)), omega: signed(rng, 32, 2.0_f64.powi(-31)), omega0: signed(rng, 32, 2.0_f64.powi(-31)), omega_dot: signed(rng, 24, 2.0_f64.powi(-43)),
+		cus: signed(rng, 16, 2.0_f64.powi(-29)), cuc: signed(rng, 16, 2.0_f64.powi(-29)), crs: signed(rng, 16, 2.0_f64.powi(-5)), crc: signed(rng, 16, 2.0_f64.powi(-5)),
+		cis: signed(rng, 16, 2.0_f64.powi(-29)), cic: signed(rng, 16, 2.0_f64.powi(-29)), i0: signed(rng, 32, 2.0_f64.powi(-31)), idot: signed(rng, 14, 2.0_f64.powi(-43)),
+		iodc: rng.gen_range(0, 1024),
+	}
+}
+
+fn random_almanac<R: Rng>(rng:&mut R) -> (f64, u32, f64, f64, u8, f64, f64, f64, f64, f64, f64) {
+	(unsigned(rng, 16, 2.0_f64.powi(-21)), rng.gen_range(0, 256) * 4096, signed(rng, 16, 2.0_f64.powi(-19)), signed(rng, 16, 2.0_f64.powi(-38)),
+	 rng.gen(), unsigned(rng, 24, 2.0_f64.powi(-11)), signed(rng, 24, 2.0_f64.powi(-23)), signed(rng, 24, 2.0_f64.powi(-23)),
+	 signed(rng, 24, 2.0_f64.powi(-23)), signed(rng, 11, 2.0_f64.powi(-20)), signed(rng, 11, 2.0_f64.powi(-38)))
+}
+
+fn random_subframes<R: Rng>(rng:&mut R, tow_truncated:u32) -> Vec<Subframe> {
+	let eph = random_ephemeris(rng);
+	let mut ans:Vec<Subframe> = super::ephemeris_subframes(&eph, rng.gen_range(0, 16), rng.gen_range(0, 64), tow_truncated).to_vec();
+
+	let page18 = subframe4::Page::Page18{ alpha0: signed(rng, 8, 2.0_f64.powi(-30)), alpha1: signed(rng, 8, 2.0_f64.powi(-27)), 
+		alpha2: signed(rng, 8, 2.0_f64.powi(-24)), alpha3: signed(rng, 8, 2.0_f64.powi(-24)), beta0: signed(rng, 8, 2.0_f64.powi(11)), 
+		beta1: signed(rng, 8, 2.0_f64.powi(14)), beta2: signed(rng, 8, 2.0_f64.powi(16)), beta3: signed(rng, 8, 2.0_f64.powi(16)),
+		a1: signed(rng, 24, 2.0_f64.powi(-50)), a0: signed(rng, 32, 2.0_f64.powi(-30)), t_ot: rng.gen_range(0, 256) * 4096, wn_t: rng.gen(), 
+		delta_t_LS: rng.gen(), wn_LSF: rng.gen(), dn: rng.gen(), delta_t_LSF: rng.gen() };
+	ans.push(Subframe{ time_of_week_truncated: tow_truncated+3, subframe_id: 4, 
+		body: SubframeBody::Subframe4(subframe4::Body{ data_id: 1, sv_id: 56, page: page18 }) });
+
+	let (e, t_oa, delta_i, omega_dot, sv_health, sqrt_a, omega0, omega, m0, af0, af1) = random_almanac(rng);
+	let almanac = subframe5::Page::AlmanacData{ e, t_oa, delta_i, omega_dot, sv_health, sqrt_a, omega0, omega, m0, af0, af1 };
+	ans.push(Subframe{ time_of_week_truncated: tow_truncated+4, subframe_id: 5, 
+		body: SubframeBody::Subframe5(subframe5::Body{ data_id: 1, sv_id: rng.gen_range(1, 25), page: almanac }) });
+
+	ans
+}
+
+#[test]
+fn subframe_round_trip() {
+	let mut rng = StdRng::seed_from_u64(2);
+	for _ in 0..100 {
+		let tow_truncated:u32 = rng.gen_range(0, 100000);
+		for sf in random_subframes(&mut rng, tow_truncated) {
+			let data = crate::gnss::gps_l1_ca::telemetry_decode::subframe::encode(&sf).unwrap();
+			let decoded = crate::gnss::gps_l1_ca::telemetry_decode::subframe::decode(data).unwrap();
+			assert_eq!(decoded, sf);
+		}
+	}
+}
+
+#[test]
+fn telemetry_decoder_round_trip() {
+	// Feed a continuous stream of encoded subframes through the full decoder, in both polarities
+	let mut rng = StdRng::seed_from_u64(3);
+	for is_inverse in [false, true].iter() {
+		let mut tlm = TelemetryDecoder::new();
+		let subframes:Vec<Subframe> = (0..4).flat_map(|i| random_subframes(&mut rng, 1000 + 5*i)).collect();
+		let mut decoded:Vec<Subframe> = vec![];
+		let mut sample_idx:usize = 0;
+		for sf in subframes.iter() {
+			for b in super::encode(sf).unwrap().iter() {
+				sample_idx += 20;
+				if let TelemetryDecoderResult::Ok(sf, _, _) = tlm.apply_sample((b ^ is_inverse, sample_idx)) { decoded.push(sf); }
+			}
+		}
+
+		// The preamble detector keeps the bits it buffers, so even the first subframe should come out intact
+		assert_eq!(decoded, subframes);
+	}
+}
+
+// Feeds the subframes through a decoder as soft symbols after applying the given corruption to each encoded subframe
+fn decode_corrupted<F: Fn(usize, &mut Vec<f64>)>(tlm:&mut TelemetryDecoder, subframes:&[Subframe], corrupt:F) -> Vec<Subframe> {
+	let mut decoded:Vec<Subframe> = vec![];
+	let mut sample_idx:usize = 0;
+	for (n, sf) in subframes.iter().enumerate() {
+		let mut symbols:Vec<f64> = super::encode(sf).unwrap().iter().map(|b| if *b { 1.0 } else { -1.0 }).collect();
+		// Leave the first subframe alone so the preamble detector can lock
+		if n > 0 { corrupt(n, &mut symbols); }
+		for s in symbols {
+			sample_idx += 20;
+			if let TelemetryDecoderResult::Ok(sf, _, _) = tlm.apply_soft_sample(s, sample_idx) { decoded.push(sf); }
+		}
+	}
+	decoded
+}
+
+#[test]
+fn telemetry_decoder_error_correction() {
+	// The corruptions are picked by hand, so a fixed seed keeps the subframes they land in the same from run to run
+	let mut rng = StdRng::seed_from_u64(1);
+	let subframes:Vec<Subframe> = (0..4).flat_map(|i| random_subframes(&mut rng, 1000 + 5*i)).collect();
+
+	// One flipped bit in each of two words
+	let single = |n:usize, s:&mut Vec<f64>| { s[30 + (n % 30)] *= -1.0; s[270 + (n % 30)] *= -1.0; };
+	let mut tlm = TelemetryDecoder::new();
+	assert_eq!(decode_corrupted(&mut tlm, &subframes, single).len(), 1);
+	let mut tlm = TelemetryDecoder::new_with_error_correction(ErrorCorrection{ max_corrected_words: 2, ..Default::default() });
+	assert_eq!(decode_corrupted(&mut tlm, &subframes, single), subframes);
+	assert_eq!(tlm.stats.words_corrected, 2*(subframes.len()-1));
+	let mut tlm = TelemetryDecoder::new_with_error_correction(ErrorCorrection{ max_corrected_words: 1, ..Default::default() });
+	assert_eq!(decode_corrupted(&mut tlm, &subframes, single).len(), 1);
+
+	// Two weak, flipped bits in one word
+	let double = |_:usize, s:&mut Vec<f64>| { s[100] *= -0.1; s[107] *= -0.2; };
+	let mut tlm = TelemetryDecoder::new_with_error_correction(ErrorCorrection{ max_corrected_words: 1, double_bit_candidates: 4, ..Default::default() });
+	assert_eq!(decode_corrupted(&mut tlm, &subframes, double), subframes);
+
+	// Garbage in subframe 1 words 4 through 6, which aren't needed for anything
+	let reserved = |n:usize, s:&mut Vec<f64>| { if subframes[n].subframe_id == 1 { for x in s[95..175].iter_mut() { *x *= -1.0; } } };
+	let mut tlm = TelemetryDecoder::new_with_error_correction(ErrorCorrection{ word_level_acceptance: true, ..Default::default() });
+	assert_eq!(decode_corrupted(&mut tlm, &subframes, reserved), subframes);
+	assert_eq!(tlm.stats.subframes_partially_accepted, 3);
+}
@@ -43,6 +43,7 @@ pub struct Tracking<A: ScalarFilter, B: ScalarFilter> {
 	pub state: TrackingState,
 	pub fs:f64,
 	pub local_code:Vec<Complex<f64>>,
+	intermediate_freq_hz:f64,
 
 	last_acq_result:AcquisitionResult,
 
@@ -56,14 +57,29 @@ pub struct Tracking<A: ScalarFilter, B: ScalarFilter> {
 	code_phase: f64,
 	code_dphase: f64,
 
+	// Accumulated carrier phase, less the intermediate frequency, since carrier tracking last started and the number of times it has started
+	carrier_cycles: f64,
+	carrier_lock_count: usize,
+
 	carrier_filter: A,
 	code_filter: B,
 
+	// Set when the navigation solution drives the code NCO, along with the number of consecutive bits below the lock threshold
+	aiding: Option<Aiding>,
+	coast_bits: usize,
+
+	// Code discriminator outputs [chips] since the last time they were read
+	code_error_sum: f64,
+	code_error_count: usize,
+
 	// Used during summation over the short interval
 	sum_early:  Complex<f64>,
 	sum_prompt: Complex<f64>,
 	sum_late:   Complex<f64>,
 	input_signal_power: f64,
+
+	// Mean power per sample over the last bit, before correlation
+	last_input_power: f64,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -75,6 +91,15 @@ pub enum TrackingState {
 	LostLock,
 }
 
+// Code and carrier from the navigation solution for vector tracking
+#[derive(Debug, Copy, Clone)]
+struct Aiding {
+	code_dphase: f64,
+	pending_code_chips: f64,
+	carrier_dphase_rad: f64,
+	max_coast_bits: usize,
+}
+
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct TrackingDebug {
 	pub prn:usize,
@@ -100,6 +125,7 @@ impl<A:ScalarFilter, B:ScalarFilter> BlockFunctionality<AcquisitionResult, (), S
 
 			// Increment the carrier and code phase
 			self.carrier = self.carrier * self.carrier_inc;
+			self.carrier_cycles += self.carrier_dphase_rad / (2.0 * consts::PI) - self.intermediate_freq_hz / self.fs;
 			self.code_phase += self.code_dphase;
 
 			// Remove the carrier from the new sample and accumulate the power sum
@@ -125,7 +151,18 @@ impl<A:ScalarFilter, B:ScalarFilter> BlockFunctionality<AcquisitionResult, (), S
 					let l:f64 = self.sum_late.norm();
 					if l+e == 0.0 { 0.0 } else { 0.5 * (l-e) / (l+e) }
 				};
-				self.code_dphase += self.code_filter.apply(code_error);
+				if matches!(self.state, TrackingState::Tracking{ .. }) && self.coast_bits == 0 {
+					self.code_error_sum   += code_error;
+					self.code_error_count += 1;
+				}
+				match self.aiding.as_mut() {
+					// With vector tracking, the navigation solution closes the code loop and any correction is spread over the next period
+					Some(aiding) => {
+						self.code_dphase = aiding.code_dphase + aiding.pending_code_chips / self.code_len_samples;
+						aiding.pending_code_chips = 0.0;
+					},
+					None => self.code_dphase += self.code_filter.apply(code_error),
+				}
 				self.sv_tow_sec_outer.set_clock_rate(self.code_dphase * (self.fs.powi(2) / 1.023e6));
 
 				let (result, opt_next_state) = match self.state {
@@ -170,9 +207,14 @@ impl<A:ScalarFilter, B:ScalarFilter> BlockFunctionality<AcquisitionResult, (), S
 						*input_power_long     += self.input_signal_power;
 
 						if *num_short_intervals % *filter_rate == 0 {
-							// Update carrier tracking; carrier_error has units [radians]
-							let carrier_error = if sum_prompt_medium.re == 0.0 { 0.0 } else { (sum_prompt_medium.im / sum_prompt_medium.re).atan() };	
-							self.carrier_dphase_rad += self.carrier_filter.apply(carrier_error);
+							// Update carrier tracking; carrier_error has units [radians].  While coasting, hold the predicted carrier instead.
+							match self.aiding {
+								Some(aiding) if self.coast_bits > 0 => self.carrier_dphase_rad = aiding.carrier_dphase_rad,
+								_ => {
+									let carrier_error = if sum_prompt_medium.re == 0.0 { 0.0 } else { (sum_prompt_medium.im / sum_prompt_medium.re).atan() };	
+									self.carrier_dphase_rad += self.carrier_filter.apply(carrier_error);
+								},
+							}
 							self.carrier_inc = Complex{ re: self.carrier_dphase_rad.cos(), im: -self.carrier_dphase_rad.sin() };
 
 							*sum_prompt_medium = ZERO;
@@ -205,20 +247,24 @@ impl<A:ScalarFilter, B:ScalarFilter> BlockFunctionality<AcquisitionResult, (), S
 							// Check the quality of the lock
 							*test_stat = sum_prompt_long.norm_sqr() / (*input_power_long * self.code_len_samples * 20.0);
 			
-							// Save the value we need for the result, then reset the long accumulators
+							// Save the values we need for the result, then reset the long accumulators
 							let prompt_i:f64     = sum_prompt_long.re;
+							self.last_input_power = *input_power_long / (self.code_len_samples * 20.0);
 							*num_short_intervals = 0;
 							*sum_prompt_long     = ZERO;
 							*input_power_long    = 0.0;
 
 							// Either return an error or the next bit
-							if *test_stat < LONG_COH_THRESH_LOSS_OF_LOCK { 	
+							let can_coast:bool = match self.aiding { Some(aiding) => self.coast_bits < aiding.max_coast_bits, None => false };
+							if *test_stat < LONG_COH_THRESH_LOSS_OF_LOCK && !can_coast { 	
 								// For a long coherent processing interval, we should be over this threshold under H0 or under this
 								// threshold with H1 with a vanishingly small likelihood, i.e. this should be a very good indicator of 
 								// the lock status without any need for other filtering or anything like that
 								// eprintln!("Loss of Lock from Long Coherent State");
 								(BlockResult::Err(DSPErr::LossOfLock), Some(TrackingState::LostLock))
 							} else { 
+								// An aided channel keeps going on the predicted code and carrier through a short blockage
+								if *test_stat < LONG_COH_THRESH_LOSS_OF_LOCK { self.coast_bits += 1; } else { self.coast_bits = 0; }
 								let v = TrackReport { id: self.prn, prompt_i, sample_idx: sample.idx,
 									test_stat: self.test_stat(), freq_hz: self.carrier_freq_hz() };
 								(BlockResult::Ready(v), None) 
@@ -253,6 +299,10 @@ impl<A: ScalarFilter, B: ScalarFilter> Tracking<A, B> {
 
 	pub fn carrier_freq_hz(&self) -> f64 { (self.carrier_dphase_rad * self.fs) / (2.0 * consts::PI) }
 	pub fn carrier_phase_rad(&self) -> f64 { self.carrier.arg() }
+	pub fn carrier_cycles(&self) -> f64 { self.carrier_cycles }
+	pub fn intermediate_freq_hz(&self) -> f64 { self.intermediate_freq_hz }
+	pub fn carrier_lock_count(&self) -> usize { self.carrier_lock_count }
+	pub fn has_carrier_lock(&self) -> bool { matches!(self.state, TrackingState::Tracking{ .. }) }
 	pub fn code_phase_samples(&self) -> f64 { self.code_phase * (self.fs / 1.023e6) }
 	pub fn code_dphase(&self) -> f64 { self.code_dphase }
 	pub fn test_stat(&self) -> f64 { match self.state {
@@ -261,6 +311,39 @@ impl<A: ScalarFilter, B: ScalarFilter> Tracking<A, B> {
 		_ => 0.0,
 	}}
 
+	// The test statistic is the fraction of the input power in the prompt correlator, i.e. the per-sample signal to signal
+	// plus noise ratio, and with complex samples the noise bandwidth is fs
+	pub fn cn0_dbhz(&self) -> f64 {
+		let test_stat:f64 = self.test_stat().min(0.999);
+		10.0 * (self.fs * test_stat / (1.0 - test_stat)).log10()
+	}
+
+	/// Mean power per sample of the input over the last bit, in the units of the samples squared, while tracking
+	pub fn input_power(&self) -> Option<f64> { if self.has_carrier_lock() && self.last_input_power > 0.0 { Some(self.last_input_power) } else { None } }
+
+	pub fn is_aided(&self) -> bool { self.aiding.is_some() }
+	pub fn is_coasting(&self) -> bool { self.coast_bits > 0 }
+
+	/// Hands the code NCO over to the navigation solution.  The correction is applied over the next code period and the code
+	/// rate is held after that, while the carrier frequency is only used when the signal is too weak for the carrier loop.
+	pub fn set_aiding(&mut self, code_correction_chips:f64, code_rate_chips_per_sec:f64, carrier_freq_hz:f64, max_coast_sec:f64) {
+		self.aiding = Some(Aiding{ code_dphase: code_rate_chips_per_sec / self.fs, pending_code_chips: code_correction_chips,
+			carrier_dphase_rad: carrier_freq_hz * 2.0 * consts::PI / self.fs, max_coast_bits: (max_coast_sec / (20.0 * SYMBOL_LEN_SEC)) as usize });
+	}
+
+	pub fn clear_aiding(&mut self) {
+		self.aiding = None;
+		self.coast_bits = 0;
+	}
+
+	/// Mean code discriminator output [chips] since the last call, positive when the signal is ahead of the local code
+	pub fn take_code_error_chips(&mut self) -> Option<f64> {
+		let ans = if self.code_error_count > 0 { Some(self.code_error_sum / self.code_error_count as f64) } else { None };
+		self.code_error_sum   = 0.0;
+		self.code_error_count = 0;
+		ans
+	}
+
 	pub fn sv_time_of_week(&self) -> f64 { self.sv_tow_sec_outer.time() }
 	pub fn reset_clock(&mut self, t:f64) {
 		self.sv_tow_sec_outer.reset(t);
@@ -279,20 +362,33 @@ impl<A: ScalarFilter, B: ScalarFilter> Tracking<A, B> {
 		}
 	}
 
+	/// Sets the intermediate frequency of the input, which the carrier NCO runs at along with the Doppler but which neither the
+	/// code rate nor the accumulated carrier phase should see
+	pub fn set_intermediate_freq_hz(&mut self, hz:f64) {
+		self.intermediate_freq_hz = hz;
+		self.code_dphase = code_dphase(self.carrier_freq_hz() - hz, self.fs);
+	}
+
 	pub fn initialize(&mut self, acq_freq_hz:f64) {
 
 		let acq_carrier_rad_per_sec = acq_freq_hz * 2.0 * consts::PI;
 		self.carrier            = Complex{ re: 1.0, im: 0.0};
 		self.carrier_dphase_rad = acq_carrier_rad_per_sec / self.fs;
+		self.carrier_cycles     = 0.0;
+		self.carrier_lock_count += 1;
 
-		let radial_velocity_factor:f64 = (1.57542e9 + acq_freq_hz) / 1.57542e9;
 		self.code_phase = 0.0;
-		self.code_dphase = (radial_velocity_factor * 1.023e6) / self.fs;
+		self.code_dphase = code_dphase(acq_freq_hz - self.intermediate_freq_hz, self.fs);
 
 		self.carrier_filter.initialize();
 		self.code_filter.initialize();
 
+		self.clear_aiding();
+		self.code_error_sum   = 0.0;
+		self.code_error_count = 0;
+
 		self.input_signal_power = 0.0;
+		self.last_input_power   = 0.0;
 		self.sum_early  = ZERO;
 		self.sum_prompt = ZERO;
 		self.sum_late   = ZERO;
@@ -304,6 +400,12 @@ impl<A: ScalarFilter, B: ScalarFilter> Tracking<A, B> {
 
 }
 
+// Code NCO increment per sample for the given Doppler
+fn code_dphase(doppler_hz:f64, fs:f64) -> f64 {
+	let radial_velocity_factor:f64 = (1.57542e9 + doppler_hz) / 1.57542e9;
+	(radial_velocity_factor * 1.023e6) / fs
+}
+
 pub fn new_tracker<T: ScalarFilter, F: Fn(f64, f64) -> T>(prn:usize, acq_freq_hz:f64, fs:f64, 
 	alpha_carrier:f64, alpha_code:f64, f:F) -> Tracking<T, T> {
 	
@@ -315,9 +417,8 @@ pub fn new_tracker<T: ScalarFilter, F: Fn(f64, f64) -> T>(prn:usize, acq_freq_hz
 	let carrier     = Complex{ re: 1.0, im: 0.0};
 	let carrier_inc = Complex{ re: carrier_dphase_rad.cos(), im: -carrier_dphase_rad.sin() };
 
-	let radial_velocity_factor:f64 = (1.57542e9 + acq_freq_hz) / 1.57542e9;
 	let code_phase      = 0.0;
-	let code_dphase     = (radial_velocity_factor * 1.023e6) / fs;
+	let code_dphase     = code_dphase(acq_freq_hz, fs);
 
 	let carrier_filter      = f(alpha_carrier, SYMBOL_LEN_SEC);
 	let code_filter         = f(alpha_code,    SYMBOL_LEN_SEC);
@@ -325,7 +426,7 @@ pub fn new_tracker<T: ScalarFilter, F: Fn(f64, f64) -> T>(prn:usize, acq_freq_hz
 	let state = TrackingState::WaitingForInitialLockStatus{ prev_prompt: ZERO, prev_test_stat: 0.0 };
 
 	Tracking { 
-		code_len_samples, prn, state, fs, local_code, 
+		code_len_samples, prn, state, fs, local_code, intermediate_freq_hz: 0.0,
 
 		last_acq_result: Default::default(),
 
@@ -333,10 +434,12 @@ pub fn new_tracker<T: ScalarFilter, F: Fn(f64, f64) -> T>(prn:usize, acq_freq_hz
 		sv_tow_sec_outer: IntegerClock::new(fs),
 
 		// Carrier and code
-		carrier, carrier_inc, carrier_dphase_rad, code_phase, code_dphase, carrier_filter, code_filter, 
+		carrier, carrier_inc, carrier_dphase_rad, code_phase, code_dphase, carrier_cycles: 0.0, carrier_lock_count: 0,
+		carrier_filter, code_filter, 
+		aiding: None, coast_bits: 0, code_error_sum: 0.0, code_error_count: 0,
 
 		// Used during summation over the short interval
-		sum_early: ZERO, sum_prompt: ZERO, sum_late: ZERO, input_signal_power: 0.0,		
+		sum_early: ZERO, sum_prompt: ZERO, sum_late: ZERO, input_signal_power: 0.0, last_input_power: 0.0,		
 	}		
 }
 
@@ -402,4 +505,36 @@ pub fn new_3rd_order_tracker(prn:usize, acq_freq_hz:f64, fs:f64, alpha_carrier:f
 
 	})
 
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_intermediate_frequency() {
+	// Noise-free signal with alternating data bits so the tracker can promote to long coherent tracking
+	let (prn, fs, if_hz, doppler_hz) = (7, 2.046e6, 0.5e6, 1000.0);
+	let code = gps_l1_ca::signal_modulation::prn_complex(prn);
+	let sample = |idx:usize| {
+		let t:f64 = idx as f64 / fs;
+		let chips:f64 = t * 1.023e6 * (1.0 + doppler_hz / 1.57542e9);
+		let bit:f64 = if (chips / (40.0 * 1023.0)).fract() < 0.5 { 1.0 } else { -1.0 };
+		let carrier = Complex{ re: 0.0, im: 2.0 * consts::PI * (if_hz + doppler_hz) * t }.exp();
+		Sample{ val: code[chips.floor() as usize % 1023] * carrier * bit, idx }
+	};
+
+	let mut trk = new_2nd_order_tracker(prn, if_hz + doppler_hz, fs, 0.0, 0.0);
+	trk.set_intermediate_freq_hz(if_hz);
+	trk.control(&AcquisitionResult{ doppler_hz: if_hz + doppler_hz, ..Default::default() }).unwrap();
+	let n:usize = (1.5 * fs) as usize;
+	let (mut cycles_at_half, mut code_rate_sum) = (0.0, 0.0);
+	for idx in 0..n {
+		trk.apply(&sample(idx));
+		if idx == n / 2 { cycles_at_half = trk.carrier_cycles(); }
+		if idx > n / 2 { code_rate_sum += trk.code_dphase() * fs; }
+	}
+	assert!(trk.has_carrier_lock());
+
+	// Over the second half, the code runs at the Doppler-shifted chip rate on average and the carrier phase only follows the Doppler
+	let half_sec:f64 = (n - 1 - n / 2) as f64 / fs;
+	let mean_code_rate:f64 = code_rate_sum / (half_sec * fs);
+	assert!((mean_code_rate - 1.023e6 * (1.0 + doppler_hz / 1.57542e9)).abs() < 1.0);
+	assert!(((trk.carrier_cycles() - cycles_at_half) / half_sec - doppler_hz).abs() < 1.0);
+}
@@ -0,0 +1,193 @@
+
+use num_complex::Complex;
+
+use crate::{Sample, DigSigProcErr as DSPErr};
+
+use crate::block::{BlockFunctionality, BlockResult};
+use crate::block::block_tree_sync_static::acquire_and_track::AcquireAndTrack;
+
+use crate::filters::{SecondOrderFIR as FIR};
+use crate::gnss::common::acquisition::{two_stage_pcps::Acquisition, AcquisitionResult};
+use crate::gnss::common::tracking::TrackReport;
+use crate::gnss::gps_l1_ca::channel::{ObservationError, C_METERS_PER_SEC};
+
+use super::{signal_modulation, tracking_cm, L2_CM_PERIOD_SEC};
+use super::tlm_decode::{error_correction, preamble_and_crc};
+use super::tlm_decode::message_decode::{Message, MessageBody, type30};
+
+pub const DEFAULT_TEST_STAT_THRESHOLD:f64 = 0.0005;
+
+// One data bit is two 20-ms symbols
+const BIT_LEN_SEC:f64 = 2.0 * L2_CM_PERIOD_SEC;
+
+// Each message carries the time of week at the start of the next one in units of 6 seconds
+const TOW_COUNT_SEC:f64 = 6.0;
+
+/// Turns CM prompt values into CNAV messages.  The tracker doesn't know which symbol of a pair it starts on, and the path
+/// metric stays high even with the pairing off by one, so both pairings are decoded side by side and only the right one
+/// ever passes the CRC.
+pub struct TelemetryDecoder {
+	decoders: [(error_correction::ViterbiDecoder, preamble_and_crc::PreambleAndCrc); 2],
+}
+
+impl TelemetryDecoder {
+
+	pub fn new() -> Self {
+		let decoder = || (error_correction::ViterbiDecoder::new(error_correction::DEFAULT_TRACEBACK_LEN), preamble_and_crc::PreambleAndCrc::new());
+		let mut ans = Self{ decoders: [decoder(), decoder()] };
+		ans.initialize();
+		ans
+	}
+
+	pub fn initialize(&mut self) {
+		for (viterbi, pac) in self.decoders.iter_mut() {
+			viterbi.initialize();
+			*pac = preamble_and_crc::PreambleAndCrc::new();
+		}
+		// A zero symbol carries no information, so this just shifts the pairing of the second decoder by one
+		self.decoders[1].0.apply(0.0);
+	}
+
+	/// Time from the end of a message to the end of the symbol it's decoded on, which is the Viterbi traceback
+	pub fn decoding_delay_sec(&self) -> f64 { (error_correction::DEFAULT_TRACEBACK_LEN as f64) * BIT_LEN_SEC }
+
+	pub fn apply(&mut self, prompt_i:f64) -> Option<Result<Message, DSPErr>> {
+		let mut ans = None;
+		for (viterbi, pac) in self.decoders.iter_mut() {
+			if let Some(bits) = viterbi.apply(prompt_i).and_then(|b| pac.apply(b)) { ans = Some(Message::new(&bits)); }
+		}
+		ans
+	}
+
+}
+
+impl Default for TelemetryDecoder {
+	fn default() -> Self { Self::new() }
+}
+
+/// Raw L2C pseudorange for pairing with the L1 C/A observation of the same SV
+#[derive(Debug, Clone)]
+pub struct Observation {
+	pub prn: usize,
+	pub sv_tow_sec: f64,
+	pub pseudorange_m: f64,				// Receive time less transmit time, without any SV clock or group delay correction
+	pub carrier_freq_hz: f64,
+	pub clock: Option<type30::Body>,	// Most recent clock and group delay message, for the ISCs
+}
+
+#[derive(Debug)]
+pub struct ChannelReport {
+	pub opt_message:Option<Message>,
+	pub opt_observation:Option<Result<Observation, ObservationError>>,
+}
+
+/// Acquires and tracks L2 CM for one SV, decodes its CNAV messages and keeps time from them
+pub struct Channel {
+	pub prn: usize,
+	pub fs:  f64,
+	pub aat: AcquireAndTrack<Sample, AcquisitionResult, TrackReport, Acquisition, tracking_cm::Tracking<FIR>>,
+	pub tlm: TelemetryDecoder,
+	pub clock: Option<type30::Body>,
+	pub pvt_rate_samples: usize,
+	has_time: bool,
+}
+
+impl BlockFunctionality<(), bool, (Sample, f64), ChannelReport> for Channel {
+
+	fn control(&mut self, _:&()) -> Result<bool, &'static str> {
+		Ok(!self.aat.awaiting_acq)
+	}
+
+	fn apply(&mut self, input:&(Sample, f64)) -> BlockResult<ChannelReport> {
+		let (s, tow_rcv) = input;
+
+		let opt_message:Option<Message> = match self.aat.apply(s) {
+			BlockResult::Ready(TrackReport{ prompt_i, .. }) => match self.tlm.apply(prompt_i) {
+				Some(Ok(msg)) => {
+					self.add_message(&msg);
+					Some(msg)
+				},
+				_ => None,
+			},
+			BlockResult::NotReady => {
+				if self.aat.awaiting_acq {
+					self.tlm.initialize();
+					self.has_time = false;
+				}
+				None
+			},
+			BlockResult::Err(_) => return BlockResult::Err(DSPErr::LossOfLock),
+		};
+
+		let opt_observation = if s.idx % self.pvt_rate_samples == 0 { Some(self.observation(*tow_rcv)) } else { None };
+		if opt_message.is_none() && opt_observation.is_none() { BlockResult::NotReady }
+		else { BlockResult::Ready(ChannelReport{ opt_message, opt_observation }) }
+	}
+
+}
+
+impl Channel {
+
+	pub fn carrier_freq_hz(&self) -> f64 { self.aat.trk.carrier_freq_hz() }
+	pub fn test_stat(&self) -> f64 { self.aat.trk.test_stat() }
+
+	fn add_message(&mut self, msg:&Message) {
+		let tow_sec:f64 = (msg.time_of_week_truncated as f64) * TOW_COUNT_SEC;
+		self.aat.trk.reset_clock(tow_sec + self.tlm.decoding_delay_sec() + (self.aat.trk.code_phase_samples()/self.fs));
+		self.has_time = true;
+		if let MessageBody::Type30(body) = &msg.body { self.clock = Some(body.clone()); }
+	}
+
+	pub fn observation(&self, rx_tow_sec:f64) -> Result<Observation, ObservationError> {
+		// There's no transmit time until the first message sets the clock
+		if self.aat.awaiting_acq || !self.has_time { return Err(ObservationError::NotTracking); }
+
+		let sv_tow_sec:f64 = self.aat.trk.sv_time_of_week();
+		Ok(Observation{ prn: self.prn, sv_tow_sec, pseudorange_m: (rx_tow_sec - sv_tow_sec) * C_METERS_PER_SEC,
+			carrier_freq_hz: self.aat.trk.carrier_freq_hz(), clock: self.clock.clone() })
+	}
+
+}
+
+/// Sets up a channel for one PRN.  Acquisition is on one 20-ms period of CM, which is time-multiplexed with CL, so every other
+/// half-chip of the replica is zero.
+pub fn new_channel(prn:usize, fs:f64, test_stat_threshold:f64, pvt_rate_samples:usize) -> Channel {
+	let cm_code:[bool; 10230] = signal_modulation::cm_code(prn);
+	let n_samples:usize = (fs * L2_CM_PERIOD_SEC) as usize;
+	let symbol:Vec<Complex<f64>> = (0..n_samples).map(|sample_idx| {
+		let chip_idx_f64:f64 = sample_idx as f64 * (10230.0 / n_samples as f64);
+		let re:f64 = if chip_idx_f64.fract() >= 0.5 { 0.0 } else if cm_code[chip_idx_f64 as usize] { 1.0 } else { -1.0 };
+		Complex{ re, im: 0.0 }
+	}).collect();
+
+	let acq = Acquisition::new(symbol, fs, prn, 140, 2, 2.0, test_stat_threshold, 0);
+	let trk = tracking_cm::new_default_tracker(prn, 0.0, fs);
+
+	Channel{ prn, fs, aat: AcquireAndTrack::new(acq, trk), tlm: TelemetryDecoder::new(), clock: None, pvt_rate_samples, has_time: false }
+}
+
+#[test]
+fn test_message_timing() {
+	use crate::gnss::gps_l2c::tlm_encode::Encoder;
+
+	// Messages every 12 seconds, each carrying the time of the start of the next one, starting from TOW 30000
+	let messages:Vec<Message> = (0..5_u32).map(|i| Message{ prn: 7, type_id: 0, time_of_week_truncated: 5002 + 2*i, alert_flag: false,
+		body: MessageBody::Unknown }).collect();
+	let mut encoder = Encoder::new();
+	let symbols:Vec<f64> = messages.iter().flat_map(|msg| encoder.encode(msg).unwrap()).map(|s| if s { -1.0 } else { 1.0 }).collect();
+
+	// Starting on the second symbol of a pair works just as well
+	for first in 0..2 {
+		let mut tlm = TelemetryDecoder::new();
+		let mut decoded:Vec<(usize, Message)> = vec![];
+		for (j, s) in symbols.iter().enumerate().skip(first) {
+			if let Some(Ok(msg)) = tlm.apply(*s) { decoded.push((j, msg)); }
+		}
+
+		assert!(decoded.len() >= 3);
+		for (j, msg) in decoded.iter() {
+			let end_of_symbol_sec:f64 = 30000.0 + ((j + 1) as f64) * L2_CM_PERIOD_SEC;
+			assert!((end_of_symbol_sec - (msg.time_of_week_truncated as f64 * TOW_COUNT_SEC + tlm.decoding_delay_sec())).abs() < 1.0e-9);
+		}
+	}
+}
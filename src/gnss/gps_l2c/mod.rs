@@ -2,9 +2,11 @@
 pub const L2_CM_PERIOD_SEC:f64 = 20.0e-3;
 pub const L2_CL_PERIOD_SEC:f64 = 1.5;
 
+pub mod channel;
 pub mod signal_modulation;
 
 pub mod tracking_cl;
 pub mod tracking_cm;
 
-pub mod tlm_decode;
\ No newline at end of file
+pub mod tlm_decode;
+pub mod tlm_encode;
\ No newline at end of file
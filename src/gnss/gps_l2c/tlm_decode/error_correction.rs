@@ -1,38 +1,14 @@
+use std::collections::VecDeque;
 
-const ERROR_THRES:usize = 5;
+// Rate 1/2, constraint length 7 convolutional code described in IS-GPS-200K, section 3.3.3.1.1
+// The six previous input bits make up the decoder state, so there are 2^6 possible states
+const NUM_STATES:usize = 64;
 
-struct History {
-	current_state: u8,
-	prev_states: Vec<u8>,
-	cumulative_error: usize,
-}
-
-impl History {
-	
-	fn new(s0:u8) -> Self { Self {current_state: s0, prev_states: vec![], cumulative_error: 0} }
-
-	fn transition(&self, next_bit:bool, expected_values:(bool, bool)) -> Self {
-
-		// Calculate error
-		let mut cumulative_error = self.cumulative_error;
-		let (g1, g2) = cnav_fec(self.current_state);
-		let (g1_exp, g2_exp) = expected_values;
-		if g1 != g1_exp { cumulative_error += 1; }
-		if g2 != g2_exp { cumulative_error += 1; }
-
-		// Update state
-		let current_state:u8 = (self.current_state / 2) + if next_bit { 64 } else { 0 };
-
-		// Update history
-		let mut prev_states = self.prev_states.clone();
-		prev_states.push(self.current_state);
-
-		Self{ current_state, prev_states, cumulative_error }
-	}
-
-}
+pub const DEFAULT_TRACEBACK_LEN:usize = 60;
 
-fn cnav_fec(x:u8) -> (bool, bool) {
+/// Computes the two output symbols (G1, G2) of the CNAV convolutional encoder for a 7-bit shift register value.
+/// Bit 6 is the newest input bit and bit 0 is the oldest.
+pub fn cnav_fec(x:u8) -> (bool, bool) {
 
 	let bit6:bool = (x & 0x40) != 0;
 	let bit5:bool = (x & 0x20) != 0;
@@ -51,25 +27,182 @@ fn cnav_fec(x:u8) -> (bool, bool) {
 
 }
 
-pub fn decode(symbols:Vec<bool>) -> Option<Vec<bool>> {
-	let mut best_solutions:Vec<History> = (0..128).map(|i| History::new(i)).collect();
+// Correlation between a received soft symbol and an expected hard symbol; positive soft values correspond to true
+fn branch_metric(expected:(bool, bool), soft:(f64, f64)) -> f64 {
+	let m0:f64 = if expected.0 { soft.0 } else { -soft.0 };
+	let m1:f64 = if expected.1 { soft.1 } else { -soft.1 };
+	m0 + m1
+}
+
+/// Streaming soft-decision Viterbi decoder for the CNAV forward error correction.  Symbols are provided one at a time
+/// (e.g. the in-phase prompt correlator values from the tracker) and decoded bits come out after a fixed traceback delay.
+pub struct ViterbiDecoder {
+	traceback_len:usize,
+	path_metrics:[f64; NUM_STATES],
+	metric_offset:f64,
+	// Each entry is a bitmask over the states; a set bit means the survivor path into that state dropped a one from the register
+	decisions:VecDeque<u64>,
+	pending_symbol:Option<f64>,
+	// Best path metric and cumulative symbol magnitude at each step, used for quality monitoring over the traceback window
+	metric_history:VecDeque<(f64, f64)>,
+	symbol_magnitude_sum:f64,
+}
+
+impl ViterbiDecoder {
+
+	pub fn new(traceback_len:usize) -> Self {
+		Self{ traceback_len, path_metrics: [0.0; NUM_STATES], metric_offset: 0.0, decisions: VecDeque::new(),
+			pending_symbol: None, metric_history: VecDeque::new(), symbol_magnitude_sum: 0.0 }
+	}
 
-	for chunk in symbols.chunks_exact(2) {
-		let mut new_solutions:Vec<History> = vec![];
-		for h in &best_solutions { 
-			let if_true  = h.transition(true,  (chunk[0], chunk[1]));
-			let if_false = h.transition(false, (chunk[0], chunk[1]));
+	pub fn initialize(&mut self) {
+		self.path_metrics = [0.0; NUM_STATES];
+		self.metric_offset = 0.0;
+		self.decisions.clear();
+		self.pending_symbol = None;
+		self.metric_history.clear();
+		self.symbol_magnitude_sum = 0.0;
+	}
 
-			if if_true.cumulative_error  < ERROR_THRES { new_solutions.push(if_true);  }
-			if if_false.cumulative_error < ERROR_THRES { new_solutions.push(if_false); }
+	/// Takes the next soft symbol.  Returns a decoded bit once a full symbol pair is available and the traceback window is full.
+	pub fn apply(&mut self, symbol:f64) -> Option<bool> {
+		match self.pending_symbol.take() {
+			None => {
+				self.pending_symbol = Some(symbol);
+				None
+			},
+			Some(g1) => {
+				self.add_compare_select((g1, symbol));
+				if self.decisions.len() > self.traceback_len {
+					let (_, best_state) = self.best_state();
+					let bit:bool = self.oldest_bit(best_state);
+					self.decisions.pop_front();
+					self.metric_history.pop_front();
+					Some(bit)
+				} else { None }
+			}
 		}
+	}
 
-		if new_solutions.len() == 0 { return None; }
+	/// Decodes everything remaining in the traceback window using the most likely final state and resets the decoder
+	pub fn flush(&mut self) -> Vec<bool> {
+		let (_, best_state) = self.best_state();
+		let bits = self.traceback(best_state);
+		self.initialize();
+		bits
+	}
 
-		best_solutions = new_solutions;
+	/// Accumulated metric of the most likely path since the last initialization
+	pub fn path_metric(&self) -> f64 { self.best_state().0 + self.metric_offset }
+
+	/// Best path metric over the traceback window normalized by the total symbol magnitude over the same window.  This is 1.0
+	/// when every symbol agrees with the most likely path and drops toward zero as the symbols become noisy or misaligned.
+	pub fn normalized_path_metric(&self) -> f64 {
+		match self.metric_history.front() {
+			Some((metric0, magnitude0)) => {
+				let magnitude:f64 = self.symbol_magnitude_sum - magnitude0;
+				if magnitude > 0.0 { (self.path_metric() - metric0) / magnitude } else { 0.0 }
+			},
+			None => 0.0
+		}
+	}
+
+	/// Number of symbol pairs currently held in the traceback window
+	pub fn window_len(&self) -> usize { self.decisions.len() }
+
+	fn best_state(&self) -> (f64, usize) {
+		let mut best:(f64, usize) = (self.path_metrics[0], 0);
+		for (state, metric) in self.path_metrics.iter().enumerate().skip(1) {
+			if *metric > best.0 { best = (*metric, state); }
+		}
+		best
+	}
 
+	fn add_compare_select(&mut self, soft:(f64, f64)) {
+		let mut next_metrics:[f64; NUM_STATES] = [0.0; NUM_STATES];
+		let mut decision:u64 = 0;
+
+		for (next_state, next_metric) in next_metrics.iter_mut().enumerate() {
+			// The newest bit of the next state is the input bit and the remaining bits are the newest five bits of the previous state
+			let input:usize = next_state >> 5;
+			let prev_0:usize = (next_state & 0x1F) << 1;
+			let prev_1:usize = prev_0 | 1;
+
+			let metric_0:f64 = self.path_metrics[prev_0] + branch_metric(cnav_fec(((input << 6) | prev_0) as u8), soft);
+			let metric_1:f64 = self.path_metrics[prev_1] + branch_metric(cnav_fec(((input << 6) | prev_1) as u8), soft);
+
+			if metric_1 > metric_0 {
+				*next_metric = metric_1;
+				decision |= 1 << next_state;
+			} else {
+				*next_metric = metric_0;
+			}
+		}
+
+		// Renormalize so the metrics don't grow without bound, but keep track of the offset for quality reporting
+		let max_metric:f64 = next_metrics.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+		for m in next_metrics.iter_mut() { *m -= max_metric; }
+
+		self.metric_history.push_back((self.path_metric(), self.symbol_magnitude_sum));
+		self.metric_offset += max_metric;
+		self.symbol_magnitude_sum += soft.0.abs() + soft.1.abs();
+		self.path_metrics = next_metrics;
+		self.decisions.push_back(decision);
 	}
 
-	best_solutions.iter().min_by_key(|h| h.cumulative_error).map(|h| h.prev_states.iter().map(|s| s%2 == 1).collect())
+	// Same as the first bit from a traceback, without collecting the rest of them, since this runs for every decoded bit
+	fn oldest_bit(&self, final_state:usize) -> bool {
+		let mut state:usize = final_state;
+		for decision in self.decisions.iter().skip(1).rev() {
+			state = ((state & 0x1F) << 1) | ((decision >> state) & 1) as usize;
+		}
+		(state >> 5) == 1
+	}
 
-}
\ No newline at end of file
+	// Returns the input bits along the survivor path ending in the given state, oldest first
+	fn traceback(&self, final_state:usize) -> Vec<bool> {
+		let mut bits:Vec<bool> = vec![false; self.decisions.len()];
+		let mut state:usize = final_state;
+		for (i, decision) in self.decisions.iter().enumerate().rev() {
+			bits[i] = (state >> 5) == 1;
+			let dropped_bit:usize = ((decision >> state) & 1) as usize;
+			state = ((state & 0x1F) << 1) | dropped_bit;
+		}
+		bits
+	}
+
+}
+
+/// Decodes a complete block of soft symbols.  Returns the decoded bits along with the normalized path metric.
+pub fn decode(symbols:&[f64]) -> (Vec<bool>, f64) {
+	let mut decoder = ViterbiDecoder::new(symbols.len() / 2);
+	for s in symbols { decoder.apply(*s); }
+	let quality:f64 = decoder.normalized_path_metric();
+	(decoder.flush(), quality)
+}
+
+#[test]
+fn test_viterbi_decode() {
+	// Encode a known pseudorandom sequence, then flip a few symbols and attenuate others to make sure it still decodes
+	let bits:Vec<bool> = (0..400_u32).map(|i| (i.wrapping_mul(2654435761) >> 13) % 2 == 1).collect();
+	let mut register:u8 = 0;
+	let mut symbols:Vec<f64> = vec![];
+	for b in bits.iter() {
+		register = (register >> 1) | if *b { 0x40 } else { 0 };
+		let (g1, g2) = cnav_fec(register);
+		symbols.push(if g1 { 1.0 } else { -1.0 });
+		symbols.push(if g2 { 1.0 } else { -1.0 });
+	}
+	for idx in [17, 101, 250, 251, 600].iter() { symbols[*idx] *= -0.5; }
+	for idx in [40, 41, 42, 43].iter() { symbols[*idx] *= 0.1; }
+
+	let mut decoder = ViterbiDecoder::new(DEFAULT_TRACEBACK_LEN);
+	let mut decoded:Vec<bool> = symbols.iter().filter_map(|s| decoder.apply(*s)).collect();
+	assert!(decoder.normalized_path_metric() > 0.9);
+	decoded.append(&mut decoder.flush());
+	assert_eq!(decoded, bits);
+
+	let (block_decoded, quality) = decode(&symbols);
+	assert_eq!(block_decoded, bits);
+	assert!(quality > 0.9);
+}
@@ -23,4 +23,20 @@ pub fn is_subframe_crc_ok(message_w_crc:&[bool]) -> bool {
   	}
 
   	true
-}
\ No newline at end of file
+}
+// Computes the 24 parity bits that get appended to a message so that the whole thing passes is_subframe_crc_ok
+pub fn crc_24q(message:&[bool]) -> Vec<bool> {
+
+	let mut m:Vec<bool> = message.to_vec();
+	m.extend_from_slice(&[false; 24]);
+
+	for i in 0..(m.len() - CRC_24Q_POLYNOMIAL.len() + 1) {
+		if m[i] {
+			for j in 0..CRC_24Q_POLYNOMIAL.len() {
+				m[i+j] ^= CRC_24Q_POLYNOMIAL[j];
+			}
+		}
+	}
+
+	m.split_off(message.len())
+}
@@ -1,10 +1,10 @@
 
 use ::serde::{Serialize, Deserialize};
 
-use crate::utils::bools_to_int;
+use crate::utils::{bools_to_int, int_to_bools};
 use crate::DigSigProcErr;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Message {
 	pub prn: u8,
 	pub type_id:u8,
@@ -13,7 +13,7 @@ pub struct Message {
 	pub body:MessageBody,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum MessageBody {
 	Type10(type10::Body),
 	Type11(type11::Body),
@@ -25,6 +25,8 @@ pub mod type10;
 pub mod type11;
 pub mod type30;
 
+pub const PREAMBLE:[bool; 8] = [true, false, false, false, true, false, true, true];
+
 impl Message {
 
 	pub fn new(bits:&[bool]) -> Result<Self, DigSigProcErr> {
@@ -33,7 +35,7 @@ impl Message {
 			let prn                    = bools_to_int::to_u8( &bits[ 8..14])?;
 			let type_id                = bools_to_int::to_u8( &bits[14..20])?;
 			let time_of_week_truncated = bools_to_int::to_u32(&bits[20..37])?;
-			let alert_flag             = bits[38];
+			let alert_flag             = bits[37];
 			let body = match type_id {
 				10 => MessageBody::Type10(type10::Body::new(&bits[38..])?),
 				11 => MessageBody::Type11(type11::Body::new(&bits[38..])?),
@@ -47,5 +49,39 @@ impl Message {
 
 	}
 
+	/// Inverse of new; produces the 276-bit message without the CRC
+	pub fn write(&self) -> Result<Vec<bool>, DigSigProcErr> {
+		let mut bits:Vec<bool> = vec![false; 276];
+		bits[0..8].copy_from_slice(&PREAMBLE);
+		int_to_bools::from_unsigned(self.prn as i64,                    &mut bits[ 8..14])?;
+		int_to_bools::from_unsigned(self.type_id as i64,                &mut bits[14..20])?;
+		int_to_bools::from_unsigned(self.time_of_week_truncated as i64, &mut bits[20..37])?;
+		bits[37] = self.alert_flag;
+		match &self.body {
+			MessageBody::Type10(body) => body.write(&mut bits[38..])?,
+			MessageBody::Type11(body) => body.write(&mut bits[38..])?,
+			MessageBody::Type30(body) => body.write(&mut bits[38..])?,
+			MessageBody::Unknown => {},
+		}
+		Ok(bits)
+	}
+
 }
 
+#[test]
+fn test_field_positions() {
+	// Bit positions straight from IS-GPS-200K, 30.3.3, rather than from the encoder
+	let mut bits = vec![false; 276];
+	bits[0..8].copy_from_slice(&PREAMBLE);
+	for (start, s) in [(14, "001011"), (37, "1"), (38 + 109, "1111111111111111"), (38 + 141, "1")].iter() {
+		for (i, c) in s.chars().enumerate() { bits[start + i] = c == '1'; }
+	}
+
+	// The alert flag directly follows the 17-bit TOW count, and the type 11 harmonic terms are signed
+	let msg = Message::new(&bits).unwrap();
+	assert!(msg.alert_flag);
+	match msg.body {
+		MessageBody::Type11(body) => assert_eq!((body.cis_n, body.crs_n), (-2.0_f64.powi(-30), -32768.0)),
+		body => panic!("Expected message type 11, got {:?}", body),
+	}
+}
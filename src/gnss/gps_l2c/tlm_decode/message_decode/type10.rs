@@ -1,17 +1,17 @@
 
 use ::serde::{Serialize, Deserialize};
 
-use crate::utils::bools_to_int;
+use crate::utils::{bools_to_int, int_to_bools};
 use crate::DigSigProcErr;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Body {
-	week_num: u16,
-	l1_health: bool, l2_health: bool, l5_health: bool,
-	t_op:  u32, ura_ed: i8,  t_oe:     u32, d_a:  f64,
-	a_dot: f64, d_n0:   f64, d_n0_dot: f64, m0_n: f64,
-	e_n:   f64, om_n:   f64,
-	integrity_status_flag: bool, l2c_phasing: bool
+	pub week_num: u16,
+	pub l1_health: bool, pub l2_health: bool, pub l5_health: bool,
+	pub t_op:  u32, pub ura_ed: i8,  pub t_oe:     u32, pub d_a:  f64,
+	pub a_dot: f64, pub d_n0:   f64, pub d_n0_dot: f64, pub m0_n: f64,
+	pub e_n:   f64, pub om_n:   f64,
+	pub integrity_status_flag: bool, pub l2c_phasing: bool
 }
 
 impl Body {
@@ -49,4 +49,28 @@ impl Body {
 		}
 	}
 
-}
\ No newline at end of file
+	pub fn write(&self, bits:&mut [bool]) -> Result<(), DigSigProcErr> {
+		if bits.len() == 238 {
+			int_to_bools::from_unsigned(self.week_num as i64,                                &mut bits[  0.. 13])?;
+			bits[13] = self.l1_health;
+			bits[14] = self.l2_health;
+			bits[15] = self.l5_health;
+			int_to_bools::from_unsigned((self.t_op / 300) as i64,                            &mut bits[ 16.. 27])?;
+			int_to_bools::from_signed(  self.ura_ed as i64,                                  &mut bits[ 27.. 32])?;
+			int_to_bools::from_unsigned((self.t_oe / 300) as i64,                            &mut bits[ 32.. 43])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.d_a,      2.0_f64.powi(-9)),  &mut bits[ 43.. 69])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.a_dot,    2.0_f64.powi(-21)), &mut bits[ 69.. 94])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.d_n0,     2.0_f64.powi(-44)), &mut bits[ 94..111])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.d_n0_dot, 2.0_f64.powi(-57)), &mut bits[111..134])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.m0_n,     2.0_f64.powi(-32)), &mut bits[134..167])?;
+			int_to_bools::from_unsigned(int_to_bools::scaled(self.e_n,      2.0_f64.powi(-34)), &mut bits[167..200])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.om_n,     2.0_f64.powi(-32)), &mut bits[200..233])?;
+			bits[233] = self.integrity_status_flag;
+			bits[234] = self.l2c_phasing;
+			Ok(())
+		} else {
+			Err(DigSigProcErr::InvalidTelemetryData("Expected a bool slice of length 238 in type10::Body::write"))
+		}
+	}
+
+}
@@ -1,14 +1,14 @@
 
 use ::serde::{Serialize, Deserialize};
 
-use crate::utils::bools_to_int;
+use crate::utils::{bools_to_int, int_to_bools};
 use crate::DigSigProcErr;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Body {
-	t_oe:     u32, om_0n: f64, i_0n:  f64, d_om_dot: f64,
-	i_0n_dot: f64, cis_n: f64, cic_n: f64, crs_n:    f64,
-	crc_n:    f64, cus_n: f64, cuc_n: f64
+	pub t_oe:     u32, pub om_0n: f64, pub i_0n:  f64, pub d_om_dot: f64,
+	pub i_0n_dot: f64, pub cis_n: f64, pub cic_n: f64, pub crs_n:    f64,
+	pub crc_n:    f64, pub cus_n: f64, pub cuc_n: f64
 }
 
 impl Body {
@@ -22,11 +22,11 @@ impl Body {
 			let d_om_dot = (bools_to_int::to_i32(&bits[ 77.. 94])? as f64) * 2.0_f64.powi(-44);		// Rate of right ascension difference from reference value of -2.6e-9 [semicircles/sec]
 			let i_0n_dot = (bools_to_int::to_i16(&bits[ 94..109])? as f64) * 2.0_f64.powi(-44);		// Rate of inclination angle [semicircles/sec]
 			let cis_n    = (bools_to_int::to_i16(&bits[109..125])? as f64) * 2.0_f64.powi(-30);		// Amplitude of the sine harmonic correction term to the angle of inclination [radians]
-			let cic_n    = (bools_to_int::to_u16(&bits[125..141])? as f64) * 2.0_f64.powi(-30);		// Amplitude of the cosine harmonic correction term to the angle of inclination [radians]
-			let crs_n    = (bools_to_int::to_u32(&bits[141..165])? as f64) * 2.0_f64.powi(-8);		// Amplitude of the sine correction term to the orbit radius [meters]
-			let crc_n    = (bools_to_int::to_u32(&bits[165..189])? as f64) * 2.0_f64.powi(-8);		// Amplitude of the cosine correction term to the orbit radius [meters]
-			let cus_n    = (bools_to_int::to_u32(&bits[189..210])? as f64) * 2.0_f64.powi(-30);		// Amplitude of the sine harmonic correction term to the argument of latitude [radians]
-			let cuc_n    = (bools_to_int::to_u32(&bits[210..231])? as f64) * 2.0_f64.powi(-30);		// Amplitude of the cosine harmonic correction term to the argument of latitude [radians]
+			let cic_n    = (bools_to_int::to_i16(&bits[125..141])? as f64) * 2.0_f64.powi(-30);		// Amplitude of the cosine harmonic correction term to the angle of inclination [radians]
+			let crs_n    = (bools_to_int::to_i32(&bits[141..165])? as f64) * 2.0_f64.powi(-8);		// Amplitude of the sine correction term to the orbit radius [meters]
+			let crc_n    = (bools_to_int::to_i32(&bits[165..189])? as f64) * 2.0_f64.powi(-8);		// Amplitude of the cosine correction term to the orbit radius [meters]
+			let cus_n    = (bools_to_int::to_i32(&bits[189..210])? as f64) * 2.0_f64.powi(-30);		// Amplitude of the sine harmonic correction term to the argument of latitude [radians]
+			let cuc_n    = (bools_to_int::to_i32(&bits[210..231])? as f64) * 2.0_f64.powi(-30);		// Amplitude of the cosine harmonic correction term to the argument of latitude [radians]
 			// 7 reserved bits
 
 			Ok(Self{ t_oe, om_0n, i_0n, d_om_dot, i_0n_dot, cis_n, cic_n, crs_n, crc_n, cus_n, cuc_n })
@@ -36,4 +36,23 @@ impl Body {
 		}
 	}
 
-}
\ No newline at end of file
+	pub fn write(&self, bits:&mut [bool]) -> Result<(), DigSigProcErr> {
+		if bits.len() == 238 {
+			int_to_bools::from_unsigned((self.t_oe / 300) as i64,                            &mut bits[  0.. 11])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.om_0n,    2.0_f64.powi(-32)), &mut bits[ 11.. 44])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.i_0n,     2.0_f64.powi(-32)), &mut bits[ 44.. 77])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.d_om_dot, 2.0_f64.powi(-44)), &mut bits[ 77.. 94])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.i_0n_dot, 2.0_f64.powi(-44)), &mut bits[ 94..109])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.cis_n,    2.0_f64.powi(-30)), &mut bits[109..125])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.cic_n,    2.0_f64.powi(-30)), &mut bits[125..141])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.crs_n,    2.0_f64.powi(-8)),  &mut bits[141..165])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.crc_n,    2.0_f64.powi(-8)),  &mut bits[165..189])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.cus_n,    2.0_f64.powi(-30)), &mut bits[189..210])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.cuc_n,    2.0_f64.powi(-30)), &mut bits[210..231])?;
+			Ok(())
+		} else {
+			Err(DigSigProcErr::InvalidTelemetryData("Expected a bool slice of length 238 in type11::Body::write"))
+		}
+	}
+
+}
@@ -1,20 +1,20 @@
 
 use ::serde::{Serialize, Deserialize};
 
-use crate::utils::bools_to_int;
+use crate::utils::{bools_to_int, int_to_bools};
 use crate::DigSigProcErr;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Body {
-	t_op: u32,
-	ura_ned0: u8, ura_ned1: u8, ura_ned2: u8,
-	t_oc: u32, 
-	a_f0n: f32, a_f1n: f32, a_f2n: f32,
-	t_gd: f32,
-	isc_l1ca: f32, isc_l2c: f32, isc_l5i5: f32, isc_l5q5: f32,
-	alpha0: f32, alpha1: f32, alpha2: f32, alpha3: f32,
-	beta0:  f32, beta1:  f32, beta2:  f32, beta3:  f32,
-	wn_op: u8
+	pub t_op: u32,
+	pub ura_ned0: u8, pub ura_ned1: u8, pub ura_ned2: u8,
+	pub t_oc: u32, 
+	pub a_f0n: f32, pub a_f1n: f32, pub a_f2n: f32,
+	pub t_gd: f32,
+	pub isc_l1ca: f32, pub isc_l2c: f32, pub isc_l5i5: f32, pub isc_l5q5: f32,
+	pub alpha0: f32, pub alpha1: f32, pub alpha2: f32, pub alpha3: f32,
+	pub beta0:  f32, pub beta1:  f32, pub beta2:  f32, pub beta3:  f32,
+	pub wn_op: u8
 }
 
 impl Body {
@@ -61,4 +61,34 @@ impl Body {
 		}
 	}
 
-}
\ No newline at end of file
+	pub fn write(&self, bits:&mut [bool]) -> Result<(), DigSigProcErr> {
+		if bits.len() == 238 {
+			int_to_bools::from_unsigned((self.t_op / 300) as i64,                                     &mut bits[  0.. 11])?;
+			int_to_bools::from_unsigned(self.ura_ned0 as i64,                                         &mut bits[ 11.. 16])?;
+			int_to_bools::from_unsigned(self.ura_ned1 as i64,                                         &mut bits[ 16.. 19])?;
+			int_to_bools::from_unsigned(self.ura_ned2 as i64,                                         &mut bits[ 19.. 22])?;
+			int_to_bools::from_unsigned((self.t_oc / 300) as i64,                                     &mut bits[ 22.. 33])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.a_f0n    as f64, 2.0_f64.powi(-35)), &mut bits[ 33.. 59])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.a_f1n    as f64, 2.0_f64.powi(-48)), &mut bits[ 59.. 79])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.a_f2n    as f64, 2.0_f64.powi(-60)), &mut bits[ 79.. 89])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.t_gd     as f64, 2.0_f64.powi(-35)), &mut bits[ 89..102])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.isc_l1ca as f64, 2.0_f64.powi(-35)), &mut bits[102..115])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.isc_l2c  as f64, 2.0_f64.powi(-35)), &mut bits[115..128])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.isc_l5i5 as f64, 2.0_f64.powi(-35)), &mut bits[128..141])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.isc_l5q5 as f64, 2.0_f64.powi(-35)), &mut bits[141..154])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.alpha0   as f64, 2.0_f64.powi(-30)), &mut bits[154..162])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.alpha1   as f64, 2.0_f64.powi(-27)), &mut bits[162..170])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.alpha2   as f64, 2.0_f64.powi(-24)), &mut bits[170..178])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.alpha3   as f64, 2.0_f64.powi(-24)), &mut bits[178..186])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.beta0    as f64, 2.0_f64.powi( 11)), &mut bits[186..194])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.beta1    as f64, 2.0_f64.powi( 14)), &mut bits[194..202])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.beta2    as f64, 2.0_f64.powi( 16)), &mut bits[202..210])?;
+			int_to_bools::from_signed(  int_to_bools::scaled(self.beta3    as f64, 2.0_f64.powi( 16)), &mut bits[210..218])?;
+			int_to_bools::from_unsigned(self.wn_op as i64,                                            &mut bits[218..226])?;
+			Ok(())
+		} else {
+			Err(DigSigProcErr::InvalidTelemetryData("Expected a bool slice of length 238 in type30::Body::write"))
+		}
+	}
+
+}
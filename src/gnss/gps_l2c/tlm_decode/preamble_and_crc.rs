@@ -1,10 +1,14 @@
 
+use crate::gnss::common::nav_data_stats::NavDataStats;
+
 use super::error_detection;
 
 #[derive(Debug)]
 pub struct PreambleAndCrc {
 	buffer: Vec<bool>,
-	state: State
+	state: State,
+	last_is_inverse: Option<bool>,
+	pub stats: NavDataStats,
 }
 
 #[derive(Debug)]
@@ -16,7 +20,7 @@ enum State {
 impl PreambleAndCrc {
 	
 	pub fn new() -> Self {
-		Self{ buffer: vec![], state: State::Initial }
+		Self{ buffer: vec![], state: State::Initial, last_is_inverse: None, stats: Default::default() }
 	}
 
 	pub fn apply(&mut self, b:bool) -> Option<Vec<bool>> {
@@ -33,6 +37,7 @@ impl PreambleAndCrc {
 					if self.buffer[0..8] == [true, false, false, false, true, false, true, true] && error_detection::is_subframe_crc_ok(&self.buffer) {
 						// eprintln!("Normal preamble detected and CRC OK");
 						let msg:Vec<bool> = self.buffer.drain(..).take(276).collect();
+						self.record_detection(false);
 						(Some(State::Valid{ is_inverse: false }), Some(msg))
 					}
 					else if self.buffer[0..8] == [false, true, true, true, false, true, false, false] {
@@ -41,6 +46,7 @@ impl PreambleAndCrc {
 						if error_detection::is_subframe_crc_ok(&inverse_buffer) {
 							// eprintln!("CRC OK");
 							let msg:Vec<bool> = inverse_buffer.drain(..).take(276).collect();
+							self.record_detection(true);
 							(Some(State::Valid{ is_inverse: true }), Some(msg))	
 						}
 						else { 
@@ -57,10 +63,12 @@ impl PreambleAndCrc {
 					if error_detection::is_subframe_crc_ok(&self.buffer) {
 						// We passed the CRC check, so no state transition is necessary; just return the current message without the CRC
 						let msg:Vec<bool> = self.buffer.drain(..).take(276).collect();
+						self.stats.subframes_decoded += 1;
 						(None, Some(msg))
 					} else {
 						// We failed the CRC check, so go back to the initial state and start over
 						// eprintln!("Failed CRC check, going back to initial state");
+						self.stats.crc_failures += 1;
 						(Some(State::Initial), None)
 					}
 				} else {
@@ -77,4 +85,14 @@ impl PreambleAndCrc {
 		opt_ans
 	}
 
-}
\ No newline at end of file
+	// Only counts detections where the CRC also passed; a preamble alone is far too common in the bit stream to be meaningful
+	fn record_detection(&mut self, is_inverse:bool) {
+		self.stats.preamble_detections += 1;
+		self.stats.subframes_decoded += 1;
+		if let Some(last_is_inverse) = self.last_is_inverse {
+			if last_is_inverse != is_inverse { self.stats.polarity_flips += 1; }
+		}
+		self.last_is_inverse = Some(is_inverse);
+	}
+
+}
@@ -0,0 +1,103 @@
+use crate::DigSigProcErr;
+use crate::gnss::gps_l2c::tlm_decode::error_correction::cnav_fec;
+use crate::gnss::gps_l2c::tlm_decode::error_detection;
+use crate::gnss::gps_l2c::tlm_decode::message_decode::Message;
+
+/*	GPS L2C Telemetry Encoding Pipeline (the inverse of tlm_decode):
+	- Message encode
+	- CRC-24Q
+	- Rate 1/2 convolutional encoding
+*/
+
+/// Produces the 300-bit message including the CRC
+pub fn message_bits(msg:&Message) -> Result<Vec<bool>, DigSigProcErr> {
+	let mut bits:Vec<bool> = msg.write()?;
+	let mut crc:Vec<bool> = error_detection::crc_24q(&bits);
+	bits.append(&mut crc);
+	Ok(bits)
+}
+
+/// Convolutional encoder for the CNAV data stream.  The encoder isn't reset between messages, so one encoder should be used for
+/// the whole stream of messages from one SV.
+pub struct Encoder {
+	register: u8,
+}
+
+impl Encoder {
+
+	pub fn new() -> Self { Self{ register: 0 } }
+
+	/// Takes the next data bit and produces the G1 and G2 symbols in that order
+	pub fn apply(&mut self, b:bool) -> (bool, bool) {
+		self.register = (self.register >> 1) | if b { 0x40 } else { 0x00 };
+		cnav_fec(self.register)
+	}
+
+	/// Encodes a whole message including the CRC into 600 symbols
+	pub fn encode(&mut self, msg:&Message) -> Result<Vec<bool>, DigSigProcErr> {
+		let mut symbols:Vec<bool> = vec![];
+		for b in message_bits(msg)? {
+			let (g1, g2) = self.apply(b);
+			symbols.push(g1);
+			symbols.push(g2);
+		}
+		Ok(symbols)
+	}
+
+}
+
+impl Default for Encoder {
+	fn default() -> Self { Self::new() }
+}
+
+#[test]
+fn cnav_round_trip() {
+	use rand::{Rng, SeedableRng};
+	use rand::rngs::StdRng;
+	use crate::gnss::gps_l2c::tlm_decode::{error_correction, preamble_and_crc};
+	use crate::gnss::gps_l2c::tlm_decode::message_decode::{MessageBody, type10, type11, type30};
+
+	let mut rng = StdRng::seed_from_u64(4);
+	let mut signed = |n:u32, lsb:f64| (rng.gen_range(-(1i64 << (n-1)), 1i64 << (n-1)) as f64) * lsb;
+
+	let t10 = type10::Body{ week_num: 2100, l1_health: false, l2_health: true, l5_health: false, t_op: 300*1000, ura_ed: -3, t_oe: 300*1200,
+		d_a: signed(26, 2.0_f64.powi(-9)), a_dot: signed(25, 2.0_f64.powi(-21)), d_n0: signed(17, 2.0_f64.powi(-44)), 
+		d_n0_dot: signed(23, 2.0_f64.powi(-57)), m0_n: signed(33, 2.0_f64.powi(-32)), e_n: signed(33, 2.0_f64.powi(-34)).abs(),
+		om_n: signed(33, 2.0_f64.powi(-32)), integrity_status_flag: true, l2c_phasing: false };
+	let t11 = type11::Body{ t_oe: 300*1200, om_0n: signed(33, 2.0_f64.powi(-32)), i_0n: signed(33, 2.0_f64.powi(-32)), 
+		d_om_dot: signed(17, 2.0_f64.powi(-44)), i_0n_dot: signed(15, 2.0_f64.powi(-44)), cis_n: signed(16, 2.0_f64.powi(-30)), 
+		cic_n: signed(16, 2.0_f64.powi(-30)), crs_n: signed(24, 2.0_f64.powi(-8)), crc_n: signed(24, 2.0_f64.powi(-8)), 
+		cus_n: signed(21, 2.0_f64.powi(-30)), cuc_n: signed(21, 2.0_f64.powi(-30)) };
+	// The clock terms are stored as f32, so keep them within 24 bits of precision
+	let mut signed_f32 = |n:u32, lsb:f64| (rng.gen_range(-(1i64 << (n-1)), 1i64 << (n-1)) as f64 * lsb) as f32;
+	let t30 = type30::Body{ t_op: 300*1000, ura_ned0: 5, ura_ned1: 2, ura_ned2: 7, t_oc: 300*1200, a_f0n: signed_f32(24, 2.0_f64.powi(-35)),
+		a_f1n: signed_f32(20, 2.0_f64.powi(-48)), a_f2n: signed_f32(10, 2.0_f64.powi(-60)), t_gd: signed_f32(13, 2.0_f64.powi(-35)),
+		isc_l1ca: signed_f32(13, 2.0_f64.powi(-35)), isc_l2c: signed_f32(13, 2.0_f64.powi(-35)), isc_l5i5: signed_f32(13, 2.0_f64.powi(-35)),
+		isc_l5q5: signed_f32(13, 2.0_f64.powi(-35)), alpha0: signed_f32(8, 2.0_f64.powi(-30)), alpha1: signed_f32(8, 2.0_f64.powi(-27)),
+		alpha2: signed_f32(8, 2.0_f64.powi(-24)), alpha3: signed_f32(8, 2.0_f64.powi(-24)), beta0: signed_f32(8, 2.0_f64.powi(11)),
+		beta1: signed_f32(8, 2.0_f64.powi(14)), beta2: signed_f32(8, 2.0_f64.powi(16)), beta3: signed_f32(8, 2.0_f64.powi(16)), wn_op: 34 };
+
+	let messages:Vec<Message> = (0..6_u32).map(|i| {
+		let (type_id, body) = match i % 3 {
+			0 => (10, MessageBody::Type10(t10.clone())),
+			1 => (11, MessageBody::Type11(t11.clone())),
+			_ => (30, MessageBody::Type30(t30.clone())),
+		};
+		Message{ prn: 15, type_id, time_of_week_truncated: 5000 + i, alert_flag: i == 4, body }
+	}).collect();
+
+	// Encode the messages as one continuous stream, then run it back through the Viterbi decoder and preamble/CRC detector
+	let mut encoder = Encoder::new();
+	let mut symbols:Vec<f64> = vec![];
+	for msg in messages.iter() {
+		for s in encoder.encode(msg).unwrap() { symbols.push(if s { 1.0 } else { -1.0 }); }
+	}
+
+	let mut viterbi = error_correction::ViterbiDecoder::new(error_correction::DEFAULT_TRACEBACK_LEN);
+	let mut bits:Vec<bool> = symbols.iter().filter_map(|s| viterbi.apply(*s)).collect();
+	bits.append(&mut viterbi.flush());
+
+	let mut pac = preamble_and_crc::PreambleAndCrc::new();
+	let decoded:Vec<Message> = bits.into_iter().filter_map(|b| pac.apply(b)).map(|msg_bits| Message::new(&msg_bits).unwrap()).collect();
+	assert_eq!(decoded, messages);
+}
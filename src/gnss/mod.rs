@@ -1,5 +1,7 @@
 
 pub mod common;
 
+pub mod galileo_e1;
 pub mod gps_l1_ca;
 pub mod gps_l2c;
+pub mod sbas_l1;
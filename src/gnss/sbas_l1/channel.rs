@@ -0,0 +1,71 @@
+
+use num_complex::Complex;
+
+use crate::{Sample, DigSigProcErr as DSPErr};
+
+use crate::block::{BlockFunctionality, BlockResult};
+use crate::block::block_tree_sync_static::acquire_and_track::AcquireAndTrack;
+
+use crate::filters::{SecondOrderFIR as FIR};
+use crate::gnss::common::acquisition::{two_stage_pcps::Acquisition, AcquisitionResult};
+use crate::gnss::common::nav_data_stats::NavDataStats;
+use crate::gnss::common::tracking::TrackReport;
+use crate::gnss::gps_l1_ca;
+
+use super::message::Message;
+use super::tlm_decode::TelemetryDecoder;
+use super::tracking;
+
+pub const DEFAULT_TEST_STAT_THRESHOLD:f64 = 0.008;
+
+/// Acquires and tracks one SBAS GEO and produces its decoded messages.  There's no ranging on the GEO, so unlike the GPS
+/// channels this doesn't produce observations.
+pub struct Channel {
+	pub prn: usize,
+	pub fs:  f64,
+	pub aat: AcquireAndTrack<Sample, AcquisitionResult, TrackReport, Acquisition, tracking::Tracking<FIR>>,
+	pub tlm: TelemetryDecoder,
+	pub last_sample_idx: usize,
+}
+
+impl BlockFunctionality<(), bool, Sample, Message> for Channel {
+
+	fn control(&mut self, _:&()) -> Result<bool, &'static str> {
+		Ok(!self.aat.awaiting_acq)
+	}
+
+	fn apply(&mut self, s:&Sample) -> BlockResult<Message> {
+		self.last_sample_idx = s.idx;
+		match self.aat.apply(s) {
+			BlockResult::Ready(TrackReport{ prompt_i, .. }) => match self.tlm.apply(prompt_i) {
+				Some(msg) => BlockResult::Ready(msg),
+				None      => BlockResult::NotReady,
+			},
+			BlockResult::NotReady => {
+				// Start the decoder over whenever the tracker has to reacquire
+				if self.aat.awaiting_acq { self.tlm.initialize(); }
+				BlockResult::NotReady
+			},
+			BlockResult::Err(_) => BlockResult::Err(DSPErr::LossOfLock),
+		}
+	}
+
+}
+
+impl Channel {
+
+	pub fn carrier_freq_hz(&self) -> f64 { self.aat.trk.carrier_freq_hz() }
+	pub fn test_stat(&self) -> f64 { self.aat.trk.test_stat() }
+	pub fn cn0_dbhz(&self) -> f64 { self.aat.trk.cn0_dbhz() }
+	pub fn nav_data_stats(&self) -> NavDataStats { self.tlm.stats }
+
+}
+
+pub fn new_channel(prn:usize, fs:f64, test_stat_threshold:f64) -> Channel {
+	let symbol_i8:Vec<i8> = gps_l1_ca::signal_modulation::prn_int_sampled(prn, fs);
+	let symbol:Vec<Complex<f64>> = symbol_i8.into_iter().map(|x| Complex{ re: x as f64, im: 0.0 }).collect();
+	let acq = Acquisition::new(symbol, fs, prn, 9, 3, 50.0, test_stat_threshold, 8);
+	let trk = tracking::new_default_tracker(prn, 0.0, fs);
+
+	Channel { prn, fs, aat: AcquireAndTrack::new(acq, trk), tlm: TelemetryDecoder::new(), last_sample_idx: 0 }
+}
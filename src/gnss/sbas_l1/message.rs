@@ -0,0 +1,303 @@
+
+use ::serde::{Serialize, Deserialize};
+
+use crate::utils::bools_to_int;
+use crate::DigSigProcErr;
+
+// Each message starts with one of these in turn, so three messages in a row spell out the 24-bit preamble
+pub const PREAMBLES:[u8; 3] = [0x53, 0x9A, 0xC6];
+
+pub const MASK_SLOTS:usize = 51;			// Most SVs a PRN mask can select
+pub const IGPS_PER_BAND:usize = 201;
+pub const IGPS_PER_BLOCK:usize = 15;
+pub const FAST_CORRECTIONS_PER_BLOCK:usize = 13;
+
+// A vertical delay with all ones means the IGP isn't monitored
+const IGP_DONT_USE:u16 = 511;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Message {
+	pub type_id: u8,
+	pub body: MessageBody,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum MessageBody {
+	DontUse,											// Type 0
+	PrnMask{ prns:Vec<usize>, iodp:u8 },				// Type 1, PRNs in mask slot order
+	FastCorrections(FastCorrections),					// Types 2-5
+	Integrity{ iodf:[u8; 4], udrei:Vec<u8> },			// Type 6, IODFs for types 2-5 and a UDREI per mask slot
+	FastCorrectionDegradation{ latency_sec:f64, iodp:u8, ai:Vec<u8> },		// Type 7, a degradation factor indicator per mask slot
+	GeoNavigation(GeoNavigation),						// Type 9
+	IgpMask{ num_bands:u8, band:u8, iodi:u8, mask:Vec<bool> },			// Type 18
+	MixedCorrections{ fast:FastCorrections, long_term:Vec<LongTermCorrection> },	// Type 24
+	LongTermCorrections(Vec<LongTermCorrection>),		// Type 25
+	IonosphericDelays{ band:u8, block:u8, iodi:u8, delays:Vec<Option<IgpDelay>> },	// Type 26
+	ClockEphemerisCovariance(Vec<ClockEphemerisCovariance>),		// Type 28
+	Unknown,
+}
+
+/// Pseudorange corrections for a run of consecutive mask slots
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FastCorrections {
+	pub first_slot: usize,			// Mask slot of the first correction, counting from one
+	pub iodf: u8,
+	pub iodp: u8,
+	pub prc_m: Vec<f64>,
+	pub udrei: Vec<u8>,
+}
+
+/// Corrections to the broadcast orbit and clock of the SV in one mask slot, valid for the GPS ephemeris with the same IODE
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct LongTermCorrection {
+	pub slot: usize,
+	pub iode: u8,
+	pub iodp: u8,
+	pub dpos_m: (f64, f64, f64),		// ECEF
+	pub dclock_sec: f64,
+	pub dvel_mps: (f64, f64, f64),		// Zero unless the message has velocity terms
+	pub dclock_rate: f64,
+	pub t0_sec: Option<f64>,			// [sec of day] reference time of the velocity terms
+}
+
+/// Position and clock of the SBAS GEO itself, for ranging
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct GeoNavigation {
+	pub t0_sec: f64,					// [sec of day]
+	pub ura: u8,
+	pub pos_m: (f64, f64, f64),
+	pub vel_mps: (f64, f64, f64),
+	pub acc_mps2: (f64, f64, f64),
+	pub af0_sec: f64,
+	pub af1: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct IgpDelay {
+	pub vertical_delay_m: f64,
+	pub givei: u8,
+}
+
+/// Cholesky factor of the covariance of one SV's clock and ephemeris errors, scaled into a covariance [m^2] in ECEF and clock
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct ClockEphemerisCovariance {
+	pub slot: usize,
+	pub iodp: u8,
+	pub covariance_m2: [[f64; 4]; 4],
+}
+
+// Reads fields one after another from the data part of a message
+struct Fields<'a> {
+	bits: &'a [bool],
+	idx: usize,
+}
+
+impl<'a> Fields<'a> {
+
+	fn unsigned(&mut self, n:usize) -> Result<u32, DigSigProcErr> {
+		let x = bools_to_int::to_u32(&self.bits[self.idx..self.idx+n])?;
+		self.idx += n;
+		Ok(x)
+	}
+
+	fn signed(&mut self, n:usize) -> Result<i32, DigSigProcErr> {
+		let x = bools_to_int::to_i32(&self.bits[self.idx..self.idx+n])?;
+		self.idx += n;
+		Ok(x)
+	}
+
+	fn skip(&mut self, n:usize) { self.idx += n; }
+
+	fn fast_corrections(&mut self, first_slot:usize, n:usize, iodf:u8, iodp:u8) -> Result<FastCorrections, DigSigProcErr> {
+		let prc_m:Vec<f64> = (0..n).map(|_| self.signed(12).map(|x| (x as f64) * 0.125)).collect::<Result<_, _>>()?;
+		let udrei:Vec<u8> = (0..n).map(|_| self.unsigned(4).map(|x| x as u8)).collect::<Result<_, _>>()?;
+		Ok(FastCorrections{ first_slot, iodf, iodp, prc_m, udrei })
+	}
+
+	// One 106-bit half of a long-term correction message, which holds either two SVs without velocity terms or one with them
+	fn long_term_half(&mut self) -> Result<Vec<LongTermCorrection>, DigSigProcErr> {
+		let lsb_clock:f64 = 2.0_f64.powi(-31);
+		if self.unsigned(1)? == 0 {
+			let mut entries:Vec<LongTermCorrection> = vec![];
+			for _ in 0..2 {
+				let slot = self.unsigned(6)? as usize;
+				let iode = self.unsigned(8)? as u8;
+				let dpos_m = (self.signed(9)? as f64 * 0.125, self.signed(9)? as f64 * 0.125, self.signed(9)? as f64 * 0.125);
+				let dclock_sec:f64 = self.signed(10)? as f64 * lsb_clock;
+				entries.push(LongTermCorrection{ slot, iode, iodp: 0, dpos_m, dclock_sec, dvel_mps: (0.0, 0.0, 0.0), dclock_rate: 0.0, t0_sec: None });
+			}
+
+			// The IODP comes after both SVs
+			let iodp = self.unsigned(2)? as u8;
+			self.skip(1);
+			Ok(entries.into_iter().filter(|ltc| ltc.slot > 0).map(|ltc| LongTermCorrection{ iodp, ..ltc }).collect())
+		} else {
+			let slot = self.unsigned(6)? as usize;
+			let iode = self.unsigned(8)? as u8;
+			let dpos_m = (self.signed(11)? as f64 * 0.125, self.signed(11)? as f64 * 0.125, self.signed(11)? as f64 * 0.125);
+			let dclock_sec:f64 = self.signed(11)? as f64 * lsb_clock;
+			let lsb_vel:f64 = 2.0_f64.powi(-11);
+			let dvel_mps = (self.signed(8)? as f64 * lsb_vel, self.signed(8)? as f64 * lsb_vel, self.signed(8)? as f64 * lsb_vel);
+			let dclock_rate:f64 = self.signed(8)? as f64 * 2.0_f64.powi(-39);
+			let t0_sec:f64 = self.unsigned(13)? as f64 * 16.0;
+			let iodp = self.unsigned(2)? as u8;
+			Ok(if slot > 0 { vec![LongTermCorrection{ slot, iode, iodp, dpos_m, dclock_sec, dvel_mps, dclock_rate, t0_sec: Some(t0_sec) }] } else { vec![] })
+		}
+	}
+
+}
+
+impl Message {
+
+	/// Parses a 250-bit message that already passed its CRC, or the 226 bits in front of the CRC
+	pub fn new(bits:&[bool]) -> Result<Self, DigSigProcErr> {
+		if bits.len() != 250 && bits.len() != 226 {
+			return Err(DigSigProcErr::InvalidTelemetryData("Expected a 250-bit SBAS message with or without the CRC"));
+		}
+
+		let type_id = bools_to_int::to_u8(&bits[8..14])?;
+		let mut f = Fields{ bits: &bits[14..226], idx: 0 };
+		let body = match type_id {
+			0 => MessageBody::DontUse,
+			1 => {
+				let prns:Vec<usize> = (0..210).filter(|i| f.bits[*i]).map(|i| i + 1).take(MASK_SLOTS).collect();
+				f.skip(210);
+				MessageBody::PrnMask{ prns, iodp: f.unsigned(2)? as u8 }
+			},
+			2..=5 => {
+				let iodf = f.unsigned(2)? as u8;
+				let iodp = f.unsigned(2)? as u8;
+				MessageBody::FastCorrections(f.fast_corrections((type_id as usize - 2) * FAST_CORRECTIONS_PER_BLOCK + 1, FAST_CORRECTIONS_PER_BLOCK, iodf, iodp)?)
+			},
+			6 => {
+				let mut iodf = [0; 4];
+				for x in iodf.iter_mut() { *x = f.unsigned(2)? as u8; }
+				let udrei:Vec<u8> = (0..MASK_SLOTS).map(|_| f.unsigned(4).map(|x| x as u8)).collect::<Result<_, _>>()?;
+				MessageBody::Integrity{ iodf, udrei }
+			},
+			7 => {
+				let latency_sec = f.unsigned(4)? as f64;
+				let iodp = f.unsigned(2)? as u8;
+				f.skip(2);
+				let ai:Vec<u8> = (0..MASK_SLOTS).map(|_| f.unsigned(4).map(|x| x as u8)).collect::<Result<_, _>>()?;
+				MessageBody::FastCorrectionDegradation{ latency_sec, iodp, ai }
+			},
+			9 => {
+				f.skip(8);
+				let t0_sec = f.unsigned(13)? as f64 * 16.0;
+				let ura = f.unsigned(4)? as u8;
+				let pos_m = (f.signed(30)? as f64 * 0.08, f.signed(30)? as f64 * 0.08, f.signed(25)? as f64 * 0.4);
+				let vel_mps = (f.signed(17)? as f64 * 0.000625, f.signed(17)? as f64 * 0.000625, f.signed(18)? as f64 * 0.004);
+				let acc_mps2 = (f.signed(10)? as f64 * 0.0000125, f.signed(10)? as f64 * 0.0000125, f.signed(10)? as f64 * 0.0000625);
+				let af0_sec = f.signed(12)? as f64 * 2.0_f64.powi(-31);
+				let af1 = f.signed(8)? as f64 * 2.0_f64.powi(-40);
+				MessageBody::GeoNavigation(GeoNavigation{ t0_sec, ura, pos_m, vel_mps, acc_mps2, af0_sec, af1 })
+			},
+			18 => {
+				let num_bands = f.unsigned(4)? as u8;
+				let band = f.unsigned(4)? as u8;
+				let iodi = f.unsigned(2)? as u8;
+				let mask:Vec<bool> = f.bits[f.idx..f.idx+IGPS_PER_BAND].to_vec();
+				MessageBody::IgpMask{ num_bands, band, iodi, mask }
+			},
+			24 => {
+				let mut g = Fields{ bits: f.bits, idx: 6*12 + 6*4 };
+				let iodp = g.unsigned(2)? as u8;
+				let block = g.unsigned(2)? as usize;
+				let iodf = g.unsigned(2)? as u8;
+				g.skip(4);
+				let fast = f.fast_corrections(block * FAST_CORRECTIONS_PER_BLOCK + 1, 6, iodf, iodp)?;
+				MessageBody::MixedCorrections{ fast, long_term: g.long_term_half()? }
+			},
+			25 => {
+				let mut long_term = f.long_term_half()?;
+				long_term.append(&mut f.long_term_half()?);
+				MessageBody::LongTermCorrections(long_term)
+			},
+			26 => {
+				let band = f.unsigned(4)? as u8;
+				let block = f.unsigned(4)? as u8;
+				let mut delays:Vec<Option<IgpDelay>> = vec![];
+				for _ in 0..IGPS_PER_BLOCK {
+					let delay = f.unsigned(9)? as u16;
+					let givei = f.unsigned(4)? as u8;
+					delays.push(if delay == IGP_DONT_USE || givei == 15 { None } else { Some(IgpDelay{ vertical_delay_m: delay as f64 * 0.125, givei }) });
+				}
+				MessageBody::IonosphericDelays{ band, block, iodi: f.unsigned(2)? as u8, delays }
+			},
+			28 => {
+				let iodp = f.unsigned(2)? as u8;
+				let mut entries:Vec<ClockEphemerisCovariance> = vec![];
+				for _ in 0..2 {
+					let slot = f.unsigned(6)? as usize;
+					let scale:f64 = 2.0_f64.powi(f.unsigned(3)? as i32 - 5);
+
+					// Upper triangular with the diagonal first, then the off-diagonal terms row by row
+					let mut r = [[0.0; 4]; 4];
+					for (i, row) in r.iter_mut().enumerate() { row[i] = f.unsigned(9)? as f64 * scale; }
+					for (i, j) in [(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)].iter() { r[*i][*j] = f.signed(10)? as f64 * scale; }
+
+					let mut covariance_m2 = [[0.0; 4]; 4];
+					for (i, row) in covariance_m2.iter_mut().enumerate() {
+						for (j, c) in row.iter_mut().enumerate() { *c = (0..4).map(|k| r[k][i] * r[k][j]).sum(); }
+					}
+					if slot > 0 { entries.push(ClockEphemerisCovariance{ slot, iodp, covariance_m2 }); }
+				}
+				MessageBody::ClockEphemerisCovariance(entries)
+			},
+			_ => MessageBody::Unknown,
+		};
+
+		Ok(Self{ type_id, body })
+	}
+
+}
+
+/// Latitude and longitude [deg] of each IGP in a band's mask order, for bands 0 through 8.  Each band is 40 degrees of
+/// longitude in 5-degree columns from west to east, and each column runs from south to north; the columns at 10-degree
+/// multiples reach 75 degrees, the others stop at 55, and a few carry an extra point at 85 north or south.  The polar bands
+/// 9 and 10 aren't supported.
+pub fn band_igps(band:u8) -> Option<Vec<(i32, i32)>> {
+	if band > 8 { return None; }
+	let mut igps:Vec<(i32, i32)> = vec![];
+	for col in 0..8 {
+		let lon:i32 = -180 + 40*(band as i32) + 5*col;
+		if [-140, -50, 40, 130].contains(&lon) { igps.push((-85, lon)); }
+		if lon % 10 == 0 { igps.extend([-75, -65].iter().map(|lat| (*lat, lon))); }
+		igps.extend((-11..=11).map(|k| (5*k, lon)));
+		if lon % 10 == 0 { igps.extend([65, 75].iter().map(|lat| (*lat, lon))); }
+		if [-180, -90, 0, 90].contains(&lon) { igps.push((85, lon)); }
+	}
+	Some(igps)
+}
+
+/// Variance [m^2] of the user differential range error for a UDREI, or None for not monitored (14) and don't use (15)
+pub fn udre_variance_m2(udrei:u8) -> Option<f64> {
+	[0.0520, 0.0924, 0.1444, 0.2830, 0.4678, 0.8315, 1.2992, 1.8709, 2.5465, 3.3260, 5.1968, 20.7870, 230.9661, 2078.695]
+		.get(udrei as usize).copied()
+}
+
+/// Variance [m^2] of the grid ionospheric vertical error for a GIVEI, or None for not monitored (15)
+pub fn give_variance_m2(givei:u8) -> Option<f64> {
+	[0.0084, 0.0333, 0.0749, 0.1331, 0.2079, 0.2994, 0.4075, 0.5322, 0.6735, 0.8315, 1.1974, 1.8709, 3.3260, 20.787, 187.0826]
+		.get(givei as usize).copied()
+}
+
+/// Fast correction degradation factor [m/s^2] for an indicator from message type 7
+pub fn degradation_factor_mps2(ai:u8) -> f64 {
+	[0.0, 0.00005, 0.00009, 0.00012, 0.00015, 0.00020, 0.00030, 0.00045, 0.00060, 0.00090, 0.00150, 0.00210, 0.00270, 0.00330, 0.00460, 0.00580]
+		[(ai & 0x0F) as usize]
+}
+
+#[test]
+fn test_igp_bands() {
+	// Every band but the last fills its mask
+	for band in 0..8 { assert_eq!(band_igps(band).unwrap().len(), IGPS_PER_BAND); }
+	assert_eq!(band_igps(8).unwrap().len(), IGPS_PER_BAND - 1);
+	assert!(band_igps(9).is_none());
+
+	let band0 = band_igps(0).unwrap();
+	assert_eq!(band0[0], (-75, -180));
+	assert_eq!(band0[27], (85, -180));
+	assert_eq!(band0[28], (-55, -175));
+}
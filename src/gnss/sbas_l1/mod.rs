@@ -0,0 +1,40 @@
+
+/*	SBAS L1 (WAAS, EGNOS, MSAS, GAGAN, ...) receive chain:
+	- Acquisition with the same C/A code family as GPS, PRNs 120-158
+	- Tracking with 2-ms symbols at 500 sps
+	- Rate 1/2 Viterbi decoding, the same code as GPS CNAV
+	- 250-bit messages framed by a rotating preamble and checked with CRC-24Q
+	- Corrections applied to GPS observations before the PVT
+*/
+
+pub const FIRST_PRN:usize = 120;
+pub const LAST_PRN:usize = 158;
+
+pub const SYMBOL_LEN_SEC:f64 = 2.0e-3;
+pub const MESSAGE_LEN_BITS:usize = 250;
+pub const MESSAGE_LEN_SEC:f64 = 1.0;
+
+pub fn is_sbas_prn(prn:usize) -> bool { (FIRST_PRN..=LAST_PRN).contains(&prn) }
+
+pub mod channel;
+pub mod message;
+pub mod tlm_decode;
+pub mod tracking;
+
+#[test]
+fn test_sbas_codes() {
+	use crate::gnss::gps_l1_ca::signal_modulation::{prn_int, prn_int_compute};
+
+	// The generator has to agree with the tabulated codes before the generated ones can be trusted
+	for prn in (1..=32).chain(FIRST_PRN..=138) {
+		assert_eq!(prn_int(prn).to_vec(), prn_int_compute(prn));
+	}
+
+	// Every SBAS code is distinct and balanced the way Gold codes are
+	let codes:Vec<Vec<i8>> = (FIRST_PRN..=LAST_PRN).map(|prn| prn_int(prn).to_vec()).collect();
+	for (i, a) in codes.iter().enumerate() {
+		let balance:i32 = a.iter().map(|x| *x as i32).sum();
+		assert!(balance.abs() <= 65);
+		for b in codes.iter().skip(i+1) { assert_ne!(a, b); }
+	}
+}
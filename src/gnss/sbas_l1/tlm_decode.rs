@@ -0,0 +1,182 @@
+
+use crate::gnss::common::nav_data_stats::NavDataStats;
+use crate::gnss::gps_l2c::tlm_decode::error_correction::{ViterbiDecoder, DEFAULT_TRACEBACK_LEN};
+use crate::gnss::gps_l2c::tlm_decode::error_detection;
+use crate::utils::bools_to_int;
+
+use super::MESSAGE_LEN_BITS;
+use super::message::{Message, PREAMBLES};
+
+const CRC_START:usize = MESSAGE_LEN_BITS - 24;
+
+// Looks for a preamble followed by a good CRC 250 bits later, then checks the CRC of each message after that
+#[derive(Debug)]
+struct FrameSync {
+	buffer: Vec<bool>,
+	state: State,
+}
+
+#[derive(Debug)]
+enum State {
+	Initial,
+	Valid{ is_inverse:bool },
+}
+
+fn is_crc_ok(bits:&[bool]) -> bool { error_detection::crc_24q(&bits[..CRC_START]) == bits[CRC_START..] }
+
+impl FrameSync {
+
+	fn new() -> Self { Self{ buffer: vec![], state: State::Initial } }
+
+	// Returns the message bits and whether they were inverted
+	fn apply(&mut self, b:bool, stats:&mut NavDataStats) -> Option<(Vec<bool>, bool)> {
+		match self.state {
+			State::Initial => {
+				self.buffer.push(b);
+				while self.buffer.len() > MESSAGE_LEN_BITS { self.buffer.remove(0); }
+				if self.buffer.len() < MESSAGE_LEN_BITS { return None; }
+
+				let first_byte:u8 = bools_to_int::to_u8(&self.buffer[0..8]).unwrap_or(0);
+				let is_inverse:bool = if PREAMBLES.contains(&first_byte) { false }
+					else if PREAMBLES.contains(&!first_byte) { true }
+					else { return None; };
+
+				let msg:Vec<bool> = self.buffer.iter().map(|x| x ^ is_inverse).collect();
+				if is_crc_ok(&msg) {
+					self.buffer.clear();
+					self.state = State::Valid{ is_inverse };
+					stats.preamble_detections += 1;
+					Some((msg, is_inverse))
+				} else { None }
+			},
+			State::Valid{ is_inverse } => {
+				self.buffer.push(b ^ is_inverse);
+				if self.buffer.len() < MESSAGE_LEN_BITS { return None; }
+
+				let msg:Vec<bool> = self.buffer.drain(..).collect();
+				if is_crc_ok(&msg) { Some((msg, is_inverse)) }
+				else {
+					stats.crc_failures += 1;
+					self.state = State::Initial;
+					None
+				}
+			},
+		}
+	}
+
+}
+
+/// Turns 500 sps soft symbols into SBAS messages.  The tracker has no way of knowing which symbols pair up into a bit, so two
+/// Viterbi decoders run on the stream offset by one symbol from each other and whichever produces messages that pass the CRC wins.
+pub struct TelemetryDecoder {
+	branches: Vec<(ViterbiDecoder, FrameSync)>,
+	num_symbols: usize,
+	last_is_inverse: Option<bool>,
+	pub stats: NavDataStats,
+}
+
+impl TelemetryDecoder {
+
+	pub fn new() -> Self {
+		let branches = (0..2).map(|_| (ViterbiDecoder::new(DEFAULT_TRACEBACK_LEN), FrameSync::new())).collect();
+		Self{ branches, num_symbols: 0, last_is_inverse: None, stats: Default::default() }
+	}
+
+	pub fn initialize(&mut self) {
+		for (viterbi, sync) in self.branches.iter_mut() {
+			viterbi.initialize();
+			*sync = FrameSync::new();
+		}
+		self.num_symbols = 0;
+		self.last_is_inverse = None;
+	}
+
+	/// Takes the next soft symbol, positive for true, and returns a message whenever one passes its CRC
+	pub fn apply(&mut self, symbol:f64) -> Option<Message> {
+		self.num_symbols += 1;
+		let mut ans:Option<Message> = None;
+		let stats = &mut self.stats;
+		for (idx, (viterbi, sync)) in self.branches.iter_mut().enumerate() {
+			// The second branch starts one symbol late
+			if idx == 1 && self.num_symbols == 1 { continue; }
+			if let Some((bits, is_inverse)) = viterbi.apply(symbol).and_then(|b| sync.apply(b, stats)) {
+				if let Ok(msg) = Message::new(&bits) {
+					stats.subframes_decoded += 1;
+					if let Some(last_is_inverse) = self.last_is_inverse {
+						if last_is_inverse != is_inverse { stats.polarity_flips += 1; }
+					}
+					self.last_is_inverse = Some(is_inverse);
+					ans = Some(msg);
+				}
+			}
+		}
+		ans
+	}
+
+}
+
+impl Default for TelemetryDecoder {
+	fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+use crate::gnss::gps_l2c::tlm_decode::error_correction::cnav_fec;
+#[cfg(test)]
+use crate::utils::int_to_bools;
+#[cfg(test)]
+use super::message::MessageBody;
+
+#[test]
+fn test_decode_stream() {
+	// PRN masks and fast corrections in turn, each with the next preamble in the rotation
+	let mut messages:Vec<Vec<bool>> = vec![];
+	for i in 0..6 {
+		let mut bits:Vec<bool> = vec![false; MESSAGE_LEN_BITS];
+		int_to_bools::from_unsigned(PREAMBLES[i % 3] as i64, &mut bits[0..8]).unwrap();
+		if i % 2 == 0 {
+			int_to_bools::from_unsigned(1, &mut bits[8..14]).unwrap();
+			for prn in [3, 7, 22, 31, 120, 138].iter() { bits[14 + prn - 1] = true; }
+			int_to_bools::from_unsigned(2, &mut bits[224..226]).unwrap();
+		} else {
+			int_to_bools::from_unsigned(2, &mut bits[8..14]).unwrap();
+			int_to_bools::from_unsigned(2, &mut bits[16..18]).unwrap();
+			for slot in 0..4 { int_to_bools::from_signed(-8 * (slot + i as i64), &mut bits[18 + 12*slot as usize..30 + 12*slot as usize]).unwrap(); }
+		}
+		let crc = error_detection::crc_24q(&bits[..CRC_START]);
+		bits[CRC_START..].copy_from_slice(&crc);
+		messages.push(bits);
+	}
+
+	// Encode as one continuous stream, then start decoding one symbol in with the polarity flipped
+	let mut register:u8 = 0;
+	let mut symbols:Vec<f64> = vec![];
+	for b in messages.iter().flatten() {
+		register = (register >> 1) | if *b { 0x40 } else { 0 };
+		let (g1, g2) = cnav_fec(register);
+		symbols.push(if g1 { -1.0 } else { 1.0 });
+		symbols.push(if g2 { -1.0 } else { 1.0 });
+	}
+
+	let mut decoder = TelemetryDecoder::new();
+	let decoded:Vec<Message> = symbols.iter().skip(1).filter_map(|s| decoder.apply(*s)).collect();
+
+	// The first message is lost while the decoder syncs and the last is still in the traceback window
+	assert_eq!(decoded.len(), 4);
+	for (msg, bits) in decoded.iter().zip(messages.iter().skip(1)) { assert_eq!(*msg, Message::new(bits).unwrap()); }
+	match &decoded[1].body {
+		MessageBody::PrnMask{ prns, iodp } => {
+			assert_eq!(*prns, vec![3, 7, 22, 31, 120, 138]);
+			assert_eq!(*iodp, 2);
+		},
+		_ => panic!("Expected a PRN mask"),
+	}
+	match &decoded[0].body {
+		MessageBody::FastCorrections(fc) => {
+			assert_eq!(fc.first_slot, 1);
+			assert_eq!(fc.iodp, 2);
+			assert_eq!(fc.prc_m[0..4], [-1.0, -2.0, -3.0, -4.0]);
+		},
+		_ => panic!("Expected fast corrections"),
+	}
+	assert_eq!(decoder.stats.crc_failures, 0);
+}
@@ -0,0 +1,247 @@
+
+use std::f64::consts;
+
+use ::rustfft::num_complex::Complex;
+
+use crate::{Sample, DigSigProcErr as DSPErr};
+use crate::block::{BlockFunctionality, BlockResult};
+use crate::filters::{ScalarFilter, SecondOrderFIR};
+use crate::gnss::common::acquisition::AcquisitionResult;
+use crate::gnss::common::tracking::TrackReport;
+use crate::gnss::gps_l1_ca;
+
+// The lock test statistic is the prompt power over the input power for each 1-ms code period.  Without a signal it's
+// exponentially distributed with a mean of one, and with one its mean is 1 + C/N0 * 1 ms, so 2.5 corresponds to about 32 dB-Hz
+// and 2.0 to 30 dB-Hz.  SBAS GEOs are usually received well above either.
+pub const TEST_STAT_THRESH_INITIAL_LOCK:f64 = 2.5;
+pub const TEST_STAT_THRESH_LOSS_OF_LOCK:f64 = 2.0;
+
+// Code periods spent finding the symbol boundaries before reporting any symbols
+pub const SYMBOL_SYNC_CODE_PERIODS:usize = 200;
+
+// Weight of each code period in the running lock statistic, about 50 symbols
+const LOCK_STAT_ALPHA:f64 = 0.01;
+
+const CODE_PERIOD_SEC:f64 = 1.0e-3;
+
+const ZERO:Complex<f64> = Complex{ re: 0.0, im: 0.0 };
+
+/// Tracks the 1-ms C/A code of an SBAS GEO and reports one soft symbol per 2-ms symbol.  Each symbol spans two code periods,
+/// so the tracker first finds which code period boundaries are symbol boundaries by comparing the energy of the two possible pairings.
+pub struct Tracking<A: ScalarFilter> {
+	pub prn:usize,
+	pub state: TrackingState,
+	pub fs:f64,
+	pub local_code:Vec<Complex<f64>>,
+
+	last_acq_result:AcquisitionResult,
+
+	// Carrier and code
+	carrier: Complex<f64>,
+	carrier_inc: Complex<f64>,
+	carrier_dphase_rad: f64,
+	code_phase: f64,
+	code_dphase: f64,
+
+	carrier_filter: A,
+	code_filter: A,
+
+	// Used during summation over one code period
+	sum_early:  Complex<f64>,
+	sum_prompt: Complex<f64>,
+	sum_late:   Complex<f64>,
+	input_signal_power: f64,
+
+	// Prompt of the previous code period and the running lock statistic
+	prev_prompt: Complex<f64>,
+	code_periods: usize,
+	lock_stat: f64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TrackingState {
+	SymbolSync{ pair_energy:[f64; 2] },
+	Tracking{ symbol_parity:usize },
+	LostLock,
+}
+
+impl<A:ScalarFilter> BlockFunctionality<AcquisitionResult, (), Sample, TrackReport> for Tracking<A> {
+
+	fn control(&mut self, acq_result:&AcquisitionResult) -> Result<(), &'static str> {
+		self.initialize(acq_result.doppler_hz);
+		self.last_acq_result = acq_result.clone();
+		Ok(())
+	}
+
+	fn apply(&mut self, sample:&Sample) -> BlockResult<TrackReport> {
+		if sample.idx < self.last_acq_result.sample_idx + self.last_acq_result.code_phase { return BlockResult::NotReady; }
+
+		// Increment the carrier and code phase
+		self.carrier *= self.carrier_inc;
+		self.code_phase += self.code_dphase;
+
+		// Remove the carrier from the new sample and accumulate the power sum
+		let x = sample.val * self.carrier;
+		self.input_signal_power += x.norm_sqr();
+
+		// Integrate early, prompt, and late sums
+		let e_idx:usize = if self.code_phase < 0.5 { 1022 } else { (self.code_phase - 0.5).floor() as usize };
+		self.sum_early  += self.local_code[e_idx%1023] * x;
+		self.sum_prompt += self.local_code[(self.code_phase.floor() as usize)%1023] * x;
+		self.sum_late   += self.local_code[(e_idx+1)%1023] * x;
+
+		if self.code_phase < 1023.0 { return BlockResult::NotReady; }
+
+		// End of a code period.  The arctangent discriminator ignores the symbol sign, so the carrier loop runs every code period.
+		self.code_phase -= 1023.0;
+		self.code_periods += 1;
+		let carrier_error = if self.sum_prompt.re == 0.0 { 0.0 } else { (self.sum_prompt.im / self.sum_prompt.re).atan() };
+		self.carrier_dphase_rad += self.carrier_filter.apply(carrier_error);
+		self.carrier_inc = Complex{ re: self.carrier_dphase_rad.cos(), im: -self.carrier_dphase_rad.sin() };
+		self.carrier = self.carrier / self.carrier.norm();
+
+		let code_error:f64 = {
+			let e:f64 = self.sum_early.norm();
+			let l:f64 = self.sum_late.norm();
+			if l+e == 0.0 { 0.0 } else { 0.5 * (l-e) / (l+e) }
+		};
+		self.code_dphase += self.code_filter.apply(code_error);
+
+		let test_stat:f64 = if self.input_signal_power > 0.0 { self.sum_prompt.norm_sqr() / self.input_signal_power } else { 0.0 };
+		let pair:Complex<f64> = self.prev_prompt + self.sum_prompt;
+
+		let (result, opt_next_state) = match self.state {
+			TrackingState::SymbolSync{ ref mut pair_energy } => {
+				self.lock_stat += test_stat / (SYMBOL_SYNC_CODE_PERIODS as f64);
+				if self.code_periods > 1 { pair_energy[self.code_periods % 2] += pair.norm_sqr(); }
+
+				if self.code_periods < SYMBOL_SYNC_CODE_PERIODS { (BlockResult::NotReady, None) }
+				else if self.lock_stat < TEST_STAT_THRESH_INITIAL_LOCK { (BlockResult::Err(DSPErr::LossOfLock), Some(TrackingState::LostLock)) }
+				else {
+					// Pairs that straddle a symbol boundary cancel whenever the symbol changes, so the pairing with more energy is the right one
+					let symbol_parity:usize = if pair_energy[0] > pair_energy[1] { 0 } else { 1 };
+					(BlockResult::NotReady, Some(TrackingState::Tracking{ symbol_parity }))
+				}
+			},
+			TrackingState::Tracking{ symbol_parity } => {
+				self.lock_stat += LOCK_STAT_ALPHA * (test_stat - self.lock_stat);
+				if self.lock_stat < TEST_STAT_THRESH_LOSS_OF_LOCK {
+					(BlockResult::Err(DSPErr::LossOfLock), Some(TrackingState::LostLock))
+				} else if self.code_periods % 2 == symbol_parity {
+					let v = TrackReport { id: self.prn, prompt_i: pair.re, sample_idx: sample.idx,
+						test_stat: self.test_stat(), freq_hz: self.carrier_freq_hz() };
+					(BlockResult::Ready(v), None)
+				} else { (BlockResult::NotReady, None) }
+			},
+			TrackingState::LostLock => (BlockResult::Err(DSPErr::LossOfLock), None),
+		};
+
+		// Reset the accumulators for the next code period
+		self.prev_prompt = self.sum_prompt;
+		self.input_signal_power = 0.0;
+		self.sum_early  = ZERO;
+		self.sum_prompt = ZERO;
+		self.sum_late   = ZERO;
+
+		if let Some(next_state) = opt_next_state { self.state = next_state; }
+
+		result
+	}
+
+}
+
+impl<A: ScalarFilter> Tracking<A> {
+
+	pub fn carrier_freq_hz(&self) -> f64 { (self.carrier_dphase_rad * self.fs) / (2.0 * consts::PI) }
+	pub fn carrier_phase_rad(&self) -> f64 { self.carrier.arg() }
+	pub fn code_phase_samples(&self) -> f64 { self.code_phase * (self.fs / 1.023e6) }
+	pub fn code_dphase(&self) -> f64 { self.code_dphase }
+	pub fn test_stat(&self) -> f64 { self.lock_stat }
+	pub fn has_symbol_sync(&self) -> bool { matches!(self.state, TrackingState::Tracking{ .. }) }
+
+	// The test statistic less its noise-only mean is the signal to noise ratio in one code period
+	pub fn cn0_dbhz(&self) -> f64 { 10.0 * ((self.lock_stat - 1.0).max(1.0e-3) / CODE_PERIOD_SEC).log10() }
+
+	pub fn initialize(&mut self, acq_freq_hz:f64) {
+		self.carrier            = Complex{ re: 1.0, im: 0.0};
+		self.carrier_dphase_rad = acq_freq_hz * 2.0 * consts::PI / self.fs;
+		self.carrier_inc        = Complex{ re: self.carrier_dphase_rad.cos(), im: -self.carrier_dphase_rad.sin() };
+
+		let radial_velocity_factor:f64 = (1.57542e9 + acq_freq_hz) / 1.57542e9;
+		self.code_phase = 0.0;
+		self.code_dphase = (radial_velocity_factor * 1.023e6) / self.fs;
+
+		self.carrier_filter.initialize();
+		self.code_filter.initialize();
+
+		self.input_signal_power = 0.0;
+		self.sum_early   = ZERO;
+		self.sum_prompt  = ZERO;
+		self.sum_late    = ZERO;
+		self.prev_prompt = ZERO;
+		self.code_periods = 0;
+		self.lock_stat    = 0.0;
+
+		self.state = TrackingState::SymbolSync{ pair_energy: [0.0; 2] };
+	}
+
+}
+
+pub fn new_default_tracker(prn:usize, acq_freq_hz:f64, fs:f64) -> Tracking<SecondOrderFIR> {
+	// Same loop as the default GPS L1 C/A tracker, updated every code period
+	let a0 = 0.0625 / CODE_PERIOD_SEC;
+	let a1 = -0.5   / CODE_PERIOD_SEC;
+	let a2 = 0.5    / CODE_PERIOD_SEC;
+
+	let mut trk = Tracking {
+		prn, state: TrackingState::LostLock, fs, local_code: gps_l1_ca::signal_modulation::prn_complex(prn),
+		last_acq_result: AcquisitionResult::default(),
+		carrier: ZERO, carrier_inc: ZERO, carrier_dphase_rad: 0.0, code_phase: 0.0, code_dphase: 0.0,
+		carrier_filter: SecondOrderFIR::new(a0/fs, a1/fs, a2/fs),
+		code_filter:    SecondOrderFIR::new(a0/fs, a1/fs, a2/fs),
+		sum_early: ZERO, sum_prompt: ZERO, sum_late: ZERO, input_signal_power: 0.0,
+		prev_prompt: ZERO, code_periods: 0, lock_stat: 0.0,
+	};
+	trk.initialize(acq_freq_hz);
+	trk
+}
+
+#[test]
+fn test_symbol_tracking() {
+	use rand::Rng;
+	use rand::SeedableRng;
+	use rand::rngs::StdRng;
+
+	let mut rng = StdRng::seed_from_u64(49);
+	let fs:f64 = 2.046e6;
+	let doppler_hz:f64 = 1200.0;
+	let prn:usize = 131;
+	let code = gps_l1_ca::signal_modulation::prn_int(prn);
+	let symbols:Vec<bool> = (0..410).map(|_| rng.gen()).collect();
+
+	// 40 dB-Hz with unit noise power per complex sample, starting a quarter chip into the code
+	let amplitude:f64 = (1.0e4 / fs).sqrt();
+	let mut trk = new_default_tracker(prn, 0.0, fs);
+	trk.control(&AcquisitionResult{ doppler_hz: doppler_hz + 20.0, ..Default::default() }).unwrap();
+
+	let mut reported:Vec<f64> = vec![];
+	for idx in 0..(0.8 * fs) as usize {
+		let t:f64 = idx as f64 / fs;
+		let chip:f64 = t * 1.023e6 * (1.0 + doppler_hz / 1.57542e9) + 0.25;
+		let sign:f64 = code[(chip as usize) % 1023] as f64 * if symbols[(chip / 2046.0) as usize] { 1.0 } else { -1.0 };
+		let phase:f64 = 2.0 * consts::PI * doppler_hz * t + 0.7;
+		let noise = Complex{ re: rng.gen::<f64>() - 0.5, im: rng.gen::<f64>() - 0.5 } * 12.0_f64.sqrt() / 2.0_f64.sqrt();
+		let val = Complex{ re: phase.cos(), im: phase.sin() } * amplitude * sign + noise;
+		if let BlockResult::Ready(report) = trk.apply(&Sample{ val, idx }) { reported.push(report.prompt_i); }
+	}
+
+	// Line the reported symbols up with the transmitted ones; the carrier loop leaves the overall sign ambiguous
+	assert!(trk.has_symbol_sync());
+	assert!((trk.cn0_dbhz() - 40.0).abs() < 2.0);
+	assert!(reported.len() > 250);
+	let agree:usize = (0..symbols.len() - reported.len()).map(|first| {
+		let n:usize = reported.iter().zip(symbols.iter().skip(first)).filter(|(p, s)| (**p > 0.0) == **s).count();
+		n.max(reported.len() - n)
+	}).max().unwrap();
+	assert_eq!(agree, reported.len());
+}
@@ -57,7 +57,7 @@ pub fn to_u64(bools:&[bool]) -> Result<u64, DigSigProcErr> {
 pub fn to_i8(bools:&[bool]) -> Result<i8, DigSigProcErr> {
     let n = bools.len();
     if n <= 8 {
-	    if bools[0] { Ok((1..n).filter(|i| !bools[*i]).map(|i| 2i8.pow((n-i-1) as u32)).fold(0i8, |acc, x| acc+x) * -1i8) }
+	    if bools[0] { Ok((1..n).filter(|i| !bools[*i]).map(|i| 2i8.pow((n-i-1) as u32)).fold(0i8, |acc, x| acc+x) * -1i8 - 1i8) }
 	    else        { Ok((1..n).filter(|i|  bools[*i]).map(|i| 2i8.pow((n-i-1) as u32)).fold(0i8, |acc, x| acc+x))        }
     } else {
     	Err(DigSigProcErr::InvalidTelemetryData("x.len() > 8 in bools_to_int::to_i8"))
@@ -67,7 +67,7 @@ pub fn to_i8(bools:&[bool]) -> Result<i8, DigSigProcErr> {
 pub fn to_i16(bools:&[bool]) -> Result<i16, DigSigProcErr> {
     let n = bools.len();
     if n <= 16 {
-	    if bools[0] { Ok((1..n).filter(|i| !bools[*i]).map(|i| 2i16.pow((n-i-1) as u32)).fold(0i16, |acc, x| acc+x) * -1i16) }
+	    if bools[0] { Ok((1..n).filter(|i| !bools[*i]).map(|i| 2i16.pow((n-i-1) as u32)).fold(0i16, |acc, x| acc+x) * -1i16 - 1i16) }
 	    else        { Ok((1..n).filter(|i|  bools[*i]).map(|i| 2i16.pow((n-i-1) as u32)).fold(0i16, |acc, x| acc+x))        }
     } else {
     	Err(DigSigProcErr::InvalidTelemetryData("x.len() > 16 in bools_to_int::to_i16"))
@@ -77,7 +77,7 @@ pub fn to_i16(bools:&[bool]) -> Result<i16, DigSigProcErr> {
 pub fn to_i32(bools:&[bool]) -> Result<i32, DigSigProcErr> {
     let n = bools.len();
     if n <= 32 {
-	    if bools[0] { Ok((1..n).filter(|i| !bools[*i]).map(|i| 2i32.pow((n-i-1) as u32)).fold(0i32, |acc, x| acc+x) * -1i32) }
+	    if bools[0] { Ok((1..n).filter(|i| !bools[*i]).map(|i| 2i32.pow((n-i-1) as u32)).fold(0i32, |acc, x| acc+x) * -1i32 - 1i32) }
 	    else        { Ok((1..n).filter(|i|  bools[*i]).map(|i| 2i32.pow((n-i-1) as u32)).fold(0i32, |acc, x| acc+x))        }
     } else {
     	Err(DigSigProcErr::InvalidTelemetryData("x.len() > 32 in bools_to_int::to_i32"))
@@ -87,10 +87,18 @@ pub fn to_i32(bools:&[bool]) -> Result<i32, DigSigProcErr> {
 pub fn to_i64(bools:&[bool]) -> Result<i64, DigSigProcErr> {
     let n = bools.len();
     if n <= 64 {
-	    if bools[0] { Ok((1..n).filter(|i| !bools[*i]).map(|i| 2i64.pow((n-i-1) as u32)).fold(0i64, |acc, x| acc+x) * -1i64) }
+	    if bools[0] { Ok((1..n).filter(|i| !bools[*i]).map(|i| 2i64.pow((n-i-1) as u32)).fold(0i64, |acc, x| acc+x) * -1i64 - 1i64) }
 	    else        { Ok((1..n).filter(|i|  bools[*i]).map(|i| 2i64.pow((n-i-1) as u32)).fold(0i64, |acc, x| acc+x))        }
     } else {
     	Err(DigSigProcErr::InvalidTelemetryData("x.len() > 64 in bools_to_int::to_i64"))
     }
 }
 
+#[test]
+fn test_twos_complement() {
+	// All ones is -1 and a lone sign bit is the most negative value, whatever the width
+	assert_eq!(to_i8(&[true; 5]).unwrap(), -1);
+	assert_eq!(to_i16(&[true, false, false, false]).unwrap(), -8);
+	assert_eq!(to_i32(&[true, false, true, true]).unwrap(), -5);
+	assert_eq!(to_i64(&[false, true, true]).unwrap(), 3);
+}
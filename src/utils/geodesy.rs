@@ -0,0 +1,227 @@
+
+extern crate nalgebra as na;
+
+use std::f64::consts;
+
+use self::na::base::{Matrix3, Vector3};
+
+use super::kinematics::{self, PositionWGS84, WGS84_SEMI_MAJOR_AXIS_METERS, WGS84_SEMI_MINOR_AXIS_METERS};
+
+pub const MEAN_EARTH_RADIUS_METERS:f64 = 6371008.8;
+
+const UTM_SCALE_FACTOR:f64 = 0.9996;
+const UTM_FALSE_EASTING_METERS:f64 = 500000.0;
+const UTM_FALSE_NORTHING_METERS:f64 = 10000000.0;		// Southern hemisphere only
+
+const MGRS_BANDS:&[u8] = b"CDEFGHJKLMNPQRSTUVWX";
+const MGRS_LETTERS:&[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ";
+
+/// Local tangent plane at a reference point, for east-north-up and north-east-down coordinates relative to it
+#[derive(Debug, Clone, Copy)]
+pub struct LocalFrame {
+	pub origin_ecef: Vector3<f64>,
+	pub latitude: f64,
+	pub longitude: f64,
+	pub height_above_ellipsoid: f64,
+	dcm_we: Matrix3<f64>,
+}
+
+impl LocalFrame {
+
+	pub fn from_ecef(origin_ecef:Vector3<f64>) -> Self {
+		let pos = kinematics::ecef_to_wgs84(origin_ecef[0], origin_ecef[1], origin_ecef[2]);
+		Self{ origin_ecef, latitude: pos.latitude, longitude: pos.longitude, height_above_ellipsoid: pos.height_above_ellipsoid,
+			dcm_we: kinematics::dcm_we(pos.latitude, pos.longitude) }
+	}
+
+	pub fn from_wgs84(pos:&PositionWGS84) -> Self {
+		Self{ origin_ecef: kinematics::wgs84_to_ecef(pos.latitude, pos.longitude, pos.height_above_ellipsoid),
+			latitude: pos.latitude, longitude: pos.longitude, height_above_ellipsoid: pos.height_above_ellipsoid,
+			dcm_we: kinematics::dcm_we(pos.latitude, pos.longitude) }
+	}
+
+	pub fn ecef_to_enu(&self, p_ecef:Vector3<f64>) -> Vector3<f64> { self.dcm_we * (p_ecef - self.origin_ecef) }
+	pub fn enu_to_ecef(&self, p_enu:Vector3<f64>) -> Vector3<f64> { self.origin_ecef + self.dcm_we.transpose() * p_enu }
+
+	pub fn ecef_to_ned(&self, p_ecef:Vector3<f64>) -> Vector3<f64> { enu_to_ned(self.ecef_to_enu(p_ecef)) }
+	pub fn ned_to_ecef(&self, p_ned:Vector3<f64>) -> Vector3<f64> { self.enu_to_ecef(enu_to_ned(p_ned)) }
+
+	// Velocities only rotate; the origin doesn't matter
+	pub fn ecef_to_enu_velocity(&self, v_ecef:Vector3<f64>) -> Vector3<f64> { self.dcm_we * v_ecef }
+	pub fn enu_to_ecef_velocity(&self, v_enu:Vector3<f64>) -> Vector3<f64> { self.dcm_we.transpose() * v_enu }
+	pub fn ecef_to_ned_velocity(&self, v_ecef:Vector3<f64>) -> Vector3<f64> { enu_to_ned(self.ecef_to_enu_velocity(v_ecef)) }
+	pub fn ned_to_ecef_velocity(&self, v_ned:Vector3<f64>) -> Vector3<f64> { self.enu_to_ecef_velocity(enu_to_ned(v_ned)) }
+
+}
+
+/// Swapping the first two axes and negating the third goes either way
+pub fn enu_to_ned(v:Vector3<f64>) -> Vector3<f64> { Vector3::new(v[1], v[0], -v[2]) }
+
+/// Haversine distance on a sphere of the mean earth radius, so good to a few tenths of a percent
+pub fn great_circle_distance_m(lat1:f64, lon1:f64, lat2:f64, lon2:f64) -> f64 {
+	let h:f64 = (0.5*(lat2 - lat1)).sin().powi(2) + lat1.cos() * lat2.cos() * (0.5*(lon2 - lon1)).sin().powi(2);
+	2.0 * MEAN_EARTH_RADIUS_METERS * h.sqrt().min(1.0).asin()
+}
+
+/// Initial bearing of the great circle from the first point to the second, clockwise from north in [0, 2*pi)
+pub fn great_circle_bearing(lat1:f64, lon1:f64, lat2:f64, lon2:f64) -> f64 {
+	let dlon:f64 = lon2 - lon1;
+	let bearing:f64 = (dlon.sin() * lat2.cos()).atan2(lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * dlon.cos());
+	if bearing < 0.0 { bearing + 2.0*consts::PI } else { bearing }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Utm {
+	pub zone: u8,
+	pub northern: bool,
+	pub easting: f64,
+	pub northing: f64,
+}
+
+// Krueger series coefficients to fourth order in the third flattening n: the forward alpha, inverse beta and
+// conformal-to-geodetic latitude delta.  Good to well under a millimeter within the zone.
+struct Krueger {
+	a_hat: f64,
+	alpha: [f64; 4],
+	beta: [f64; 4],
+	delta: [f64; 4],
+	e: f64,
+}
+
+fn krueger() -> Krueger {
+	let f:f64 = 1.0 - WGS84_SEMI_MINOR_AXIS_METERS / WGS84_SEMI_MAJOR_AXIS_METERS;
+	let n:f64 = f / (2.0 - f);
+	let (n2, n3, n4) = (n*n, n*n*n, n*n*n*n);
+	Krueger{
+		a_hat: WGS84_SEMI_MAJOR_AXIS_METERS / (1.0 + n) * (1.0 + n2/4.0 + n4/64.0),
+		alpha: [n/2.0 - 2.0*n2/3.0 + 5.0*n3/16.0 + 41.0*n4/180.0,
+			13.0*n2/48.0 - 3.0*n3/5.0 + 557.0*n4/1440.0,
+			61.0*n3/240.0 - 103.0*n4/140.0,
+			49561.0*n4/161280.0],
+		beta: [n/2.0 - 2.0*n2/3.0 + 37.0*n3/96.0 - n4/360.0,
+			n2/48.0 + n3/15.0 - 437.0*n4/1440.0,
+			17.0*n3/480.0 - 37.0*n4/840.0,
+			4397.0*n4/161280.0],
+		delta: [2.0*n - 2.0*n2/3.0 - 2.0*n3 + 116.0*n4/45.0,
+			7.0*n2/3.0 - 8.0*n3/5.0 - 227.0*n4/45.0,
+			56.0*n3/15.0 - 136.0*n4/35.0,
+			4279.0*n4/630.0],
+		e: 2.0 * n.sqrt() / (1.0 + n),
+	}
+}
+
+/// Standard zone for a position, including the exceptions around Norway and Svalbard
+pub fn utm_zone(lat:f64, lon:f64) -> u8 {
+	let (lat_deg, lon_deg) = (lat.to_degrees(), lon.to_degrees());
+	let lon_deg:f64 = (lon_deg + 180.0).rem_euclid(360.0) - 180.0;
+	if (56.0..64.0).contains(&lat_deg) && (3.0..12.0).contains(&lon_deg) { return 32; }
+	if (72.0..84.0).contains(&lat_deg) && (0.0..42.0).contains(&lon_deg) {
+		return if lon_deg < 9.0 { 31 } else if lon_deg < 21.0 { 33 } else if lon_deg < 33.0 { 35 } else { 37 };
+	}
+	(((lon_deg + 180.0) / 6.0).floor() as u8).min(59) + 1
+}
+
+fn central_meridian(zone:u8) -> f64 { (6.0 * (zone as f64) - 183.0).to_radians() }
+
+impl Utm {
+
+	pub fn from_wgs84(lat:f64, lon:f64) -> Result<Self, &'static str> {
+		Self::from_wgs84_in_zone(lat, lon, utm_zone(lat, lon))
+	}
+
+	/// Projects into a given zone, e.g. to keep a track that crosses a zone boundary in one grid
+	pub fn from_wgs84_in_zone(lat:f64, lon:f64, zone:u8) -> Result<Self, &'static str> {
+		if !(-80.0..=84.0).contains(&lat.to_degrees()) { return Err("UTM doesn't cover the polar regions"); }
+		if !(1..=60).contains(&zone) { return Err("UTM zone must be between 1 and 60"); }
+
+		let k = krueger();
+		let dlon:f64 = ((lon - central_meridian(zone) + consts::PI).rem_euclid(2.0*consts::PI)) - consts::PI;
+		let t:f64 = (lat.sin().atanh() - k.e * (k.e * lat.sin()).atanh()).sinh();
+		let xi_p:f64 = t.atan2(dlon.cos());
+		let eta_p:f64 = (dlon.sin() / (1.0 + t*t).sqrt()).atanh();
+
+		let (mut xi, mut eta) = (xi_p, eta_p);
+		for (j, alpha) in k.alpha.iter().enumerate() {
+			let jj:f64 = 2.0 * (j + 1) as f64;
+			xi  += alpha * (jj*xi_p).sin() * (jj*eta_p).cosh();
+			eta += alpha * (jj*xi_p).cos() * (jj*eta_p).sinh();
+		}
+
+		let northern:bool = lat >= 0.0;
+		let northing:f64 = UTM_SCALE_FACTOR * k.a_hat * xi + if northern { 0.0 } else { UTM_FALSE_NORTHING_METERS };
+		Ok(Self{ zone, northern, easting: UTM_FALSE_EASTING_METERS + UTM_SCALE_FACTOR * k.a_hat * eta, northing })
+	}
+
+	/// Latitude and longitude [rad]
+	pub fn to_wgs84(&self) -> (f64, f64) {
+		let k = krueger();
+		let xi:f64 = (self.northing - if self.northern { 0.0 } else { UTM_FALSE_NORTHING_METERS }) / (UTM_SCALE_FACTOR * k.a_hat);
+		let eta:f64 = (self.easting - UTM_FALSE_EASTING_METERS) / (UTM_SCALE_FACTOR * k.a_hat);
+
+		let (mut xi_p, mut eta_p) = (xi, eta);
+		for (j, beta) in k.beta.iter().enumerate() {
+			let jj:f64 = 2.0 * (j + 1) as f64;
+			xi_p  -= beta * (jj*xi).sin() * (jj*eta).cosh();
+			eta_p -= beta * (jj*xi).cos() * (jj*eta).sinh();
+		}
+
+		let chi:f64 = (xi_p.sin() / eta_p.cosh()).asin();
+		let lat:f64 = chi + k.delta.iter().enumerate().map(|(j, delta)| delta * (2.0 * (j + 1) as f64 * chi).sin()).sum::<f64>();
+		let lon:f64 = central_meridian(self.zone) + eta_p.sinh().atan2(xi_p.cos());
+		(lat, lon)
+	}
+
+}
+
+/// MGRS reference with the given number of digits (0 to 5) per coordinate, e.g. 31NAA6602100000 at 5 digits,
+/// which is a 1 m square.  Digits are truncated rather than rounded, as the grid square contains the point.
+pub fn mgrs(lat:f64, lon:f64, digits:usize) -> Result<String, &'static str> {
+	if digits > 5 { return Err("MGRS has at most five digits per coordinate"); }
+	let utm = Utm::from_wgs84(lat, lon)?;
+
+	let band:u8 = MGRS_BANDS[(((lat.to_degrees() + 80.0) / 8.0).floor().max(0.0) as usize).min(MGRS_BANDS.len() - 1)];
+
+	// Column letters cycle every three zones, rows every two, and both skip I and O
+	let set:usize = (utm.zone as usize - 1) % 3;
+	let col:usize = (utm.easting / 100000.0).floor() as usize;
+	if !(1..=8).contains(&col) { return Err("Easting is outside the UTM zone"); }
+	let col_letter:u8 = MGRS_LETTERS[set*8 + col - 1];
+	let row_offset:usize = if utm.zone % 2 == 0 { 5 } else { 0 };
+	let row:usize = ((utm.northing / 100000.0).floor() as usize + row_offset) % 20;
+	let row_letter:u8 = MGRS_LETTERS[row];
+
+	let scale:f64 = 10f64.powi(5 - digits as i32);
+	let e:u64 = ((utm.easting % 100000.0) / scale).floor() as u64;
+	let n:u64 = ((utm.northing % 100000.0) / scale).floor() as u64;
+	let mut s = format!("{}{}{}{}", utm.zone, band as char, col_letter as char, row_letter as char);
+	if digits > 0 { s += &format!("{:0width$}{:0width$}", e, n, width = digits); }
+	Ok(s)
+}
+
+#[test]
+fn test_utm_and_mgrs() {
+	// On the equator at a central meridian the projection is just the scaled meridian arc
+	let utm = Utm::from_wgs84(0.0, 3.0f64.to_radians()).unwrap();
+	assert_eq!((utm.zone, utm.northern), (31, true));
+	assert!((utm.easting - 500000.0).abs() < 1.0e-6 && utm.northing.abs() < 1.0e-6);
+	let utm = Utm::from_wgs84(1.0f64.to_radians(), 3.0f64.to_radians()).unwrap();
+	assert!((utm.northing - 0.9996 * 110574.4).abs() < 1.0);
+
+	// Round trip away from the central meridian, in both hemispheres
+	for (lat_deg, lon_deg) in [(37.4f64, -122.1f64), (-33.9, 151.2), (60.2, 5.3), (78.2, 15.6)].iter() {
+		let utm = Utm::from_wgs84(lat_deg.to_radians(), lon_deg.to_radians()).unwrap();
+		let (lat, lon) = utm.to_wgs84();
+		assert!((lat.to_degrees() - lat_deg).abs() < 1.0e-9 && (lon.to_degrees() - lon_deg).abs() < 1.0e-9);
+	}
+	assert_eq!(utm_zone(60.2f64.to_radians(), 5.3f64.to_radians()), 32);
+	assert_eq!(utm_zone(78.2f64.to_radians(), 15.6f64.to_radians()), 33);
+
+	assert_eq!(mgrs(0.0, 0.0, 5).unwrap(), "31NAA6602100000");
+	assert_eq!(mgrs(0.0, 0.0, 1).unwrap(), "31NAA60");
+	assert!(mgrs(-85.0f64.to_radians(), 0.0, 5).is_err());
+
+	// A quarter of the way around the equator
+	let d:f64 = great_circle_distance_m(0.0, 0.0, 0.0, consts::FRAC_PI_2);
+	assert!((d - 0.5 * consts::PI * MEAN_EARTH_RADIUS_METERS).abs() < 1.0e-6);
+	assert!((great_circle_bearing(0.0, 0.0, 0.1, 0.0)).abs() < 1.0e-12);
+}
@@ -0,0 +1,121 @@
+
+use std::io::Read;
+
+use super::kinematics::WGS84_SEMI_MAJOR_AXIS_METERS;
+
+const MAX_DEGREE:usize = 4;
+const ZERO_DEGREE_TERM_METERS:f64 = -0.53;		// EGM96 geoid relative to the WGS-84 ellipsoid
+
+// Fully-normalized EGM96 coefficients (C, S) by degree and order, with the even zonals of the WGS-84 normal field already
+// subtracted from C20 and C40 so the sum is the disturbing potential
+const EGM96_CS:[[(f64, f64); MAX_DEGREE+1]; MAX_DEGREE+1] = [
+	[(0.0, 0.0); MAX_DEGREE+1],
+	[(0.0, 0.0); MAX_DEGREE+1],
+	[(-0.484165371736e-3 + 0.484166774985e-3, 0.0), (-0.186987635955e-9, 0.119528012031e-8), (0.243914352398e-5, -0.140016683654e-5), (0.0, 0.0), (0.0, 0.0)],
+	[(0.957254173792e-6, 0.0), (0.203046201047e-5, 0.248200415856e-6), (0.904787894809e-6, -0.619005475177e-6), (0.721321757121e-6, 0.141434926192e-5), (0.0, 0.0)],
+	[(0.539873863789e-6 - 0.790303733511e-6, 0.0), (-0.536157389388e-6, -0.473567346518e-6), (0.350501623962e-6, 0.662480026275e-6), (0.990856766672e-6, -0.200956723567e-6), (-0.188560802735e-6, 0.308803882149e-6)],
+];
+
+// Fully-normalized associated Legendre functions of sin(lat) up to MAX_DEGREE
+fn legendre(lat:f64) -> [[f64; MAX_DEGREE+1]; MAX_DEGREE+1] {
+	let (t, u) = (lat.sin(), lat.cos());
+	let mut p = [[0.0; MAX_DEGREE+1]; MAX_DEGREE+1];
+	p[0][0] = 1.0;
+	for m in 0..=MAX_DEGREE {
+		if m > 0 {
+			let k:f64 = if m == 1 { 3.0 } else { (2.0*m as f64 + 1.0) / (2.0*m as f64) };
+			p[m][m] = k.sqrt() * u * p[m-1][m-1];
+		}
+		if m < MAX_DEGREE { p[m+1][m] = (2.0*m as f64 + 3.0).sqrt() * t * p[m][m]; }
+		for n in (m+2)..=MAX_DEGREE {
+			let (nf, mf) = (n as f64, m as f64);
+			let a:f64 = ((2.0*nf + 1.0) * (2.0*nf - 1.0) / ((nf - mf) * (nf + mf))).sqrt();
+			let b:f64 = ((2.0*nf + 1.0) * (nf + mf - 1.0) * (nf - mf - 1.0) / ((nf - mf) * (nf + mf) * (2.0*nf - 3.0))).sqrt();
+			p[n][m] = a * t * p[n-1][m] - b * p[n-2][m];
+		}
+	}
+	p
+}
+
+/// Geoid height above the WGS-84 ellipsoid [m] from the EGM96 expansion truncated at degree and order 4.  This only has the
+/// continental-scale features, so it can be off by 30 m or so; load the EGM96 grid with `GeoidGrid` when that matters.
+pub fn undulation_m(lat:f64, lon:f64) -> f64 {
+	let p = legendre(lat);
+	let mut sum:f64 = 0.0;
+	for n in 2..=MAX_DEGREE {
+		for m in 0..=n {
+			let (c, s) = EGM96_CS[n][m];
+			sum += (c * (m as f64 * lon).cos() + s * (m as f64 * lon).sin()) * p[n][m];
+		}
+	}
+	ZERO_DEGREE_TERM_METERS + WGS84_SEMI_MAJOR_AXIS_METERS * sum
+}
+
+pub fn height_msl_m(lat:f64, lon:f64, height_above_ellipsoid:f64) -> f64 { height_above_ellipsoid - undulation_m(lat, lon) }
+
+/// Geoid heights on a regular latitude/longitude grid, in the text format NGA distributes EGM96 in (WW15MGH.GRD): a header
+/// of south, north, west and east bounds and the latitude and longitude spacing in degrees, then rows from north to south.
+#[derive(Debug, Clone)]
+pub struct GeoidGrid {
+	pub south_deg: f64,
+	pub north_deg: f64,
+	pub west_deg: f64,
+	pub east_deg: f64,
+	pub dlat_deg: f64,
+	pub dlon_deg: f64,
+	rows: usize,
+	cols: usize,
+	values: Vec<f64>,
+}
+
+impl GeoidGrid {
+
+	pub fn from_grd<R: Read>(mut reader:R) -> Result<Self, &'static str> {
+		let mut text = String::new();
+		reader.read_to_string(&mut text).map_err(|_| "Unable to read geoid grid")?;
+		let mut numbers = text.split_whitespace().map(|s| s.parse::<f64>().map_err(|_| "Unable to parse geoid grid"));
+
+		let mut header = [0.0; 6];
+		for h in header.iter_mut() { *h = numbers.next().ok_or("Geoid grid header is incomplete")??; }
+		let [south_deg, north_deg, west_deg, east_deg, dlat_deg, dlon_deg] = header;
+		if dlat_deg <= 0.0 || dlon_deg <= 0.0 || north_deg <= south_deg || east_deg <= west_deg { return Err("Invalid geoid grid header"); }
+
+		let rows:usize = ((north_deg - south_deg) / dlat_deg).round() as usize + 1;
+		let cols:usize = ((east_deg - west_deg) / dlon_deg).round() as usize + 1;
+		let values:Vec<f64> = numbers.collect::<Result<_, _>>()?;
+		if values.len() != rows * cols { return Err("Geoid grid size doesn't match its header"); }
+
+		Ok(Self{ south_deg, north_deg, west_deg, east_deg, dlat_deg, dlon_deg, rows, cols, values })
+	}
+
+	/// Bilinear interpolation of the geoid height [m], or None outside the grid
+	pub fn undulation_m(&self, lat:f64, lon:f64) -> Option<f64> {
+		let lat_deg:f64 = lat.to_degrees();
+		let mut lon_deg:f64 = lon.to_degrees();
+		while lon_deg < self.west_deg { lon_deg += 360.0; }
+		while lon_deg > self.east_deg && lon_deg - 360.0 >= self.west_deg { lon_deg -= 360.0; }
+		if lat_deg < self.south_deg || lat_deg > self.north_deg || lon_deg > self.east_deg { return None; }
+
+		let y:f64 = (self.north_deg - lat_deg) / self.dlat_deg;
+		let x:f64 = (lon_deg - self.west_deg) / self.dlon_deg;
+		let (i, j) = ((y.floor() as usize).min(self.rows - 2), (x.floor() as usize).min(self.cols - 2));
+		let (fy, fx) = (y - i as f64, x - j as f64);
+		let v = |r:usize, c:usize| self.values[r*self.cols + c];
+		Some((1.0 - fy) * ((1.0 - fx) * v(i, j) + fx * v(i, j+1)) + fy * ((1.0 - fx) * v(i+1, j) + fx * v(i+1, j+1)))
+	}
+
+}
+
+#[test]
+fn test_geoid() {
+	// Against EGM96 at a few points with large undulations, to within what degree 4 can capture
+	for (lat_deg, lon_deg, egm96) in [(0.0f64, 0.0f64, 17.2), (5.0, 78.0, -100.0), (-5.0, 145.0, 70.0), (64.0, -20.0, 65.0), (60.0, -85.0, -40.0)].iter() {
+		let n:f64 = undulation_m(lat_deg.to_radians(), lon_deg.to_radians());
+		assert!((n - egm96).abs() < 35.0);
+	}
+
+	let grid = GeoidGrid::from_grd("-10 10 0 360 10 180\n 1 2 3\n 4 5 6\n 7 8 9\n".as_bytes()).unwrap();
+	assert!((grid.undulation_m(0.0, 0.0).unwrap() - 4.0).abs() < 1.0e-12);
+	assert!((grid.undulation_m(5.0f64.to_radians(), (-90.0f64).to_radians()).unwrap() - 4.0).abs() < 1.0e-12);
+	assert!(grid.undulation_m(20.0f64.to_radians(), 0.0).is_none());
+}
@@ -0,0 +1,26 @@
+use crate::DigSigProcErr;
+
+// Inverse of the functions in bools_to_int; the length of the destination slice sets the field width
+
+pub fn scaled(x:f64, lsb:f64) -> i64 { (x / lsb).round() as i64 }
+
+pub fn from_unsigned(x:i64, bools:&mut [bool]) -> Result<(), DigSigProcErr> {
+    let n = bools.len();
+    if x >= 0 && (n >= 63 || x < (1i64 << n)) {
+        for (i, b) in bools.iter_mut().enumerate() { *b = (x >> (n-i-1)) & 1 == 1; }
+        Ok(())
+    } else {
+    	Err(DigSigProcErr::InvalidTelemetryData("Value out of range in int_to_bools::from_unsigned"))
+    }
+}
+
+pub fn from_signed(x:i64, bools:&mut [bool]) -> Result<(), DigSigProcErr> {
+    let n = bools.len();
+    if n > 0 && n <= 64 && (n == 64 || (x >= -(1i64 << (n-1)) && x < (1i64 << (n-1)))) {
+        // Two's complement representation
+        for (i, b) in bools.iter_mut().enumerate() { *b = (x >> (n-i-1)) & 1 == 1; }
+        Ok(())
+    } else {
+    	Err(DigSigProcErr::InvalidTelemetryData("Value out of range in int_to_bools::from_signed"))
+    }
+}
@@ -23,6 +23,31 @@ pub fn dcm_we(lat:f64, lon:f64) -> Matrix3<f64> {
 		          lat.cos()*lon.cos(),  lat.cos()*lon.sin(), lat.sin())
 }
 
+/// Rotation from ECEF to north, east, down at the given latitude and longitude
+pub fn dcm_ne(lat:f64, lon:f64) -> Matrix3<f64> {
+	Matrix3::new(-lat.sin()*lon.cos(), -lat.sin()*lon.sin(),  lat.cos(),
+		         -lon.sin(),            lon.cos(),            0.0,
+		         -lat.cos()*lon.cos(), -lat.cos()*lon.sin(), -lat.sin())
+}
+
+/// Rotation from a forward-right-down body frame to north, east, down for the given roll, pitch and yaw (applied in that
+/// order about the body axes, i.e. yaw first from the navigation frame's point of view)
+pub fn dcm_from_euler(roll:f64, pitch:f64, yaw:f64) -> Matrix3<f64> {
+	let (sr, cr, sp, cp, sy, cy) = (roll.sin(), roll.cos(), pitch.sin(), pitch.cos(), yaw.sin(), yaw.cos());
+	Matrix3::new(cp*cy, -cr*sy + sr*sp*cy,  sr*sy + cr*sp*cy,
+		         cp*sy,  cr*cy + sr*sp*sy, -sr*cy + cr*sp*sy,
+		         -sp,    sr*cp,             cr*cp)
+}
+
+/// Roll, pitch and yaw of a body-to-NED rotation, with yaw in [0, 2*pi)
+pub fn euler_from_dcm(c_bn:&Matrix3<f64>) -> (f64, f64, f64) {
+	let roll:f64 = c_bn[(2,1)].atan2(c_bn[(2,2)]);
+	let pitch:f64 = (-c_bn[(2,0)]).clamp(-1.0, 1.0).asin();
+	let mut yaw:f64 = c_bn[(1,0)].atan2(c_bn[(0,0)]);
+	if yaw < 0.0 { yaw += 2.0*consts::PI; }
+	(roll, pitch, yaw)
+}
+
 pub fn az_el(lat:f64, lon:f64, h:f64, los_e:Vector3<f64>) -> (f64, f64) {
 	let los_enu:Vector3<f64> = dcm_we(lat, lon) * los_e;
 	let dot_01:f64 = los_enu[0]*los_enu[0] + los_enu[1]*los_enu[1];
@@ -55,4 +80,13 @@ pub fn ecef_to_wgs84(e1:f64, e2:f64, e3:f64) -> PositionWGS84 {
 	let height_above_ellipsoid = p*latitude.cos() + e3*latitude.sin() - (WGS84_SEMI_MAJOR_AXIS_METERS.powi(2) / v);
 
 	PositionWGS84{ latitude, longitude, height_above_ellipsoid }
-}
\ No newline at end of file
+}
+
+/// ECEF position [m] of a point given by geodetic latitude and longitude [rad] and height above the WGS-84 ellipsoid [m]
+pub fn wgs84_to_ecef(lat:f64, lon:f64, height_above_ellipsoid:f64) -> Vector3<f64> {
+	let e_sq:f64 = 1.0 - (WGS84_SEMI_MINOR_AXIS_METERS / WGS84_SEMI_MAJOR_AXIS_METERS).powi(2);
+	let n:f64 = WGS84_SEMI_MAJOR_AXIS_METERS / (1.0 - e_sq*lat.sin().powi(2)).sqrt();
+	Vector3::new((n + height_above_ellipsoid) * lat.cos() * lon.cos(),
+		         (n + height_above_ellipsoid) * lat.cos() * lon.sin(),
+		         (n * (1.0 - e_sq) + height_above_ellipsoid) * lat.sin())
+}
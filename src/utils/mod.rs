@@ -1,5 +1,8 @@
 
 pub mod bools_to_int;
+pub mod geodesy;
+pub mod geoid;
+pub mod int_to_bools;
 pub mod kinematics;
 
 pub struct IntegerClock {